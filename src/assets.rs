@@ -0,0 +1,680 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+use anyhow::{anyhow, bail};
+use log::{info, warn};
+
+use crate::audio::Sound;
+use crate::bundle::Bundle;
+
+
+//
+//  asset server: load::<T>(path) returns a Handle<T> at once and the bytes arrive later through
+//  an AssetIo (fetch on the web, a thread per file on native, memory in tests). update() once
+//  per frame decodes what arrived. the same path loads once while anything holds a handle to it,
+//  when the last handle is dropped the asset is unloaded on the next update
+//
+//  readiness: poll handle.state() every frame, or handle.ready().await in an async task
+//
+//  bundles: load_bundle() fetches a packed bundle, loads started meanwhile wait for it and are
+//  served from it when it has the path. Preload follows a set of handles for a loading screen
+//
+
+
+pub trait Asset: Sized + 'static {
+    fn decode(path: &str, bytes: &[u8]) -> anyhow::Result<Self>;
+}
+
+impl Asset for Vec<u8> {
+    fn decode(_path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Asset for String {
+    fn decode(path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| anyhow!("{} is not utf-8", path))
+    }
+}
+
+// wav only, compressed music goes through the music loader and the browser's decoder
+impl Asset for Sound {
+    fn decode(_path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        Sound::from_wav(bytes)
+    }
+}
+
+
+//
+//  decoded image, rgba8 rows from the top, uploaded by the renderer
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Asset for Texture {
+    fn decode(path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| anyhow!("{}: {}", path, e))?;
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(|e| anyhow!("{}: {}", path, e))?;
+        let buf = &buf[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf.to_vec(),
+            png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+            other => bail!("{}: unsupported png colour type {:?}", path, other),
+        };
+
+        Ok(Texture { width: info.width, height: info.height, pixels })
+    }
+}
+
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+
+struct HandleInner {
+    id: u32,
+    path: String,
+    state: Mutex<LoadState>,
+    // one per pending Ready, by its key
+    wakers: Mutex<Vec<(u64, Waker)>>,
+    next_ready: AtomicU64,
+}
+
+impl HandleInner {
+    fn finish(&self, state: LoadState) {
+        *self.state.lock().unwrap() = state;
+        for (_, w) in self.wakers.lock().unwrap().drain(..) {
+            w.wake();
+        }
+    }
+}
+
+
+//
+//  typed reference to an asset, cloning it keeps the asset alive
+//  Send + Sync so it can sit in an ecs component
+//
+pub struct Handle<T> {
+    inner: Arc<HandleInner>,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle { inner: self.inner.clone(), _t: PhantomData }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.id == other.inner.id
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Handle({}, {:?})", self.inner.id, self.inner.path)
+    }
+}
+
+impl<T> Handle<T> {
+
+    pub fn id(&self) -> u32 {
+        self.inner.id
+    }
+
+    pub fn path(&self) -> &str {
+        &self.inner.path
+    }
+
+    pub fn state(&self) -> LoadState {
+        self.inner.state.lock().unwrap().clone()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.state() == LoadState::Loaded
+    }
+
+    //
+    //  resolves once loaded or failed, the server still has to be updated by someone
+    //
+    pub fn ready(&self) -> Ready {
+        let key = self.inner.next_ready.fetch_add(1, Ordering::Relaxed);
+        Ready { inner: self.inner.clone(), key }
+    }
+}
+
+
+pub struct Ready {
+    inner: Arc<HandleInner>,
+    key: u64,
+}
+
+impl Future for Ready {
+    type Output = LoadState;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<LoadState> {
+        // waker first, so a finish between the check and the push is not missed.
+        // polled again it replaces its own waker, the list does not grow
+        {
+            let mut wakers = self.inner.wakers.lock().unwrap();
+            match wakers.iter_mut().find(|(k, _)| *k == self.key) {
+                Some((_, w)) => {
+                    if !w.will_wake(cx.waker()) {
+                        *w = cx.waker().clone();
+                    }
+                }
+                None => wakers.push((self.key, cx.waker().clone())),
+            }
+        }
+        match self.inner.state.lock().unwrap().clone() {
+            LoadState::Loading => Poll::Pending,
+            done => Poll::Ready(done),
+        }
+    }
+}
+
+impl Drop for Ready {
+    fn drop(&mut self) {
+        self.inner.wakers.lock().unwrap().retain(|(k, _)| *k != self.key);
+    }
+}
+
+
+
+//
+//  progress over a set of handles of any type, for loading screens.
+//  update() after the server's update, callbacks run when something finished since the last call
+//
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Progress {
+    pub loaded: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl Progress {
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.loaded + self.failed) as f32 / self.total as f32
+    }
+
+    pub fn finished(&self) -> bool {
+        self.loaded + self.failed >= self.total
+    }
+}
+
+
+type OnProgress = Box<dyn FnMut(&Progress)>;
+
+#[derive(Default)]
+pub struct Preload {
+    handles: Vec<Arc<HandleInner>>,
+    callbacks: Vec<OnProgress>,
+    last: Option<Progress>,
+}
+
+impl Preload {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<T>(mut self, h: &Handle<T>) -> Self {
+        self.add(h);
+        self
+    }
+
+    pub fn add<T>(&mut self, h: &Handle<T>) {
+        if !self.handles.iter().any(|i| i.id == h.inner.id) {
+            self.handles.push(h.inner.clone());
+        }
+    }
+
+    pub fn on_progress(mut self, f: impl FnMut(&Progress) + 'static) -> Self {
+        self.callbacks.push(Box::new(f));
+        self
+    }
+
+    pub fn progress(&self) -> Progress {
+        let mut p = Progress { total: self.handles.len(), ..Default::default() };
+        for h in &self.handles {
+            match *h.state.lock().unwrap() {
+                LoadState::Loading => (),
+                LoadState::Loaded => p.loaded += 1,
+                LoadState::Failed(_) => p.failed += 1,
+            }
+        }
+        p
+    }
+
+    pub fn update(&mut self) -> Progress {
+        let p = self.progress();
+        if self.last != Some(p) {
+            self.last = Some(p);
+            for f in &mut self.callbacks {
+                f(&p);
+            }
+        }
+        p
+    }
+
+    // the first path still loading, for a label under the bar
+    pub fn current(&self) -> Option<&str> {
+        self.handles.iter()
+            .find(|h| *h.state.lock().unwrap() == LoadState::Loading)
+            .map(|h| h.path.as_str())
+    }
+
+    // (path, error) of every failed load
+    pub fn failures(&self) -> Vec<(String, String)> {
+        self.handles.iter()
+            .filter_map(|h| match &*h.state.lock().unwrap() {
+                LoadState::Failed(e) => Some((h.path.clone(), e.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+
+
+//
+//  where bytes come from. start() kicks off a read, results come back from poll() in any order
+//
+pub trait AssetIo {
+    fn start(&mut self, id: u32, path: &str);
+    fn poll(&mut self) -> Vec<(u32, Result<Vec<u8>, String>)>;
+}
+
+
+//
+//  files added up front, every read finishes on the next poll
+//
+#[derive(Default)]
+pub struct MemoryIo {
+    files: HashMap<String, Vec<u8>>,
+    pending: Vec<(u32, String)>,
+}
+
+impl MemoryIo {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, path: &str, bytes: &[u8]) -> Self {
+        self.files.insert(path.to_string(), bytes.to_vec());
+        self
+    }
+}
+
+impl AssetIo for MemoryIo {
+    fn start(&mut self, id: u32, path: &str) {
+        self.pending.push((id, path.to_string()));
+    }
+
+    fn poll(&mut self) -> Vec<(u32, Result<Vec<u8>, String>)> {
+        let files = &self.files;
+        self.pending.drain(..)
+            .map(|(id, path)| (id, files.get(&path).cloned().ok_or(format!("{}: not found", path))))
+            .collect()
+    }
+}
+
+
+//
+//  native: every file is read on its own thread under `root`
+//
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileIo {
+    root: std::path::PathBuf,
+    tx: Sender<(u32, Result<Vec<u8>, String>)>,
+    rx: Receiver<(u32, Result<Vec<u8>, String>)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileIo {
+    pub fn new(root: &str) -> Self {
+        let (tx, rx) = channel();
+        FileIo { root: root.into(), tx, rx }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AssetIo for FileIo {
+    fn start(&mut self, id: u32, path: &str) {
+        let full = self.root.join(path);
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let result = std::fs::read(&full).map_err(|e| format!("{}: {}", full.display(), e));
+            let _ = tx.send((id, result));
+        });
+    }
+
+    fn poll(&mut self) -> Vec<(u32, Result<Vec<u8>, String>)> {
+        self.rx.try_iter().collect()
+    }
+}
+
+
+//
+//  browser: fetch relative to `base` (the page when empty)
+//
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+pub struct FetchIo {
+    base: String,
+    tx: Sender<(u32, Result<Vec<u8>, String>)>,
+    rx: Receiver<(u32, Result<Vec<u8>, String>)>,
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+impl FetchIo {
+    pub fn new(base: &str) -> Self {
+        let (tx, rx) = channel();
+        FetchIo { base: base.trim_end_matches('/').to_string(), tx, rx }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+impl AssetIo for FetchIo {
+    fn start(&mut self, id: u32, path: &str) {
+        let url = if self.base.is_empty() { path.to_string() } else { format!("{}/{}", self.base, path) };
+        let tx = self.tx.clone();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            // reqwest wants an absolute url in the browser
+            let full = match web_sys::window().and_then(|w| w.location().href().ok()) {
+                Some(page) => reqwest::Url::parse(&page).and_then(|p| p.join(&url)).map(|u| u.to_string()).unwrap_or(url),
+                None => url,
+            };
+
+            let result = match reqwest::get(&full).await.and_then(|r| r.error_for_status()) {
+                Ok(r) => r.bytes().await.map(|b| b.to_vec()).map_err(|e| format!("{}: {}", full, e)),
+                Err(e) => Err(format!("{}: {}", full, e)),
+            };
+            let _ = tx.send((id, result));
+        });
+    }
+
+    fn poll(&mut self) -> Vec<(u32, Result<Vec<u8>, String>)> {
+        self.rx.try_iter().collect()
+    }
+}
+
+
+
+// T::decode with the type erased
+type Decode = fn(&str, &[u8]) -> anyhow::Result<Box<dyn Any>>;
+
+struct Slot {
+    path: String,
+    type_id: TypeId,
+    handle: Weak<HandleInner>,
+    asset: Option<Box<dyn Any>>,
+    decode: Decode,
+}
+
+fn decode_any<T: Asset>(path: &str, bytes: &[u8]) -> anyhow::Result<Box<dyn Any>> {
+    Ok(Box::new(T::decode(path, bytes)?))
+}
+
+
+pub struct AssetServer {
+    io: Box<dyn AssetIo>,
+    slots: HashMap<u32, Slot>,
+    by_path: HashMap<(String, TypeId), u32>,
+    next_id: u32,
+    bundles: Vec<Bundle>,
+    // bundles on the way, other loads wait in `waiting` until they are all settled
+    bundle_loads: Vec<Handle<Bundle>>,
+    waiting: Vec<(u32, String)>,
+    // reads served from a mounted bundle, decoded on the next update
+    served: Vec<(u32, Result<Vec<u8>, String>)>,
+}
+
+impl AssetServer {
+
+    pub fn new(io: Box<dyn AssetIo>) -> Self {
+        AssetServer {
+            io,
+            slots: HashMap::new(),
+            by_path: HashMap::new(),
+            next_id: 1,
+            bundles: vec![],
+            bundle_loads: vec![],
+            waiting: vec![],
+            served: vec![],
+        }
+    }
+
+    //
+    //  fetch under `root` in the browser, files under `root` on native
+    //
+    pub fn platform(root: &str) -> Self {
+        #[cfg(all(target_arch = "wasm32", feature = "client"))]
+        let io: Box<dyn AssetIo> = Box::new(FetchIo::new(root));
+        #[cfg(not(target_arch = "wasm32"))]
+        let io: Box<dyn AssetIo> = Box::new(FileIo::new(root));
+        #[cfg(all(target_arch = "wasm32", not(feature = "client")))]
+        let io: Box<dyn AssetIo> = Box::new(MemoryIo::new());
+        AssetServer::new(io)
+    }
+
+
+    //
+    //  the cached handle when the path is already loaded as a T, otherwise starts loading
+    //
+    pub fn load<T: Asset>(&mut self, path: &str) -> Handle<T> {
+        if let Some(h) = self.cached(path) {
+            return h;
+        }
+
+        let (h, id) = self.slot::<T>(path);
+        if self.bundle_loads.is_empty() || TypeId::of::<T>() == TypeId::of::<Bundle>() {
+            self.fetch(id, path);
+        } else {
+            self.waiting.push((id, path.to_string()));
+        }
+        h
+    }
+
+    //
+    //  mounted once loaded, a bundle that fails to load only means separate requests
+    //
+    pub fn load_bundle(&mut self, path: &str) -> Handle<Bundle> {
+        let h = self.load::<Bundle>(path);
+        if h.state() == LoadState::Loading && !self.bundle_loads.contains(&h) {
+            self.bundle_loads.push(h.clone());
+        }
+        h
+    }
+
+    // later mounts win when two bundles have the same path
+    pub fn mount(&mut self, bundle: Bundle) {
+        info!("mounted bundle with {} files", bundle.manifest.entries.len());
+        self.bundles.insert(0, bundle);
+    }
+
+    //
+    //  something made in code, under a path so load() finds it
+    //
+    pub fn insert<T: Asset>(&mut self, path: &str, asset: T) -> Handle<T> {
+        let h = match self.cached::<T>(path) {
+            Some(h) => h,
+            None => self.slot::<T>(path).0,
+        };
+
+        self.slots.get_mut(&h.id()).unwrap().asset = Some(Box::new(asset));
+        h.inner.finish(LoadState::Loaded);
+        h
+    }
+
+    pub fn get<T: Asset>(&self, h: &Handle<T>) -> Option<&T> {
+        self.slots.get(&h.id())?.asset.as_ref()?.downcast_ref()
+    }
+
+    pub fn get_mut<T: Asset>(&mut self, h: &Handle<T>) -> Option<&mut T> {
+        self.slots.get_mut(&h.id())?.asset.as_mut()?.downcast_mut()
+    }
+
+    // assets in memory or on the way
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn loading(&self) -> usize {
+        self.slots.values()
+            .filter(|s| s.handle.upgrade().is_some_and(|h| *h.state.lock().unwrap() == LoadState::Loading))
+            .count()
+    }
+
+
+    //
+    //  decode whatever arrived, unload what nobody holds any more
+    //
+    pub fn update(&mut self) {
+        let mut arrived = self.io.poll();
+        arrived.append(&mut self.served);
+        self.decode(arrived);
+
+        if !self.bundle_loads.is_empty() {
+            let (done, loading): (Vec<_>, Vec<_>) = self.bundle_loads.drain(..).partition(|h| h.state() != LoadState::Loading);
+            self.bundle_loads = loading;
+            for h in done {
+                if let Some(b) = self.get(&h).cloned() {
+                    self.mount(b);
+                }
+            }
+
+            if self.bundle_loads.is_empty() {
+                for (id, path) in std::mem::take(&mut self.waiting) {
+                    if self.slots.contains_key(&id) {
+                        self.fetch(id, &path);
+                    }
+                }
+                let served = std::mem::take(&mut self.served);
+                self.decode(served);
+            }
+        }
+
+        let dead: Vec<u32> = self.slots.iter().filter(|(_, s)| s.handle.strong_count() == 0).map(|(id, _)| *id).collect();
+        for id in dead {
+            let s = self.slots.remove(&id).unwrap();
+            // the path may already point at a newer load
+            let key = (s.path, s.type_id);
+            if self.by_path.get(&key) == Some(&id) {
+                self.by_path.remove(&key);
+            }
+        }
+    }
+
+    //
+    //  native tools and tests: update until the handle is done or `timeout` runs out
+    //
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait<T: Asset>(&mut self, h: &Handle<T>, timeout: std::time::Duration) -> LoadState {
+        let start = std::time::Instant::now();
+        loop {
+            self.update();
+            let state = h.state();
+            if state != LoadState::Loading || start.elapsed() > timeout {
+                return state;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+
+    fn fetch(&mut self, id: u32, path: &str) {
+        match self.bundles.iter().find(|b| b.contains(path)) {
+            Some(b) => self.served.push((id, b.read(path).map_err(|e| e.to_string()))),
+            None => self.io.start(id, path),
+        }
+    }
+
+    fn decode(&mut self, arrived: Vec<(u32, Result<Vec<u8>, String>)>) {
+        for (id, result) in arrived {
+            let slot = match self.slots.get_mut(&id) {
+                Some(s) => s,
+                // dropped while loading
+                None => continue,
+            };
+
+            let state = match result.map_err(|e| anyhow!(e)).and_then(|bytes| (slot.decode)(&slot.path, &bytes)) {
+                Ok(asset) => {
+                    slot.asset = Some(asset);
+                    LoadState::Loaded
+                }
+                Err(e) => {
+                    warn!("asset {}: {}", slot.path, e);
+                    LoadState::Failed(e.to_string())
+                }
+            };
+
+            if let Some(h) = slot.handle.upgrade() {
+                h.finish(state);
+            }
+        }
+    }
+
+    fn cached<T: Asset>(&self, path: &str) -> Option<Handle<T>> {
+        let id = self.by_path.get(&(path.to_string(), TypeId::of::<T>()))?;
+        let inner = self.slots.get(id)?.handle.upgrade()?;
+        Some(Handle { inner, _t: PhantomData })
+    }
+
+    fn slot<T: Asset>(&mut self, path: &str) -> (Handle<T>, u32) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let inner = Arc::new(HandleInner {
+            id,
+            path: path.to_string(),
+            state: Mutex::new(LoadState::Loading),
+            wakers: Mutex::new(vec![]),
+            next_ready: AtomicU64::new(0),
+        });
+
+        self.slots.insert(id, Slot {
+            path: path.to_string(),
+            type_id: TypeId::of::<T>(),
+            handle: Arc::downgrade(&inner),
+            asset: None,
+            decode: decode_any::<T>,
+        });
+        self.by_path.insert((path.to_string(), TypeId::of::<T>()), id);
+
+        (Handle { inner, _t: PhantomData }, id)
+    }
+}
@@ -1,10 +1,828 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
 
+use anyhow::{anyhow, bail};
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+use log::warn;
 
+use crate::music::*;
 
 
+//
+//  software mixer: every playing sound is mixed in rust into one stereo stream, the output only
+//  has to play that stream. the same mixer runs in the browser (AudioContext) and on native
+//  (null output, or a WAV recorder for tests)
+//
+//     Audio::update(now) --> output.wanted(now) frames --> Mixer::render --> output.write
+//
+//  sounds go through a bus (music, sfx, ui), every bus has its own gain on top of the master
+//
 
 
+pub const MIX_RATE: u32 = 44100;
 
-struct Audio {
-    
-}
\ No newline at end of file
+
+//
+//  decoded samples, interleaved when stereo, shared between all voices playing it
+//  Arc so a sound can sit in an ecs component
+//
+#[derive(Clone, PartialEq)]
+pub struct Sound {
+    pub rate: u32,
+    pub channels: u16,
+    pub samples: Arc<[f32]>,
+}
+
+impl Sound {
+
+    pub fn new(rate: u32, channels: u16, samples: Vec<f32>) -> Self {
+        Sound {
+            rate: rate.max(1),
+            channels: channels.clamp(1, 2),
+            samples: samples.into(),
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    // seconds
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.rate as f64
+    }
+
+    fn frame(&self, i: usize) -> (f32, f32) {
+        match self.channels {
+            1 => (self.samples[i], self.samples[i]),
+            _ => (self.samples[i * 2], self.samples[i * 2 + 1]),
+        }
+    }
+
+
+    //
+    //  RIFF WAVE, 8/16/24/32 bit pcm or 32 bit float, mono or stereo
+    //  extra channels are dropped
+    //
+    pub fn from_wav(bytes: &[u8]) -> anyhow::Result<Sound> {
+        let h = WavHeader::parse(bytes)?.ok_or(anyhow!("wav has no data chunk"))?;
+        let end = h.data_start.saturating_add(h.data_len).min(bytes.len());
+
+        let keep = h.channels.min(2) as usize;
+        let mut samples = vec![];
+        for frame in bytes[h.data_start..end].chunks_exact(h.frame_bytes()) {
+            for c in 0..keep {
+                samples.push(h.sample(frame, c));
+            }
+        }
+
+        Ok(Sound::new(h.rate, keep as u16, samples))
+    }
+
+    //
+    //  16 bit pcm
+    //
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len = self.samples.len() as u32 * 2;
+        let block = self.channels as u32 * 2;
+
+        let mut out = Vec::with_capacity(44 + data_len as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&self.channels.to_le_bytes());
+        out.extend_from_slice(&self.rate.to_le_bytes());
+        out.extend_from_slice(&(self.rate * block).to_le_bytes());
+        out.extend_from_slice(&(block as u16).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+
+        for s in self.samples.iter() {
+            let v = (s.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Sound> {
+        Sound::from_wav(&std::fs::read(path)?)
+    }
+}
+
+
+
+//
+//  the part of a wav file before the samples, the music stream parses it before the rest arrived
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WavHeader {
+    // 1 pcm, 3 float
+    pub format: u16,
+    pub channels: u16,
+    pub rate: u32,
+    pub bits: u16,
+    // byte offset and length of the samples, the length is usize::MAX when the writer did not know it
+    pub data_start: usize,
+    pub data_len: usize,
+}
+
+impl WavHeader {
+
+    //
+    //  None while `bytes` does not reach the start of the data chunk
+    //
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Option<WavHeader>> {
+        if bytes.len() < 12 {
+            return Ok(None);
+        }
+        if &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            bail!("not a wav file");
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        // (format, channels, rate, bits)
+        let mut fmt = None;
+
+        let mut i = 12;
+        while i + 8 <= bytes.len() {
+            let id = &bytes[i..i + 4];
+            let len = u32_at(i + 4) as usize;
+            let body = i + 8;
+
+            match id {
+                b"fmt " => {
+                    if body + 16 > bytes.len() {
+                        return Ok(None);
+                    }
+                    let mut format = u16_at(body);
+                    // WAVE_FORMAT_EXTENSIBLE, the real format starts the sub format guid
+                    if format == 0xFFFE && len >= 26 && body + 26 <= bytes.len() {
+                        format = u16_at(body + 24);
+                    }
+                    fmt = Some((format, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+                }
+                b"data" => {
+                    let (format, channels, rate, bits) = fmt.ok_or(anyhow!("wav has no fmt chunk before the data"))?;
+                    if channels == 0 {
+                        bail!("wav has no channels");
+                    }
+                    if !matches!((format, bits), (1, 8) | (1, 16) | (1, 24) | (1, 32) | (3, 32)) {
+                        bail!("unsupported wav format {} with {} bits", format, bits);
+                    }
+
+                    let data_len = if len == 0 || len == u32::MAX as usize { usize::MAX } else { len };
+                    return Ok(Some(WavHeader { format, channels, rate, bits, data_start: body, data_len }));
+                }
+                _ => (),
+            }
+
+            // chunks are padded to an even size, the length can be anything on 32 bit targets
+            i = match body.checked_add(len).and_then(|end| end.checked_add(len & 1)) {
+                Some(next) => next,
+                None => bail!("wav chunk of {} bytes does not fit", len),
+            };
+        }
+
+        Ok(None)
+    }
+
+    pub fn frame_bytes(&self) -> usize {
+        (self.bits as usize).div_ceil(8) * self.channels as usize
+    }
+
+    // channel `c` of one frame
+    pub fn sample(&self, frame: &[u8], c: usize) -> f32 {
+        let s = &frame[c * ((self.bits as usize).div_ceil(8))..];
+        match (self.format, self.bits) {
+            (1, 8) => (s[0] as f32 - 128.0) / 128.0,
+            (1, 16) => i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+            (1, 24) => (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8388608.0,
+            (1, 32) => i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2147483648.0,
+            (3, 32) => f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+            _ => 0.0,
+        }
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bus {
+    Music,
+    Sfx,
+    Ui,
+    // speech, ducks the music while it plays
+    Dialogue,
+}
+
+impl Bus {
+
+    pub const ALL: [Bus; 4] = [Bus::Music, Bus::Sfx, Bus::Ui, Bus::Dialogue];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bus::Music => "music",
+            Bus::Sfx => "sfx",
+            Bus::Ui => "ui",
+            Bus::Dialogue => "dialogue",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Bus> {
+        Bus::ALL.iter().copied().find(|b| b.name() == s)
+    }
+}
+
+
+
+//
+//  how to play a sound: Play::new().volume(0.5).pitch(1.2).looping()
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Play {
+    pub volume: f32,
+    // playback speed, 2 is an octave up
+    pub pitch: f32,
+    // -1 left .. 1 right
+    pub pan: f32,
+    pub looping: bool,
+    pub bus: Bus,
+    // important sound, the music ducks while it plays
+    pub duck: bool,
+}
+
+impl Default for Play {
+    fn default() -> Self {
+        Play {
+            volume: 1.0,
+            pitch: 1.0,
+            pan: 0.0,
+            looping: false,
+            bus: Bus::Sfx,
+            duck: false,
+        }
+    }
+}
+
+impl Play {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume.max(0.0);
+        self
+    }
+
+    pub fn pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch.max(0.01);
+        self
+    }
+
+    pub fn pan(mut self, pan: f32) -> Self {
+        self.pan = pan.clamp(-1.0, 1.0);
+        self
+    }
+
+    pub fn looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+
+    pub fn bus(mut self, bus: Bus) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    pub fn ducks(mut self) -> Self {
+        self.duck = true;
+        self
+    }
+}
+
+
+// a playing sound, stays valid (and does nothing) after the sound ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Voice(u64);
+
+
+struct Playing {
+    id: u64,
+    sound: Sound,
+    params: Play,
+    // in source frames
+    pos: f64,
+    done: bool,
+}
+
+
+pub fn clip(out: &mut [f32]) {
+    for s in out.iter_mut() {
+        *s = s.clamp(-1.0, 1.0);
+    }
+}
+
+
+//
+//  balance style pan: the centre keeps both sides at full volume
+//
+fn pan_gains(pan: f32) -> (f32, f32) {
+    ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+}
+
+
+
+pub struct Mixer {
+    pub rate: u32,
+    pub master: f32,
+    // past this the oldest one-shot voice is cut, or the new one when every voice loops
+    pub max_voices: usize,
+    voices: Vec<Playing>,
+    buses: [f32; 4],
+    next_id: u64,
+}
+
+impl Mixer {
+
+    pub fn new(rate: u32) -> Self {
+        Mixer {
+            rate: rate.max(1),
+            master: 1.0,
+            max_voices: 32,
+            voices: vec![],
+            buses: [1.0; 4],
+            next_id: 1,
+        }
+    }
+
+    pub fn play(&mut self, sound: &Sound, params: Play) -> Voice {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        // voices are kept oldest first. looping ones (the music) are never cut, with only
+        // those playing the new sound is dropped instead
+        if self.voices.len() >= self.max_voices {
+            match self.voices.iter().position(|v| !v.params.looping) {
+                Some(i) => {
+                    self.voices.remove(i);
+                }
+                None => return Voice(id),
+            }
+        }
+
+        self.voices.push(Playing { id, sound: sound.clone(), params, pos: 0.0, done: false });
+        Voice(id)
+    }
+
+    pub fn stop(&mut self, voice: Voice) {
+        self.voices.retain(|v| v.id != voice.0);
+    }
+
+    pub fn stop_bus(&mut self, bus: Bus) {
+        self.voices.retain(|v| v.params.bus != bus);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    pub fn is_playing(&self, voice: Voice) -> bool {
+        self.voices.iter().any(|v| v.id == voice.0)
+    }
+
+    // number of voices
+    pub fn playing(&self) -> usize {
+        self.voices.len()
+    }
+
+    // something is playing that the music should get out of the way of
+    pub fn ducking(&self) -> bool {
+        self.voices.iter().any(|v| v.params.duck || v.params.bus == Bus::Dialogue)
+    }
+
+    // what the voice currently plays with, None once it ended
+    pub fn params(&self, voice: Voice) -> Option<Play> {
+        self.voices.iter().find(|v| v.id == voice.0).map(|v| v.params)
+    }
+
+    pub fn set_volume(&mut self, voice: Voice, volume: f32) {
+        self.with(voice, |p| *p = p.volume(volume));
+    }
+
+    pub fn set_pitch(&mut self, voice: Voice, pitch: f32) {
+        self.with(voice, |p| *p = p.pitch(pitch));
+    }
+
+    pub fn set_pan(&mut self, voice: Voice, pan: f32) {
+        self.with(voice, |p| *p = p.pan(pan));
+    }
+
+    pub fn set_bus_gain(&mut self, bus: Bus, gain: f32) {
+        self.buses[bus as usize] = gain.max(0.0);
+    }
+
+    pub fn bus_gain(&self, bus: Bus) -> f32 {
+        self.buses[bus as usize]
+    }
+
+    fn with(&mut self, voice: Voice, f: impl FnOnce(&mut Play)) {
+        if let Some(v) = self.voices.iter_mut().find(|v| v.id == voice.0) {
+            f(&mut v.params);
+        }
+    }
+
+
+    //
+    //  overwrites `out` with the next out.len() / 2 stereo frames
+    //
+    pub fn render(&mut self, out: &mut [f32]) {
+        out.iter_mut().for_each(|s| *s = 0.0);
+        self.mix(out);
+        clip(out);
+    }
+
+    //
+    //  adds the voices to `out` without clipping, for mixing other sources in before the output
+    //  sounds at another rate are resampled on the fly (linear), pitch is just a faster step
+    //
+    pub fn mix(&mut self, out: &mut [f32]) {
+        for v in self.voices.iter_mut() {
+            let frames = v.sound.frames();
+            if frames == 0 {
+                v.done = true;
+                continue;
+            }
+
+            let step = v.params.pitch as f64 * v.sound.rate as f64 / self.rate as f64;
+            let gain = v.params.volume * self.buses[v.params.bus as usize] * self.master;
+            let (gl, gr) = pan_gains(v.params.pan);
+
+            for f in out.chunks_exact_mut(2) {
+                if v.pos >= frames as f64 {
+                    v.pos %= frames as f64;
+                }
+
+                let i = v.pos as usize;
+                let t = (v.pos - i as f64) as f32;
+                let next = if i + 1 < frames { i + 1 } else if v.params.looping { 0 } else { i };
+
+                let (l0, r0) = v.sound.frame(i);
+                let (l1, r1) = v.sound.frame(next);
+                f[0] += (l0 + (l1 - l0) * t) * gain * gl;
+                f[1] += (r0 + (r1 - r0) * t) * gain * gr;
+
+                v.pos += step;
+                if v.pos >= frames as f64 && !v.params.looping {
+                    v.done = true;
+                    break;
+                }
+            }
+        }
+
+        self.voices.retain(|v| !v.done);
+    }
+}
+
+
+
+//
+//  where the mixed stream goes, pulled once per frame
+//
+pub trait AudioOutput {
+    fn rate(&self) -> u32;
+    // stereo frames to mix now to keep the device fed
+    fn wanted(&mut self, now_ms: f64) -> usize;
+    // interleaved stereo
+    fn write(&mut self, frames: &[f32]);
+    // browsers start audio suspended until the user interacts with the page
+    fn resume(&mut self) {}
+}
+
+
+// frames worth of wall clock time since the last call
+#[derive(Default)]
+struct Clock {
+    last: Option<f64>,
+    carry: f64,
+}
+
+impl Clock {
+    fn frames(&mut self, now_ms: f64, rate: u32) -> usize {
+        let elapsed = (now_ms - self.last.unwrap_or(now_ms)).max(0.0);
+        self.last = Some(now_ms);
+
+        let exact = elapsed * rate as f64 / 1000.0 + self.carry;
+        let n = exact.floor();
+        self.carry = exact - n;
+        n as usize
+    }
+}
+
+
+//
+//  discards everything, for the server and machines without a sound device
+//
+pub struct NullOutput {
+    pub rate: u32,
+    pub frames: u64,
+    clock: Clock,
+}
+
+impl NullOutput {
+    pub fn new(rate: u32) -> Self {
+        NullOutput { rate, frames: 0, clock: Clock::default() }
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn wanted(&mut self, now_ms: f64) -> usize {
+        self.clock.frames(now_ms, self.rate)
+    }
+
+    fn write(&mut self, frames: &[f32]) {
+        self.frames += frames.len() as u64 / 2;
+    }
+}
+
+
+//
+//  keeps everything that was played, recording() stays readable after the output is boxed
+//
+pub struct WavOutput {
+    pub rate: u32,
+    samples: Rc<RefCell<Vec<f32>>>,
+    clock: Clock,
+}
+
+impl WavOutput {
+
+    pub fn new(rate: u32) -> Self {
+        WavOutput { rate, samples: Rc::new(RefCell::new(vec![])), clock: Clock::default() }
+    }
+
+    pub fn recording(&self) -> Rc<RefCell<Vec<f32>>> {
+        self.samples.clone()
+    }
+
+    pub fn to_sound(&self) -> Sound {
+        Sound::new(self.rate, 2, self.samples.borrow().clone())
+    }
+}
+
+impl AudioOutput for WavOutput {
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn wanted(&mut self, now_ms: f64) -> usize {
+        self.clock.frames(now_ms, self.rate)
+    }
+
+    fn write(&mut self, frames: &[f32]) {
+        self.samples.borrow_mut().extend_from_slice(frames);
+    }
+}
+
+
+
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+pub mod web {
+    use super::*;
+
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{AudioBuffer, AudioContext, AudioContextState};
+
+    // mixed this far ahead of the device, higher survives slower frames but lags more
+    const LOOKAHEAD: f64 = 0.1;
+    const MIN_CHUNK: usize = 512;
+
+
+    //
+    //  every write becomes an AudioBufferSourceNode scheduled right after the previous one
+    //
+    pub struct WebOutput {
+        pub ctx: AudioContext,
+        rate: u32,
+        // context time the scheduled audio runs out
+        scheduled: f64,
+    }
+
+    impl WebOutput {
+        pub fn new() -> anyhow::Result<Self> {
+            let ctx = AudioContext::new().map_err(|e| anyhow!("no AudioContext: {:?}", e))?;
+            let rate = ctx.sample_rate() as u32;
+            Ok(WebOutput { ctx, rate, scheduled: 0.0 })
+        }
+    }
+
+    impl AudioOutput for WebOutput {
+        fn rate(&self) -> u32 {
+            self.rate
+        }
+
+        fn wanted(&mut self, _now_ms: f64) -> usize {
+            if self.ctx.state() != AudioContextState::Running {
+                return 0;
+            }
+
+            // fell behind (tab in background), start over instead of catching up
+            let now = self.ctx.current_time();
+            if self.scheduled < now {
+                self.scheduled = now;
+            }
+
+            let n = ((now + LOOKAHEAD - self.scheduled) * self.rate as f64) as usize;
+            if n < MIN_CHUNK { 0 } else { n }
+        }
+
+        fn write(&mut self, frames: &[f32]) {
+            let n = frames.len() / 2;
+            if n == 0 {
+                return;
+            }
+
+            let left: Vec<f32> = frames.iter().step_by(2).copied().collect();
+            let right: Vec<f32> = frames.iter().skip(1).step_by(2).copied().collect();
+
+            let result = (|| -> Result<(), wasm_bindgen::JsValue> {
+                let buffer = self.ctx.create_buffer(2, n as u32, self.rate as f32)?;
+                buffer.copy_to_channel(&left, 0)?;
+                buffer.copy_to_channel(&right, 1)?;
+
+                let source = self.ctx.create_buffer_source()?;
+                source.set_buffer(Some(&buffer));
+                source.connect_with_audio_node(&self.ctx.destination())?;
+                source.start_with_when(self.scheduled)?;
+                Ok(())
+            })();
+
+            match result {
+                Ok(()) => self.scheduled += n as f64 / self.rate as f64,
+                Err(e) => warn!("audio write failed: {:?}", e),
+            }
+        }
+
+        fn resume(&mut self) {
+            if self.ctx.state() == AudioContextState::Suspended {
+                let _ = self.ctx.resume();
+            }
+        }
+    }
+
+
+    //
+    //  anything the browser can decode (ogg, mp3, ...), wav also works everywhere via Sound::from_wav
+    //
+    pub async fn decode(ctx: &AudioContext, bytes: &[u8]) -> anyhow::Result<Sound> {
+        let array = web_sys::js_sys::Uint8Array::from(bytes);
+        let promise = ctx.decode_audio_data(&array.buffer()).map_err(|e| anyhow!("{:?}", e))?;
+        let decoded = JsFuture::from(promise).await.map_err(|e| anyhow!("could not decode audio: {:?}", e))?;
+        let buffer: AudioBuffer = decoded.dyn_into().map_err(|_| anyhow!("decode did not give an AudioBuffer"))?;
+
+        let channels = buffer.number_of_channels().clamp(1, 2) as usize;
+        let data: Vec<Vec<f32>> = (0..channels)
+            .map(|c| buffer.get_channel_data(c as u32).map_err(|e| anyhow!("{:?}", e)))
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut samples = Vec::with_capacity(data[0].len() * channels);
+        for i in 0..data[0].len() {
+            for ch in &data {
+                samples.push(ch[i]);
+            }
+        }
+        Ok(Sound::new(buffer.sample_rate() as u32, channels as u16, samples))
+    }
+}
+
+
+
+//
+//  turns file bytes into a Sound from async loaders, cheap to clone into them
+//
+#[derive(Clone, Default)]
+pub struct Decoder {
+    #[cfg(all(target_arch = "wasm32", feature = "client"))]
+    ctx: Option<web_sys::AudioContext>,
+}
+
+impl Decoder {
+
+    //
+    //  wav everywhere, plus anything the browser can decode when there is an AudioContext
+    //
+    pub async fn decode(&self, bytes: &[u8]) -> anyhow::Result<Sound> {
+        if bytes.starts_with(b"RIFF") {
+            return Sound::from_wav(bytes);
+        }
+
+        #[cfg(all(target_arch = "wasm32", feature = "client"))] {
+            if let Some(ctx) = self.ctx.as_ref() {
+                return web::decode(ctx, bytes).await;
+            }
+        }
+
+        bail!("only wav can be decoded here")
+    }
+}
+
+
+
+pub struct Audio {
+    pub mixer: Mixer,
+    pub music: MusicPlayer,
+    output: Box<dyn AudioOutput>,
+    buffer: Vec<f32>,
+    decoder: Decoder,
+}
+
+impl Audio {
+
+    pub fn new(output: Box<dyn AudioOutput>) -> Self {
+        Audio {
+            mixer: Mixer::new(output.rate()),
+            music: MusicPlayer::new(),
+            output,
+            buffer: vec![],
+            decoder: Decoder::default(),
+        }
+    }
+
+    pub fn null() -> Self {
+        Audio::new(Box::new(NullOutput::new(MIX_RATE)))
+    }
+
+    //
+    //  web audio in the browser, silence anywhere else
+    //
+    pub fn platform() -> Self {
+        #[cfg(all(target_arch = "wasm32", feature = "client"))] {
+            match web::WebOutput::new() {
+                Ok(o) => {
+                    let ctx = o.ctx.clone();
+                    let mut audio = Audio::new(Box::new(o));
+                    audio.decoder.ctx = Some(ctx);
+                    return audio;
+                }
+                Err(e) => warn!("audio disabled: {}", e),
+            }
+        }
+
+        Audio::null()
+    }
+
+    // once per frame
+    pub fn update(&mut self, now_ms: f64) {
+        let n = self.output.wanted(now_ms);
+        if n == 0 {
+            return;
+        }
+
+        self.buffer.clear();
+        self.buffer.resize(n * 2, 0.0);
+        self.mixer.mix(&mut self.buffer);
+
+        let gain = self.mixer.bus_gain(Bus::Music) * self.mixer.master;
+        self.music.mix(&mut self.buffer, self.mixer.rate, gain, self.mixer.ducking());
+
+        clip(&mut self.buffer);
+        self.output.write(&self.buffer);
+    }
+
+    pub fn decoder(&self) -> Decoder {
+        self.decoder.clone()
+    }
+
+    // call from input handlers, see AudioOutput::resume
+    pub fn resume(&mut self) {
+        self.output.resume();
+    }
+
+    pub fn play(&mut self, sound: &Sound, params: Play) -> Voice {
+        self.mixer.play(sound, params)
+    }
+
+    pub fn stop(&mut self, voice: Voice) {
+        self.mixer.stop(voice);
+    }
+
+    pub fn set_bus_gain(&mut self, bus: Bus, gain: f32) {
+        self.mixer.set_bus_gain(bus, gain);
+    }
+
+    pub fn bus_gain(&self, bus: Bus) -> f32 {
+        self.mixer.bus_gain(bus)
+    }
+}
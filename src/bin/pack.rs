@@ -0,0 +1,82 @@
+//
+//  asset packer: pack <dir> <out.bundle> [--compress]
+//  every file under <dir> goes in, under its path relative to <dir> with / separators
+//
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use std::path::{Path, PathBuf};
+    use yo_yo::bundle::{BundleWriter, Compression, Bundle};
+
+    // symlinked directories are skipped, one pointing up the tree would recurse forever
+    fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_symlink() && path.is_dir() {
+                eprintln!("{}: skipping the symlinked directory", path.display());
+            } else if path.is_dir() {
+                walk(&path, out)?;
+            } else {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let compress = args.iter().any(|a| a == "--compress");
+    let args: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+
+    let (dir, out) = match args.as_slice() {
+        [dir, out] => (Path::new(dir.as_str()), Path::new(out.as_str())),
+        _ => {
+            eprintln!("usage: pack <dir> <out.bundle> [--compress]");
+            std::process::exit(2);
+        }
+    };
+
+    let mut files = vec![];
+    if let Err(e) = walk(dir, &mut files) {
+        eprintln!("{}: {}", dir.display(), e);
+        std::process::exit(2);
+    }
+    files.sort();
+
+    let mut writer = BundleWriter::new().compress(compress);
+    for file in &files {
+        // do not pack an old bundle into the new one
+        if file.canonicalize().ok() == out.canonicalize().ok() {
+            continue;
+        }
+
+        let name = file.strip_prefix(dir).unwrap().components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        match std::fs::read(file) {
+            Ok(bytes) => writer.add(&name, bytes),
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let bytes = writer.finish();
+    if let Err(e) = std::fs::write(out, &bytes) {
+        eprintln!("{}: {}", out.display(), e);
+        std::process::exit(2);
+    }
+
+    let bundle = Bundle::parse(&bytes).unwrap();
+    for e in &bundle.manifest.entries {
+        let packed = if e.compression == Compression::Deflate { format!(" -> {}", e.size) } else { String::new() };
+        println!("{:>10}{:<12} {}", e.raw_size, packed, e.path);
+    }
+    println!("{} files, {} bytes -> {} bytes", bundle.manifest.entries.len(), bundle.raw_size(), bytes.len());
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
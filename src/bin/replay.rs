@@ -0,0 +1,43 @@
+//
+//  headless replay runner: replay <file>
+//  exits with 1 when the simulation diverges from the recorded hashes
+//
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use yo_yo::replay::Replay;
+    use yo_yo::sim::run_replay;
+
+    let path = match std::env::args().nth(1) {
+        Some(p) => p,
+        None => {
+            eprintln!("usage: replay <file>");
+            std::process::exit(2);
+        }
+    };
+
+    let replay = match Replay::load(&path) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        }
+    };
+
+    let report = match run_replay(&replay) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}: {:#}", path, e);
+            std::process::exit(2);
+        }
+    };
+    println!("seed {} ticks {} hash {:016x}", replay.seed, report.ticks, report.final_hash);
+
+    if let Some(tick) = report.first_mismatch {
+        eprintln!("diverged at tick {}", tick);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
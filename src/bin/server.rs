@@ -0,0 +1,44 @@
+//
+//  authoritative game server: server [addr] [latency,jitter,loss[,duplicate[,bandwidth]]]
+//  default addr is 127.0.0.1:9001, open the game with ?server=ws://127.0.0.1:9001
+//  the second argument puts every client behind a simulated bad network
+//  headless, build it with: cargo run --bin server --no-default-features
+//
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use yo_yo::rng::Rng;
+    use yo_yo::server::{Server, ServerConfig};
+    use yo_yo::transport::{Listener, WsListener};
+    use yo_yo::netsim::{Conditions, SimulatedListener};
+
+    let addr = std::env::args().nth(1).unwrap_or("127.0.0.1:9001".to_string());
+
+    let listener = match WsListener::bind(&addr) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("{}: {}", addr, e);
+            std::process::exit(2);
+        }
+    };
+
+    println!("listening on ws://{}", listener.local_addr());
+
+    let listener: Box<dyn Listener> = match std::env::args().nth(2).and_then(|s| Conditions::parse(&s)) {
+        Some(c) => {
+            println!("simulating {:?}", c);
+            Box::new(SimulatedListener::new(Box::new(listener), c))
+        }
+        None => Box::new(listener),
+    };
+    let mut server = Server::new(listener, ServerConfig::new(Rng::random_seed()));
+    let start = std::time::Instant::now();
+
+    loop {
+        server.update(start.elapsed().as_secs_f64() * 1000.0);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use anyhow::{anyhow, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::Asset;
+use crate::rng::Fnv;
+
+
+//
+//  asset bundle: many files in one download. made by the pack tool, mounted into the AssetServer,
+//  after which loads of the paths inside are served from memory instead of separate requests
+//
+//  layout, little endian:
+//      b"YOBN", u32 version, u32 manifest length, manifest (json), file data back to back
+//
+//  offsets in the manifest are from the start of the file data. every file carries the fnv-1a
+//  hash of its uncompressed bytes, checked on read
+//
+
+
+pub const BUNDLE_MAGIC: &[u8; 4] = b"YOBN";
+pub const BUNDLE_VERSION: u32 = 1;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub offset: u64,
+    // stored bytes
+    pub size: u64,
+    // bytes after decompression
+    pub raw_size: u64,
+    pub hash: u64,
+    pub compression: Compression,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: Vec<Entry>,
+}
+
+
+//
+//  collects files and writes the bundle. with compression on, a file is only stored
+//  deflated when that actually makes it smaller (png and most audio will not shrink)
+//
+#[derive(Default)]
+pub struct BundleWriter {
+    compress: bool,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl BundleWriter {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    // a path added twice keeps the last bytes
+    pub fn add(&mut self, path: &str, bytes: Vec<u8>) {
+        self.files.retain(|(p, _)| p != path);
+        self.files.push((path.to_string(), bytes));
+    }
+
+    pub fn with(mut self, path: &str, bytes: &[u8]) -> Self {
+        self.add(path, bytes.to_vec());
+        self
+    }
+
+    pub fn finish(&self) -> Vec<u8> {
+        let mut manifest = Manifest::default();
+        let mut data = vec![];
+
+        for (path, raw) in &self.files {
+            let packed = if self.compress { Some(miniz_oxide::deflate::compress_to_vec(raw, 8)) } else { None };
+            let (stored, compression) = match packed {
+                Some(p) if p.len() < raw.len() => (p, Compression::Deflate),
+                _ => (raw.clone(), Compression::None),
+            };
+
+            manifest.entries.push(Entry {
+                path: path.clone(),
+                offset: data.len() as u64,
+                size: stored.len() as u64,
+                raw_size: raw.len() as u64,
+                hash: Fnv::hash(raw),
+                compression,
+            });
+            data.extend_from_slice(&stored);
+        }
+
+        let json = serde_json::to_vec(&manifest).unwrap();
+        let mut out = Vec::with_capacity(12 + json.len() + data.len());
+        out.extend_from_slice(BUNDLE_MAGIC);
+        out.extend_from_slice(&BUNDLE_VERSION.to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(&json);
+        out.extend_from_slice(&data);
+        out
+    }
+}
+
+
+
+//
+//  a parsed bundle, cheap to clone. files are decompressed on every read, the asset server
+//  caches the decoded assets so that happens once per load
+//
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub manifest: Manifest,
+    index: HashMap<String, usize>,
+    data: Arc<[u8]>,
+}
+
+impl Bundle {
+
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        ensure!(bytes.len() >= 12 && &bytes[..4] == BUNDLE_MAGIC, "not a bundle");
+
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        ensure!(version == BUNDLE_VERSION, "bundle version {} (expected {})", version, BUNDLE_VERSION);
+
+        let len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        // a u32 length can overflow usize on wasm32
+        let end = 12usize.checked_add(len).filter(|end| *end <= bytes.len());
+        let end = end.ok_or_else(|| anyhow!("bundle manifest is cut short"))?;
+
+        let manifest: Manifest = serde_json::from_slice(&bytes[12..end])?;
+        let data: Arc<[u8]> = bytes[end..].into();
+
+        let mut index = HashMap::new();
+        for (i, e) in manifest.entries.iter().enumerate() {
+            ensure!(e.offset.checked_add(e.size).is_some_and(|end| end <= data.len() as u64), "{} is outside the bundle", e.path);
+            index.insert(e.path.clone(), i);
+        }
+
+        Ok(Bundle { manifest, index, data })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        Bundle::parse(&std::fs::read(path)?)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.index.contains_key(path)
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&Entry> {
+        self.index.get(path).map(|i| &self.manifest.entries[*i])
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.manifest.entries.iter().map(|e| e.path.as_str())
+    }
+
+    // uncompressed size of everything inside
+    pub fn raw_size(&self) -> u64 {
+        self.manifest.entries.iter().map(|e| e.raw_size).sum()
+    }
+
+    pub fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let e = self.entry(path).ok_or_else(|| anyhow!("{}: not in bundle", path))?;
+        let stored = &self.data[e.offset as usize..(e.offset + e.size) as usize];
+
+        let raw = match e.compression {
+            Compression::None => stored.to_vec(),
+            Compression::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(stored, e.raw_size as usize)
+                .map_err(|err| anyhow!("{}: inflate failed: {:?}", path, err.status))?,
+        };
+
+        ensure!(raw.len() as u64 == e.raw_size && Fnv::hash(&raw) == e.hash, "{}: corrupt, hash mismatch", path);
+        Ok(raw)
+    }
+}
+
+impl Asset for Bundle {
+    fn decode(_path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        Bundle::parse(bytes)
+    }
+}
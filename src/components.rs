@@ -0,0 +1,92 @@
+extern crate rapier2d;
+use rapier2d::prelude::RigidBodyHandle;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+
+//
+//  components stored in the hecs World
+//
+
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Name(pub String);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub x: f32,
+    pub y: f32,
+    pub rotation: f32,
+}
+
+impl Transform {
+    pub fn new(x: f32, y: f32) -> Self {
+        Transform { x, y, rotation: 0.0 }
+    }
+
+    // `local` is relative to self, the result is in the space self is in
+    pub fn then(&self, local: Transform) -> Transform {
+        let (sin, cos) = self.rotation.sin_cos();
+        Transform {
+            x: self.x + local.x * cos - local.y * sin,
+            y: self.y + local.x * sin + local.y * cos,
+            rotation: self.rotation + local.rotation,
+        }
+    }
+}
+
+//
+//  entity is driven by a rigid body, Transform is copied from it every step
+//
+#[derive(Debug, Clone, Copy)]
+pub struct Body(pub RigidBodyHandle);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Circle {
+    pub radius: f32,
+    pub color: [f32; 3],
+}
+
+//
+//  body controlled by a connected client, the id is assigned by the server
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Player(pub u32);
+
+//
+//  spawned by a Player's input, on the tick it happened
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnedBy {
+    pub player: u32,
+    pub tick: u64,
+}
+
+//
+//  asset paths by role ("sprite", "sound", ...), from scene files. the game loads them
+//  through the AssetServer, the simulation never looks at them
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetRefs(pub BTreeMap<String, String>);
+
+//
+//  left out of scene captures and save games: effects, debris, anything the game remakes
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transient;
+
+//
+//  spawned from a prefab, by its name in the Prefabs it came from
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrefabRef(pub String);
+
+//
+//  spawned as a nested prefab of another entity. nothing moves it along, a joint or a
+//  system has to do that
+//
+#[derive(Debug, Clone, Copy)]
+pub struct Parent(pub hecs::Entity);
@@ -0,0 +1,617 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+extern crate hecs;
+use hecs::*;
+
+use log::{Level, Log, Metadata, Record};
+use winit::{
+    event::{ElementState, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use crate::physics::*;
+use crate::components::*;
+use crate::replay::*;
+use crate::rng::*;
+use crate::sim::TICK_DT;
+use crate::audio::*;
+use crate::synth::*;
+use crate::spatial::*;
+use crate::scene::*;
+use crate::prefab::*;
+use super::gui::*;
+use super::render::*;
+use super::save_file;
+
+
+//
+//  developer console: captured log, frame graph, world inspector, commands
+//  toggled with the ` key
+//
+
+
+const LOG_CAPACITY: usize = 512;
+// command lines and their results kept on screen
+const OUTPUT_CAPACITY: usize = 512;
+const FRAME_CAPACITY: usize = 120;
+
+
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: Level,
+    pub text: String,
+}
+
+// lines logged since the console last took them
+static LOG_LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+struct CaptureLogger;
+
+static LOGGER: CaptureLogger = CaptureLogger;
+
+impl Log for CaptureLogger {
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // still goes to the browser console
+        console_log::log(record);
+
+        if let Ok(mut lines) = LOG_LINES.lock() {
+            if lines.len() == LOG_CAPACITY {
+                lines.pop_front();
+            }
+            lines.push_back(LogLine {
+                level: record.level(),
+                text: format!("{}", record.args()),
+            });
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+//
+//  replaces console_log::init_with_level
+//
+pub fn init_logger(level: Level) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level.to_level_filter());
+    }
+}
+
+// moves the new lines out, nothing is copied
+fn take_log() -> VecDeque<LogLine> {
+    LOG_LINES.lock().map(|mut l| std::mem::take(&mut *l)).unwrap_or_default()
+}
+
+
+
+#[derive(Debug, Clone)]
+pub struct DebugSettings {
+    pub draw: bool,
+    // pixels per physics meter
+    pub scale: f32,
+}
+
+impl Default for DebugSettings {
+    fn default() -> Self {
+        DebugSettings {
+            draw: false,
+            scale: 32.0,
+        }
+    }
+}
+
+
+//
+//  everything a command or inspector is allowed to touch
+//
+pub struct ConsoleCtx<'a> {
+    pub world: &'a mut World,
+    pub physics: &'a mut Physics,
+    pub debug: &'a mut DebugSettings,
+    pub replay: &'a mut ReplayState,
+    pub audio: &'a mut Audio,
+    pub prefabs: &'a Prefabs,
+}
+
+pub type Command = Box<dyn FnMut(&mut ConsoleCtx, &[&str]) -> anyhow::Result<String>>;
+// (world, entity, ui, whether edits are written back), returns true when they were
+pub type InspectFn = Box<dyn Fn(&mut World, Entity, &mut GUI, bool) -> bool>;
+
+
+//
+//  components that can be shown and edited in the inspector
+//
+pub trait Inspect {
+    fn inspect(&mut self, ui: &mut GUI) -> bool;
+}
+
+impl Inspect for Name {
+    fn inspect(&mut self, ui: &mut GUI) -> bool {
+        ui.text_input("name", &mut self.0)
+    }
+}
+
+impl Inspect for Transform {
+    fn inspect(&mut self, ui: &mut GUI) -> bool {
+        let mut changed = false;
+        // a level can be any size, a range would cut it off
+        changed |= ui.drag_value("x", &mut self.x, 0.05);
+        changed |= ui.drag_value("y", &mut self.y, 0.05);
+        changed |= ui.slider("rotation", &mut self.rotation, -std::f32::consts::PI, std::f32::consts::PI);
+        changed
+    }
+}
+
+impl Inspect for Circle {
+    fn inspect(&mut self, ui: &mut GUI) -> bool {
+        let mut changed = false;
+        changed |= ui.slider("radius", &mut self.radius, 0.05, 5.0);
+        changed |= ui.slider("r", &mut self.color[0], 0.0, 1.0);
+        changed |= ui.slider("g", &mut self.color[1], 0.0, 1.0);
+        changed |= ui.slider("b", &mut self.color[2], 0.0, 1.0);
+        changed
+    }
+}
+
+
+
+pub struct Console {
+    pub open: bool,
+    input: String,
+    output: VecDeque<String>,
+    commands: BTreeMap<String, (String, Command)>,
+    inspectors: Vec<(String, InspectFn)>,
+    log: VecDeque<LogLine>,
+    frame_times: VecDeque<f32>,
+    selected: Option<Entity>,
+    pub min_level: Level,
+}
+
+impl Console {
+
+    pub fn new() -> Self {
+        let mut c = Console {
+            open: false,
+            input: String::new(),
+            output: VecDeque::new(),
+            commands: BTreeMap::new(),
+            inspectors: vec![],
+            log: VecDeque::new(),
+            frame_times: VecDeque::new(),
+            selected: None,
+            min_level: Level::Info,
+        };
+
+        c.register_component::<Name>("Name");
+        c.register_component::<Transform>("Transform");
+        c.register_component::<Circle>("Circle");
+        c.register_builtin_commands();
+
+        c
+    }
+
+    pub fn register_command<F>(&mut self, name: &str, help: &str, f: F)
+    where
+        F: FnMut(&mut ConsoleCtx, &[&str]) -> anyhow::Result<String> + 'static,
+    {
+        self.commands.insert(name.to_string(), (help.to_string(), Box::new(f)));
+    }
+
+    // shown, under its name, for entities that have the component
+    pub fn register_component<T: Component + Inspect + Clone>(&mut self, name: &str) {
+        let label = name.to_string();
+        self.inspectors.push((name.to_string(), Box::new(move |world: &mut World, e: Entity, ui: &mut GUI, editable: bool| {
+            // edited as a copy, the world only changes when the edit is kept
+            let mut c = match world.get::<&T>(e) {
+                Ok(c) => (*c).clone(),
+                Err(_) => return false,
+            };
+            ui.label(&label);
+            if !c.inspect(ui) || !editable {
+                return false;
+            }
+            if let Ok(mut slot) = world.get::<&mut T>(e) {
+                *slot = c;
+            }
+            true
+        })));
+    }
+
+    //
+    //  returns true when the event toggled the console
+    //
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput { event, .. } = event {
+            if event.state == ElementState::Pressed && !event.repeat
+                && event.physical_key == PhysicalKey::Code(KeyCode::Backquote) {
+                self.open = !self.open;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn frame(&mut self, ms: f32) {
+        if self.frame_times.len() == FRAME_CAPACITY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(ms);
+
+        self.log.extend(take_log());
+        let extra = self.log.len().saturating_sub(LOG_CAPACITY);
+        self.log.drain(..extra);
+    }
+
+    // the last LOG_CAPACITY lines, oldest first
+    pub fn captured_log(&self) -> impl Iterator<Item = &LogLine> {
+        self.log.iter()
+    }
+
+    pub fn execute(&mut self, ctx: &mut ConsoleCtx, line: &str) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args.is_empty() {
+            return;
+        }
+
+        self.print([format!("> {}", line)]);
+
+        if args[0] == "help" {
+            let help: Vec<String> = self.commands.iter().map(|(n, (h, _))| format!("{} - {}", n, h)).collect();
+            self.print(help);
+            return;
+        }
+
+        if args[0] == "clear" {
+            self.output.clear();
+            self.log.clear();
+            take_log();
+            return;
+        }
+
+        let out = match self.commands.get_mut(args[0]) {
+            Some((_, cmd)) => match cmd(ctx, &args[1..]) {
+                Ok(s) => s.lines().map(String::from).collect(),
+                Err(e) => vec![format!("error: {}", e)],
+            },
+            None => vec![format!("unknown command: {}", args[0])],
+        };
+        self.print(out);
+    }
+
+    // the oldest lines go once there are more than OUTPUT_CAPACITY
+    fn print<I: IntoIterator<Item = String>>(&mut self, lines: I) {
+        self.output.extend(lines);
+        let extra = self.output.len().saturating_sub(OUTPUT_CAPACITY);
+        self.output.drain(..extra);
+    }
+
+    pub fn ui(&mut self, ui: &mut GUI, ctx: &mut ConsoleCtx, screen: (f32, f32)) {
+        if !self.open {
+            return;
+        }
+
+        let (w, h) = screen;
+        let left = Rect::new(0.0, 0.0, w * 0.6, h * 0.5);
+        let right = Rect::new(w * 0.6, 0.0, w * 0.4, h);
+
+        let mut run: Option<String> = None;
+
+        ui.panel("console", left, |ui| {
+            let times: Vec<f32> = self.frame_times.iter().copied().collect();
+            let last = times.last().copied().unwrap_or(0.0);
+            ui.plot(&format!("frame {:.1} ms", last), &times, 50.0, 40.0);
+
+            ui.scroll_area("log", left.h - 150.0, |ui| {
+                for l in self.captured_log().filter(|l| l.level <= self.min_level) {
+                    ui.label(&format!("[{}] {}", l.level, l.text));
+                }
+                for o in &self.output {
+                    ui.label(o);
+                }
+            });
+
+            ui.text_input("command", &mut self.input);
+            // Enter in an inspector field is not a command
+            let enter = ui.has_focus("command") && ui.key_pressed(KeyCode::Enter);
+            let clicked = ui.button("run");
+            if clicked || enter {
+                run = Some(std::mem::take(&mut self.input));
+            }
+        });
+
+        if let Some(line) = run {
+            self.execute(ctx, &line);
+        }
+
+        let mut changed = false;
+        let selected = self.selected;
+        let mut select = None;
+
+        ui.panel("inspector", right, |ui| {
+            ui.scroll_area("entities", right.h * 0.4, |ui| {
+                let mut entities: Vec<Entity> = ctx.world.iter().map(|e| e.entity()).collect();
+                entities.sort_by_key(|e| e.id());
+
+                for e in entities {
+                    let name = ctx.world.get::<&Name>(e).map(|n| n.0.clone()).unwrap_or_default();
+                    let mark = if Some(e) == selected { "*" } else { " " };
+                    if ui.button(&format!("{}{} {}", mark, e.to_bits(), name)) {
+                        select = Some(e);
+                    }
+                }
+            });
+
+            ui.separator();
+
+            // while recording or replaying, edits would not be part of the input
+            let editable = !ctx.replay.active();
+            if let Some(e) = selected.filter(|e| ctx.world.contains(*e)) {
+                for (_, inspect) in &self.inspectors {
+                    changed |= inspect(ctx.world, e, ui, editable);
+                }
+            }
+        });
+
+        if select.is_some() {
+            self.selected = select;
+        }
+
+        // moving an entity in the inspector moves its rigid body too
+        if changed {
+            if let Some(e) = self.selected {
+                sync_body(ctx, e);
+            }
+        }
+    }
+
+
+
+    fn register_builtin_commands(&mut self) {
+
+        self.register_command("entities", "list entities", |ctx, _| {
+            let mut out = vec![];
+            for e in ctx.world.iter() {
+                let name = e.get::<&Name>().map(|n| n.0.clone()).unwrap_or_default();
+                out.push(format!("{} {}", e.entity().to_bits(), name));
+            }
+            Ok(out.join("\n"))
+        });
+
+        self.register_command("spawn", "spawn [x y radius] - drop a ball", |ctx, args| {
+            not_while_replaying(ctx)?;
+            let x = arg(args, 0, 0.0)?;
+            let y = arg(args, 1, 10.0)?;
+            let r = arg(args, 2, 0.5)?;
+
+            let handle = ctx.physics.add_ball(x, y, r);
+            let e = ctx.world.spawn((
+                Name("ball".to_string()),
+                Transform::new(x, y),
+                Body(handle),
+                Circle { radius: r, color: [1.0, 1.0, 1.0] },
+            ));
+            Ok(format!("spawned {}", e.to_bits()))
+        });
+
+        self.register_command("prefab", "prefab <name> [x y] - spawn a prefab", |ctx, args| {
+            not_while_replaying(ctx)?;
+            let name = args.first().ok_or_else(|| anyhow::anyhow!("which prefab? {} loaded", ctx.prefabs.len()))?;
+            let x = arg(args, 1, 0.0)?;
+            let y = arg(args, 2, 10.0)?;
+
+            let spawned = ctx.prefabs.spawn_prefab(ctx.world, ctx.physics, &Instance::new(name).at(x, y))?;
+            Ok(format!("spawned {} entities", spawned.len()))
+        });
+
+        self.register_command("teleport", "teleport <entity> <x> <y>", |ctx, args| {
+            not_while_replaying(ctx)?;
+            let e = entity_arg(args, 0)?;
+            let x = arg(args, 1, 0.0)?;
+            let y = arg(args, 2, 0.0)?;
+
+            {
+                let mut t = ctx.world.get::<&mut Transform>(e)
+                    .map_err(|_| anyhow::anyhow!("entity has no Transform"))?;
+                t.x = x;
+                t.y = y;
+            }
+            sync_body(ctx, e);
+            Ok(String::new())
+        });
+
+        self.register_command("scene", "print the level as scene json", |ctx, _| {
+            Scene::capture(ctx.world, ctx.physics).to_json()
+        });
+
+        self.register_command("debug_draw", "toggle collider drawing", |ctx, _| {
+            ctx.debug.draw = !ctx.debug.draw;
+            Ok(format!("debug draw {}", if ctx.debug.draw { "on" } else { "off" }))
+        });
+
+        self.register_command("record", "start / stop recording input, restarts the game", |ctx, _| {
+            match ctx.replay.recording.take() {
+                Some(r) => {
+                    let n = r.len();
+                    let file = format!("replay-{}.yorp", r.seed);
+                    let saved = save_file(&file, &r.to_bytes()?);
+                    ctx.replay.last = Some(r);
+                    saved?;
+                    Ok(format!("recorded {} ticks to {}", n, file))
+                }
+                None => {
+                    let seed = Rng::random_seed();
+                    ctx.replay.recording = Some(Replay::new(seed, TICK_DT));
+                    ctx.replay.reset_seed = Some(seed);
+                    Ok(format!("recording, seed {}", seed))
+                }
+            }
+        });
+
+        self.register_command("replay", "play the last recording from the start", |ctx, _| {
+            let r = ctx.replay.last.clone().ok_or(anyhow::anyhow!("nothing recorded"))?;
+            ctx.replay.reset_seed = Some(r.seed);
+            ctx.replay.recording = None;
+            ctx.replay.playing = Some(ReplayPlayer::new(r));
+            Ok(String::new())
+        });
+
+        self.register_command("volume", "volume [music|sfx|ui|master] [gain]", |ctx, args| {
+            if args.is_empty() {
+                let mut out: Vec<String> = Bus::ALL.iter().map(|b| format!("{} {:.2}", b.name(), ctx.audio.bus_gain(*b))).collect();
+                out.push(format!("master {:.2}", ctx.audio.mixer.master));
+                return Ok(out.join("\n"));
+            }
+
+            let gain = arg(args, 1, 1.0)?.max(0.0);
+            match args[0] {
+                "master" => ctx.audio.mixer.master = gain,
+                name => {
+                    let bus = Bus::parse(name).ok_or(anyhow::anyhow!("no bus {}", name))?;
+                    ctx.audio.set_bus_gain(bus, gain);
+                }
+            }
+            Ok(format!("{} {:.2}", args[0], gain))
+        });
+
+        self.register_command("sfx", "sfx <preset> [seed] - jump coin explosion laser hit powerup blip", |ctx, args| {
+            let name = args.first().ok_or(anyhow::anyhow!("presets: {}", SynthParams::PRESETS.join(" ")))?;
+            let seed = match args.get(1) {
+                Some(s) => s.parse::<u64>()?,
+                None => Rng::random_seed(),
+            };
+
+            let params = SynthParams::preset(name, &mut Rng::new(seed))
+                .ok_or(anyhow::anyhow!("no preset {}, try {}", name, SynthParams::PRESETS.join(" ")))?;
+            let sound = params.render(ctx.audio.mixer.rate, seed);
+            ctx.audio.play(&sound, Play::new());
+            Ok(format!("{} seed {}, {:.2}s", name, seed, sound.duration()))
+        });
+
+        self.register_command("emit", "emit <entity> <preset> [loop] - synth sound from an entity", |ctx, args| {
+            let e = entity_arg(args, 0)?;
+            let name = args.get(1).ok_or(anyhow::anyhow!("presets: {}", SynthParams::PRESETS.join(" ")))?;
+            let seed = Rng::random_seed();
+
+            let params = SynthParams::preset(name, &mut Rng::new(seed)).ok_or(anyhow::anyhow!("no preset {}", name))?;
+            let mut play = Play::new();
+            if args.get(2) == Some(&"loop") {
+                play = play.looping();
+            }
+
+            let emitter = Emitter::new(params.render(ctx.audio.mixer.rate, seed), play).doppler(1.0);
+            ctx.world.insert_one(e, emitter).map_err(|_| anyhow::anyhow!("no such entity"))?;
+            Ok(String::new())
+        });
+
+        self.register_command("music", "music [next|stop|<track>] - list or switch tracks", |ctx, args| {
+            let music = &mut ctx.audio.music;
+            match args.first().copied() {
+                None => {
+                    let current = music.current().map(|s| s.to_string());
+                    Ok(music.tracks().iter().enumerate()
+                        .map(|(i, t)| format!("{} {}{}", i, t.name, if Some(&t.name) == current.as_ref() { " *" } else { "" }))
+                        .collect::<Vec<_>>()
+                        .join("\n"))
+                }
+                Some("next") => {
+                    music.next();
+                    Ok(music.current().unwrap_or("stopped").to_string())
+                }
+                Some("stop") => {
+                    music.stop();
+                    Ok(String::new())
+                }
+                Some(i) => {
+                    let i: usize = i.parse()?;
+                    if i >= music.tracks().len() {
+                        anyhow::bail!("no track {}", i);
+                    }
+                    music.play(i);
+                    Ok(music.tracks()[i].name.clone())
+                }
+            }
+        });
+
+        self.register_command("gravity", "gravity <x> <y>", |ctx, args| {
+            not_while_replaying(ctx)?;
+            let x = arg(args, 0, 0.0)?;
+            let y = arg(args, 1, -9.81)?;
+            ctx.physics.set_gravity(x, y);
+            Ok(format!("gravity {} {}", x, y))
+        });
+    }
+}
+
+
+//
+//  collider outlines in the overlay, world origin is the screen centre
+//
+pub fn debug_shapes(world: &World, debug: &DebugSettings, screen: (f32, f32)) -> Vec<Vertex> {
+
+    let sx = debug.scale * 2.0 / screen.0.max(1.0);
+    let sy = debug.scale * 2.0 / screen.1.max(1.0);
+    let style = StrokeStyle::new(0.005);
+
+    let mut out = vec![];
+    for (_, (t, c, _)) in world.query::<(&Transform, &Circle, &Body)>().iter() {
+        let (x, y) = (t.x * sx, t.y * sy);
+        let (rx, ry) = (c.radius * sx, c.radius * sy);
+
+        // ellipse so the circle stays round on non-square windows
+        let mut b = Path::builder().move_to(x + rx, y);
+        let steps = 32;
+        for i in 1..=steps {
+            let a = std::f32::consts::PI * 2.0 * i as f32 / steps as f32;
+            b = b.line_to(x + rx * a.cos(), y + ry * a.sin());
+        }
+        let outline = b.close()
+            .move_to(x, y)
+            .line_to(x + rx * t.rotation.cos(), y + ry * t.rotation.sin())
+            .build();
+
+        out.extend(outline.stroke(&style, [0.0, 1.0, 0.0]));
+    }
+
+    out
+}
+
+
+fn not_while_replaying(ctx: &ConsoleCtx) -> anyhow::Result<()> {
+    if ctx.replay.active() {
+        anyhow::bail!("not while recording or replaying, it is not part of the input");
+    }
+    Ok(())
+}
+
+fn sync_body(ctx: &mut ConsoleCtx, e: Entity) {
+    let t = ctx.world.get::<&Transform>(e).map(|t| *t);
+    let b = ctx.world.get::<&Body>(e).map(|b| *b);
+    if let (Ok(t), Ok(b)) = (t, b) {
+        ctx.physics.set_body_transform(b.0, t.x, t.y, t.rotation);
+    }
+}
+
+fn arg(args: &[&str], i: usize, default: f32) -> anyhow::Result<f32> {
+    match args.get(i) {
+        Some(s) => s.parse().map_err(|_| anyhow::anyhow!("bad number: {}", s)),
+        None => Ok(default),
+    }
+}
+
+fn entity_arg(args: &[&str], i: usize) -> anyhow::Result<Entity> {
+    let bits: u64 = args.get(i)
+        .ok_or(anyhow::anyhow!("missing entity"))?
+        .parse()?;
+    Entity::from_bits(bits).ok_or(anyhow::anyhow!("bad entity: {}", bits))
+}
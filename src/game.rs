@@ -5,14 +5,31 @@ use web_sys::{AudioContext, Request, RequestInit, RequestMode, Response};
 
 use web_sys::js_sys::Date;
 
-#[path="physics.rs"]
-mod physics;
-use physics::*;
+use crate::physics::*;
+use crate::components::*;
+use crate::replay::*;
+use crate::rng::*;
+use crate::sim::*;
+use crate::protocol::*;
+use crate::net::*;
+use crate::netsim::*;
+use crate::transport::connect;
+use crate::snapshot::*;
+use crate::prediction::*;
+use crate::audio::*;
+use crate::synth::*;
+use crate::spatial::*;
+use crate::music::*;
+use crate::assets::{AssetServer, Handle, LoadState, Preload, Texture};
+use crate::scene::*;
+use crate::tiled::{relative_to, Map};
+use crate::prefab::Prefabs;
+use crate::save::*;
 
 extern crate hecs;
 use hecs::*;
 
-use std::{rc::Rc, sync::mpsc::{channel, Receiver, Sender}, time::Duration};
+use std::{cell::RefCell, rc::Rc, sync::mpsc::{channel, Receiver, Sender}, time::Duration};
 
 use log::{debug, info, warn};
 use wasm_bindgen::{prelude::Closure, JsCast};
@@ -31,8 +48,31 @@ use setup::*;
 mod render;
 use render::*;
 
+#[path="gui.rs"]
+mod gui;
+use gui::*;
+
+#[path="gamepad.rs"]
+mod gamepad;
+use gamepad::*;
+
+#[path="input.rs"]
+mod input;
+use input::*;
+
+#[path="lobby_ui.rs"]
+mod lobby_ui;
+use lobby_ui::*;
+
+#[path="console.rs"]
+mod console;
+use console::*;
+pub use console::init_logger;
+
 
 const url: &str = "ws://193.124.66.129:443";
+// file.txt in there lists the tracks
+const music_url: &str = "http://oleja.ru/music";
 
 
 const FPS_30: u32 = 33; // milliseconds
@@ -43,25 +83,73 @@ const FPS_60: u32 = 16;
 pub async fn game_loop(event_loop: EventLoop<()>, mut window: Window) {
 
 
-    // работает!
-    wasm_bindgen_futures::spawn_local(async move {
-        let a = reqwest::get("http://oleja.ru/music/file.txt").await;
-        warn!("{:?}", a);
-        if a.is_ok() {
-            warn!("{:?}", a.unwrap().text().await.unwrap());
-        }
-    });
-
 
     info!("game loop is run");
     setup_canvas(&window);
 
     window.request_inner_size(PhysicalSize::new(640, 640));
    
-    let websocket = WebSocket::new(url).unwrap();
-    let rx = setup_onmessage(websocket.clone());
-    websocket.onopen();
-    websocket.send_with_str("I am ready!");
+    // ?netsim=latency,jitter,loss[,duplicate[,bandwidth]] to play over a bad connection
+    let config = NetConfig::new(&server_url(url));
+    let mut net = match url_param("netsim").and_then(|s| Conditions::parse(&s)) {
+        Some(c) => {
+            info!("simulating network conditions {:?}", c);
+            Net::with_connector(config, simulated(Box::new(|u: &str| connect(u)), c))
+        }
+        None => Net::new(config),
+    };
+    net.connect();
+    let mut lobby = LobbyView::new();
+    // local player, created when our room starts
+    let mut predictor: Option<Predictor> = None;
+    let mut remote = Snapshot::default();
+    let mut history = SnapshotHistory::new(SNAPSHOT_HISTORY);
+    // ~6 ticks behind the server, enough to ride out one lost snapshot
+    let mut interp = Interpolator::new(100.0, TICK_DT);
+
+    let mut audio = Audio::platform();
+    // emitters in the local sim, heard from the world origin
+    let mut spatial = SpatialAudio::new();
+    let mut sfx_rng = Rng::new(Rng::random_seed());
+    let blip = SynthParams::blip(&mut sfx_rng).render(audio.mixer.rate, 1);
+    let coin = SynthParams::coin(&mut sfx_rng).render(audio.mixer.rate, 2);
+
+    let mut assets = AssetServer::platform("assets");
+    // one request for everything packed, loads below come out of it
+    let bundle = assets.load_bundle("game.bundle");
+    // the built-in font until a ui.ttf arrives, if the game ships one
+    let mut ui_font: Option<Handle<Font>> = Some(assets.load("ui.ttf"));
+    let mut preload = Preload::new()
+        .with(&bundle)
+        .on_progress(|p| info!("loaded {}/{} assets, {} failed", p.loaded, p.total, p.failed));
+
+    // ?scene=levels/1.json replaces the built-in ground and ball
+    let mut level: Option<Handle<Scene>> = url_param("scene").map(|p| assets.load(&p));
+    if let Some(h) = &level {
+        preload.add(h);
+    }
+    let mut scene: Option<Scene> = None;
+
+    // ?map=maps/1.tmj adds a tiled map, solid where its layers say so
+    let mut level_map: Option<Handle<Map>> = url_param("map").map(|p| assets.load(&p));
+    if let Some(h) = &level_map {
+        preload.add(h);
+    }
+    let mut map: Option<Map> = None;
+    // the map waits here for its external tilesets, loaded from next to it
+    let mut map_waiting: Option<(String, Map)> = None;
+    let mut map_tilesets: Vec<(usize, Handle<Vec<u8>>)> = vec![];
+    let mut tileset_images: Vec<(usize, Handle<Texture>)> = vec![];
+
+    // for the console's prefab command, there may be none
+    let mut prefabs_file: Option<Handle<Prefabs>> = Some(assets.load("prefabs.json"));
+    let mut prefabs = Prefabs::new();
+
+    // F5 saves, F9 loads the last save
+    let mut saves = Saves::platform("saves");
+
+    let (music_tx, music_rx) = channel::<Track>();
+    wasm_bindgen_futures::spawn_local(load_music(music_url, audio.decoder(), music_tx));
 
     let mut surface_configured = false;
     
@@ -69,10 +157,18 @@ pub async fn game_loop(event_loop: EventLoop<()>, mut window: Window) {
     let mut gpu = RenderWebGpu::new(gpu_config);
 
 
-    let mut phys = Physics::new();
+    let mut sim = Sim::new(Rng::random_seed());
+    let mut step = FixedStep::new(TICK_DT);
+    let mut replay = ReplayState::default();
+    // input for the next tick, presses survive frames where no tick runs
+    let mut pending = InputFrame::default();
+
+    let mut console = Console::new();
+    let mut debug = DebugSettings::default();
+    let mut input = Input::new(default_actions());
+    input.sticks.push(VirtualJoystick::new([90.0, 550.0], 60.0));
 
     let win = &window;
-    let ws = websocket.clone();
 
 
     let mut t = vec![];
@@ -116,28 +212,341 @@ pub async fn game_loop(event_loop: EventLoop<()>, mut window: Window) {
 
     let mut time = 0.0f32; 
 
+    let mut gui = GUI::new();
+    let mut ui_font_id = gpu.add_font(Font::builtin());
+    gui.set_font(ui_font_id, Font::builtin());
+    let mut last_frame = Date::now();
+    let mut fps = 0.0;
+
     let mut time_begin = Date::new_0();
     event_loop.run(move |event, control_flow| 
 
         match event {
             Event::WindowEvent { window_id, event } => {
 
-                match event {
+                if console.handle_event(&event) || gui.handle_event(&event) {
+                    input.handle_captured_event(&event);
+                } else {
+                    input.handle_event(&event);
+                }
 
-                    WindowEvent::CursorMoved { device_id, position } => {
+                // the browser only lets audio start from a user gesture
+                if let WindowEvent::MouseInput { .. } | WindowEvent::KeyboardInput { .. } | WindowEvent::Touch(_) = event {
+                    audio.resume();
+                }
 
-                        if let Ok(message) = rx.try_recv() {
-                            info!("{:?}", message);
-                        }
-                       
-                    },
+                match event {
 
                     WindowEvent::RedrawRequested => {
                         if !surface_configured { return; }
                         let dt = Date::new_0().get_milliseconds().overflowing_sub(time_begin.get_milliseconds()).0;
 
-   
-                        
+
+                        let now = Date::now();
+                        if now > last_frame {
+                            fps = fps * 0.9 + 0.1 * 1000.0 / (now - last_frame);
+                        }
+                        let dt_ms = now - last_frame;
+                        console.frame(dt_ms as f32);
+                        let ticks = step.advance(now - last_frame);
+                        last_frame = now;
+                        while let Ok(track) = music_rx.try_recv() {
+                            audio.music.enqueue(track);
+                        }
+                        audio.update(now);
+
+                        assets.update();
+                        if let Some(h) = ui_font.take() {
+                            match (assets.get(&h), h.state()) {
+                                (Some(font), _) => {
+                                    ui_font_id = gpu.add_font(font.clone());
+                                    gui.set_font(ui_font_id, font.clone());
+                                }
+                                (None, LoadState::Loading) => ui_font = Some(h),
+                                (None, _) => (),
+                            }
+                        }
+
+                        let progress = preload.update();
+                        if !progress.finished() {
+                            gpu.draw_loading(progress.fraction(), preload.current().unwrap_or("loading"), Some(ui_font_id));
+                            gpu.draw();
+                            return;
+                        }
+
+                        if let Some(h) = level.take() {
+                            if let Some(s) = assets.get(&h) {
+                                match Sim::from_scene(sim.seed, s) {
+                                    Ok(new) => {
+                                        sim = new;
+                                        scene = Some(s.clone());
+                                        spatial.clear(&mut audio.mixer);
+                                    }
+                                    Err(e) => warn!("{}: {:#}", h.path(), e),
+                                }
+                            }
+                        }
+
+                        if let Some(h) = level_map.take() {
+                            if let Some(m) = assets.get(&h).cloned() {
+                                map_tilesets = m.external_tilesets().into_iter().map(|(i, s)| (i, assets.load(&relative_to(h.path(), &s)))).collect();
+                                map_waiting = Some((h.path().to_string(), m));
+                            }
+                        }
+                        if map_tilesets.iter().all(|(_, t)| t.state() != LoadState::Loading) {
+                            if let Some((map_path, mut m)) = map_waiting.take() {
+                                for (i, t) in map_tilesets.drain(..) {
+                                    match (assets.get(&t), t.state()) {
+                                        (Some(bytes), _) => {
+                                            if let Err(e) = m.load_tileset(i, bytes) {
+                                                warn!("{}: {:#}", t.path(), e);
+                                            }
+                                        }
+                                        (None, LoadState::Failed(e)) => warn!("{}: {}", t.path(), e),
+                                        (None, _) => (),
+                                    }
+                                }
+                                if let Err(e) = m.add_colliders(&mut sim.physics) {
+                                    warn!("{}: {:#}", map_path, e);
+                                }
+                                gpu.set_tilemap(&m);
+                                tileset_images = m.tileset_images(&map_path).into_iter().map(|(i, p)| (i, assets.load(&p))).collect();
+                                map = Some(m);
+                            }
+                        }
+                        if let Some(h) = prefabs_file.take() {
+                            match (assets.get(&h), h.state()) {
+                                (Some(p), _) => prefabs = p.clone(),
+                                (None, LoadState::Loading) => prefabs_file = Some(h),
+                                (None, _) => (),
+                            }
+                        }
+                        tileset_images.retain(|(i, h)| match assets.get(h) {
+                            Some(image) => {
+                                gpu.set_tileset_image(*i, image);
+                                false
+                            }
+                            None => match h.state() {
+                                LoadState::Failed(e) => {
+                                    warn!("{}: {}", h.path(), e);
+                                    false
+                                }
+                                _ => true,
+                            },
+                        });
+
+                        for e in net.update(now) {
+                            match e {
+                                NetEvent::Connected => net.send(&Message::Hello { name: "player".to_string() }),
+                                NetEvent::Message(Message::GameStarted { room }) => {
+                                    lobby.handle(&Message::GameStarted { room });
+                                    if let Some(id) = lobby.id {
+                                        info!("room {} started, we are player {}", room, id);
+                                        audio.play(&coin, Play::new().bus(Bus::Ui));
+                                        predictor = Some(Predictor::new(id));
+                                        history = SnapshotHistory::new(SNAPSHOT_HISTORY);
+                                        interp.clear();
+                                    }
+                                }
+                                NetEvent::Message(Message::Welcome { id }) => {
+                                    lobby.handle(&Message::Welcome { id });
+                                    net.send(&Message::ListRooms);
+                                }
+                                NetEvent::Message(Message::Snapshot(delta)) => {
+                                    if let Some(s) = history.receive(&delta) {
+                                        net.send(&Message::Ack { tick: s.tick });
+                                        if let Some(p) = predictor.as_mut() {
+                                            p.reconcile(&s);
+                                        }
+                                        interp.push(s, now);
+                                    }
+                                }
+                                NetEvent::Disconnected { code, reason } => {
+                                    info!("disconnected: {} {}", code, reason);
+                                    predictor = None;
+                                    lobby = LobbyView::new();
+                                    history = SnapshotHistory::new(SNAPSHOT_HISTORY);
+                                    interp.clear();
+                                }
+                                NetEvent::Message(m) if lobby.handle(&m) => (),
+                                e => info!("{:?}", e),
+                            }
+                        }
+
+                        let (w, h) = gpu.webgpu_config.size();
+                        let screen = (w as f32, h as f32);
+                        gpu.set_tile_camera(0.0, 0.0, debug.scale);
+
+                        input.update();
+                        for e in input.gamepad_events() {
+                            info!("{:?}", e);
+                        }
+
+                        if input.sticks[0].touch.is_none() {
+                            input.sticks[0] = VirtualJoystick::new([90.0, screen.1 - 90.0], 60.0);
+                        }
+
+                        let c = input.cursor();
+                        let pointer = [(c[0] - screen.0 * 0.5) / debug.scale, (screen.1 * 0.5 - c[1]) / debug.scale];
+                        pending.merge_pressed(input.snapshot(&["jump", "spawn"], &["move_x"], pointer));
+
+                        if let Some(seed) = replay.reset_seed.take() {
+                            let played = replay.playing.as_ref().map(|p| Sim::from_replay(&p.replay));
+                            sim = match (played, &scene) {
+                                (Some(Ok(s)), _) => s,
+                                (Some(Err(e)), _) => {
+                                    warn!("replay: {:#}", e);
+                                    replay.playing = None;
+                                    Sim::new(seed)
+                                }
+                                (None, Some(s)) => Sim::from_scene(seed, s).unwrap_or_else(|e| {
+                                    warn!("scene: {:#}", e);
+                                    Sim::new(seed)
+                                }),
+                                (None, None) => Sim::new(seed),
+                            };
+                            if replay.playing.is_none() {
+                                if let Some(m) = &map {
+                                    if let Err(e) = m.add_colliders(&mut sim.physics) {
+                                        warn!("map: {:#}", e);
+                                    }
+                                }
+                            }
+
+                            // the recording starts from the level as a scene, rebuilt from it the
+                            // way playback will, so it replays without the scene or map files
+                            if let Some(r) = replay.recording.take() {
+                                let start = Scene::capture(&sim.world, &sim.physics);
+                                match r.with_scene(&start).and_then(|r| Ok((Sim::from_replay(&r)?, r))) {
+                                    Ok((s, r)) => {
+                                        sim = s;
+                                        replay.recording = Some(r);
+                                    }
+                                    Err(e) => warn!("not recording: {:#}", e),
+                                }
+                            }
+                            spatial.clear(&mut audio.mixer);
+                        }
+
+                        if input.key_pressed(KeyCode::F5) {
+                            match saves.save("quick", &SaveGame::capture(&sim)) {
+                                Ok(()) => info!("saved at tick {}", sim.tick),
+                                Err(e) => warn!("{:#}", e),
+                            }
+                        }
+                        if input.key_pressed(KeyCode::F9) && replay.active() {
+                            warn!("no loading while recording or replaying");
+                        } else if input.key_pressed(KeyCode::F9) {
+                            match saves.load("quick").and_then(|s| s.ok_or_else(|| anyhow::anyhow!("nothing saved yet"))) {
+                                Ok(save) => match save.restore() {
+                                    Ok(new) => {
+                                        sim = new;
+                                        spatial.clear(&mut audio.mixer);
+                                        info!("loaded tick {}", sim.tick);
+                                    }
+                                    Err(e) => warn!("{:#}", e),
+                                },
+                                Err(e) => warn!("{:#}", e),
+                            }
+                        }
+
+                        for _ in 0..ticks {
+                            let frame = match replay.playing.as_mut().and_then(|p| p.next()) {
+                                Some(f) => f,
+                                None => {
+                                    if replay.playing.take().is_some() {
+                                        info!("replay finished at tick {}", sim.tick);
+                                    }
+                                    pending.clone()
+                                }
+                            };
+                            pending.pressed.clear();
+
+                            sim.step(&frame);
+                            let hash = sim.state_hash();
+
+                            if let Some(p) = predictor.as_mut() {
+                                let tick = p.step(&frame);
+                                net.send(&Message::Input { tick, frame: frame.clone() });
+                            }
+
+                            if let Some(expected) = replay.playing.as_ref().and_then(|p| p.expected_hash()) {
+                                if expected != hash {
+                                    warn!("replay diverged at tick {}", sim.tick);
+                                }
+                            }
+                            if let Some(r) = replay.recording.as_mut() {
+                                r.push(frame, hash);
+                            }
+                        }
+
+                        spatial.update(&mut sim.world, &sim.physics, &mut audio.mixer);
+
+                        if let Some(p) = predictor.as_mut() {
+                            p.update(dt_ms as f32 / 1000.0);
+
+                            // everyone else is interpolated, we are drawn where we predict to be
+                            remote = interp.sample(now).unwrap_or_default();
+                            let (x, y, rotation) = p.position();
+                            for e in remote.entities.iter_mut().filter(|e| e.player == Some(p.player)) {
+                                e.x = x;
+                                e.y = y;
+                                e.rotation = rotation;
+                            }
+                            gpu.draw_overlay(&snapshot_shapes(&remote, debug.scale, screen));
+                        }
+
+                        if debug.draw {
+                            gpu.draw_overlay(&debug_shapes(&sim.world, &debug, screen));
+                        }
+
+                        gui.begin_frame();
+                        let mut leave = false;
+                        gui.panel("stats", Rect::new(8.0, 8.0, 180.0, 170.0), |ui| {
+                            ui.label(&format!("fps: {:.0}", fps));
+                            let stats = net.stats();
+                            match stats.rtt {
+                                Some(rtt) => ui.label(&format!("rtt: {:.0} ms, loss {:.0}%", rtt, stats.loss() * 100.0)),
+                                None => ui.label("rtt: -"),
+                            };
+                            ui.label(&format!("in {:.1} kB / out {:.1} kB", stats.bytes_in as f64 / 1024.0, stats.bytes_out as f64 / 1024.0));
+                            if let Some(p) = predictor.as_ref() {
+                                ui.label(&format!("player {} tick {}", p.player, remote.tick));
+                                ui.label(&format!("unacked inputs: {}", p.pending()));
+                                if ui.button("leave game") {
+                                    leave = true;
+                                }
+                            }
+                        });
+
+                        if leave {
+                            net.send(&Message::LeaveRoom);
+                            lobby.room = None;
+                            predictor = None;
+                        }
+                        if lobby.id.is_some() && predictor.is_none() {
+                            for m in lobby.ui(&mut gui, screen) {
+                                audio.play(&blip, Play::new().bus(Bus::Ui).volume(0.5));
+                                net.send(&m);
+                            }
+                        }
+
+                        if input.touch_seen {
+                            gui.virtual_joystick(&input.sticks[0]);
+                        }
+                        input.end_frame();
+
+                        let mut ctx = ConsoleCtx {
+                            world: &mut sim.world,
+                            physics: &mut sim.physics,
+                            debug: &mut debug,
+                            replay: &mut replay,
+                            audio: &mut audio,
+                            prefabs: &prefabs,
+                        };
+                        console.ui(&mut gui, &mut ctx, screen);
+
+                        gui.end_frame(&mut gpu);
 
                         gpu.draw();
                     },
@@ -156,10 +565,108 @@ pub async fn game_loop(event_loop: EventLoop<()>, mut window: Window) {
                 time_begin = Date::new_0();
             }
 
+            Event::LoopExiting => net.close(),
+
             _ => ()
         }
     )
     .unwrap();
+}
+
 
-    websocket.close();
-}
\ No newline at end of file
+
+//
+//  filled circles for the entities in a server snapshot
+//
+fn snapshot_shapes(snapshot: &Snapshot, scale: f32, screen: (f32, f32)) -> Vec<Vertex> {
+    let sx = scale * 2.0 / screen.0.max(1.0);
+    let sy = scale * 2.0 / screen.1.max(1.0);
+
+    let mut out = vec![];
+    for e in &snapshot.entities {
+        let (x, y) = (e.x * sx, e.y * sy);
+        let (rx, ry) = (e.radius * sx, e.radius * sy);
+
+        let mut b = Path::builder().move_to(x + rx, y);
+        for i in 1..=32 {
+            let a = std::f32::consts::PI * 2.0 * i as f32 / 32.0;
+            b = b.line_to(x + rx * a.cos(), y + ry * a.sin());
+        }
+        out.extend(b.close().build().fill(FillRule::NonZero, e.color));
+    }
+
+    out
+}
+
+
+//
+//  fetches the tracks one by one, see fetch_track
+//
+async fn load_music(base: &'static str, decoder: Decoder, tx: Sender<Track>) {
+    let list = match reqwest::get(format!("{}/file.txt", base)).await.and_then(|r| r.error_for_status()) {
+        Ok(r) => r.text().await.unwrap_or_default(),
+        Err(e) => {
+            warn!("no music: {}", e);
+            return;
+        }
+    };
+
+    for name in list.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let result = match reqwest::get(format!("{}/{}", base, name)).await.and_then(|r| r.error_for_status()) {
+            Ok(r) => fetch_track(name, r, &decoder, &tx).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            warn!("music {}: {}", name, e);
+        }
+    }
+}
+
+//
+//  a wav is handed over as soon as its header arrived and keeps streaming in while it plays,
+//  anything else needs all of its bytes for the browser's decoder
+//
+async fn fetch_track(name: &str, response: reqwest::Response, decoder: &Decoder, tx: &Sender<Track>) -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let mut body = response.bytes_stream();
+
+    // enough to tell a wav
+    let mut head = vec![];
+    while head.len() < 4 {
+        match body.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+
+    if !head.starts_with(b"RIFF") {
+        while let Some(chunk) = body.next().await {
+            head.extend_from_slice(&chunk?);
+        }
+        let _ = tx.send(Track::new(name, decoder.decode(&head).await?));
+        return Ok(());
+    }
+
+    let stream = Rc::new(RefCell::new(WavStream::new()));
+    let mut sent = false;
+    let mut result = stream.borrow_mut().push(&head);
+    while result.is_ok() {
+        if !sent && stream.borrow().header().is_some() {
+            let _ = tx.send(Track::new(name, stream.clone()));
+            sent = true;
+        }
+        result = match body.next().await {
+            Some(Ok(chunk)) => stream.borrow_mut().push(&chunk),
+            Some(Err(e)) => Err(e.into()),
+            None => break,
+        };
+    }
+
+    // a broken download ends the track where it broke
+    stream.borrow_mut().finish();
+    if result.is_ok() && !sent {
+        anyhow::bail!("wav has no data chunk");
+    }
+    result
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+
+//
+//  gamepads in the standard layout (xbox names: South = A, East = B ...)
+//  browser: navigator.getGamepads() polled every frame, native: gilrs
+//
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Mode,
+}
+
+// same order as the w3c standard gamepad mapping
+pub const BUTTONS: [GamepadButton; 17] = [
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::West,
+    GamepadButton::North,
+    GamepadButton::LeftBumper,
+    GamepadButton::RightBumper,
+    GamepadButton::LeftTrigger,
+    GamepadButton::RightTrigger,
+    GamepadButton::Select,
+    GamepadButton::Start,
+    GamepadButton::LeftStick,
+    GamepadButton::RightStick,
+    GamepadButton::DPadUp,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+    GamepadButton::DPadRight,
+    GamepadButton::Mode,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    // sticks are -1..1 with y up, triggers 0..1
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GamepadEvent {
+    Connected(usize, String),
+    Disconnected(usize),
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct GamepadState {
+    pub id: usize,
+    pub name: String,
+    pub buttons: [bool; 17],
+    pub axes: [f32; 6],
+}
+
+impl GamepadState {
+
+    pub fn pressed(&self, button: GamepadButton) -> bool {
+        self.buttons[button as usize]
+    }
+
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis.index()]
+    }
+}
+
+
+//
+//  rescale so the value starts at 0 right outside the dead zone
+//
+pub fn apply_dead_zone(v: f32, dead_zone: f32) -> f32 {
+    let dz = dead_zone.clamp(0.0, 0.99);
+    if v.abs() <= dz {
+        0.0
+    } else {
+        v.signum() * (v.abs() - dz) / (1.0 - dz)
+    }
+}
+
+
+
+pub struct Gamepads {
+    pads: BTreeMap<usize, GamepadState>,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Gamepads {
+
+    pub fn new() -> Self {
+        Gamepads {
+            pads: BTreeMap::new(),
+
+            #[cfg(not(target_arch = "wasm32"))]
+            gilrs: gilrs::Gilrs::new().ok(),
+        }
+    }
+
+    pub fn pads(&self) -> impl Iterator<Item = &GamepadState> {
+        self.pads.values()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&GamepadState> {
+        self.pads.get(&id)
+    }
+
+    //
+    //  refresh every pad, connection changes come back as events
+    //
+    pub fn poll(&mut self) -> Vec<GamepadEvent> {
+        let now = self.read();
+        let mut events = vec![];
+
+        for id in self.pads.keys() {
+            if !now.contains_key(id) {
+                events.push(GamepadEvent::Disconnected(*id));
+            }
+        }
+        for (id, pad) in &now {
+            if !self.pads.contains_key(id) {
+                events.push(GamepadEvent::Connected(*id, pad.name.clone()));
+            }
+        }
+
+        self.pads = now;
+        events
+    }
+
+
+    #[cfg(target_arch = "wasm32")]
+    fn read(&mut self) -> BTreeMap<usize, GamepadState> {
+        use wasm_bindgen::JsCast;
+
+        let mut out = BTreeMap::new();
+
+        let list = match web_sys::window().and_then(|w| w.navigator().get_gamepads().ok()) {
+            Some(l) => l,
+            None => return out,
+        };
+
+        for value in list.iter() {
+            let pad = match value.dyn_into::<web_sys::Gamepad>() {
+                Ok(p) if p.connected() => p,
+                _ => continue,
+            };
+
+            let mut state = GamepadState {
+                id: pad.index() as usize,
+                name: pad.id(),
+                ..Default::default()
+            };
+
+            for (i, b) in pad.buttons().iter().enumerate().take(BUTTONS.len()) {
+                if let Ok(b) = b.dyn_into::<web_sys::GamepadButton>() {
+                    state.buttons[i] = b.pressed();
+                    if i == 6 { state.axes[GamepadAxis::LeftTrigger.index()] = b.value() as f32; }
+                    if i == 7 { state.axes[GamepadAxis::RightTrigger.index()] = b.value() as f32; }
+                }
+            }
+
+            let axes: Vec<f32> = pad.axes().iter().map(|a| a.as_f64().unwrap_or(0.0) as f32).collect();
+            // browsers report y down
+            state.axes[GamepadAxis::LeftX.index()] = axes.first().copied().unwrap_or(0.0);
+            state.axes[GamepadAxis::LeftY.index()] = -axes.get(1).copied().unwrap_or(0.0);
+            state.axes[GamepadAxis::RightX.index()] = axes.get(2).copied().unwrap_or(0.0);
+            state.axes[GamepadAxis::RightY.index()] = -axes.get(3).copied().unwrap_or(0.0);
+
+            out.insert(state.id, state);
+        }
+
+        out
+    }
+
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read(&mut self) -> BTreeMap<usize, GamepadState> {
+        use gilrs::{Axis, Button};
+
+        let mut out = BTreeMap::new();
+
+        let gilrs = match self.gilrs.as_mut() {
+            Some(g) => g,
+            None => return out,
+        };
+
+        // gilrs updates its cached state while draining events
+        while gilrs.next_event().is_some() {}
+
+        let map = [
+            Button::South, Button::East, Button::West, Button::North,
+            Button::LeftTrigger, Button::RightTrigger, Button::LeftTrigger2, Button::RightTrigger2,
+            Button::Select, Button::Start, Button::LeftThumb, Button::RightThumb,
+            Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+            Button::Mode,
+        ];
+
+        for (id, pad) in gilrs.gamepads() {
+            let mut state = GamepadState {
+                id: usize::from(id),
+                name: pad.name().to_string(),
+                ..Default::default()
+            };
+
+            for (i, b) in map.iter().enumerate() {
+                state.buttons[i] = pad.is_pressed(*b);
+            }
+
+            state.axes[GamepadAxis::LeftX.index()] = pad.value(Axis::LeftStickX);
+            state.axes[GamepadAxis::LeftY.index()] = pad.value(Axis::LeftStickY);
+            state.axes[GamepadAxis::RightX.index()] = pad.value(Axis::RightStickX);
+            state.axes[GamepadAxis::RightY.index()] = pad.value(Axis::RightStickY);
+            state.axes[GamepadAxis::LeftTrigger.index()] = pad.button_data(Button::LeftTrigger2).map(|d| d.value()).unwrap_or(0.0);
+            state.axes[GamepadAxis::RightTrigger.index()] = pad.button_data(Button::RightTrigger2).map(|d| d.value()).unwrap_or(0.0);
+
+            out.insert(state.id, state);
+        }
+
+        out
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_zone_rescales_the_rest() {
+        assert_eq!(apply_dead_zone(0.1, 0.2), 0.0);
+        assert_eq!(apply_dead_zone(-0.2, 0.2), 0.0);
+        assert!((apply_dead_zone(0.6, 0.2) - 0.5).abs() < 1e-6);
+        assert!((apply_dead_zone(-1.0, 0.2) + 1.0).abs() < 1e-6);
+        // a dead zone of 1 would divide by zero
+        assert!(apply_dead_zone(1.0, 1.0).is_finite());
+    }
+}
@@ -1,10 +1,875 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
 
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
 
+use super::render::*;
+use super::input::VirtualJoystick;
 
 
+//
+//  immediate mode gui: widgets are functions called every frame,
+//  state that must survive between frames is keyed by WidgetId
+//
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WidgetId(u64);
 
-struct GUI {
-    
-}
\ No newline at end of file
+
+#[derive(Debug, Clone)]
+pub struct Style {
+    pub text_px: f32,
+    pub padding: f32,
+    pub spacing: f32,
+    pub row_height: f32,
+    pub text: [f32; 4],
+    pub panel: [f32; 3],
+    pub title: [f32; 3],
+    pub widget: [f32; 3],
+    pub hovered: [f32; 3],
+    pub active: [f32; 3],
+    pub accent: [f32; 3],
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            text_px: 16.0,
+            padding: 6.0,
+            spacing: 4.0,
+            row_height: 22.0,
+            text: [0.9, 0.9, 0.9, 1.0],
+            panel: [0.1, 0.1, 0.12],
+            title: [0.2, 0.2, 0.3],
+            widget: [0.22, 0.22, 0.26],
+            hovered: [0.3, 0.3, 0.36],
+            active: [0.36, 0.36, 0.5],
+            accent: [0.4, 0.6, 1.0],
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Rect { x, y, w, h }
+    }
+
+    pub fn contains(&self, p: [f32; 2]) -> bool {
+        p[0] >= self.x && p[0] < self.x + self.w && p[1] >= self.y && p[1] < self.y + self.h
+    }
+
+    fn intersect(&self, o: &Rect) -> Rect {
+        let x0 = self.x.max(o.x);
+        let y0 = self.y.max(o.y);
+        let x1 = (self.x + self.w).min(o.x + o.w);
+        let y1 = (self.y + self.h).min(o.y + o.h);
+        Rect::new(x0, y0, (x1 - x0).max(0.0), (y1 - y0).max(0.0))
+    }
+}
+
+
+struct Frame {
+    rect: Rect,
+    clip: Rect,
+    cursor: [f32; 2],
+    horizontal: bool,
+    row_h: f32,
+}
+
+
+struct GuiText {
+    text: String,
+    x: f32,
+    y: f32,
+    color: [f32; 4],
+    clip: Rect,
+}
+
+
+#[derive(Default)]
+struct InputState {
+    mouse: [f32; 2],
+    // where the mouse was last frame
+    last_mouse: [f32; 2],
+    down: bool,
+    pressed: bool,
+    released: bool,
+    wheel: f32,
+    typed: String,
+    keys: Vec<KeyCode>,
+}
+
+
+pub struct GUI {
+    pub style: Style,
+    screen: [f32; 2],
+    font: Option<(FontId, Font)>,
+
+    input: InputState,
+    hot: Option<WidgetId>,
+    active: Option<WidgetId>,
+    focus: Option<WidgetId>,
+    // widgets that accepted focus this frame, in order, for tab navigation
+    focusable: Vec<WidgetId>,
+    focus_next: bool,
+
+    id_stack: Vec<u64>,
+    frames: Vec<Frame>,
+    scroll: HashMap<WidgetId, f32>,
+    cursor_pos: HashMap<WidgetId, usize>,
+
+    shapes: Vec<Vertex>,
+    texts: Vec<GuiText>,
+    // true when the pointer is over gui this frame, the game should ignore clicks then
+    pub wants_mouse: bool,
+    pub wants_keyboard: bool,
+}
+
+impl GUI {
+
+    pub fn new() -> Self {
+        GUI {
+            style: Style::default(),
+            screen: [640.0, 640.0],
+            font: None,
+            input: InputState::default(),
+            hot: None,
+            active: None,
+            focus: None,
+            focusable: vec![],
+            focus_next: false,
+            id_stack: vec![],
+            frames: vec![],
+            scroll: HashMap::new(),
+            cursor_pos: HashMap::new(),
+            shapes: vec![],
+            texts: vec![],
+            wants_mouse: false,
+            wants_keyboard: false,
+        }
+    }
+
+    //
+    //  the font is also kept here to measure text while laying out
+    //
+    pub fn set_font(&mut self, id: FontId, font: Font) {
+        self.font = Some((id, font));
+    }
+
+    //
+    //  feed every WindowEvent, returns true when the gui used it
+    //
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+
+            WindowEvent::Resized(size) => {
+                self.screen = [size.width as f32, size.height as f32];
+                false
+            }
+
+            WindowEvent::CursorMoved { position, .. } => {
+                self.input.mouse = [position.x as f32, position.y as f32];
+                self.wants_mouse
+            }
+
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                match state {
+                    ElementState::Pressed => {
+                        self.input.down = true;
+                        self.input.pressed = true;
+                    }
+                    ElementState::Released => {
+                        self.input.down = false;
+                        self.input.released = true;
+                    }
+                }
+                self.wants_mouse
+            }
+
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.input.wheel += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y * self.style.row_height,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32,
+                };
+                self.wants_mouse
+            }
+
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.state != ElementState::Pressed || self.focus.is_none() {
+                    return false;
+                }
+
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    self.input.keys.push(code);
+                }
+                if let Some(text) = &event.text {
+                    self.input.typed.extend(text.chars().filter(|c| !c.is_control()));
+                }
+                true
+            }
+
+            _ => false,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.shapes.clear();
+        self.texts.clear();
+        self.frames.clear();
+        self.id_stack.clear();
+        self.hot = None;
+
+        if self.input.keys.contains(&KeyCode::Tab) {
+            self.focus_next = true;
+        }
+        if self.input.keys.contains(&KeyCode::Escape) {
+            self.focus = None;
+        }
+        self.focusable.clear();
+
+        let screen = Rect::new(0.0, 0.0, self.screen[0], self.screen[1]);
+        self.frames.push(Frame {
+            rect: screen,
+            clip: screen,
+            cursor: [self.style.padding, self.style.padding],
+            horizontal: false,
+            row_h: 0.0,
+        });
+    }
+
+    //
+    //  send the frame to the renderer, text is drawn over shapes
+    //
+    pub fn end_frame(&mut self, gpu: &mut RenderWebGpu) {
+        self.finish_input();
+
+        gpu.draw_overlay(&self.shapes);
+
+        if let Some((font, _)) = &self.font {
+            let style = TextStyle::new(self.style.text_px);
+            for t in &self.texts {
+                let c = t.clip;
+                gpu.draw_text(*font, &t.text, t.x, t.y, &style.clone().color(t.color).clip(c.x, c.y, c.w, c.h));
+            }
+        }
+    }
+
+    // focus, capture flags and the per frame input, once the widgets have run
+    fn finish_input(&mut self) {
+        if self.focus_next {
+            // Tab moves to the widget after the focused one
+            let next = match self.focus.and_then(|f| self.focusable.iter().position(|x| *x == f)) {
+                Some(i) => self.focusable.get(i + 1).or(self.focusable.first()).copied(),
+                None => self.focusable.first().copied(),
+            };
+            self.focus = next;
+            self.focus_next = false;
+        }
+
+        if self.input.pressed && self.hot.is_none() {
+            self.focus = None;
+        }
+
+        if !self.input.down {
+            self.active = None;
+        }
+
+        self.wants_mouse = self.hot.is_some() || self.active.is_some();
+        self.wants_keyboard = self.focus.is_some();
+        self.input.last_mouse = self.input.mouse;
+        self.input.pressed = false;
+        self.input.released = false;
+        self.input.wheel = 0.0;
+        self.input.typed.clear();
+        self.input.keys.clear();
+    }
+
+
+
+    //
+    //  widgets
+    //
+
+    pub fn label(&mut self, text: &str) {
+        let (w, _) = self.measure(text);
+        let r = self.allocate(w, self.style.row_height);
+        let color = self.style.text;
+        self.text(text, r.x, r.y, r.h, color);
+    }
+
+    pub fn space(&mut self, h: f32) {
+        self.allocate(0.0, h);
+    }
+
+    pub fn separator(&mut self) {
+        let width = self.frame().rect.w - self.style.padding * 2.0;
+        let r = self.allocate(width, 1.0);
+        let c = self.style.title;
+        self.rect(r, c);
+    }
+
+    pub fn button(&mut self, text: &str) -> bool {
+        let id = self.id(text);
+        let (w, _) = self.measure(text);
+        let r = self.allocate(w + self.style.padding * 2.0, self.style.row_height);
+
+        let clicked = self.interact(id, r);
+        let color = self.widget_color(id);
+        self.rect(r, color);
+
+        let tc = self.style.text;
+        self.text(text, r.x + self.style.padding, r.y, r.h, tc);
+
+        if self.focus == Some(id) && self.input.keys.contains(&KeyCode::Enter) {
+            return true;
+        }
+        clicked
+    }
+
+    pub fn checkbox(&mut self, text: &str, value: &mut bool) -> bool {
+        let id = self.id(text);
+        let (w, _) = self.measure(text);
+        let bh = self.style.row_height;
+        let r = self.allocate(bh + self.style.spacing + w, bh);
+
+        let mut changed = self.interact(id, r);
+        if self.focus == Some(id) && self.input.keys.contains(&KeyCode::Space) {
+            changed = true;
+        }
+        if changed {
+            *value = !*value;
+        }
+
+        let bx = Rect::new(r.x + 3.0, r.y + 3.0, bh - 6.0, bh - 6.0);
+        let color = self.widget_color(id);
+        self.rect(bx, color);
+        if *value {
+            let accent = self.style.accent;
+            self.rect(Rect::new(bx.x + 4.0, bx.y + 4.0, bx.w - 8.0, bx.h - 8.0), accent);
+        }
+
+        let tc = self.style.text;
+        self.text(text, r.x + bh + self.style.spacing, r.y, r.h, tc);
+        changed
+    }
+
+    pub fn slider(&mut self, text: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let id = self.id(text);
+        let width = (self.frame().rect.w - self.style.padding * 2.0).max(60.0);
+        let r = self.allocate(width, self.style.row_height);
+
+        self.interact(id, r);
+
+        // a value outside the range stays as it is until the slider is moved
+        let old = *value;
+        let mut moved = false;
+        if self.active == Some(id) && max > min {
+            let t = ((self.input.mouse[0] - r.x) / r.w).clamp(0.0, 1.0);
+            *value = min + (max - min) * t;
+            moved = true;
+        }
+        if self.focus == Some(id) {
+            let step = (max - min) / 100.0;
+            if self.input.keys.contains(&KeyCode::ArrowLeft) { *value -= step; moved = true; }
+            if self.input.keys.contains(&KeyCode::ArrowRight) { *value += step; moved = true; }
+        }
+        if moved {
+            *value = value.clamp(min.min(max), max.max(min));
+        }
+
+        let color = self.widget_color(id);
+        self.rect(r, color);
+
+        let t = if max > min { ((*value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.0 };
+        let accent = self.style.accent;
+        self.rect(Rect::new(r.x, r.y, r.w * t, r.h), accent);
+
+        let tc = self.style.text;
+        self.text(&format!("{}: {:.2}", text, value), r.x + self.style.padding, r.y, r.h, tc);
+
+        *value != old
+    }
+
+    //
+    //  unbounded number, dragging sideways moves it by `speed` per pixel, the arrows by ten pixels
+    //
+    pub fn drag_value(&mut self, text: &str, value: &mut f32, speed: f32) -> bool {
+        let id = self.id(text);
+        let width = (self.frame().rect.w - self.style.padding * 2.0).max(60.0);
+        let r = self.allocate(width, self.style.row_height);
+
+        self.interact(id, r);
+
+        let old = *value;
+        // the press itself does not move it, the mouse may have moved since last frame
+        if self.active == Some(id) && !self.input.pressed {
+            *value += (self.input.mouse[0] - self.input.last_mouse[0]) * speed;
+        }
+        if self.focus == Some(id) {
+            if self.input.keys.contains(&KeyCode::ArrowLeft) { *value -= speed * 10.0; }
+            if self.input.keys.contains(&KeyCode::ArrowRight) { *value += speed * 10.0; }
+        }
+
+        let color = self.widget_color(id);
+        self.rect(r, color);
+
+        let tc = self.style.text;
+        self.text(&format!("{}: {:.2}", text, value), r.x + self.style.padding, r.y, r.h, tc);
+
+        *value != old
+    }
+
+    //
+    //  bar graph of the values, newest on the right
+    //
+    pub fn plot(&mut self, label: &str, values: &[f32], max: f32, height: f32) {
+        let width = (self.frame().rect.w - self.style.padding * 2.0).max(60.0);
+        let r = self.allocate(width, height);
+
+        let panel = self.style.widget;
+        self.rect(r, panel);
+
+        if !values.is_empty() && max > 0.0 {
+            let bar_w = r.w / values.len() as f32;
+            let accent = self.style.accent;
+            for (i, v) in values.iter().enumerate() {
+                let bh = (v / max).clamp(0.0, 1.0) * r.h;
+                self.rect(Rect::new(r.x + bar_w * i as f32, r.y + r.h - bh, bar_w.max(1.0), bh), accent);
+            }
+        }
+
+        let tc = self.style.text;
+        self.text(label, r.x + self.style.padding, r.y, self.style.row_height, tc);
+    }
+
+    //
+    //  draws an on-screen stick, the stick itself is driven by Input
+    //
+    pub fn virtual_joystick(&mut self, stick: &VirtualJoystick) {
+        let (widget, accent) = (self.style.widget, self.style.accent);
+        self.circle(stick.center, stick.radius, widget);
+        self.circle(stick.knob, stick.radius * 0.4, accent);
+    }
+
+    //
+    //  keys reach the gui only while a widget has focus
+    //
+    pub fn key_pressed(&self, key: KeyCode) -> bool {
+        self.input.keys.contains(&key)
+    }
+
+    // whether the widget with this label, in the current panel, has keyboard focus
+    pub fn has_focus(&self, label: &str) -> bool {
+        self.focus == Some(self.id(label))
+    }
+
+    //
+    //  single line text field, returns true when the text changed
+    //
+    pub fn text_input(&mut self, label: &str, value: &mut String) -> bool {
+        let id = self.id(label);
+        let width = (self.frame().rect.w - self.style.padding * 2.0).max(60.0);
+        let r = self.allocate(width, self.style.row_height);
+
+        self.interact(id, r);
+
+        let mut changed = false;
+        let mut cursor = *self.cursor_pos.get(&id).unwrap_or(&value.chars().count());
+        cursor = cursor.min(value.chars().count());
+
+        if self.focus == Some(id) {
+            for c in self.input.typed.chars() {
+                let at = byte_index(value, cursor);
+                value.insert(at, c);
+                cursor += 1;
+                changed = true;
+            }
+
+            for key in self.input.keys.clone() {
+                match key {
+                    KeyCode::Backspace if cursor > 0 => {
+                        let at = byte_index(value, cursor - 1);
+                        value.remove(at);
+                        cursor -= 1;
+                        changed = true;
+                    }
+                    KeyCode::Delete if cursor < value.chars().count() => {
+                        let at = byte_index(value, cursor);
+                        value.remove(at);
+                        changed = true;
+                    }
+                    KeyCode::ArrowLeft => cursor = cursor.saturating_sub(1),
+                    KeyCode::ArrowRight => cursor = (cursor + 1).min(value.chars().count()),
+                    KeyCode::Home => cursor = 0,
+                    KeyCode::End => cursor = value.chars().count(),
+                    _ => (),
+                }
+            }
+        }
+        self.cursor_pos.insert(id, cursor);
+
+        let color = if self.focus == Some(id) { self.style.active } else { self.widget_color(id) };
+        self.rect(r, color);
+
+        let shown = if value.is_empty() && self.focus != Some(id) { label } else { value.as_str() };
+        let tc = self.style.text;
+        self.text(shown, r.x + self.style.padding, r.y, r.h, tc);
+
+        if self.focus == Some(id) {
+            let before: String = value.chars().take(cursor).collect();
+            let (cx, _) = self.measure(&before);
+            let accent = self.style.accent;
+            self.rect(Rect::new(r.x + self.style.padding + cx, r.y + 3.0, 2.0, r.h - 6.0), accent);
+        }
+
+        changed
+    }
+
+
+
+    //
+    //  containers
+    //
+
+    pub fn panel(&mut self, title: &str, rect: Rect, f: impl FnOnce(&mut GUI)) {
+        let id = self.id(title);
+        if rect.contains(self.input.mouse) {
+            self.hot = Some(id);
+        }
+
+        let clip = rect.intersect(&self.frame().clip);
+        let (panel, title_color) = (self.style.panel, self.style.title);
+        self.rect_clipped(rect, panel, clip);
+
+        let th = self.style.row_height;
+        self.rect_clipped(Rect::new(rect.x, rect.y, rect.w, th), title_color, clip);
+        let tc = self.style.text;
+        self.push_text(title, rect.x + self.style.padding, rect.y, th, tc, clip);
+
+        self.id_stack.push(id.0);
+        self.frames.push(Frame {
+            rect,
+            clip,
+            cursor: [rect.x + self.style.padding, rect.y + th + self.style.padding],
+            horizontal: false,
+            row_h: 0.0,
+        });
+
+        f(self);
+
+        self.frames.pop();
+        self.id_stack.pop();
+    }
+
+    pub fn horizontal(&mut self, f: impl FnOnce(&mut GUI)) {
+        let (x, y) = (self.frame().cursor[0], self.frame().cursor[1]);
+        let parent = self.frame();
+        let rect = Rect::new(x, y, parent.rect.x + parent.rect.w - x, 0.0);
+        let clip = parent.clip;
+
+        self.frames.push(Frame {
+            rect,
+            clip,
+            cursor: [x, y],
+            horizontal: true,
+            row_h: 0.0,
+        });
+
+        f(self);
+
+        let h = self.frames.pop().unwrap().row_h;
+        self.allocate(0.0, h);
+    }
+
+    //
+    //  fixed height area scrolled with the mouse wheel, content outside is clipped
+    //
+    pub fn scroll_area(&mut self, name: &str, height: f32, f: impl FnOnce(&mut GUI)) {
+        let id = self.id(name);
+        let width = self.frame().rect.w - self.style.padding * 2.0;
+        let r = self.allocate(width, height);
+
+        let hovered = r.contains(self.input.mouse) && self.frame().clip.contains(self.input.mouse);
+        let offset = *self.scroll.get(&id).unwrap_or(&0.0);
+        let clip = r.intersect(&self.frame().clip);
+
+        let panel = self.style.panel;
+        self.rect_clipped(r, panel, clip);
+
+        self.id_stack.push(id.0);
+        self.frames.push(Frame {
+            rect: r,
+            clip,
+            cursor: [r.x + self.style.padding, r.y + self.style.padding - offset],
+            horizontal: false,
+            row_h: 0.0,
+        });
+
+        f(self);
+
+        let frame = self.frames.pop().unwrap();
+        self.id_stack.pop();
+
+        let content = frame.cursor[1] + offset - r.y;
+        let max = (content - r.h).max(0.0);
+        let mut offset = offset;
+        if hovered {
+            offset -= self.input.wheel;
+        }
+        offset = offset.clamp(0.0, max);
+        self.scroll.insert(id, offset);
+
+        if max > 0.0 {
+            let bar_h = (r.h * r.h / content).max(10.0);
+            let bar_y = r.y + (r.h - bar_h) * (offset / max);
+            let accent = self.style.accent;
+            self.rect_clipped(Rect::new(r.x + r.w - 4.0, bar_y, 4.0, bar_h), accent, clip);
+        }
+    }
+
+
+
+    //
+    //  internals
+    //
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().unwrap()
+    }
+
+    fn id(&self, label: &str) -> WidgetId {
+        let mut h = DefaultHasher::new();
+        self.id_stack.hash(&mut h);
+        label.hash(&mut h);
+        WidgetId(h.finish())
+    }
+
+    fn allocate(&mut self, w: f32, h: f32) -> Rect {
+        let spacing = self.style.spacing;
+        let frame = self.frames.last_mut().unwrap();
+        let r = Rect::new(frame.cursor[0], frame.cursor[1], w, h);
+
+        if frame.horizontal {
+            frame.cursor[0] += w + spacing;
+            frame.row_h = frame.row_h.max(h);
+        } else {
+            frame.cursor[1] += h + spacing;
+        }
+
+        r
+    }
+
+    // click = press and release on the same widget
+    fn interact(&mut self, id: WidgetId, r: Rect) -> bool {
+        self.focusable.push(id);
+
+        let over = r.contains(self.input.mouse) && self.frame().clip.contains(self.input.mouse);
+        if over {
+            self.hot = Some(id);
+        }
+
+        if over && self.input.pressed {
+            self.active = Some(id);
+            self.focus = Some(id);
+        }
+
+        over && self.input.released && self.active == Some(id)
+    }
+
+    fn widget_color(&self, id: WidgetId) -> [f32; 3] {
+        if self.active == Some(id) {
+            self.style.active
+        } else if self.hot == Some(id) {
+            self.style.hovered
+        } else {
+            self.style.widget
+        }
+    }
+
+    fn measure(&self, text: &str) -> (f32, f32) {
+        match &self.font {
+            Some((_, font)) => {
+                let l = layout_text(font, text, &TextStyle::new(self.style.text_px));
+                (l.width, l.height)
+            }
+            None => (text.chars().count() as f32 * self.style.text_px * 0.5, self.style.text_px),
+        }
+    }
+
+    fn rect(&mut self, r: Rect, color: [f32; 3]) {
+        let clip = self.frame().clip;
+        self.rect_clipped(r, color, clip);
+    }
+
+    fn rect_clipped(&mut self, r: Rect, color: [f32; 3], clip: Rect) {
+        let r = r.intersect(&clip);
+        if r.w <= 0.0 || r.h <= 0.0 {
+            return;
+        }
+
+        let sx = 2.0 / self.screen[0].max(1.0);
+        let sy = 2.0 / self.screen[1].max(1.0);
+        let (x0, y0) = (r.x * sx - 1.0, 1.0 - r.y * sy);
+        let (x1, y1) = ((r.x + r.w) * sx - 1.0, 1.0 - (r.y + r.h) * sy);
+
+        let a = Vertex::new(x0, y0, 0.0, color);
+        let b = Vertex::new(x1, y0, 0.0, color);
+        let c = Vertex::new(x1, y1, 0.0, color);
+        let d = Vertex::new(x0, y1, 0.0, color);
+        self.shapes.extend_from_slice(&[a, b, c, a, c, d]);
+    }
+
+    fn circle(&mut self, center: [f32; 2], r: f32, color: [f32; 3]) {
+        let sx = 2.0 / self.screen[0].max(1.0);
+        let sy = 2.0 / self.screen[1].max(1.0);
+
+        let mesh = Path::builder()
+            .tolerance(0.5)
+            .circle(center[0], center[1], r)
+            .build()
+            .fill(FillRule::NonZero, color);
+
+        self.shapes.extend(mesh.into_iter().map(|v| {
+            Vertex::new(v.pos[0] * sx - 1.0, 1.0 - v.pos[1] * sy, 0.0, color)
+        }));
+    }
+
+    // text is vertically centred in a row of height h
+    fn text(&mut self, text: &str, x: f32, y: f32, h: f32, color: [f32; 4]) {
+        let clip = self.frame().clip;
+        self.push_text(text, x, y, h, color, clip);
+    }
+
+    fn push_text(&mut self, text: &str, x: f32, y: f32, h: f32, color: [f32; 4], clip: Rect) {
+        let (w, th) = self.measure(text);
+        let y = y + (h - th) * 0.5;
+
+        // entirely outside, the rest is cut glyph by glyph when drawn
+        let r = Rect::new(x, y, w, th).intersect(&clip);
+        if r.w <= 0.0 || r.h <= 0.0 {
+            return;
+        }
+
+        self.texts.push(GuiText { text: text.to_string(), x, y, color, clip });
+    }
+}
+
+
+fn byte_index(s: &str, chars: usize) -> usize {
+    s.char_indices().nth(chars).map(|(i, _)| i).unwrap_or(s.len())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{dpi::PhysicalPosition, event::DeviceId};
+
+    fn mouse(gui: &mut GUI, x: f32, y: f32) -> bool {
+        let device_id = unsafe { DeviceId::dummy() };
+        gui.handle_event(&WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(x as f64, y as f64) })
+    }
+
+    fn click(gui: &mut GUI, state: ElementState) -> bool {
+        let device_id = unsafe { DeviceId::dummy() };
+        gui.handle_event(&WindowEvent::MouseInput { device_id, state, button: MouseButton::Left })
+    }
+
+    fn frame(gui: &mut GUI) -> bool {
+        gui.begin_frame();
+        let clicked = gui.button("ok");
+        gui.finish_input();
+        clicked
+    }
+
+    #[test]
+    fn clicks_on_widgets_are_taken() {
+        let mut gui = GUI::new();
+        frame(&mut gui);
+
+        // the first move only finds out what is under the cursor
+        assert!(!mouse(&mut gui, 10.0, 10.0));
+        assert!(!frame(&mut gui));
+        assert!(gui.wants_mouse);
+
+        assert!(click(&mut gui, ElementState::Pressed));
+        assert!(!frame(&mut gui));
+        assert!(click(&mut gui, ElementState::Released));
+        assert!(frame(&mut gui));
+    }
+
+    #[test]
+    fn clicks_elsewhere_go_to_the_game() {
+        let mut gui = GUI::new();
+        mouse(&mut gui, 300.0, 300.0);
+        frame(&mut gui);
+        assert!(!gui.wants_mouse);
+        assert!(!click(&mut gui, ElementState::Pressed));
+        assert!(!frame(&mut gui));
+
+        // dragged onto the button, the release is not a click
+        assert!(!mouse(&mut gui, 10.0, 10.0));
+        frame(&mut gui);
+        click(&mut gui, ElementState::Released);
+        assert!(!frame(&mut gui));
+    }
+
+    #[test]
+    fn sliders_change_only_when_moved() {
+        let mut gui = GUI::new();
+        let mut value = 50.0;
+        let slide = |gui: &mut GUI, value: &mut f32| {
+            gui.begin_frame();
+            let changed = gui.slider("x", value, -20.0, 20.0);
+            gui.finish_input();
+            changed
+        };
+
+        // out of range, shown and hovered but not touched
+        mouse(&mut gui, 10.0, 10.0);
+        assert!(!slide(&mut gui, &mut value));
+        assert!(!slide(&mut gui, &mut value));
+        assert_eq!(value, 50.0);
+
+        click(&mut gui, ElementState::Pressed);
+        assert!(slide(&mut gui, &mut value));
+        assert!(value < 20.0);
+    }
+
+    #[test]
+    fn drag_values_are_unbounded() {
+        let mut gui = GUI::new();
+        let mut value = 50.0;
+        let drag = |gui: &mut GUI, value: &mut f32| {
+            gui.begin_frame();
+            let changed = gui.drag_value("x", value, 0.5);
+            gui.finish_input();
+            changed
+        };
+
+        mouse(&mut gui, 10.0, 10.0);
+        assert!(!drag(&mut gui, &mut value));
+        click(&mut gui, ElementState::Pressed);
+        assert!(!drag(&mut gui, &mut value));
+
+        mouse(&mut gui, 30.0, 10.0);
+        assert!(drag(&mut gui, &mut value));
+        assert_eq!(value, 60.0);
+    }
+}
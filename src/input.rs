@@ -0,0 +1,698 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use super::gamepad::*;
+use crate::replay::InputFrame;
+
+
+//
+//  per frame input state + named actions and axes
+//  feed WindowEvents with handle_event, call update before the game reads input
+//  and end_frame after the game update
+//
+
+
+// a touch shorter than this and moving less than TAP_DISTANCE pixels is a tap
+const TAP_FRAMES: u64 = 20;
+const TAP_DISTANCE: f32 = 12.0;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // any connected gamepad
+    Gamepad(GamepadButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+    // -1 while negative is held, +1 while positive is held
+    Keys { negative: Binding, positive: Binding },
+    WheelX,
+    WheelY,
+    Gamepad { axis: GamepadAxis, dead_zone: f32 },
+    // on-screen joystick by index in Input::sticks
+    Stick { index: usize, vertical: bool },
+}
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActionMap {
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl ActionMap {
+
+    pub fn new() -> Self {
+        ActionMap::default()
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let list = self.actions.entry(action.to_string()).or_default();
+        if !list.contains(&binding) {
+            list.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding) {
+        if let Some(list) = self.actions.get_mut(action) {
+            list.retain(|b| *b != binding);
+        }
+    }
+
+    //
+    //  replace every binding of the action
+    //
+    pub fn rebind(&mut self, action: &str, binding: Binding) {
+        self.actions.insert(action.to_string(), vec![binding]);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        let list = self.axes.entry(axis.to_string()).or_default();
+        if !list.contains(&binding) {
+            list.push(binding);
+        }
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(s: &str) -> anyhow::Result<ActionMap> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchPoint {
+    pub id: u64,
+    pub pos: [f32; 2],
+    pub start: [f32; 2],
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap { pos: [f32; 2] },
+    Drag { pos: [f32; 2], delta: [f32; 2] },
+    // scale is relative to the previous frame
+    Pinch { center: [f32; 2], scale: f32 },
+}
+
+
+//
+//  on-screen stick for touch devices, claims a touch that starts near it
+//
+#[derive(Debug, Clone)]
+pub struct VirtualJoystick {
+    pub center: [f32; 2],
+    pub radius: f32,
+    pub touch: Option<u64>,
+    // -1..1, y up
+    pub value: [f32; 2],
+    pub knob: [f32; 2],
+}
+
+impl VirtualJoystick {
+
+    pub fn new(center: [f32; 2], radius: f32) -> Self {
+        VirtualJoystick {
+            center,
+            radius,
+            touch: None,
+            value: [0.0, 0.0],
+            knob: center,
+        }
+    }
+
+    fn update(&mut self, touches: &HashMap<u64, TouchPoint>, started: &[u64]) {
+
+        if self.touch.is_none() {
+            for id in started {
+                if let Some(t) = touches.get(id) {
+                    let d = dist(t.pos, self.center);
+                    if d <= self.radius * 2.0 {
+                        self.touch = Some(*id);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let t = match self.touch.and_then(|id| touches.get(&id)) {
+            Some(t) => *t,
+            None => {
+                self.touch = None;
+                self.value = [0.0, 0.0];
+                self.knob = self.center;
+                return;
+            }
+        };
+
+        let mut off = [t.pos[0] - self.center[0], t.pos[1] - self.center[1]];
+        let len = (off[0] * off[0] + off[1] * off[1]).sqrt();
+        if len > self.radius {
+            off = [off[0] * self.radius / len, off[1] * self.radius / len];
+        }
+
+        self.knob = [self.center[0] + off[0], self.center[1] + off[1]];
+        self.value = [off[0] / self.radius, -off[1] / self.radius];
+    }
+}
+
+
+#[derive(Default)]
+pub struct Input {
+    pub actions: ActionMap,
+    pub sticks: Vec<VirtualJoystick>,
+    // set by the first touch event, the game shows the virtual sticks then
+    pub touch_seen: bool,
+
+    held: HashSet<Binding>,
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+
+    cursor: [f32; 2],
+    cursor_delta: [f32; 2],
+    wheel: [f32; 2],
+
+    touches: HashMap<u64, TouchPoint>,
+    touches_started: Vec<u64>,
+    touches_ended: Vec<TouchPoint>,
+    touch_frame: HashMap<u64, u64>,
+    touch_prev: HashMap<u64, [f32; 2]>,
+    pinch_prev: Option<f32>,
+    gestures: Vec<Gesture>,
+    frame: u64,
+
+    gamepads: Option<Gamepads>,
+    pad_events: Vec<GamepadEvent>,
+    pad_axes: [f32; 6],
+
+    // the next pressed key or button is bound to this action
+    rebinding: Option<String>,
+}
+
+impl Input {
+
+    pub fn new(actions: ActionMap) -> Self {
+        Input {
+            actions,
+            gamepads: Some(Gamepads::new()),
+            ..Default::default()
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    if !event.repeat {
+                        self.set(Binding::Key(code), event.state);
+                    }
+                }
+            }
+
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.set(Binding::Mouse(*button), *state);
+            }
+
+            WindowEvent::CursorMoved { position, .. } => {
+                let p = [position.x as f32, position.y as f32];
+                self.cursor_delta[0] += p[0] - self.cursor[0];
+                self.cursor_delta[1] += p[1] - self.cursor[1];
+                self.cursor = p;
+            }
+
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (*x, *y),
+                    // roughly one line per 40 pixels
+                    MouseScrollDelta::PixelDelta(p) => (p.x as f32 / 40.0, p.y as f32 / 40.0),
+                };
+                self.wheel[0] += x;
+                self.wheel[1] += y;
+            }
+
+            WindowEvent::Touch(t) => {
+                self.touch_seen = true;
+                let pos = [t.location.x as f32, t.location.y as f32];
+                match t.phase {
+                    TouchPhase::Started => {
+                        self.touches.insert(t.id, TouchPoint { id: t.id, pos, start: pos });
+                        self.touches_started.push(t.id);
+                        self.touch_frame.insert(t.id, self.frame);
+                    }
+                    TouchPhase::Moved => {
+                        if let Some(p) = self.touches.get_mut(&t.id) {
+                            p.pos = pos;
+                        }
+                    }
+                    TouchPhase::Ended | TouchPhase::Cancelled => {
+                        if let Some(mut p) = self.touches.remove(&t.id) {
+                            p.pos = pos;
+                            self.touches_ended.push(p);
+                        }
+                    }
+                }
+            }
+
+            // keys held while the window loses focus never get a release
+            WindowEvent::Focused(false) => {
+                self.released.extend(self.held.drain());
+            }
+
+            _ => (),
+        }
+    }
+
+    //
+    //  an event the console or gui used. releases, cursor moves and focus loss still go
+    //  through so nothing stays held, presses and scrolling are theirs
+    //
+    pub fn handle_captured_event(&mut self, event: &WindowEvent) {
+        let press = match event {
+            WindowEvent::KeyboardInput { event, .. } => event.state == ElementState::Pressed,
+            WindowEvent::MouseInput { state, .. } => *state == ElementState::Pressed,
+            WindowEvent::MouseWheel { .. } => true,
+            WindowEvent::Touch(t) => t.phase == TouchPhase::Started,
+            _ => false,
+        };
+        if !press {
+            self.handle_event(event);
+        }
+    }
+
+    //
+    //  poll gamepads, move the virtual sticks and recognise gestures
+    //
+    pub fn update(&mut self) {
+        self.frame += 1;
+
+        let mut pads = std::mem::take(&mut self.gamepads);
+        if let Some(pads) = pads.as_mut() {
+            self.pad_events = pads.poll();
+
+            let mut down = [false; BUTTONS.len()];
+            for p in pads.pads() {
+                for (d, b) in down.iter_mut().zip(p.buttons) {
+                    *d |= b;
+                }
+            }
+            self.pad_buttons(&down);
+
+            self.pad_axes = [0.0; 6];
+            for p in pads.pads() {
+                for (i, a) in p.axes.iter().enumerate() {
+                    if a.abs() > self.pad_axes[i].abs() {
+                        self.pad_axes[i] = *a;
+                    }
+                }
+            }
+        }
+        self.gamepads = pads;
+
+        let mut claimed: HashSet<u64> = HashSet::new();
+        for stick in &mut self.sticks {
+            claimed.extend(stick.touch);
+            stick.update(&self.touches, &self.touches_started);
+            claimed.extend(stick.touch);
+        }
+
+        self.detect_gestures(&claimed);
+    }
+
+    // buttons down on any pad, the ones that changed since last frame are pressed or released
+    fn pad_buttons(&mut self, down: &[bool; BUTTONS.len()]) {
+        for (button, down) in BUTTONS.iter().zip(down) {
+            let binding = Binding::Gamepad(*button);
+            if *down != self.held.contains(&binding) {
+                self.set(binding, if *down { ElementState::Pressed } else { ElementState::Released });
+            }
+        }
+    }
+
+    fn detect_gestures(&mut self, claimed: &HashSet<u64>) {
+        self.gestures.clear();
+
+        for t in &self.touches_ended {
+            let start = self.touch_frame.remove(&t.id).unwrap_or(self.frame);
+            self.touch_prev.remove(&t.id);
+            if claimed.contains(&t.id) {
+                continue;
+            }
+            if self.frame - start <= TAP_FRAMES && dist(t.pos, t.start) < TAP_DISTANCE {
+                self.gestures.push(Gesture::Tap { pos: t.pos });
+            }
+        }
+
+        let mut free: Vec<TouchPoint> = self.touches.values()
+            .filter(|t| !claimed.contains(&t.id))
+            .copied()
+            .collect();
+        free.sort_by_key(|t| t.id);
+
+        if free.len() == 1 {
+            let t = free[0];
+            if let Some(prev) = self.touch_prev.get(&t.id) {
+                let delta = [t.pos[0] - prev[0], t.pos[1] - prev[1]];
+                if delta != [0.0, 0.0] {
+                    self.gestures.push(Gesture::Drag { pos: t.pos, delta });
+                }
+            }
+        }
+
+        if free.len() == 2 {
+            let d = dist(free[0].pos, free[1].pos);
+            let center = [(free[0].pos[0] + free[1].pos[0]) * 0.5, (free[0].pos[1] + free[1].pos[1]) * 0.5];
+            if let Some(prev) = self.pinch_prev {
+                if prev > 0.0 && d != prev {
+                    self.gestures.push(Gesture::Pinch { center, scale: d / prev });
+                }
+            }
+            self.pinch_prev = Some(d);
+        } else {
+            self.pinch_prev = None;
+        }
+
+        for t in &free {
+            self.touch_prev.insert(t.id, t.pos);
+        }
+    }
+
+    //
+    //  call once per frame after the game has read the input
+    //
+    pub fn end_frame(&mut self) {
+        self.pad_events.clear();
+        self.pressed.clear();
+        self.released.clear();
+        self.cursor_delta = [0.0, 0.0];
+        self.wheel = [0.0, 0.0];
+        self.touches_started.clear();
+        self.touches_ended.clear();
+    }
+
+    fn set(&mut self, binding: Binding, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                // the press that picks a binding does nothing else, it is held so
+                // polled gamepad buttons do not see it as a new press next frame
+                if let Some(action) = self.rebinding.take() {
+                    self.actions.rebind(&action, binding);
+                    self.held.insert(binding);
+                    return;
+                }
+
+                if self.held.insert(binding) {
+                    self.pressed.insert(binding);
+                }
+            }
+            ElementState::Released => {
+                if self.held.remove(&binding) {
+                    self.released.insert(binding);
+                }
+            }
+        }
+    }
+
+
+
+    pub fn key_pressed(&self, key: KeyCode) -> bool {
+        self.pressed.contains(&Binding::Key(key))
+    }
+
+    pub fn key_held(&self, key: KeyCode) -> bool {
+        self.held.contains(&Binding::Key(key))
+    }
+
+    pub fn key_released(&self, key: KeyCode) -> bool {
+        self.released.contains(&Binding::Key(key))
+    }
+
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        self.pressed.contains(&Binding::Mouse(button))
+    }
+
+    pub fn mouse_held(&self, button: MouseButton) -> bool {
+        self.held.contains(&Binding::Mouse(button))
+    }
+
+    pub fn mouse_released(&self, button: MouseButton) -> bool {
+        self.released.contains(&Binding::Mouse(button))
+    }
+
+    pub fn cursor(&self) -> [f32; 2] {
+        self.cursor
+    }
+
+    pub fn cursor_delta(&self) -> [f32; 2] {
+        self.cursor_delta
+    }
+
+    // in lines, positive y is scrolling up
+    pub fn wheel(&self) -> [f32; 2] {
+        self.wheel
+    }
+
+    pub fn touches(&self) -> impl Iterator<Item = &TouchPoint> {
+        self.touches.values()
+    }
+
+    pub fn touch_started(&self) -> &[u64] {
+        &self.touches_started
+    }
+
+    pub fn touch_ended(&self) -> &[TouchPoint] {
+        &self.touches_ended
+    }
+
+
+
+    //
+    //  the part of the input gameplay reads, recorded for replays
+    //
+    pub fn snapshot(&self, actions: &[&str], axes: &[&str], pointer: [f32; 2]) -> InputFrame {
+        InputFrame {
+            pressed: actions.iter().filter(|a| self.action_pressed(a)).map(|a| a.to_string()).collect(),
+            held: actions.iter().filter(|a| self.action_held(a)).map(|a| a.to_string()).collect(),
+            axes: axes.iter().map(|a| (a.to_string(), self.axis(a))).collect(),
+            pointer,
+        }
+    }
+
+    pub fn gestures(&self) -> &[Gesture] {
+        &self.gestures
+    }
+
+    pub fn gamepad_events(&self) -> &[GamepadEvent] {
+        &self.pad_events
+    }
+
+    pub fn gamepads(&self) -> impl Iterator<Item = &GamepadState> {
+        self.gamepads.iter().flat_map(|g| g.pads())
+    }
+
+    // strongest value over all connected pads, no dead zone
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.pad_axes[axis as usize]
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| self.pressed.contains(b))
+    }
+
+    pub fn action_held(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| self.held.contains(b))
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| self.released.contains(b))
+            && !self.action_held(action)
+    }
+
+    //
+    //  sum of all bindings clamped to -1..1
+    //
+    pub fn axis(&self, axis: &str) -> f32 {
+        let list = match self.actions.axes.get(axis) {
+            Some(l) => l,
+            None => return 0.0,
+        };
+
+        let mut v = 0.0;
+        for b in list {
+            v += match b {
+                AxisBinding::Keys { negative, positive } => {
+                    let mut k = 0.0;
+                    if self.held.contains(negative) { k -= 1.0; }
+                    if self.held.contains(positive) { k += 1.0; }
+                    k
+                }
+                AxisBinding::WheelX => self.wheel[0],
+                AxisBinding::WheelY => self.wheel[1],
+                AxisBinding::Gamepad { axis, dead_zone } => apply_dead_zone(self.gamepad_axis(*axis), *dead_zone),
+                AxisBinding::Stick { index, vertical } => match self.sticks.get(*index) {
+                    Some(s) => if *vertical { s.value[1] } else { s.value[0] },
+                    None => 0.0,
+                },
+            };
+        }
+
+        v.clamp(-1.0, 1.0)
+    }
+
+    //
+    //  the next key or mouse button pressed replaces the action bindings
+    //
+    pub fn start_rebind(&mut self, action: &str) {
+        self.rebinding = Some(action.to_string());
+    }
+
+    pub fn is_rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+
+    fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.actions.get(action).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+}
+
+
+fn dist(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+
+//
+//  bindings used by the game when nothing was saved
+//
+pub fn default_actions() -> ActionMap {
+    let mut map = ActionMap::new();
+
+    map.bind("jump", Binding::Key(KeyCode::Space));
+    map.bind("jump", Binding::Key(KeyCode::KeyW));
+    map.bind("jump", Binding::Gamepad(GamepadButton::South));
+    map.bind("spawn", Binding::Mouse(MouseButton::Right));
+    map.bind("spawn", Binding::Gamepad(GamepadButton::West));
+
+    map.bind_axis("move_x", AxisBinding::Keys {
+        negative: Binding::Key(KeyCode::KeyA),
+        positive: Binding::Key(KeyCode::KeyD),
+    });
+    map.bind_axis("move_x", AxisBinding::Keys {
+        negative: Binding::Key(KeyCode::ArrowLeft),
+        positive: Binding::Key(KeyCode::ArrowRight),
+    });
+    map.bind_axis("move_x", AxisBinding::Gamepad { axis: GamepadAxis::LeftX, dead_zone: 0.2 });
+    map.bind_axis("move_x", AxisBinding::Stick { index: 0, vertical: false });
+
+    map
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{dpi::PhysicalPosition, event::DeviceId};
+
+    fn device() -> DeviceId {
+        unsafe { DeviceId::dummy() }
+    }
+
+    fn click(state: ElementState) -> WindowEvent {
+        WindowEvent::MouseInput { device_id: device(), state, button: MouseButton::Left }
+    }
+
+    fn input() -> Input {
+        let mut actions = ActionMap::new();
+        actions.bind("fire", Binding::Mouse(MouseButton::Left));
+        actions.bind("jump", Binding::Key(KeyCode::Space));
+        // no gamepads in tests
+        Input { actions, ..Default::default() }
+    }
+
+    #[test]
+    fn captured_presses_are_dropped_releases_are_not() {
+        let mut input = input();
+        input.handle_captured_event(&click(ElementState::Pressed));
+        assert!(!input.action_held("fire"));
+
+        // pressed in the game, released over the gui
+        input.handle_event(&click(ElementState::Pressed));
+        assert!(input.action_pressed("fire"));
+        input.end_frame();
+        input.handle_captured_event(&click(ElementState::Released));
+        assert!(!input.action_held("fire"));
+        assert!(input.action_released("fire"));
+    }
+
+    #[test]
+    fn captured_cursor_and_focus_still_count() {
+        let mut input = input();
+        input.handle_captured_event(&WindowEvent::CursorMoved { device_id: device(), position: PhysicalPosition::new(30.0, 40.0) });
+        assert_eq!(input.cursor(), [30.0, 40.0]);
+        assert_eq!(input.cursor_delta(), [30.0, 40.0]);
+
+        input.set(Binding::Key(KeyCode::Space), ElementState::Pressed);
+        input.handle_captured_event(&WindowEvent::Focused(false));
+        assert!(!input.action_held("jump"));
+        assert!(input.action_released("jump"));
+
+        input.handle_captured_event(&WindowEvent::MouseWheel {
+            device_id: device(),
+            delta: MouseScrollDelta::LineDelta(0.0, 1.0),
+            phase: TouchPhase::Moved,
+        });
+        assert_eq!(input.wheel(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn the_rebinding_press_does_not_fire() {
+        let mut input = input();
+        input.start_rebind("jump");
+        input.set(Binding::Key(KeyCode::KeyJ), ElementState::Pressed);
+        assert!(!input.is_rebinding());
+        assert_eq!(input.actions.actions["jump"], [Binding::Key(KeyCode::KeyJ)]);
+        assert!(!input.action_pressed("jump"));
+
+        input.end_frame();
+        input.set(Binding::Key(KeyCode::KeyJ), ElementState::Released);
+        input.end_frame();
+        input.set(Binding::Key(KeyCode::KeyJ), ElementState::Pressed);
+        assert!(input.action_pressed("jump"));
+    }
+
+    #[test]
+    fn the_rebinding_gamepad_press_does_not_fire() {
+        let mut input = input();
+        let mut down = [false; BUTTONS.len()];
+        input.start_rebind("jump");
+
+        // polled every frame while the button stays down
+        down[GamepadButton::South as usize] = true;
+        for _ in 0..3 {
+            input.pad_buttons(&down);
+            assert!(!input.action_pressed("jump"));
+            input.end_frame();
+        }
+        assert_eq!(input.actions.actions["jump"], [Binding::Gamepad(GamepadButton::South)]);
+
+        down[GamepadButton::South as usize] = false;
+        input.pad_buttons(&down);
+        input.end_frame();
+        down[GamepadButton::South as usize] = true;
+        input.pad_buttons(&down);
+        assert!(input.action_pressed("jump"));
+    }
+}
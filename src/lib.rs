@@ -1,24 +1,57 @@
 #![allow(warnings)]
 
+#[cfg(feature = "client")]
 use wasm_bindgen::prelude::*;
 
-extern crate console_log;
 extern crate log;
 use log::{debug, error, info, warn};
 
+#[cfg(feature = "client")]
+extern crate console_log;
+#[cfg(feature = "client")]
 extern crate console_error_panic_hook;
+#[cfg(feature = "client")]
 extern crate pollster;
+#[cfg(feature = "client")]
 extern crate winit;
 
+pub mod physics;
+pub mod components;
+pub mod rng;
+pub mod replay;
+pub mod sim;
+pub mod protocol;
+pub mod transport;
+pub mod netsim;
+pub mod net;
+pub mod snapshot;
+pub mod prediction;
+pub mod lobby;
+pub mod server;
+pub mod audio;
+pub mod synth;
+pub mod spatial;
+pub mod music;
+pub mod assets;
+pub mod bundle;
+pub mod scene;
+pub mod prefab;
+pub mod save;
+pub mod tiled;
+
+// everything below needs a window, the server builds with --no-default-features
+#[cfg(feature = "client")]
 mod game;
+#[cfg(feature = "client")]
 use game::*;
 
 
+#[cfg(feature = "client")]
 #[wasm_bindgen(start)]
 pub async fn main() -> Result<(), JsValue> {
 
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-        console_log::init_with_level(log::Level::Info);
+        init_logger(log::Level::Info);
 
         info!("main is run");
         let main_loop = winit::event_loop::EventLoop::new().unwrap();
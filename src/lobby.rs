@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::*;
+use crate::sim::*;
+use crate::snapshot::*;
+
+
+//
+//  rooms and matchmaking, owned by the server
+//
+//  a room waits in the lobby until the host starts it with everyone ready, then gets its own Sim
+//  the host is whoever joined first, when the host leaves the next player takes over
+//  FindMatch queues a client until `size` clients want the same size, they get a room that starts at once
+//
+//  handle() / leave() return the messages to send, (client id, message)
+//
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub id: u32,
+    pub name: String,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub id: u32,
+    pub name: String,
+    pub max_players: u8,
+    pub host: u32,
+    pub players: Vec<PlayerInfo>,
+    pub playing: bool,
+}
+
+
+pub struct Room {
+    pub id: u32,
+    pub name: String,
+    pub max_players: u8,
+    pub host: u32,
+    pub players: Vec<PlayerInfo>,
+    // Some while the game runs
+    pub sim: Option<Sim>,
+    pub history: SnapshotHistory,
+    seed: u64,
+}
+
+impl Room {
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            id: self.id,
+            name: self.name.clone(),
+            max_players: self.max_players,
+            host: self.host,
+            players: self.players.clone(),
+            playing: self.sim.is_some(),
+        }
+    }
+
+    pub fn has(&self, client: u32) -> bool {
+        self.players.iter().any(|p| p.id == client)
+    }
+
+    fn start(&mut self) {
+        let mut sim = Sim::new(self.seed);
+        for p in &self.players {
+            sim.add_player(p.id);
+        }
+        self.sim = Some(sim);
+        self.history = SnapshotHistory::new(SNAPSHOT_HISTORY);
+    }
+
+    fn update(&self) -> Vec<(u32, Message)> {
+        self.players.iter().map(|p| (p.id, Message::RoomUpdate(self.info()))).collect()
+    }
+}
+
+
+
+pub struct Lobby {
+    pub rooms: Vec<Room>,
+    // (client, match size, player name)
+    queue: Vec<(u32, u8, String)>,
+    seed: u64,
+    next_room: u32,
+}
+
+impl Lobby {
+
+    pub fn new(seed: u64) -> Self {
+        Lobby {
+            rooms: vec![],
+            queue: vec![],
+            seed,
+            next_room: 1,
+        }
+    }
+
+    pub fn room_of(&self, client: u32) -> Option<&Room> {
+        self.rooms.iter().find(|r| r.has(client))
+    }
+
+    pub fn queued(&self, client: u32) -> bool {
+        self.queue.iter().any(|(c, _, _)| *c == client)
+    }
+
+
+    //
+    //  lobby messages from a client, anything else is ignored
+    //
+    pub fn handle(&mut self, client: u32, name: &str, message: &Message) -> Vec<(u32, Message)> {
+        let error = |reason: &str| vec![(client, Message::LobbyError { reason: reason.to_string() })];
+
+        match message {
+            Message::ListRooms => {
+                let rooms = self.rooms.iter().map(|r| r.info()).collect();
+                vec![(client, Message::RoomList { rooms })]
+            }
+
+            Message::CreateRoom { name: room_name, max_players } => {
+                if self.room_of(client).is_some() {
+                    return error("already in a room");
+                }
+                self.cancel(client);
+
+                let id = self.create(room_name, (*max_players).max(1), vec![PlayerInfo { id: client, name: name.to_string(), ready: false }]);
+                self.room(id).update()
+            }
+
+            Message::JoinRoom { room } => {
+                if self.room_of(client).is_some() {
+                    return error("already in a room");
+                }
+
+                let r = match self.rooms.iter_mut().find(|r| r.id == *room) {
+                    Some(r) => r,
+                    None => return error("no such room"),
+                };
+                if r.sim.is_some() {
+                    return error("game already started");
+                }
+                if r.players.len() >= r.max_players as usize {
+                    return error("room is full");
+                }
+
+                r.players.push(PlayerInfo { id: client, name: name.to_string(), ready: false });
+                let out = r.update();
+                self.cancel(client);
+                out
+            }
+
+            Message::LeaveRoom => {
+                let mut out = self.leave(client);
+                out.push((client, Message::RoomList { rooms: self.rooms.iter().map(|r| r.info()).collect() }));
+                out
+            }
+
+            Message::SetReady { ready } => {
+                match self.rooms.iter_mut().find(|r| r.has(client)) {
+                    Some(r) => {
+                        for p in r.players.iter_mut().filter(|p| p.id == client) {
+                            p.ready = *ready;
+                        }
+                        r.update()
+                    }
+                    None => error("not in a room"),
+                }
+            }
+
+            Message::StartGame => {
+                let r = match self.rooms.iter_mut().find(|r| r.has(client)) {
+                    Some(r) => r,
+                    None => return error("not in a room"),
+                };
+                if r.host != client {
+                    return error("only the host can start");
+                }
+                if r.sim.is_some() {
+                    return error("game already started");
+                }
+                if r.players.iter().any(|p| p.id != r.host && !p.ready) {
+                    return error("not everyone is ready");
+                }
+
+                r.start();
+                Lobby::started(r)
+            }
+
+            Message::FindMatch { size } => {
+                if self.room_of(client).is_some() {
+                    return error("already in a room");
+                }
+                let size = (*size).max(1);
+
+                self.cancel(client);
+                self.queue.push((client, size, name.to_string()));
+                self.matchmake(size)
+            }
+
+            Message::CancelMatch => {
+                self.cancel(client);
+                vec![]
+            }
+
+            _ => vec![],
+        }
+    }
+
+
+    //
+    //  client left the room or disconnected
+    //
+    pub fn leave(&mut self, client: u32) -> Vec<(u32, Message)> {
+        self.cancel(client);
+
+        let i = match self.rooms.iter().position(|r| r.has(client)) {
+            Some(i) => i,
+            None => return vec![],
+        };
+
+        let r = &mut self.rooms[i];
+        r.players.retain(|p| p.id != client);
+        if let Some(sim) = r.sim.as_mut() {
+            sim.remove_player(client);
+        }
+
+        if r.players.is_empty() {
+            self.rooms.remove(i);
+            return vec![];
+        }
+
+        // host migration
+        if r.host == client {
+            r.host = r.players[0].id;
+        }
+        r.update()
+    }
+
+
+    fn create(&mut self, name: &str, max_players: u8, players: Vec<PlayerInfo>) -> u32 {
+        let id = self.next_room;
+        self.next_room += 1;
+
+        self.rooms.push(Room {
+            id,
+            name: name.to_string(),
+            max_players,
+            host: players[0].id,
+            players,
+            sim: None,
+            history: SnapshotHistory::new(SNAPSHOT_HISTORY),
+            // every room gets its own deterministic seed
+            seed: self.seed.wrapping_add(id as u64),
+        });
+
+        id
+    }
+
+    fn room(&self, id: u32) -> &Room {
+        self.rooms.iter().find(|r| r.id == id).unwrap()
+    }
+
+    fn cancel(&mut self, client: u32) {
+        self.queue.retain(|(c, _, _)| *c != client);
+    }
+
+    //
+    //  first come first served, once `size` clients wait for the same size
+    //
+    fn matchmake(&mut self, size: u8) -> Vec<(u32, Message)> {
+        let players: Vec<PlayerInfo> = self.queue.iter()
+            .filter(|(_, s, _)| *s == size)
+            .take(size as usize)
+            .map(|(c, _, name)| PlayerInfo { id: *c, name: name.clone(), ready: true })
+            .collect();
+        if players.len() < size as usize {
+            return vec![];
+        }
+
+        for p in &players {
+            self.cancel(p.id);
+        }
+
+        let id = self.create(&format!("match {}", self.next_room), size, players);
+
+        let r = self.rooms.iter_mut().find(|r| r.id == id).unwrap();
+        r.start();
+        Lobby::started(r)
+    }
+
+    fn started(r: &Room) -> Vec<(u32, Message)> {
+        let mut out = r.update();
+        for p in &r.players {
+            out.push((p.id, Message::GameStarted { room: r.id }));
+        }
+        out
+    }
+}
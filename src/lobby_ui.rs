@@ -0,0 +1,126 @@
+use log::warn;
+
+use crate::lobby::*;
+use crate::protocol::*;
+
+use super::gui::*;
+
+
+//
+//  client side of the lobby: what the server told us + the lobby panel
+//
+
+
+#[derive(Default)]
+pub struct LobbyView {
+    // our client id, from Welcome
+    pub id: Option<u32>,
+    pub room: Option<RoomInfo>,
+    pub rooms: Vec<RoomInfo>,
+    pub searching: bool,
+    ready: bool,
+    error: String,
+}
+
+impl LobbyView {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //
+    //  true when the message was a lobby message
+    //
+    pub fn handle(&mut self, message: &Message) -> bool {
+        match message {
+            Message::Welcome { id } => self.id = Some(*id),
+            Message::RoomList { rooms } => self.rooms = rooms.clone(),
+            Message::RoomUpdate(info) => {
+                self.searching = false;
+                self.ready = info.players.iter().any(|p| Some(p.id) == self.id && p.ready);
+                self.room = Some(info.clone());
+            }
+            Message::GameStarted { .. } => self.error.clear(),
+            Message::LobbyError { reason } => {
+                warn!("lobby: {}", reason);
+                self.error = reason.clone();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn host(&self) -> bool {
+        self.room.as_ref().is_some_and(|r| Some(r.host) == self.id)
+    }
+
+    //
+    //  room list / room panel, returns the messages to send
+    //
+    pub fn ui(&mut self, gui: &mut GUI, screen: (f32, f32)) -> Vec<Message> {
+        let mut out = vec![];
+        let host = self.host();
+
+        gui.panel("lobby", Rect::new(screen.0 - 228.0, 8.0, 220.0, 260.0), |ui| {
+            match self.room.as_ref() {
+                Some(r) => {
+                    ui.label(&format!("{} ({}/{})", r.name, r.players.len(), r.max_players));
+                    ui.separator();
+                    for p in &r.players {
+                        let mark = if p.id == r.host { "* " } else { "" };
+                        let state = if p.ready { " ready" } else { "" };
+                        ui.label(&format!("{}{}{}", mark, p.name, state));
+                    }
+                    ui.separator();
+
+                    if ui.checkbox("ready", &mut self.ready) {
+                        out.push(Message::SetReady { ready: self.ready });
+                    }
+                    if host && ui.button("start") {
+                        out.push(Message::StartGame);
+                    }
+                    if ui.button("leave") {
+                        out.push(Message::LeaveRoom);
+                    }
+                }
+
+                None => {
+                    let search = if self.searching { "cancel search" } else { "find match" };
+                    if ui.button(search) {
+                        out.push(if self.searching { Message::CancelMatch } else { Message::FindMatch { size: 2 } });
+                        self.searching = !self.searching;
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("create room") {
+                            out.push(Message::CreateRoom { name: "room".to_string(), max_players: 4 });
+                        }
+                        if ui.button("refresh") {
+                            out.push(Message::ListRooms);
+                        }
+                    });
+                    ui.separator();
+
+                    ui.scroll_area("rooms", 120.0, |ui| {
+                        for r in &self.rooms {
+                            let label = format!("join {} #{} ({}/{})", r.name, r.id, r.players.len(), r.max_players);
+                            if ui.button(&label) {
+                                out.push(Message::JoinRoom { room: r.id });
+                            }
+                        }
+                    });
+                }
+            }
+
+            if !self.error.is_empty() {
+                ui.label(&self.error);
+            }
+        });
+
+        // the room is gone for us as soon as we ask to leave
+        if out.contains(&Message::LeaveRoom) {
+            self.room = None;
+        }
+
+        out
+    }
+}
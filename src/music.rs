@@ -0,0 +1,696 @@
+use std::{cell::RefCell, rc::Rc};
+
+use log::info;
+#[cfg(not(target_arch = "wasm32"))]
+use log::warn;
+
+use crate::audio::*;
+use crate::rng::*;
+
+
+//
+//  streamed music: a track is a MusicSource that decodes a few thousand frames at a time around
+//  the play position, so a long wav never sits in memory as f32, and drops the bytes it played
+//  unless the loop comes back to them. the player crossfades between tracks, loops between loop
+//  points, walks a playlist, and ducks under dialogue / important sfx (Mixer::ducking)
+//
+//  Audio::update mixes it into the Music bus, after the mixer's voices
+//
+
+
+// frames decoded per read
+const CHUNK: usize = 4096;
+
+
+pub trait MusicSource {
+    fn rate(&self) -> u32;
+    // total frames, None while still arriving with an unknown length
+    fn total_frames(&self) -> Option<usize>;
+    //
+    //  stereo frames starting at `pos`, returns how many there were
+    //  fewer than asked means the end, or that the rest did not arrive yet
+    //
+    fn read(&mut self, pos: usize, out: &mut [f32]) -> usize;
+
+    // the first frame read() can return, above 0 once a stream dropped its start for good
+    fn first_frame(&self) -> usize {
+        0
+    }
+
+    // frames before `before` are not needed anymore
+    fn discard(&mut self, _before: usize) {}
+}
+
+
+// shared with whatever is still pushing into it, e.g. a download
+impl<S: MusicSource> MusicSource for Rc<RefCell<S>> {
+    fn rate(&self) -> u32 {
+        self.borrow().rate()
+    }
+
+    fn total_frames(&self) -> Option<usize> {
+        self.borrow().total_frames()
+    }
+
+    fn read(&mut self, pos: usize, out: &mut [f32]) -> usize {
+        self.borrow_mut().read(pos, out)
+    }
+
+    fn first_frame(&self) -> usize {
+        self.borrow().first_frame()
+    }
+
+    fn discard(&mut self, before: usize) {
+        self.borrow_mut().discard(before)
+    }
+}
+
+
+// already decoded, e.g. whatever the browser decoded for us
+impl MusicSource for Sound {
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn total_frames(&self) -> Option<usize> {
+        Some(self.frames())
+    }
+
+    fn read(&mut self, pos: usize, out: &mut [f32]) -> usize {
+        let n = (out.len() / 2).min(self.frames().saturating_sub(pos));
+        for i in 0..n {
+            let c = self.channels as usize;
+            let l = self.samples[(pos + i) * c];
+            let r = self.samples[(pos + i) * c + c - 1];
+            out[i * 2] = l;
+            out[i * 2 + 1] = r;
+        }
+        n
+    }
+}
+
+
+
+//
+//  wav kept as raw bytes and decoded on read. bytes come from push(), or are pulled from a file
+//  on native as playback gets to them. discard() drops the played ones, a file seeks back for
+//  them, pushed bytes are gone
+//
+pub struct WavStream {
+    // the wav from byte `offset` on
+    bytes: Vec<u8>,
+    offset: usize,
+    header: Option<WavHeader>,
+    complete: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    file: Option<std::fs::File>,
+}
+
+impl Default for WavStream {
+    fn default() -> Self {
+        WavStream::new()
+    }
+}
+
+impl WavStream {
+
+    pub fn new() -> Self {
+        WavStream {
+            bytes: vec![],
+            offset: 0,
+            header: None,
+            complete: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            file: None,
+        }
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        let mut s = WavStream::new();
+        s.push(&bytes)?;
+        s.finish();
+        if s.header.is_none() {
+            anyhow::bail!("wav has no data chunk");
+        }
+        Ok(s)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let mut s = WavStream::new();
+        s.file = Some(std::fs::File::open(path)?);
+
+        while s.header.is_none() && !s.complete {
+            s.pull(s.bytes.len() + CHUNK)?;
+        }
+        if s.header.is_none() {
+            anyhow::bail!("{}: wav has no data chunk", path);
+        }
+        Ok(s)
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.bytes.extend_from_slice(data);
+        if self.header.is_none() {
+            self.header = WavHeader::parse(&self.bytes)?;
+        }
+        Ok(())
+    }
+
+    // no more bytes will come
+    pub fn finish(&mut self) {
+        self.complete = true;
+    }
+
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+
+    // None until enough bytes arrived to parse it
+    pub fn header(&self) -> Option<WavHeader> {
+        self.header
+    }
+
+    // frames up to which reading works right now
+    pub fn buffered(&self) -> usize {
+        match self.header {
+            Some(h) => {
+                let end = h.data_start.saturating_add(h.data_len).min(self.offset + self.bytes.len());
+                end.saturating_sub(h.data_start) / h.frame_bytes()
+            }
+            None => 0,
+        }
+    }
+
+    // bytes held in memory
+    pub fn held_bytes(&self) -> usize {
+        self.bytes.len()
+    }
+
+    // first frame still in `bytes`
+    fn kept(&self) -> usize {
+        match self.header {
+            Some(h) => self.offset.saturating_sub(h.data_start) / h.frame_bytes(),
+            None => 0,
+        }
+    }
+
+    //
+    //  read the file until it is in `bytes` up to byte `upto`
+    //
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pull(&mut self, upto: usize) -> anyhow::Result<()> {
+        use std::io::Read;
+
+        let file = match self.file.as_mut() {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+
+        let mut chunk = vec![0u8; CHUNK * 4];
+        let mut read = vec![];
+        while self.offset + self.bytes.len() + read.len() < upto {
+            let n = file.read(&mut chunk)?;
+            if n == 0 {
+                // stays open, a replay seeks back
+                self.complete = true;
+                break;
+            }
+            read.extend_from_slice(&chunk[..n]);
+        }
+        self.push(&read)
+    }
+
+    //
+    //  frame `pos` was dropped, start over from it
+    //
+    #[cfg(not(target_arch = "wasm32"))]
+    fn seek(&mut self, pos: usize) -> anyhow::Result<()> {
+        use std::io::{Seek, SeekFrom};
+
+        if pos >= self.kept() {
+            return Ok(());
+        }
+        let (h, file) = match (self.header, self.file.as_mut()) {
+            (Some(h), Some(f)) => (h, f),
+            _ => return Ok(()),
+        };
+
+        let at = h.data_start + pos * h.frame_bytes();
+        file.seek(SeekFrom::Start(at as u64))?;
+        self.bytes.clear();
+        self.offset = at;
+        Ok(())
+    }
+}
+
+impl MusicSource for WavStream {
+
+    fn rate(&self) -> u32 {
+        self.header.map_or(MIX_RATE, |h| h.rate)
+    }
+
+    fn total_frames(&self) -> Option<usize> {
+        let h = self.header?;
+        if h.data_len != usize::MAX {
+            Some(h.data_len / h.frame_bytes())
+        } else if self.complete {
+            Some(self.buffered())
+        } else {
+            None
+        }
+    }
+
+    fn read(&mut self, pos: usize, out: &mut [f32]) -> usize {
+        let h = match self.header {
+            Some(h) => h,
+            None => return 0,
+        };
+        let want = out.len() / 2;
+        let fb = h.frame_bytes();
+
+        #[cfg(not(target_arch = "wasm32"))] {
+            if let Err(e) = self.seek(pos).and_then(|_| self.pull(h.data_start + (pos + want) * fb)) {
+                warn!("music stream: {}", e);
+                self.file = None;
+                self.complete = true;
+            }
+        }
+
+        if pos < self.kept() {
+            return 0;
+        }
+
+        let n = want.min(self.buffered().saturating_sub(pos));
+        let last = (h.channels as usize).min(2) - 1;
+        for i in 0..n {
+            let at = h.data_start + (pos + i) * fb - self.offset;
+            let frame = &self.bytes[at..at + fb];
+            out[i * 2] = h.sample(frame, 0);
+            out[i * 2 + 1] = h.sample(frame, last);
+        }
+        n
+    }
+
+    fn first_frame(&self) -> usize {
+        #[cfg(not(target_arch = "wasm32"))] {
+            if self.file.is_some() {
+                return 0;
+            }
+        }
+        self.kept()
+    }
+
+    fn discard(&mut self, before: usize) {
+        let h = match self.header {
+            Some(h) => h,
+            None => return,
+        };
+        let to = h.data_start.saturating_add(before.saturating_mul(h.frame_bytes()));
+        let n = to.saturating_sub(self.offset).min(self.bytes.len());
+
+        // a few frames at a time would move the rest of the buffer on every mix
+        if n < CHUNK * h.frame_bytes() {
+            return;
+        }
+        self.bytes.drain(..n);
+        self.offset += n;
+        if self.bytes.capacity() > self.bytes.len() * 2 {
+            self.bytes.shrink_to_fit();
+        }
+    }
+}
+
+
+
+pub struct Track {
+    pub name: String,
+    pub volume: f32,
+    // Some = loops back here after loop_end (or the end of the track), in frames
+    pub loop_start: Option<usize>,
+    pub loop_end: Option<usize>,
+    source: Box<dyn MusicSource>,
+}
+
+impl Track {
+
+    pub fn new(name: &str, source: impl MusicSource + 'static) -> Self {
+        Track {
+            name: name.to_string(),
+            volume: 1.0,
+            loop_start: None,
+            loop_end: None,
+            source: Box::new(source),
+        }
+    }
+
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume.max(0.0);
+        self
+    }
+
+    //
+    //  intro plays once, then start..end repeats
+    //
+    pub fn looping(mut self, start: usize, end: Option<usize>) -> Self {
+        self.loop_start = Some(start);
+        self.loop_end = end;
+        self
+    }
+
+    pub fn looped(&self) -> bool {
+        self.loop_start.is_some()
+    }
+
+    // seconds, None while unknown
+    pub fn duration(&self) -> Option<f64> {
+        self.source.total_frames().map(|n| n as f64 / self.source.rate() as f64)
+    }
+
+    fn loop_range(&self) -> Option<(usize, usize)> {
+        let start = self.loop_start?;
+        let end = self.loop_end.or(self.source.total_frames())?;
+        if end > start { Some((start, end)) } else { None }
+    }
+
+    fn wrap(&self, i: usize) -> usize {
+        match self.loop_range() {
+            Some((start, end)) if i >= end => start + (i - start) % (end - start),
+            _ => i,
+        }
+    }
+}
+
+
+// a track being played, two of them while crossfading
+struct Deck {
+    track: usize,
+    // source frames
+    pos: f64,
+    gain: f32,
+    // gain per second, negative fades out
+    fade: f32,
+    done: bool,
+    cache: Vec<f32>,
+    cache_start: usize,
+    cache_len: usize,
+}
+
+impl Deck {
+
+    fn new(track: usize, fade_seconds: f32) -> Self {
+        let (gain, fade) = if fade_seconds > 0.0 { (0.0, 1.0 / fade_seconds) } else { (1.0, 0.0) };
+        Deck { track, pos: 0.0, gain, fade, done: false, cache: vec![0.0; CHUNK * 2], cache_start: 0, cache_len: 0 }
+    }
+
+    fn frame(&mut self, track: &mut Track, i: usize) -> Option<(f32, f32)> {
+        let i = track.wrap(i);
+        if i < self.cache_start || i >= self.cache_start + self.cache_len {
+            self.cache_start = i;
+            self.cache_len = track.source.read(i, &mut self.cache);
+            if self.cache_len == 0 {
+                return None;
+            }
+        }
+        let at = (i - self.cache_start) * 2;
+        Some((self.cache[at], self.cache[at + 1]))
+    }
+}
+
+
+
+pub struct MusicPlayer {
+    // seconds
+    pub crossfade: f32,
+    // music gain while ducked, and how fast it gets there and back in seconds
+    pub duck_level: f32,
+    pub duck_attack: f32,
+    pub duck_release: f32,
+
+    // track indices
+    pub playlist: Vec<usize>,
+    pub repeat: bool,
+    // reshuffled every time the playlist wraps
+    pub shuffle: bool,
+
+    tracks: Vec<Track>,
+    current: Option<Deck>,
+    fading: Vec<Deck>,
+    index: usize,
+    duck: f32,
+    rng: Rng,
+}
+
+impl Default for MusicPlayer {
+    fn default() -> Self {
+        MusicPlayer::new()
+    }
+}
+
+impl MusicPlayer {
+
+    pub fn new() -> Self {
+        MusicPlayer {
+            crossfade: 2.0,
+            duck_level: 0.3,
+            duck_attack: 0.1,
+            duck_release: 0.8,
+            playlist: vec![],
+            repeat: true,
+            shuffle: false,
+            tracks: vec![],
+            current: None,
+            fading: vec![],
+            index: 0,
+            duck: 1.0,
+            rng: Rng::new(Rng::random_seed()),
+        }
+    }
+
+    pub fn add(&mut self, track: Track) -> usize {
+        self.tracks.push(track);
+        self.tracks.len() - 1
+    }
+
+    //
+    //  add to the end of the playlist, starts it when nothing plays
+    //
+    pub fn enqueue(&mut self, track: Track) -> usize {
+        let i = self.add(track);
+        self.playlist.push(i);
+        if self.current.is_none() {
+            self.index = self.playlist.len() - 1;
+            self.play(i);
+        }
+        i
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    // name of the playing track
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_ref().map(|d| self.tracks[d.track].name.as_str())
+    }
+
+    // seconds into the playing track
+    pub fn position(&self) -> Option<f64> {
+        self.current.as_ref().map(|d| d.pos / self.tracks[d.track].source.rate() as f64)
+    }
+
+    pub fn duck_gain(&self) -> f32 {
+        self.duck
+    }
+
+    //
+    //  crossfade to track `i`
+    //
+    pub fn play(&mut self, i: usize) {
+        if i >= self.tracks.len() || self.current.as_ref().is_some_and(|d| d.track == i) {
+            return;
+        }
+        if !self.playable(i) {
+            info!("music: {} was streamed and cannot play again", self.tracks[i].name);
+            return;
+        }
+        if let Some(p) = self.playlist.iter().position(|t| *t == i) {
+            self.index = p;
+        }
+
+        info!("music: {}", self.tracks[i].name);
+        self.fade_out();
+        self.current = Some(Deck::new(i, self.fade_time(i)));
+    }
+
+    pub fn play_list(&mut self, playlist: Vec<usize>, repeat: bool) {
+        self.playlist = playlist;
+        self.repeat = repeat;
+        self.index = 0;
+        if self.shuffle {
+            self.reshuffle();
+        }
+        if let Some(i) = self.playlist.first().copied() {
+            self.play(i);
+        }
+    }
+
+    pub fn next(&mut self) {
+        if self.playlist.is_empty() {
+            return;
+        }
+
+        // streamed tracks that dropped their start are skipped
+        for _ in 0..self.playlist.len() {
+            self.index += 1;
+            if self.index >= self.playlist.len() {
+                if !self.repeat {
+                    self.stop();
+                    return;
+                }
+                self.index = 0;
+                if self.shuffle {
+                    self.reshuffle();
+                }
+            }
+
+            let i = self.playlist[self.index];
+            if !self.playable(i) {
+                continue;
+            }
+
+            // a one track playlist restarts itself
+            if self.current.as_ref().is_some_and(|d| d.track == i) {
+                self.fade_out();
+                self.current = Some(Deck::new(i, self.fade_time(i)));
+            } else {
+                self.play(i);
+            }
+            return;
+        }
+
+        self.stop();
+    }
+
+    // fades out
+    pub fn stop(&mut self) {
+        self.fade_out();
+    }
+
+    fn playable(&self, i: usize) -> bool {
+        self.tracks[i].source.first_frame() == 0
+    }
+
+    fn fade_out(&mut self) {
+        if let Some(mut d) = self.current.take() {
+            let fade = self.fade_time(d.track);
+            d.fade = if fade > 0.0 { -1.0 / fade } else { 0.0 };
+            if d.fade == 0.0 {
+                return;
+            }
+            self.fading.push(d);
+        }
+    }
+
+    // the crossfade, cut to half of a shorter track so its fade in and out both fit
+    fn fade_time(&self, i: usize) -> f32 {
+        let fade = self.crossfade.max(0.0);
+        match self.tracks[i].duration() {
+            Some(d) => fade.min(d as f32 / 2.0),
+            None => fade,
+        }
+    }
+
+    fn reshuffle(&mut self) {
+        for i in (1..self.playlist.len()).rev() {
+            let j = (self.rng.next_u64() % (i as u64 + 1)) as usize;
+            self.playlist.swap(i, j);
+        }
+    }
+
+
+    //
+    //  adds the music to `out` (stereo at `rate`), gain is the Music bus times master
+    //
+    pub fn mix(&mut self, out: &mut [f32], rate: u32, gain: f32, ducking: bool) {
+        let frames = out.len() / 2;
+        let dt = 1.0 / rate.max(1) as f32;
+
+        // start the next track early enough to crossfade into it
+        if let Some(d) = self.current.as_ref() {
+            let t = &self.tracks[d.track];
+            if !t.looped() {
+                if let Some(len) = t.source.total_frames() {
+                    let left = (len as f64 - d.pos) / t.source.rate() as f64;
+                    if left <= self.fade_time(d.track) as f64 {
+                        self.next();
+                    }
+                }
+            }
+        }
+
+        // per frame duck gain, shared by every deck
+        let mut ducks = Vec::with_capacity(frames);
+        for _ in 0..frames {
+            let (target, time) = if ducking { (self.duck_level, self.duck_attack) } else { (1.0, self.duck_release) };
+            let speed = (1.0 - self.duck_level).abs() / time.max(0.001) * dt;
+            self.duck += (target - self.duck).clamp(-speed, speed);
+            ducks.push(self.duck);
+        }
+
+        let tracks = &mut self.tracks;
+        for d in self.current.iter_mut().chain(self.fading.iter_mut()) {
+            let track = &mut tracks[d.track];
+            let step = track.source.rate() as f64 / rate.max(1) as f64;
+
+            for (k, f) in out.chunks_exact_mut(2).enumerate() {
+                if let Some(len) = track.source.total_frames() {
+                    if !track.looped() && d.pos >= len as f64 {
+                        d.done = true;
+                        break;
+                    }
+                }
+
+                let i = d.pos as usize;
+                let t = (d.pos - i as f64) as f32;
+                let (l0, r0) = match d.frame(track, i) {
+                    Some(s) => s,
+                    // not downloaded yet, wait for it without moving on
+                    None => continue,
+                };
+                let (l1, r1) = d.frame(track, i + 1).unwrap_or((l0, r0));
+
+                let g = gain * track.volume * d.gain * ducks[k];
+                f[0] += (l0 + (l1 - l0) * t) * g;
+                f[1] += (r0 + (r1 - r0) * t) * g;
+
+                d.pos += step;
+                if let Some((start, end)) = track.loop_range() {
+                    if d.pos >= end as f64 {
+                        d.pos -= (end - start) as f64;
+                    }
+                }
+
+                d.gain = (d.gain + d.fade * dt).clamp(0.0, 1.0);
+                if d.fade < 0.0 && d.gain <= 0.0 {
+                    d.done = true;
+                    break;
+                }
+            }
+        }
+
+        // the frames every deck is past, and the loop does not come back to
+        for (i, track) in tracks.iter_mut().enumerate() {
+            let decks = self.current.iter().chain(self.fading.iter()).filter(|d| d.track == i && !d.done);
+            if let Some(pos) = decks.map(|d| d.pos as usize).min() {
+                track.source.discard(pos.min(track.loop_start.unwrap_or(usize::MAX)));
+            }
+        }
+
+        self.fading.retain(|d| !d.done);
+        if self.current.as_ref().is_some_and(|d| d.done) {
+            self.current = None;
+            self.next();
+        }
+    }
+}
@@ -1,6 +1,411 @@
+use std::collections::VecDeque;
 
+use log::{info, warn};
 
+use crate::protocol::*;
+use crate::rng::*;
+use crate::transport::*;
 
 
+//
+//  connection manager on top of a Transport (browser / native websocket, loopback)
+//  call update(now) once per frame, everything that happened comes back as NetEvent
+//
+//     Closed --connect--> Connecting --onopen--> Open --close--> Closing --onclose--> Closed
+//        ^                    |                    |
+//        +--- backoff <-------+---- onclose -------+
+//
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetState {
+    Connecting,
+    Open,
+    Closing,
+    Closed,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetEvent {
+    Connected,
+    Disconnected { code: u16, reason: String },
+    Reconnecting { attempt: u32, delay_ms: f64 },
+    Message(Message),
+    Error(String),
+}
+
+
+#[derive(Debug, Clone)]
+pub struct NetConfig {
+    pub url: String,
+    pub heartbeat_ms: f64,
+    // no message for this long means the connection is dead
+    pub timeout_ms: f64,
+    pub backoff_min_ms: f64,
+    pub backoff_max_ms: f64,
+    pub max_queue: usize,
+    pub reconnect: bool,
+}
+
+impl NetConfig {
+
+    pub fn new(url: &str) -> Self {
+        NetConfig {
+            url: url.to_string(),
+            heartbeat_ms: 2000.0,
+            timeout_ms: 10000.0,
+            backoff_min_ms: 500.0,
+            backoff_max_ms: 30000.0,
+            max_queue: 256,
+            reconnect: true,
+        }
+    }
+
+    pub fn heartbeat(mut self, ms: f64) -> Self {
+        self.heartbeat_ms = ms;
+        self
+    }
+
+    pub fn timeout(mut self, ms: f64) -> Self {
+        self.timeout_ms = ms;
+        self
+    }
+
+    pub fn backoff(mut self, min_ms: f64, max_ms: f64) -> Self {
+        self.backoff_min_ms = min_ms;
+        self.backoff_max_ms = max_ms.max(min_ms);
+        self
+    }
+
+    pub fn max_queue(mut self, n: usize) -> Self {
+        self.max_queue = n;
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+}
+
+
+
+//
+//  per connection counters, kept across reconnects
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetStats {
+    pub rtt: Option<f64>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub pings: u64,
+    pub pongs: u64,
+    // heartbeats that never came back
+    pub lost: u64,
+}
+
+impl NetStats {
+    // fraction of heartbeats lost, a cheap estimate of packet loss
+    pub fn loss(&self) -> f32 {
+        let answered = self.pongs + self.lost;
+        if answered == 0 { 0.0 } else { self.lost as f32 / answered as f32 }
+    }
+}
+
+
+
+pub struct Net {
+    pub config: NetConfig,
+    state: NetState,
+    connector: Connector,
+    // dropping a transport drops its pending events, so a stale socket never leaks into a new one
+    socket: Option<Box<dyn Transport>>,
+
+    // encoded messages waiting for the socket to open
+    queue: VecDeque<Vec<u8>>,
+    // a connection dropped, what is sent until the next one opens belongs to the old session
+    lost: bool,
+    // close() was called, do not reconnect
+    manual_close: bool,
+    attempt: u32,
+    retry_at: Option<f64>,
+    rng: Rng,
+
+    now: f64,
+    connect_started: f64,
+    last_ping: f64,
+    last_recv: f64,
+    // send times of the pings without a pong yet
+    pings: Vec<f64>,
+    stats: NetStats,
+}
+
+impl Net {
+
+    pub fn new(config: NetConfig) -> Self {
+        Net::with_connector(config, Box::new(|url: &str| connect(url)))
+    }
+
+    //
+    //  custom transports, e.g. LoopbackListener::connector() in tests
+    //
+    pub fn with_connector(config: NetConfig, connector: Connector) -> Self {
+        Net {
+            config,
+            state: NetState::Closed,
+            connector,
+            socket: None,
+            queue: VecDeque::new(),
+            lost: false,
+            manual_close: false,
+            attempt: 0,
+            retry_at: None,
+            rng: Rng::new(Rng::random_seed()),
+            now: 0.0,
+            connect_started: 0.0,
+            last_ping: 0.0,
+            last_recv: 0.0,
+            pings: vec![],
+            stats: NetStats::default(),
+        }
+    }
+
+    pub fn state(&self) -> NetState {
+        self.state
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == NetState::Open
+    }
+
+    // round trip time of the last heartbeat in ms
+    pub fn rtt(&self) -> Option<f64> {
+        self.stats.rtt
+    }
+
+    pub fn stats(&self) -> NetStats {
+        self.stats
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn connect(&mut self) {
+        self.manual_close = false;
+        self.lost = false;
+        self.retry_at = None;
+        if self.socket.is_none() {
+            self.open_socket(&mut vec![]);
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.manual_close = true;
+        self.retry_at = None;
+
+        match self.socket.as_mut() {
+            Some(s) => {
+                s.close();
+                self.state = NetState::Closing;
+            }
+            None => self.state = NetState::Closed,
+        }
+    }
+
+    //
+    //  sent right away when open, otherwise queued until the next open. after a
+    //  disconnect nothing is queued, the server would take old inputs and acks as new
+    //  ones, so resend what the new session needs once Connected arrives
+    //
+    pub fn send(&mut self, message: &Message) {
+        let data = encode(message);
+
+        if self.state == NetState::Open {
+            if let Some(s) = self.socket.as_mut() {
+                if s.send(&data) {
+                    self.stats.bytes_out += data.len() as u64;
+                    self.stats.messages_out += 1;
+                    return;
+                }
+            }
+        }
+
+        if self.lost {
+            return;
+        }
+
+        if self.queue.len() >= self.config.max_queue {
+            warn!("net send queue is full, dropping the oldest message");
+            self.queue.pop_front();
+        }
+        self.queue.push_back(data);
+    }
+
+
+    pub fn update(&mut self, now: f64) -> Vec<NetEvent> {
+        self.now = now;
+        let mut events = vec![];
+
+        let polled = match self.socket.as_mut() {
+            Some(s) => {
+                s.set_time(now);
+                s.poll()
+            }
+            None => vec![],
+        };
+
+        for e in polled {
+            match e {
+                TransportEvent::Open => {
+                    info!("connected to {}", self.config.url);
+                    self.state = NetState::Open;
+                    self.lost = false;
+                    self.attempt = 0;
+                    self.last_recv = now;
+                    self.last_ping = now;
+                    self.pings.clear();
+                    self.flush();
+                    events.push(NetEvent::Connected);
+                }
+
+                TransportEvent::Data(data) => {
+                    self.last_recv = now;
+                    self.stats.bytes_in += data.len() as u64;
+                    self.stats.messages_in += 1;
+                    match decode(&data) {
+                        Ok(Message::Ping { time }) => self.send(&Message::Pong { time }),
+                        Ok(Message::Pong { time }) => self.pong(time),
+                        Ok(m) => events.push(NetEvent::Message(m)),
+                        Err(err) => warn!("dropped message: {}", err),
+                    }
+                }
+
+                TransportEvent::Text(text) => {
+                    self.last_recv = now;
+                    events.push(NetEvent::Message(Message::Text(text)));
+                }
+
+                TransportEvent::Close(code, reason) => {
+                    self.drop_socket();
+                    events.push(NetEvent::Disconnected { code, reason });
+                    self.schedule_retry(&mut events);
+                }
+
+                // transports always follow an error with close
+                TransportEvent::Error(err) => events.push(NetEvent::Error(err)),
+            }
+        }
+
+        match self.state {
+            NetState::Open => {
+                if now - self.last_recv > self.config.timeout_ms {
+                    warn!("connection to {} timed out", self.config.url);
+                    self.drop_socket();
+                    events.push(NetEvent::Disconnected { code: 4000, reason: "timed out".to_string() });
+                    self.schedule_retry(&mut events);
+                } else if now - self.last_ping >= self.config.heartbeat_ms {
+                    self.last_ping = now;
+                    self.pings.push(now);
+                    self.stats.pings += 1;
+                    self.send(&Message::Ping { time: now });
+                }
+            }
+
+            NetState::Connecting => {
+                if now - self.connect_started > self.config.timeout_ms {
+                    warn!("connecting to {} timed out", self.config.url);
+                    self.drop_socket();
+                    self.schedule_retry(&mut events);
+                }
+            }
+
+            NetState::Closed => {
+                if let Some(at) = self.retry_at {
+                    if now >= at {
+                        self.retry_at = None;
+                        self.open_socket(&mut events);
+                    }
+                }
+            }
+
+            NetState::Closing => (),
+        }
+
+        events
+    }
+
+
+    fn open_socket(&mut self, events: &mut Vec<NetEvent>) {
+        self.connect_started = self.now;
+
+        match (self.connector)(&self.config.url) {
+            Ok(s) => {
+                self.socket = Some(s);
+                self.state = NetState::Connecting;
+            }
+            Err(err) => {
+                events.push(NetEvent::Error(err));
+                self.schedule_retry(events);
+            }
+        }
+    }
+
+    //
+    //  messages arrive in order, so every ping sent before this one that is still waiting was lost
+    //
+    fn pong(&mut self, time: f64) {
+        let i = match self.pings.iter().position(|t| *t == time) {
+            Some(i) => i,
+            // duplicate or from an older connection
+            None => return,
+        };
+
+        self.stats.lost += i as u64;
+        self.stats.pongs += 1;
+        self.stats.rtt = Some(self.now - time);
+        self.pings.drain(..=i);
+    }
+
+    fn drop_socket(&mut self) {
+        self.socket = None;
+        self.queue.clear();
+        self.lost = true;
+    }
+
+    fn flush(&mut self) {
+        let s = match self.socket.as_mut() {
+            Some(s) => s,
+            None => return,
+        };
+
+        while let Some(data) = self.queue.front() {
+            if !s.send(data) {
+                break;
+            }
+            self.stats.bytes_out += data.len() as u64;
+            self.stats.messages_out += 1;
+            self.queue.pop_front();
+        }
+    }
+
+    //
+    //  exponential backoff with +-25% jitter so clients do not reconnect in lockstep
+    //
+    fn schedule_retry(&mut self, events: &mut Vec<NetEvent>) {
+        self.state = NetState::Closed;
+        if self.manual_close || !self.config.reconnect {
+            return;
+        }
+
+        let base = self.config.backoff_min_ms * 2f64.powi(self.attempt.min(16) as i32);
+        let delay_ms = base.min(self.config.backoff_max_ms) * (0.75 + 0.5 * self.rng.next_f32() as f64);
+
+        self.attempt += 1;
+        self.retry_at = Some(self.now + delay_ms);
+        events.push(NetEvent::Reconnecting { attempt: self.attempt, delay_ms });
+    }
+}
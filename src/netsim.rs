@@ -0,0 +1,271 @@
+use crate::rng::*;
+use crate::transport::*;
+
+
+//
+//  bad network on demand: wraps any Transport and delays, drops, duplicates and throttles what
+//  goes through it, in both directions. messages stay in order like on a websocket, so jitter
+//  shows up as bursts of late messages rather than reordering
+//
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conditions {
+    // one way, each direction gets it
+    pub latency_ms: f64,
+    // +- random extra delay
+    pub jitter_ms: f64,
+    // 0..1 chance a message is dropped
+    pub loss: f32,
+    // 0..1 chance a message arrives twice
+    pub duplicate: f32,
+    // bytes per second per direction, None = unlimited
+    pub bandwidth: Option<f64>,
+    pub seed: u64,
+}
+
+impl Default for Conditions {
+    fn default() -> Self {
+        Conditions {
+            latency_ms: 0.0,
+            jitter_ms: 0.0,
+            loss: 0.0,
+            duplicate: 0.0,
+            bandwidth: None,
+            seed: 1,
+        }
+    }
+}
+
+impl Conditions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn latency(mut self, ms: f64) -> Self {
+        self.latency_ms = ms.max(0.0);
+        self
+    }
+
+    pub fn jitter(mut self, ms: f64) -> Self {
+        self.jitter_ms = ms.max(0.0);
+        self
+    }
+
+    pub fn loss(mut self, p: f32) -> Self {
+        self.loss = p.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn duplicate(mut self, p: f32) -> Self {
+        self.duplicate = p.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn bandwidth(mut self, bytes_per_second: f64) -> Self {
+        self.bandwidth = Some(bytes_per_second.max(1.0));
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    //
+    //  "latency,jitter,loss[,duplicate[,bandwidth]]", e.g. ?netsim=100,20,0.05 in the page url
+    //
+    pub fn parse(s: &str) -> Option<Conditions> {
+        let v: Vec<f64> = s.split(',').map(|p| p.trim().parse().ok()).collect::<Option<_>>()?;
+
+        let mut c = Conditions::new()
+            .latency(*v.first()?)
+            .jitter(v.get(1).copied().unwrap_or(0.0))
+            .loss(v.get(2).copied().unwrap_or(0.0) as f32)
+            .duplicate(v.get(3).copied().unwrap_or(0.0) as f32);
+        if let Some(bw) = v.get(4) {
+            c = c.bandwidth(*bw);
+        }
+        Some(c)
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimStats {
+    pub dropped_in: u64,
+    pub dropped_out: u64,
+    pub duplicated_in: u64,
+    pub duplicated_out: u64,
+}
+
+
+// what the link did with one message
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fate {
+    Dropped,
+    Delivered,
+    Duplicated,
+}
+
+
+// one direction of the link
+#[derive(Default)]
+struct Pipe {
+    // (due time, event), due times never decrease
+    queue: Vec<(f64, TransportEvent)>,
+    // bandwidth: when the link is free again
+    free_at: f64,
+    last_due: f64,
+}
+
+
+pub struct SimulatedTransport {
+    inner: Box<dyn Transport>,
+    pub conditions: Conditions,
+    pub stats: SimStats,
+    rng: Rng,
+    now: f64,
+    outgoing: Pipe,
+    incoming: Pipe,
+}
+
+impl SimulatedTransport {
+
+    pub fn new(inner: Box<dyn Transport>, conditions: Conditions) -> Self {
+        SimulatedTransport {
+            inner,
+            rng: Rng::new(conditions.seed),
+            conditions,
+            stats: SimStats::default(),
+            now: 0.0,
+            outgoing: Pipe::default(),
+            incoming: Pipe::default(),
+        }
+    }
+
+    //
+    //  Data can be lost / duplicated, everything else only delayed
+    //
+    fn schedule(pipe: &mut Pipe, c: &Conditions, rng: &mut Rng, now: f64, e: TransportEvent) -> Fate {
+        let data_len = match &e {
+            TransportEvent::Data(d) => Some(d.len()),
+            TransportEvent::Text(t) => Some(t.len()),
+            _ => None,
+        };
+
+        if data_len.is_some() && c.loss > 0.0 && rng.next_f32() < c.loss {
+            return Fate::Dropped;
+        }
+
+        let mut sent = now;
+        if let (Some(len), Some(bw)) = (data_len, c.bandwidth) {
+            let start = pipe.free_at.max(now);
+            pipe.free_at = start + len as f64 * 1000.0 / bw;
+            sent = pipe.free_at;
+        }
+
+        let jitter = if c.jitter_ms > 0.0 { rng.range(-1.0, 1.0) as f64 * c.jitter_ms } else { 0.0 };
+        let due = (sent + c.latency_ms + jitter).max(pipe.last_due).max(now);
+        pipe.last_due = due;
+
+        let twice = data_len.is_some() && c.duplicate > 0.0 && rng.next_f32() < c.duplicate;
+        if twice {
+            pipe.queue.push((due, e.clone()));
+        }
+        pipe.queue.push((due, e));
+        if twice { Fate::Duplicated } else { Fate::Delivered }
+    }
+
+    fn due(pipe: &mut Pipe, now: f64) -> Vec<TransportEvent> {
+        let n = pipe.queue.iter().take_while(|(t, _)| *t <= now).count();
+        pipe.queue.drain(..n).map(|(_, e)| e).collect()
+    }
+}
+
+impl Transport for SimulatedTransport {
+
+    fn send(&mut self, data: &[u8]) -> bool {
+        let e = TransportEvent::Data(data.to_vec());
+        let c = &self.conditions;
+        match SimulatedTransport::schedule(&mut self.outgoing, c, &mut self.rng, self.now, e) {
+            Fate::Dropped => self.stats.dropped_out += 1,
+            Fate::Duplicated => self.stats.duplicated_out += 1,
+            Fate::Delivered => (),
+        }
+        // a dropped message looks sent to the caller, like on a real network
+        true
+    }
+
+    // nothing polls a closed transport, so whatever is still delayed never arrives
+    fn close(&mut self) {
+        let lost = self.outgoing.queue.drain(..).filter(|(_, e)| matches!(e, TransportEvent::Data(_))).count();
+        self.stats.dropped_out += lost as u64;
+        self.inner.close();
+    }
+
+    fn set_time(&mut self, now_ms: f64) {
+        self.now = now_ms;
+        self.inner.set_time(now_ms);
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        for e in SimulatedTransport::due(&mut self.outgoing, self.now) {
+            if let TransportEvent::Data(data) = e {
+                self.inner.send(&data);
+            }
+        }
+
+        for e in self.inner.poll() {
+            let c = &self.conditions;
+            match SimulatedTransport::schedule(&mut self.incoming, c, &mut self.rng, self.now, e) {
+                Fate::Dropped => self.stats.dropped_in += 1,
+                Fate::Duplicated => self.stats.duplicated_in += 1,
+                Fate::Delivered => (),
+            }
+        }
+
+        SimulatedTransport::due(&mut self.incoming, self.now)
+    }
+}
+
+
+//
+//  every transport a connector makes goes through the simulator, for Net::with_connector
+//
+pub fn simulated(mut connector: Connector, conditions: Conditions) -> Connector {
+    let mut count = 0;
+    Box::new(move |url: &str| {
+        // different seed per connection so reconnects do not replay the same losses
+        count += 1;
+        let c = conditions.clone().seed(conditions.seed.wrapping_add(count));
+        Ok(Box::new(SimulatedTransport::new(connector(url)?, c)) as Box<dyn Transport>)
+    })
+}
+
+
+//
+//  same for every client a server accepts
+//
+pub struct SimulatedListener {
+    inner: Box<dyn Listener>,
+    pub conditions: Conditions,
+    count: u64,
+}
+
+impl SimulatedListener {
+    pub fn new(inner: Box<dyn Listener>, conditions: Conditions) -> Self {
+        SimulatedListener { inner, conditions, count: 0 }
+    }
+}
+
+impl Listener for SimulatedListener {
+    fn accept(&mut self) -> Option<Box<dyn Transport>> {
+        let t = self.inner.accept()?;
+        self.count += 1;
+        let c = self.conditions.clone().seed(self.conditions.seed.wrapping_add(self.count));
+        Some(Box::new(SimulatedTransport::new(t, c)))
+    }
+}
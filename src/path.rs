@@ -0,0 +1,743 @@
+use super::Vertex;
+
+
+//
+//  vector paths: builder -> flatten -> tessellate into Vertex triangles
+//  the output is a plain triangle list, so it goes straight into RenderWebGpu::push_vertex
+//
+
+
+const EPS: f32 = 1e-6;
+const DEFAULT_TOLERANCE: f32 = 0.001;
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub dash: Vec<f32>,
+    pub dash_offset: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    pub miter_limit: f32,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle {
+            width: 0.01,
+            dash: vec![],
+            dash_offset: 0.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+}
+
+impl StrokeStyle {
+
+    pub fn new(width: f32) -> Self {
+        StrokeStyle {
+            width,
+            ..Default::default()
+        }
+    }
+
+    pub fn dash(mut self, pattern: Vec<f32>, offset: f32) -> Self {
+        self.dash = pattern;
+        self.dash_offset = offset;
+        self
+    }
+
+    pub fn join(mut self, join: LineJoin) -> Self {
+        self.join = join;
+        self
+    }
+
+    pub fn cap(mut self, cap: LineCap) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    pub fn miter_limit(mut self, limit: f32) -> Self {
+        self.miter_limit = limit;
+        self
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCmd {
+    MoveTo([f32; 2]),
+    LineTo([f32; 2]),
+    QuadTo([f32; 2], [f32; 2]),
+    CubicTo([f32; 2], [f32; 2], [f32; 2]),
+    Close,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    pub cmds: Vec<PathCmd>,
+    pub tolerance: f32,
+}
+
+
+#[derive(Debug, Clone)]
+struct Polyline {
+    points: Vec<[f32; 2]>,
+    closed: bool,
+}
+
+
+
+pub struct PathBuilder {
+    cmds: Vec<PathCmd>,
+    start: Option<[f32; 2]>,
+    current: Option<[f32; 2]>,
+    tolerance: f32,
+}
+
+impl PathBuilder {
+
+    pub fn new() -> Self {
+        PathBuilder {
+            cmds: vec![],
+            start: None,
+            current: None,
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    //
+    //  max distance between the curve and its flattened polyline
+    //
+    pub fn tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance.max(EPS);
+        self
+    }
+
+    pub fn move_to(mut self, x: f32, y: f32) -> Self {
+        self.cmds.push(PathCmd::MoveTo([x, y]));
+        self.start = Some([x, y]);
+        self.current = Some([x, y]);
+        self
+    }
+
+    pub fn line_to(mut self, x: f32, y: f32) -> Self {
+        if self.current.is_none() {
+            return self.move_to(x, y);
+        }
+        self.cmds.push(PathCmd::LineTo([x, y]));
+        self.current = Some([x, y]);
+        self
+    }
+
+    pub fn quad_to(mut self, cx: f32, cy: f32, x: f32, y: f32) -> Self {
+        if self.current.is_none() {
+            self = self.move_to(cx, cy);
+        }
+        self.cmds.push(PathCmd::QuadTo([cx, cy], [x, y]));
+        self.current = Some([x, y]);
+        self
+    }
+
+    pub fn cubic_to(mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) -> Self {
+        if self.current.is_none() {
+            self = self.move_to(c1x, c1y);
+        }
+        self.cmds.push(PathCmd::CubicTo([c1x, c1y], [c2x, c2y], [x, y]));
+        self.current = Some([x, y]);
+        self
+    }
+
+    //
+    //  same as canvas arc(): angles in radians, joins the current point with a line
+    //
+    pub fn arc(mut self, cx: f32, cy: f32, radius: f32, start_angle: f32, end_angle: f32, ccw: bool) -> Self {
+
+        let mut sweep = end_angle - start_angle;
+        let full = std::f32::consts::PI * 2.0;
+
+        if ccw {
+            if sweep > 0.0 { sweep -= full * ((sweep / full).floor() + 1.0); }
+            if sweep < -full { sweep = -full; }
+        } else {
+            if sweep < 0.0 { sweep += full * ((-sweep / full).floor() + 1.0); }
+            if sweep > full { sweep = full; }
+        }
+
+        let begin = [cx + radius * start_angle.cos(), cy + radius * start_angle.sin()];
+        self = match self.current {
+            Some(_) => self.line_to(begin[0], begin[1]),
+            None => self.move_to(begin[0], begin[1]),
+        };
+
+        let steps = arc_steps(radius, sweep.abs(), self.tolerance);
+        for i in 1..=steps {
+            let a = start_angle + sweep * (i as f32 / steps as f32);
+            self = self.line_to(cx + radius * a.cos(), cy + radius * a.sin());
+        }
+
+        self
+    }
+
+    pub fn rect(self, x: f32, y: f32, w: f32, h: f32) -> Self {
+        self.move_to(x, y)
+            .line_to(x + w, y)
+            .line_to(x + w, y + h)
+            .line_to(x, y + h)
+            .close()
+    }
+
+    pub fn circle(self, cx: f32, cy: f32, radius: f32) -> Self {
+        self.move_to(cx + radius, cy)
+            .arc(cx, cy, radius, 0.0, std::f32::consts::PI * 2.0, false)
+            .close()
+    }
+
+    pub fn close(mut self) -> Self {
+        if self.current.is_some() {
+            self.cmds.push(PathCmd::Close);
+            self.current = self.start;
+        }
+        self
+    }
+
+    pub fn build(self) -> Path {
+        Path {
+            cmds: self.cmds,
+            tolerance: self.tolerance,
+        }
+    }
+}
+
+
+
+impl Path {
+
+    pub fn builder() -> PathBuilder {
+        PathBuilder::new()
+    }
+
+    //
+    //  fill the path with triangles, self-intersections are handled by the fill rule
+    //
+    pub fn fill(&self, rule: FillRule, color: [f32; 3]) -> Vec<Vertex> {
+        let polylines = self.flatten();
+        to_vertex(&tessellate_fill(&polylines, rule), color)
+    }
+
+    pub fn stroke(&self, style: &StrokeStyle, color: [f32; 3]) -> Vec<Vertex> {
+
+        let mut tris = vec![];
+        let hw = style.width * 0.5;
+        if hw <= 0.0 {
+            return vec![];
+        }
+
+        for line in self.flatten() {
+            for piece in apply_dash(&line, &style.dash, style.dash_offset) {
+                stroke_polyline(&piece, hw, style, self.tolerance, &mut tris);
+            }
+        }
+
+        to_vertex(&tris, color)
+    }
+
+
+    fn flatten(&self) -> Vec<Polyline> {
+
+        let tol = if self.tolerance > 0.0 { self.tolerance } else { DEFAULT_TOLERANCE };
+
+        let mut out = vec![];
+        let mut cur = Polyline { points: vec![], closed: false };
+        let mut last = [0.0, 0.0];
+
+        for cmd in &self.cmds {
+            match *cmd {
+
+                PathCmd::MoveTo(p) => {
+                    if cur.points.len() > 1 {
+                        out.push(cur);
+                    }
+                    cur = Polyline { points: vec![p], closed: false };
+                    last = p;
+                }
+
+                PathCmd::LineTo(p) => {
+                    push_point(&mut cur.points, p);
+                    last = p;
+                }
+
+                PathCmd::QuadTo(c, p) => {
+                    let dd = len(add(sub(last, scale(c, 2.0)), p));
+                    let n = ((dd / (8.0 * tol)).sqrt().ceil() as usize).clamp(1, 256);
+                    for i in 1..=n {
+                        let t = i as f32 / n as f32;
+                        let mt = 1.0 - t;
+                        let q = [
+                            mt * mt * last[0] + 2.0 * mt * t * c[0] + t * t * p[0],
+                            mt * mt * last[1] + 2.0 * mt * t * c[1] + t * t * p[1],
+                        ];
+                        push_point(&mut cur.points, q);
+                    }
+                    last = p;
+                }
+
+                PathCmd::CubicTo(c1, c2, p) => {
+                    let d1 = len(add(sub(last, scale(c1, 2.0)), c2));
+                    let d2 = len(add(sub(c1, scale(c2, 2.0)), p));
+                    let dd = d1.max(d2);
+                    let n = ((0.75 * dd / tol).sqrt().ceil() as usize).clamp(1, 256);
+                    for i in 1..=n {
+                        let t = i as f32 / n as f32;
+                        let mt = 1.0 - t;
+                        let a = mt * mt * mt;
+                        let b = 3.0 * mt * mt * t;
+                        let c = 3.0 * mt * t * t;
+                        let d = t * t * t;
+                        let q = [
+                            a * last[0] + b * c1[0] + c * c2[0] + d * p[0],
+                            a * last[1] + b * c1[1] + c * c2[1] + d * p[1],
+                        ];
+                        push_point(&mut cur.points, q);
+                    }
+                    last = p;
+                }
+
+                PathCmd::Close => {
+                    if cur.points.len() > 1 {
+                        let first = cur.points[0];
+                        if len(sub(*cur.points.last().unwrap(), first)) < EPS {
+                            cur.points.pop();
+                        }
+                        cur.closed = true;
+                        last = first;
+                        out.push(cur);
+                    } else {
+                        last = cur.points.first().copied().unwrap_or(last);
+                    }
+                    cur = Polyline { points: vec![last], closed: false };
+                }
+            }
+        }
+
+        if cur.points.len() > 1 {
+            out.push(cur);
+        }
+
+        out
+    }
+}
+
+
+
+//
+//  fill: split the plane into horizontal bands at every vertex and every edge crossing,
+//  inside a band the edges never cross, so spans are plain trapezoids
+//
+fn tessellate_fill(lines: &[Polyline], rule: FillRule) -> Vec<[f32; 2]> {
+
+    // (top, bottom, winding)
+    let mut edges: Vec<([f32; 2], [f32; 2], i32)> = vec![];
+
+    for line in lines {
+        let n = line.points.len();
+        if n < 2 { continue; }
+        // NaN / inf has no place in the bands, drop the whole outline
+        if line.points.iter().any(|p| !p[0].is_finite() || !p[1].is_finite()) { continue; }
+
+        for i in 0..n {
+            let a = line.points[i];
+            let b = line.points[(i + 1) % n];
+            if (a[1] - b[1]).abs() < EPS { continue; }
+
+            if a[1] < b[1] {
+                edges.push((a, b, 1));
+            } else {
+                edges.push((b, a, -1));
+            }
+        }
+    }
+
+    let mut ys: Vec<f32> = vec![];
+    for e in &edges {
+        ys.push(e.0[1]);
+        ys.push(e.1[1]);
+    }
+
+    for i in 0..edges.len() {
+        for j in (i + 1)..edges.len() {
+            if let Some(y) = edge_cross_y(&edges[i], &edges[j]) {
+                ys.push(y);
+            }
+        }
+    }
+
+    ys.sort_by(|a, b| a.total_cmp(b));
+    ys.dedup_by(|a, b| (*a - *b).abs() < EPS);
+
+    let mut tris = vec![];
+    let mut active: Vec<(f32, f32, f32, i32)> = vec![];
+
+    for band in ys.windows(2) {
+        let (y0, y1) = (band[0], band[1]);
+        let ym = (y0 + y1) * 0.5;
+
+        active.clear();
+        for e in &edges {
+            if e.0[1] <= ym && e.1[1] >= ym {
+                active.push((x_at(e, y0), x_at(e, ym), x_at(e, y1), e.2));
+            }
+        }
+        active.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut winding = 0;
+        let mut left: Option<(f32, f32)> = None;
+
+        for e in &active {
+            let was_inside = is_inside(winding, rule);
+            winding += e.3;
+            let inside = is_inside(winding, rule);
+
+            if !was_inside && inside {
+                left = Some((e.0, e.2));
+            } else if was_inside && !inside {
+                if let Some((l0, l1)) = left.take() {
+                    quad(&mut tris, [l0, y0], [e.0, y0], [e.2, y1], [l1, y1]);
+                }
+            }
+        }
+    }
+
+    tris
+}
+
+fn is_inside(winding: i32, rule: FillRule) -> bool {
+    match rule {
+        FillRule::NonZero => winding != 0,
+        FillRule::EvenOdd => winding % 2 != 0,
+    }
+}
+
+fn x_at(e: &([f32; 2], [f32; 2], i32), y: f32) -> f32 {
+    let (a, b) = (e.0, e.1);
+    let t = ((y - a[1]) / (b[1] - a[1])).clamp(0.0, 1.0);
+    a[0] + (b[0] - a[0]) * t
+}
+
+fn edge_cross_y(e0: &([f32; 2], [f32; 2], i32), e1: &([f32; 2], [f32; 2], i32)) -> Option<f32> {
+
+    let top = e0.0[1].max(e1.0[1]);
+    let bottom = e0.1[1].min(e1.1[1]);
+    if bottom - top < EPS {
+        return None;
+    }
+
+    let d_top = x_at(e0, top) - x_at(e1, top);
+    let d_bottom = x_at(e0, bottom) - x_at(e1, bottom);
+    if d_top * d_bottom >= 0.0 {
+        return None;
+    }
+
+    let t = d_top / (d_top - d_bottom);
+    Some(top + (bottom - top) * t)
+}
+
+
+
+//
+//  dash pattern: even entries are drawn, odd entries are gaps
+//
+fn apply_dash(line: &Polyline, dash: &[f32], offset: f32) -> Vec<Polyline> {
+
+    let total: f32 = dash.iter().sum();
+    if dash.is_empty() || total <= EPS || dash.iter().any(|d| *d < 0.0) {
+        return vec![line.clone()];
+    }
+
+    let mut points = line.points.clone();
+    if line.closed {
+        points.push(line.points[0]);
+    }
+
+    let mut index = 0;
+    let mut left = offset.rem_euclid(total);
+    while left > dash[index % dash.len()] {
+        left -= dash[index % dash.len()];
+        index += 1;
+    }
+    let mut remaining = dash[index % dash.len()] - left;
+
+    let mut out = vec![];
+    let mut cur: Vec<[f32; 2]> = if index % 2 == 0 { vec![points[0]] } else { vec![] };
+
+    for seg in points.windows(2) {
+        let (mut a, b) = (seg[0], seg[1]);
+        let mut seg_len = len(sub(b, a));
+
+        while seg_len > remaining {
+            let p = add(a, scale(sub(b, a), remaining / seg_len));
+
+            if index % 2 == 0 {
+                cur.push(p);
+                if cur.len() > 1 {
+                    out.push(Polyline { points: cur, closed: false });
+                }
+                cur = vec![];
+            } else {
+                cur = vec![p];
+            }
+
+            seg_len -= remaining;
+            a = p;
+            index += 1;
+            remaining = dash[index % dash.len()];
+        }
+
+        remaining -= seg_len;
+        if index % 2 == 0 {
+            push_point(&mut cur, b);
+        }
+    }
+
+    if index % 2 == 0 && cur.len() > 1 {
+        out.push(Polyline { points: cur, closed: false });
+    }
+
+    out
+}
+
+
+
+fn stroke_polyline(line: &Polyline, hw: f32, style: &StrokeStyle, tol: f32, tris: &mut Vec<[f32; 2]>) {
+
+    let mut pts = line.points.clone();
+    pts.dedup_by(|a, b| len(sub(*a, *b)) < EPS);
+    if line.closed && pts.len() > 2 && len(sub(pts[0], *pts.last().unwrap())) < EPS {
+        pts.pop();
+    }
+
+    let closed = line.closed && pts.len() > 2;
+
+    if pts.len() < 2 {
+        // a lone point still gets a dot for round and square caps
+        if let Some(&p) = pts.first() {
+            match style.cap {
+                LineCap::Round => fan(tris, p, hw, 0.0, std::f32::consts::PI * 2.0, tol),
+                LineCap::Square => quad(tris, [p[0] - hw, p[1] - hw], [p[0] + hw, p[1] - hw], [p[0] + hw, p[1] + hw], [p[0] - hw, p[1] + hw]),
+                LineCap::Butt => (),
+            }
+        }
+        return;
+    }
+
+    if !closed && style.cap == LineCap::Square {
+        let n = pts.len();
+        let d0 = norm(sub(pts[1], pts[0]));
+        let d1 = norm(sub(pts[n - 1], pts[n - 2]));
+        pts[0] = sub(pts[0], scale(d0, hw));
+        pts[n - 1] = add(pts[n - 1], scale(d1, hw));
+    }
+
+    let n = pts.len();
+    let seg_count = if closed { n } else { n - 1 };
+
+    for i in 0..seg_count {
+        let a = pts[i];
+        let b = pts[(i + 1) % n];
+        let nrm = scale(perp(norm(sub(b, a))), hw);
+        quad(tris, add(a, nrm), sub(a, nrm), sub(b, nrm), add(b, nrm));
+    }
+
+    let joins = if closed { 0..n } else { 1..(n - 1) };
+    for i in joins {
+        let a = pts[(i + n - 1) % n];
+        let b = pts[i];
+        let c = pts[(i + 1) % n];
+        join(tris, a, b, c, hw, style, tol);
+    }
+
+    if !closed && style.cap == LineCap::Round {
+        let d0 = norm(sub(pts[1], pts[0]));
+        let d1 = norm(sub(pts[n - 1], pts[n - 2]));
+        let a0 = d0[1].atan2(d0[0]);
+        let a1 = d1[1].atan2(d1[0]);
+        let half = std::f32::consts::FRAC_PI_2;
+        fan(tris, pts[0], hw, a0 + half, a0 + 3.0 * half, tol);
+        fan(tris, pts[n - 1], hw, a1 - half, a1 + half, tol);
+    }
+}
+
+fn join(tris: &mut Vec<[f32; 2]>, a: [f32; 2], b: [f32; 2], c: [f32; 2], hw: f32, style: &StrokeStyle, tol: f32) {
+
+    let d0 = norm(sub(b, a));
+    let d1 = norm(sub(c, b));
+    let cross = d0[0] * d1[1] - d0[1] * d1[0];
+    if cross.abs() < EPS {
+        return;
+    }
+
+    // the outer side of the turn is opposite to the turning direction
+    let side = if cross > 0.0 { -1.0 } else { 1.0 };
+    let n0 = scale(perp(d0), side);
+    let n1 = scale(perp(d1), side);
+    let o0 = add(b, scale(n0, hw));
+    let o1 = add(b, scale(n1, hw));
+
+    match style.join {
+
+        LineJoin::Bevel => tris.extend_from_slice(&[b, o0, o1]),
+
+        LineJoin::Miter => {
+            let m = norm(add(n0, n1));
+            let cos_half = dot(m, n0);
+            if cos_half > EPS && 1.0 / cos_half <= style.miter_limit {
+                let tip = add(b, scale(m, hw / cos_half));
+                tris.extend_from_slice(&[b, o0, tip, b, tip, o1]);
+            } else {
+                tris.extend_from_slice(&[b, o0, o1]);
+            }
+        }
+
+        LineJoin::Round => {
+            let a0 = n0[1].atan2(n0[0]);
+            let mut a1 = n1[1].atan2(n1[0]);
+            let pi = std::f32::consts::PI;
+            while a1 - a0 > pi { a1 -= 2.0 * pi; }
+            while a0 - a1 > pi { a1 += 2.0 * pi; }
+            fan(tris, b, hw, a0, a1, tol);
+        }
+    }
+}
+
+fn fan(tris: &mut Vec<[f32; 2]>, center: [f32; 2], r: f32, a0: f32, a1: f32, tol: f32) {
+    let steps = arc_steps(r, (a1 - a0).abs(), tol);
+    let mut prev = [center[0] + r * a0.cos(), center[1] + r * a0.sin()];
+    for i in 1..=steps {
+        let a = a0 + (a1 - a0) * (i as f32 / steps as f32);
+        let p = [center[0] + r * a.cos(), center[1] + r * a.sin()];
+        tris.extend_from_slice(&[center, prev, p]);
+        prev = p;
+    }
+}
+
+fn arc_steps(radius: f32, sweep: f32, tol: f32) -> usize {
+    if radius <= tol {
+        return ((sweep / std::f32::consts::FRAC_PI_2).ceil() as usize).max(1);
+    }
+    let step = 2.0 * (1.0 - tol / radius).acos();
+    ((sweep / step).ceil() as usize).clamp(1, 512)
+}
+
+
+
+fn quad(tris: &mut Vec<[f32; 2]>, a: [f32; 2], b: [f32; 2], c: [f32; 2], d: [f32; 2]) {
+    tris.extend_from_slice(&[a, b, c, a, c, d]);
+}
+
+fn to_vertex(tris: &[[f32; 2]], color: [f32; 3]) -> Vec<Vertex> {
+    tris.iter().map(|p| Vertex::new(p[0], p[1], 0.0, color)).collect()
+}
+
+fn push_point(points: &mut Vec<[f32; 2]>, p: [f32; 2]) {
+    match points.last() {
+        Some(last) if len(sub(*last, p)) < EPS => (),
+        _ => points.push(p),
+    }
+}
+
+fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] { [a[0] + b[0], a[1] + b[1]] }
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] { [a[0] - b[0], a[1] - b[1]] }
+fn scale(a: [f32; 2], s: f32) -> [f32; 2] { [a[0] * s, a[1] * s] }
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 { a[0] * b[0] + a[1] * b[1] }
+fn len(a: [f32; 2]) -> f32 { dot(a, a).sqrt() }
+fn perp(a: [f32; 2]) -> [f32; 2] { [-a[1], a[0]] }
+
+fn norm(a: [f32; 2]) -> [f32; 2] {
+    let l = len(a);
+    if l < EPS { [0.0, 0.0] } else { scale(a, 1.0 / l) }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // total area of a triangle list, windings either way
+    fn area(v: &[Vertex]) -> f32 {
+        v.chunks(3).map(|t| {
+            let (a, b, c) = (t[0].pos, t[1].pos, t[2].pos);
+            ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])).abs() * 0.5
+        }).sum()
+    }
+
+    fn square(b: PathBuilder, x: f32, y: f32, size: f32) -> PathBuilder {
+        b.move_to(x, y).line_to(x + size, y).line_to(x + size, y + size).line_to(x, y + size).close()
+    }
+
+    #[test]
+    fn fills_rects_and_circles() {
+        let rect = Path::builder().rect(0.0, 0.0, 2.0, 0.5).build();
+        assert!((area(&rect.fill(FillRule::NonZero, [1.0; 3])) - 1.0).abs() < 1e-5);
+
+        let circle = Path::builder().circle(0.0, 0.0, 1.0).build();
+        let a = area(&circle.fill(FillRule::NonZero, [1.0; 3]));
+        assert!((a - std::f32::consts::PI).abs() < 0.01, "{}", a);
+    }
+
+    #[test]
+    fn fill_rules_differ_on_holes() {
+        // both squares wound the same way
+        let path = square(square(Path::builder(), 0.0, 0.0, 4.0), 1.0, 1.0, 2.0).build();
+        assert!((area(&path.fill(FillRule::NonZero, [1.0; 3])) - 16.0).abs() < 1e-4);
+        assert!((area(&path.fill(FillRule::EvenOdd, [1.0; 3])) - 12.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn non_finite_points_are_skipped() {
+        let path = square(Path::builder(), 0.0, 0.0, 1.0)
+            .move_to(0.0, 0.0).line_to(f32::NAN, 1.0).line_to(1.0, f32::INFINITY).close()
+            .build();
+        assert!((area(&path.fill(FillRule::NonZero, [1.0; 3])) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn strokes_and_dashes() {
+        let line = Path::builder().move_to(0.0, 0.0).line_to(1.0, 0.0).build();
+        let solid = line.stroke(&StrokeStyle::new(0.1), [1.0; 3]);
+        assert!((area(&solid) - 0.1).abs() < 1e-5);
+
+        let dashed = line.stroke(&StrokeStyle::new(0.1).dash(vec![0.25, 0.25], 0.0), [1.0; 3]);
+        assert!((area(&dashed) - 0.05).abs() < 1e-5);
+
+        // square caps add half the width at each end
+        let capped = line.stroke(&StrokeStyle::new(0.1).cap(LineCap::Square), [1.0; 3]);
+        assert!((area(&capped) - 0.11).abs() < 1e-5);
+    }
+}
@@ -6,9 +6,14 @@
 extern crate rapier2d;
 use rapier2d::prelude::*;
 
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Fnv;
+
 
 
 pub struct Physics {
+    gravity: Vector<Real>,
     phys_pipeline: PhysicsPipeline,
     phys_setting: PhysicsSetting,
     ball_body_handle: RigidBodyHandle,
@@ -19,7 +24,7 @@ pub struct Physics {
 pub struct PhysicsSetting {
     integration_params: IntegrationParameters,
     island_manager: IslandManager,
-    broad_phase: Box<BroadPhase>,
+    broad_phase: Box<dyn BroadPhase>,
     narrow_phase: NarrowPhase,
     impulse_join_set: ImpulseJointSet,
     multi_body_join_set: MultibodyJointSet,
@@ -28,34 +33,143 @@ pub struct PhysicsSetting {
 }
 
 
+//
+//  plain data versions of rigid bodies and colliders, for scene files and anything else that
+//  builds physics from data. the position of a body comes from its entity's Transform
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyKind {
+    Dynamic,
+    Fixed,
+    // moved by setting its velocity
+    Kinematic,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BodyDesc {
+    pub kind: BodyKind,
+    pub linvel: [f32; 2],
+    pub angvel: f32,
+    pub gravity_scale: f32,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    pub lock_rotation: bool,
+    pub ccd: bool,
+}
+
+impl Default for BodyDesc {
+    fn default() -> Self {
+        BodyDesc {
+            kind: BodyKind::Dynamic,
+            linvel: [0.0, 0.0],
+            angvel: 0.0,
+            gravity_scale: 1.0,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            lock_rotation: false,
+            ccd: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ColliderShape {
+    Ball { radius: f32 },
+    Cuboid { hx: f32, hy: f32 },
+    // vertical, half_height is the straight part
+    Capsule { half_height: f32, radius: f32 },
+    // open chain of segments, for terrain
+    Polyline { points: Vec<[f32; 2]> },
+    // convex hull of the points
+    Convex { points: Vec<[f32; 2]> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColliderDesc {
+    pub shape: ColliderShape,
+    // relative to the body, or the world position for colliders without one
+    pub offset: [f32; 2],
+    pub rotation: f32,
+    pub restitution: f32,
+    pub friction: f32,
+    pub density: f32,
+    pub sensor: bool,
+}
+
+impl Default for ColliderDesc {
+    fn default() -> Self {
+        ColliderDesc {
+            shape: ColliderShape::Ball { radius: 0.5 },
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+            restitution: 0.0,
+            friction: 0.5,
+            density: 1.0,
+            sensor: false,
+        }
+    }
+}
+
+impl ColliderDesc {
+    pub fn new(shape: ColliderShape) -> Self {
+        ColliderDesc { shape, ..Default::default() }
+    }
+
+    pub fn offset(mut self, x: f32, y: f32) -> Self {
+        self.offset = [x, y];
+        self
+    }
+
+    pub fn restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+        self
+    }
+}
+
+
+
+impl Default for Physics {
+    fn default() -> Self {
+        Physics::new()
+    }
+}
+
 impl Physics {
     pub fn new() -> Physics {
-
-        let mut rigid_body_set = RigidBodySet::new();
-        let mut collider_set = ColliderSet::new();
+        let mut phys = Physics::empty();
 
         let collider = ColliderBuilder::cuboid(100.0, 0.0).build();
-        collider_set.insert(collider);
-   
+        phys.collider_set.insert(collider);
 
-        let rigid_body = RigidBodyBuilder::dynamic()
-            .translation(vector![0.0, 10.0])
-            .build();
-
-        let collider = ColliderBuilder::ball(0.5).restitution(0.7).build();
+        phys.ball_body_handle = phys.add_ball(0.0, 10.0, 0.5);
+        phys
+    }
 
-        let ball_body_handle = rigid_body_set.insert(rigid_body);
-        collider_set.insert_with_parent(collider, ball_body_handle, &mut rigid_body_set);
+    //
+    //  no ground and no ball, scenes fill it in
+    //
+    pub fn empty() -> Physics {
+        let rigid_body_set = RigidBodySet::new();
+        let collider_set = ColliderSet::new();
+        let ball_body_handle = RigidBodyHandle::invalid();
 
         let integration_parameters = IntegrationParameters::default();
-        let mut physics_pipeline = PhysicsPipeline::new();
-        let mut island_manager = IslandManager::new();
-        let mut broad_phase = DefaultBroadPhase::new();
-        let mut narrow_phase = NarrowPhase::new();
-        let mut impulse_joint_set = ImpulseJointSet::new();
-        let mut multibody_joint_set = MultibodyJointSet::new();
-        let mut ccd_solver = CCDSolver::new();
-        let mut query_pipeline = QueryPipeline::new();
+        let physics_pipeline = PhysicsPipeline::new();
+        let island_manager = IslandManager::new();
+        let broad_phase = DefaultBroadPhase::new();
+        let narrow_phase = NarrowPhase::new();
+        let impulse_joint_set = ImpulseJointSet::new();
+        let multibody_joint_set = MultibodyJointSet::new();
+        let ccd_solver = CCDSolver::new();
+        let query_pipeline = QueryPipeline::new();
 
         let phys_setting = PhysicsSetting {
             integration_params: integration_parameters,
@@ -68,23 +182,20 @@ impl Physics {
             query_pipeline
         };
 
-        let phys = Physics {
+        Physics {
+            gravity: vector![0.0, -9.81],
             ball_body_handle,
             phys_setting,
             phys_pipeline: physics_pipeline,
             rigid_body_set,
             collider_set,
-        };
-
-        phys
+        }
     }
 
     pub fn update_physics(&mut self) -> (f32, f32){
 
-        let mut gravity = vector![0.0, -9.81];
-
         self.phys_pipeline.step(
-            &mut gravity, 
+            &self.gravity, 
                 &self.phys_setting.integration_params, 
                             &mut self.phys_setting.island_manager, 
                                     &mut *self.phys_setting.broad_phase, 
@@ -99,10 +210,237 @@ impl Physics {
                             &()
         );
 
-        let b = &self.rigid_body_set[self.ball_body_handle];
-        let x = b.translation().x;
-        let y = b.translation().y;
-        
-        (x, y - 0.8)
+        match self.rigid_body_set.get(self.ball_body_handle) {
+            Some(b) => (b.translation().x, b.translation().y - 0.8),
+            None => (0.0, 0.0),
+        }
+    }
+
+    pub fn set_timestep(&mut self, dt: f32) {
+        self.phys_setting.integration_params.dt = dt;
+    }
+
+    //
+    //  fnv-1a over every body state, equal hashes = same simulation
+    //
+    pub fn state_hash(&self) -> u64 {
+        let mut h = Fnv::new();
+        let mut add = |v: f32| h.write(&v.to_bits().to_le_bytes());
+
+        for (_, b) in self.rigid_body_set.iter() {
+            add(b.translation().x);
+            add(b.translation().y);
+            add(b.rotation().angle());
+            add(b.linvel().x);
+            add(b.linvel().y);
+            add(b.angvel());
+        }
+
+        h.finish()
+    }
+
+    pub fn ball(&self) -> RigidBodyHandle {
+        self.ball_body_handle
+    }
+
+    // the body single player input drives
+    pub fn set_ball(&mut self, handle: RigidBodyHandle) {
+        self.ball_body_handle = handle;
+    }
+
+    pub fn set_gravity(&mut self, x: f32, y: f32) {
+        self.gravity = vector![x, y];
+    }
+
+    pub fn gravity(&self) -> (f32, f32) {
+        (self.gravity.x, self.gravity.y)
+    }
+
+    pub fn add_body(&mut self, desc: &BodyDesc, x: f32, y: f32, rotation: f32) -> RigidBodyHandle {
+        let builder = match desc.kind {
+            BodyKind::Dynamic => RigidBodyBuilder::dynamic(),
+            BodyKind::Fixed => RigidBodyBuilder::fixed(),
+            BodyKind::Kinematic => RigidBodyBuilder::kinematic_velocity_based(),
+        };
+
+        let mut builder = builder
+            .translation(vector![x, y])
+            .rotation(rotation)
+            .linvel(vector![desc.linvel[0], desc.linvel[1]])
+            .angvel(desc.angvel)
+            .gravity_scale(desc.gravity_scale)
+            .linear_damping(desc.linear_damping)
+            .angular_damping(desc.angular_damping)
+            .ccd_enabled(desc.ccd);
+        if desc.lock_rotation {
+            builder = builder.lock_rotations();
+        }
+
+        self.rigid_body_set.insert(builder.build())
+    }
+
+    //
+    //  attached to `parent`, or fixed in the world without one
+    //
+    pub fn add_collider(&mut self, desc: &ColliderDesc, parent: Option<RigidBodyHandle>) -> anyhow::Result<ColliderHandle> {
+        let points = |p: &[[f32; 2]]| p.iter().map(|p| point![p[0], p[1]]).collect::<Vec<_>>();
+
+        let builder = match &desc.shape {
+            ColliderShape::Ball { radius } => ColliderBuilder::ball(*radius),
+            ColliderShape::Cuboid { hx, hy } => ColliderBuilder::cuboid(*hx, *hy),
+            ColliderShape::Capsule { half_height, radius } => ColliderBuilder::capsule_y(*half_height, *radius),
+            ColliderShape::Polyline { points: p } => {
+                anyhow::ensure!(p.len() >= 2, "polyline needs at least 2 points");
+                ColliderBuilder::polyline(points(p), None)
+            }
+            ColliderShape::Convex { points: p } => {
+                // parry panics on fewer
+                anyhow::ensure!(p.len() >= 3, "convex hull needs at least 3 points");
+                ColliderBuilder::convex_hull(&points(p)).ok_or_else(|| anyhow::anyhow!("points have no convex hull"))?
+            }
+        };
+
+        let collider = builder
+            .position(Isometry::new(vector![desc.offset[0], desc.offset[1]], desc.rotation))
+            .restitution(desc.restitution)
+            .friction(desc.friction)
+            .density(desc.density)
+            .sensor(desc.sensor)
+            .build();
+
+        Ok(match parent {
+            Some(p) => self.collider_set.insert_with_parent(collider, p, &mut self.rigid_body_set),
+            None => self.collider_set.insert(collider),
+        })
+    }
+
+    pub fn describe_body(&self, handle: RigidBodyHandle) -> Option<BodyDesc> {
+        let b = self.rigid_body_set.get(handle)?;
+        let kind = match b.body_type() {
+            RigidBodyType::Dynamic => BodyKind::Dynamic,
+            RigidBodyType::Fixed => BodyKind::Fixed,
+            _ => BodyKind::Kinematic,
+        };
+
+        Some(BodyDesc {
+            kind,
+            linvel: [b.linvel().x, b.linvel().y],
+            angvel: b.angvel(),
+            gravity_scale: b.gravity_scale(),
+            linear_damping: b.linear_damping(),
+            angular_damping: b.angular_damping(),
+            lock_rotation: b.locked_axes().contains(LockedAxes::ROTATION_LOCKED),
+            ccd: b.is_ccd_enabled(),
+        })
+    }
+
+    //
+    //  the colliders of a body, or with None every collider that has no body.
+    //  shapes that ColliderShape cannot express are left out
+    //
+    pub fn describe_colliders(&self, parent: Option<RigidBodyHandle>) -> Vec<ColliderDesc> {
+        let mut out = vec![];
+        for (_, c) in self.collider_set.iter().filter(|(_, c)| c.parent() == parent) {
+            let points = |p: &[Point<Real>]| p.iter().map(|p| [p.x, p.y]).collect::<Vec<_>>();
+
+            let shape = c.shape();
+            let shape = if let Some(b) = shape.as_ball() {
+                ColliderShape::Ball { radius: b.radius }
+            } else if let Some(b) = shape.as_cuboid() {
+                ColliderShape::Cuboid { hx: b.half_extents.x, hy: b.half_extents.y }
+            } else if let Some(b) = shape.as_capsule() {
+                ColliderShape::Capsule { half_height: b.half_height(), radius: b.radius }
+            } else if let Some(p) = shape.as_polyline() {
+                ColliderShape::Polyline { points: points(p.vertices()) }
+            } else if let Some(p) = shape.as_convex_polygon() {
+                ColliderShape::Convex { points: points(p.points()) }
+            } else {
+                continue;
+            };
+
+            let position = match parent {
+                Some(_) => c.position_wrt_parent().copied().unwrap_or_else(Isometry::identity),
+                None => *c.position(),
+            };
+
+            out.push(ColliderDesc {
+                shape,
+                offset: [position.translation.x, position.translation.y],
+                rotation: position.rotation.angle(),
+                restitution: c.restitution(),
+                friction: c.friction(),
+                density: c.density(),
+                sensor: c.is_sensor(),
+            });
+        }
+        out
+    }
+
+    pub fn add_ball(&mut self, x: f32, y: f32, radius: f32) -> RigidBodyHandle {
+        let rigid_body = RigidBodyBuilder::dynamic()
+            .translation(vector![x, y])
+            .build();
+
+        let collider = ColliderBuilder::ball(radius).restitution(0.7).build();
+
+        let handle = self.rigid_body_set.insert(rigid_body);
+        self.collider_set.insert_with_parent(collider, handle, &mut self.rigid_body_set);
+
+        handle
+    }
+
+    pub fn remove_body(&mut self, handle: RigidBodyHandle) {
+        self.rigid_body_set.remove(
+            handle,
+            &mut self.phys_setting.island_manager,
+            &mut self.collider_set,
+            &mut self.phys_setting.impulse_join_set,
+            &mut self.phys_setting.multi_body_join_set,
+            true,
+        );
+    }
+
+    pub fn remove_collider(&mut self, handle: ColliderHandle) {
+        self.collider_set.remove(handle, &mut self.phys_setting.island_manager, &mut self.rigid_body_set, true);
+    }
+
+    pub fn apply_impulse(&mut self, handle: RigidBodyHandle, x: f32, y: f32) {
+        if let Some(b) = self.rigid_body_set.get_mut(handle) {
+            b.apply_impulse(vector![x, y], true);
+        }
+    }
+
+    //
+    //  (x, y, rotation)
+    //
+    pub fn body_transform(&self, handle: RigidBodyHandle) -> Option<(f32, f32, f32)> {
+        self.rigid_body_set.get(handle).map(|b| {
+            (b.translation().x, b.translation().y, b.rotation().angle())
+        })
+    }
+
+    //
+    //  (vx, vy, angular)
+    //
+    pub fn body_velocity(&self, handle: RigidBodyHandle) -> Option<(f32, f32, f32)> {
+        self.rigid_body_set.get(handle).map(|b| {
+            (b.linvel().x, b.linvel().y, b.angvel())
+        })
+    }
+
+    pub fn set_body_velocity(&mut self, handle: RigidBodyHandle, vx: f32, vy: f32, angular: f32) {
+        if let Some(b) = self.rigid_body_set.get_mut(handle) {
+            b.set_linvel(vector![vx, vy], true);
+            b.set_angvel(angular, true);
+        }
+    }
+
+    pub fn set_body_transform(&mut self, handle: RigidBodyHandle, x: f32, y: f32, rotation: f32) {
+        if let Some(b) = self.rigid_body_set.get_mut(handle) {
+            b.set_translation(vector![x, y], true);
+            b.set_rotation(Rotation::new(rotation), true);
+            b.set_linvel(vector![0.0, 0.0], true);
+            b.set_angvel(0.0, true);
+        }
     }
 }
\ No newline at end of file
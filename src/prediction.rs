@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+extern crate hecs;
+use hecs::*;
+
+use crate::replay::*;
+use crate::sim::*;
+use crate::snapshot::*;
+
+
+//
+//  client-side prediction of the local player
+//
+//  the local Sim only holds our own body (+ the ground), everything else comes from
+//  interpolated snapshots. every tick the input is applied locally right away and kept;
+//  when a snapshot acks tick N the body is reset to the server state and inputs after N
+//  are replayed. the jump between the old and the new prediction is kept as a visual
+//  offset that fades out instead of snapping
+//
+
+
+// inputs older than this are never replayed
+const MAX_HISTORY: usize = 256;
+
+
+pub struct Predictor {
+    pub player: u32,
+    pub sim: Sim,
+    // tick of the last input, sent as Message::Input { tick }
+    pub tick: u64,
+    // fraction of the visual error removed per second
+    pub smoothing: f32,
+    // errors larger than this are a teleport, not a misprediction
+    pub snap_distance: f32,
+    history: VecDeque<(u64, InputFrame)>,
+    error: [f32; 2],
+    synced: bool,
+}
+
+impl Predictor {
+
+    pub fn new(player: u32) -> Self {
+        let mut sim = Sim::new(0);
+
+        let others: Vec<Entity> = sim.world.iter().map(|e| e.entity()).collect();
+        for e in others {
+            sim.remove_entity(e);
+        }
+        sim.add_player(player);
+
+        Predictor {
+            player,
+            sim,
+            tick: 0,
+            smoothing: 10.0,
+            snap_distance: 2.0,
+            history: VecDeque::new(),
+            error: [0.0, 0.0],
+            synced: false,
+        }
+    }
+
+    //
+    //  run one tick of local input, returns the tick number to send with it
+    //
+    pub fn step(&mut self, frame: &InputFrame) -> u64 {
+        // spawning is left to the server, a locally spawned ball would never be corrected
+        let mut frame = frame.clone();
+        frame.pressed.retain(|a| a != "spawn");
+
+        self.tick += 1;
+        self.sim.step_players(&[(self.player, frame.clone())]);
+
+        if self.history.len() >= MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.tick, frame));
+
+        self.tick
+    }
+
+    //
+    //  rewind to the server state for snapshot.ack and replay what the server has not seen yet
+    //
+    pub fn reconcile(&mut self, snapshot: &Snapshot) {
+        let server = match snapshot.entities.iter().find(|e| e.player == Some(self.player)) {
+            Some(e) => e,
+            None => return,
+        };
+        let body = match self.sim.player_body(self.player) {
+            Some(b) => b,
+            None => return,
+        };
+
+        while self.history.front().is_some_and(|(t, _)| *t <= snapshot.ack) {
+            self.history.pop_front();
+        }
+
+        let before = self.raw_position();
+
+        self.sim.physics.set_body_transform(body, server.x, server.y, server.rotation);
+        self.sim.physics.set_body_velocity(body, server.vx, server.vy, server.spin);
+        for (_, frame) in self.history.iter() {
+            self.sim.step_players(&[(self.player, frame.clone())]);
+        }
+
+        let after = self.raw_position();
+        let error = [self.error[0] + before.0 - after.0, self.error[1] + before.1 - after.1];
+
+        if !self.synced || (error[0] * error[0] + error[1] * error[1]).sqrt() > self.snap_distance {
+            self.error = [0.0, 0.0];
+        } else {
+            self.error = error;
+        }
+        self.synced = true;
+    }
+
+    // fade the visual error, call once per rendered frame
+    pub fn update(&mut self, dt: f32) {
+        let keep = (1.0 - self.smoothing * dt).max(0.0);
+        self.error[0] *= keep;
+        self.error[1] *= keep;
+    }
+
+    //
+    //  where to draw the player: prediction + the error that is still fading out
+    //
+    pub fn position(&self) -> (f32, f32, f32) {
+        let (x, y, r) = self.raw_position();
+        (x + self.error[0], y + self.error[1], r)
+    }
+
+    // inputs the server has not acked yet
+    pub fn pending(&self) -> usize {
+        self.history.len()
+    }
+
+    fn raw_position(&self) -> (f32, f32, f32) {
+        self.sim.player_body(self.player)
+            .and_then(|b| self.sim.physics.body_transform(b))
+            .unwrap_or((0.0, 0.0, 0.0))
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context};
+use hecs::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::assets::Asset;
+use crate::components::*;
+use crate::physics::*;
+use crate::scene::{EntityDesc, Spawned};
+
+
+//
+//  prefabs: entities described once and spawned many times. a prefab has the fields of a
+//  scene entity, can start from another prefab ("base") and spawn more prefabs with it
+//  ("children", placed relative to it). every field can be overridden, per child or per spawn.
+//  overrides are merged into the json: objects field by field, anything else replaced
+//
+//      {
+//        "crate": {
+//          "circle": { "radius": 0.5, "color": [0.6, 0.4, 0.2] },
+//          "body": { "kind": "Dynamic" },
+//          "colliders": [ { "shape": { "type": "Cuboid", "hx": 0.5, "hy": 0.5 } } ]
+//        },
+//        "heavy crate": { "base": "crate", "circle": { "color": [0.3, 0.3, 0.3] } },
+//        "cart": {
+//          "body": { "kind": "Dynamic" },
+//          "colliders": [ { "shape": { "type": "Cuboid", "hx": 1.0, "hy": 0.25 } } ],
+//          "children": [
+//            { "prefab": "crate", "transform": { "x": -0.5, "y": 0.75 } },
+//            { "prefab": "heavy crate", "transform": { "x": 0.5, "y": 0.75 }, "circle": { "radius": 0.4 } }
+//          ]
+//        }
+//      }
+//
+//  the transform of a prefab is relative to where it is spawned
+//
+
+
+// bases and children deep enough for any real prefab, deeper is a mistake
+const MAX_DEPTH: usize = 16;
+// entities in one resolved prefab, a few children each 16 deep would be billions
+const MAX_ENTITIES: usize = 4096;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prefab {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<PrefabChild>,
+    // EntityDesc fields, kept as json so they can be merged
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrefabChild {
+    pub prefab: String,
+    // relative to the parent
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(flatten)]
+    pub overrides: Map<String, Value>,
+}
+
+impl Prefab {
+
+    pub fn from_json(s: &str) -> anyhow::Result<Prefab> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    // a prefab of exactly this entity
+    pub fn from_entity(desc: &EntityDesc) -> Prefab {
+        let fields = match serde_json::to_value(desc) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Map::new(),
+        };
+        Prefab { fields, ..Default::default() }
+    }
+}
+
+
+//
+//  a prefab worked out down to its entities: bases merged, overrides applied
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPrefab {
+    pub name: String,
+    pub desc: EntityDesc,
+    pub children: Vec<ResolvedPrefab>,
+}
+
+
+//
+//  one spawn of a prefab
+//
+//      prefabs.spawn_prefab(&mut world, &mut physics, &Instance::new("crate").at(3.0, 1.0).set("circle.radius", 0.3))
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub prefab: String,
+    pub transform: Transform,
+    pub overrides: Map<String, Value>,
+}
+
+impl Instance {
+
+    pub fn new(prefab: &str) -> Self {
+        Instance {
+            prefab: prefab.to_string(),
+            transform: Transform::default(),
+            overrides: Map::new(),
+        }
+    }
+
+    pub fn at(mut self, x: f32, y: f32) -> Self {
+        self.transform.x = x;
+        self.transform.y = y;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: f32) -> Self {
+        self.transform.rotation = rotation;
+        self
+    }
+
+    //
+    //  one field, by a dotted path: "body.linvel", "circle.radius", "name"
+    //
+    pub fn set(mut self, path: &str, value: impl Into<Value>) -> Self {
+        let mut value = value.into();
+        for key in path.rsplit('.') {
+            let mut m = Map::new();
+            m.insert(key.to_string(), value);
+            value = Value::Object(m);
+        }
+        if let Value::Object(m) = value {
+            merge(&mut self.overrides, &m);
+        }
+        self
+    }
+
+    pub fn overrides(mut self, overrides: &Map<String, Value>) -> Self {
+        merge(&mut self.overrides, overrides);
+        self
+    }
+}
+
+
+
+//
+//  prefabs by name. a prefab file is a json object of them, several files can be added
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Prefabs {
+    pub prefabs: BTreeMap<String, Prefab>,
+}
+
+impl Prefabs {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, prefab: Prefab) -> Self {
+        self.insert(name, prefab);
+        self
+    }
+
+    pub fn insert(&mut self, name: &str, prefab: Prefab) {
+        self.prefabs.insert(name.to_string(), prefab);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefabs.is_empty()
+    }
+
+    // the ones in `other` replace these on a name clash
+    pub fn extend(&mut self, other: Prefabs) {
+        self.prefabs.extend(other.prefabs);
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(&self.prefabs)?)
+    }
+
+    pub fn from_json(s: &str) -> anyhow::Result<Prefabs> {
+        Ok(Prefabs { prefabs: serde_json::from_str(s)? })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Prefabs> {
+        let s = std::fs::read_to_string(path).with_context(|| path.to_string())?;
+        Prefabs::from_json(&s).with_context(|| path.to_string())
+    }
+
+
+    //
+    //  the fields of a prefab after its bases, without its children
+    //
+    pub fn fields(&self, name: &str) -> anyhow::Result<Map<String, Value>> {
+        let mut chain = vec![];
+        let mut next = Some(name);
+        while let Some(name) = next {
+            if chain.contains(&name) {
+                bail!("prefab {} is its own base", name);
+            }
+            let prefab = self.get(name).ok_or_else(|| anyhow!("no prefab {}", name))?;
+            chain.push(name);
+            next = prefab.base.as_deref();
+        }
+
+        // the furthest base first, each prefab on top
+        let mut fields = Map::new();
+        for name in chain.iter().rev() {
+            merge(&mut fields, &self.prefabs[*name].fields);
+        }
+        Ok(fields)
+    }
+
+    pub fn resolve(&self, name: &str, overrides: &Map<String, Value>) -> anyhow::Result<ResolvedPrefab> {
+        self.resolve_at(name, overrides, &mut vec![], &mut 0)
+    }
+
+    // `count` is every entity resolved so far, the whole tree counts towards MAX_ENTITIES
+    fn resolve_at(&self, name: &str, overrides: &Map<String, Value>, stack: &mut Vec<String>, count: &mut usize) -> anyhow::Result<ResolvedPrefab> {
+        if stack.iter().any(|s| s == name) {
+            bail!("prefab {} contains itself: {} -> {}", name, stack.join(" -> "), name);
+        }
+        if stack.len() >= MAX_DEPTH {
+            bail!("prefabs nested deeper than {}: {}", MAX_DEPTH, stack.join(" -> "));
+        }
+        if *count >= MAX_ENTITIES {
+            bail!("prefab {} is more than {} entities", stack.first().map_or(name, |s| s.as_str()), MAX_ENTITIES);
+        }
+        *count += 1;
+
+        let mut fields = self.fields(name)?;
+        merge(&mut fields, overrides);
+        let desc: EntityDesc = serde_json::from_value(Value::Object(fields)).with_context(|| format!("prefab {}", name))?;
+
+        // children of the bases come first
+        let mut children = vec![];
+        let mut base = Some(name);
+        let mut chain = vec![];
+        while let Some(b) = base {
+            chain.push(b);
+            base = self.prefabs[b].base.as_deref();
+        }
+
+        stack.push(name.to_string());
+        for b in chain.iter().rev() {
+            for child in &self.prefabs[*b].children {
+                let mut r = self.resolve_at(&child.prefab, &child.overrides, stack, count).with_context(|| format!("prefab {}", name))?;
+                r.desc.transform = child.transform.then(r.desc.transform);
+                children.push(r);
+            }
+        }
+        stack.pop();
+
+        Ok(ResolvedPrefab { name: name.to_string(), desc, children })
+    }
+
+
+    //
+    //  the prefab and everything nested in it, parents before their children. nothing is left
+    //  in the world when it fails
+    //
+    pub fn spawn_prefab(&self, world: &mut World, physics: &mut Physics, instance: &Instance) -> anyhow::Result<Vec<Entity>> {
+        let resolved = self.resolve(&instance.prefab, &instance.overrides)?;
+
+        let mut spawned = Spawned::default();
+        match spawn_resolved(&resolved, instance.transform, None, world, physics, &mut spawned) {
+            Ok(()) => Ok(spawned.entities),
+            Err(e) => {
+                spawned.undo(world, physics);
+                Err(e)
+            }
+        }
+    }
+}
+
+
+fn spawn_resolved(r: &ResolvedPrefab, at: Transform, parent: Option<Entity>, world: &mut World, physics: &mut Physics, out: &mut Spawned) -> anyhow::Result<()> {
+    let mut desc = r.desc.clone();
+    desc.transform = at.then(r.desc.transform);
+
+    let e = desc.spawn_into(world, physics, out).with_context(|| format!("prefab {}", r.name))?;
+    world.insert_one(e, PrefabRef(r.name.clone())).ok();
+    if let Some(p) = parent {
+        world.insert_one(e, Parent(p)).ok();
+    }
+
+    for child in &r.children {
+        spawn_resolved(child, desc.transform, Some(e), world, physics, out)?;
+    }
+    Ok(())
+}
+
+// objects field by field, anything else replaced. null stays, so an override can clear an Option
+fn merge(into: &mut Map<String, Value>, from: &Map<String, Value>) {
+    for (k, v) in from {
+        match (into.get_mut(k), v) {
+            (Some(Value::Object(a)), Value::Object(b)) => merge(a, b),
+            _ => {
+                into.insert(k.clone(), v.clone());
+            }
+        }
+    }
+}
+
+
+impl Asset for Prefabs {
+    fn decode(path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        let s = std::str::from_utf8(bytes).map_err(|_| anyhow!("{} is not utf-8", path))?;
+        Prefabs::from_json(s)
+    }
+}
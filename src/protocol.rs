@@ -0,0 +1,83 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::lobby::RoomInfo;
+use crate::replay::InputFrame;
+use crate::snapshot::SnapshotDelta;
+
+
+//
+//  wire format: "YY" + protocol version (u16 le) + bincode(Message)
+//  bincode with varint integers, small snapshot deltas stay small on the wire
+//  bincode numbers variants by position. a new Message variant goes to the end and only bumps
+//  PROTOCOL_VERSION, payloads from older versions back to MIN_PROTOCOL_VERSION still decode.
+//  changing or reordering anything already sent bumps both
+//
+
+
+pub const PROTOCOL_VERSION: u16 = 3;
+pub const MIN_PROTOCOL_VERSION: u16 = 3;
+const MAGIC: &[u8; 2] = b"YY";
+const HEADER_LEN: usize = 4;
+
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    Hello { name: String },
+    Ping { time: f64 },
+    Pong { time: f64 },
+    Chat { text: String },
+    // text frames from servers that do not speak the binary protocol
+    Text(String),
+
+    // server -> client after Hello, id is the Player controlled by this client
+    Welcome { id: u32 },
+    // client -> server once per client tick
+    Input { tick: u64, frame: InputFrame },
+    // server -> client
+    Snapshot(SnapshotDelta),
+    // client -> server, newest snapshot tick received, deltas are based on it
+    Ack { tick: u64 },
+
+    // lobby, see lobby.rs
+    ListRooms,
+    RoomList { rooms: Vec<RoomInfo> },
+    CreateRoom { name: String, max_players: u8 },
+    JoinRoom { room: u32 },
+    LeaveRoom,
+    SetReady { ready: bool },
+    // host only, everyone else has to be ready
+    StartGame,
+    FindMatch { size: u8 },
+    CancelMatch,
+    // server -> every member whenever the room changes
+    RoomUpdate(RoomInfo),
+    // snapshots for the room follow, Input ticks start again from 1
+    GameStarted { room: u32 },
+    LobbyError { reason: String },
+}
+
+
+pub fn encode(message: &Message) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    // serialising into a Vec cannot fail for these types
+    out.extend(bincode::DefaultOptions::new().serialize(message).unwrap());
+    out
+}
+
+pub fn decode(data: &[u8]) -> anyhow::Result<Message> {
+    if data.len() < HEADER_LEN || &data[..2] != MAGIC {
+        anyhow::bail!("bad message header");
+    }
+
+    // a newer peer may send variants we do not know, or a layout we cannot read
+    let version = u16::from_le_bytes([data[2], data[3]]);
+    if version < MIN_PROTOCOL_VERSION || version > PROTOCOL_VERSION {
+        anyhow::bail!("protocol version {} is not supported, expected {} to {}", version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION);
+    }
+
+    Ok(bincode::DefaultOptions::new().deserialize(&data[HEADER_LEN..])?)
+}
@@ -1,11 +1,20 @@
 #![allow(warnings)]
 
 
-#[path="physics.rs"]
-mod physics;
-
 mod setup;
-use setup::Shaders;
+use setup::{default_font, Shaders};
+
+#[path="path.rs"]
+pub mod path;
+pub use path::*;
+
+#[path="text.rs"]
+pub mod text;
+pub use text::*;
+
+#[path="tilemap.rs"]
+pub mod tilemap;
+pub use tilemap::*;
 
 extern crate hecs;
 use hecs::*;
@@ -41,8 +50,6 @@ extern crate bytemuck;
 use bytemuck::*;
 
 
-use super::{default_shader, test_shader};
-
 
 #[repr(C)]
 #[derive(bytemuck::Pod, bytemuck::Zeroable, Default, Debug, Clone, Copy)]
@@ -93,6 +100,12 @@ pub struct RenderWebGpu<'s> {
     pub pipeline: Vec<RenderPipeline>,
     pub buffer: Vec<Buffer>,
     pub shader: Option<ShaderModule>,
+    pub text: Option<TextRenderer>,
+    // drawn over the meshes and under the overlay
+    pub tilemap: Option<TilemapRenderer>,
+    // triangles rebuilt every frame (gui, debug draw), drawn over the meshes
+    pub overlay: Vec<Vertex>,
+    pub overlay_buffer: Option<Buffer>,
 }
 
 
@@ -125,7 +138,7 @@ impl<'s> RenderWebGpu<'s> {
     pub fn create_shader(&mut self) {
         let shader = self.webgpu_config.device().create_shader_module(ShaderModuleDescriptor {
             label: None,
-            source: ShaderSource::Wgsl(std::borrow::Cow::Borrowed(test_shader))
+            source: ShaderSource::Wgsl(std::borrow::Cow::Borrowed(Shaders::Test.source()))
         });
 
         self.shader = Some(shader);
@@ -168,9 +181,10 @@ impl<'s> RenderWebGpu<'s> {
 
             primitive: wgpu::PrimitiveState {
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                // tessellated paths come out in both windings
+                cull_mode: None,
                 polygon_mode: wgpu::PolygonMode::Fill,
-                topology: PrimitiveTopology::PointList,
+                topology: PrimitiveTopology::TriangleList,
                 ..Default::default()
             },
 
@@ -186,6 +200,92 @@ impl<'s> RenderWebGpu<'s> {
     }
 
 
+    pub fn draw_overlay(&mut self, data: &[Vertex]) {
+        self.overlay.extend_from_slice(data);
+    }
+
+    //
+    //  text is off until the first font is loaded
+    //
+    pub fn enable_text(&mut self, mode: GlyphMode) {
+        let format = self.webgpu_config.surface_format.unwrap_or(TextureFormat::Rgba8UnormSrgb);
+        self.text = Some(TextRenderer::new(self.webgpu_config.device(), format, mode));
+    }
+
+    pub fn load_font(&mut self, data: &[u8]) -> anyhow::Result<FontId> {
+        Ok(self.add_font(Font::from_bytes(data)?))
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        if self.text.is_none() {
+            self.enable_text(GlyphMode::Bitmap);
+        }
+        self.text.as_mut().unwrap().add_font(font)
+    }
+
+    //
+    //  (x, y) is the top-left corner of the text in window pixels
+    //
+    pub fn draw_text(&mut self, font: FontId, text: &str, x: f32, y: f32, style: &TextStyle) {
+        let screen = self.webgpu_config.size();
+        if let Some(t) = self.text.as_mut() {
+            t.queue(font, text, x, y, style, screen);
+        }
+    }
+
+    pub fn measure_text(&self, font: FontId, text: &str, style: &TextStyle) -> (f32, f32) {
+        self.text.as_ref().and_then(|t| t.measure(font, text, style)).unwrap_or((0.0, 0.0))
+    }
+
+    //
+    //  built-in loading screen: covers the scene with a progress bar and, once a font is there,
+    //  the label under it. queue it instead of the game's own overlay, then draw()
+    //
+    pub fn draw_loading(&mut self, fraction: f32, label: &str, font: Option<FontId>) {
+        let quad = |x0: f32, y0: f32, x1: f32, y1: f32, color: [f32; 3]| {
+            let (a, b, c, d) = (Vertex::new(x0, y0, 0.0, color), Vertex::new(x1, y0, 0.0, color), Vertex::new(x1, y1, 0.0, color), Vertex::new(x0, y1, 0.0, color));
+            [a, b, c, a, c, d]
+        };
+
+        let fraction = fraction.clamp(0.0, 1.0);
+        self.draw_overlay(&quad(-1.0, -1.0, 1.0, 1.0, [0.02, 0.02, 0.05]));
+        self.draw_overlay(&quad(-0.5, -0.02, 0.5, 0.02, [0.15, 0.15, 0.2]));
+        self.draw_overlay(&quad(-0.5, -0.02, -0.5 + fraction, 0.02, [0.3, 0.6, 1.0]));
+
+        if let Some(font) = font {
+            let (w, h) = self.webgpu_config.size();
+            let text = format!("{} {:.0}%", label, fraction * 100.0);
+            let style = TextStyle::new(16.0);
+            let (tw, _) = self.measure_text(font, &text, &style);
+            self.draw_text(font, &text, (w as f32 - tw) * 0.5, h as f32 * 0.5 + 20.0, &style);
+        }
+    }
+
+    //
+    //  the tilemap renderer is made on the first map
+    //
+    pub fn set_tilemap(&mut self, map: &crate::tiled::Map) {
+        let format = self.webgpu_config.surface_format.unwrap_or(TextureFormat::Rgba8UnormSrgb);
+        let device = self.webgpu_config.device.as_ref().unwrap();
+        self.tilemap.get_or_insert_with(|| TilemapRenderer::new(device, format)).set_map(device, map);
+    }
+
+    pub fn set_tileset_image(&mut self, tileset: usize, image: &crate::assets::Texture) {
+        if let Some(t) = self.tilemap.as_mut() {
+            t.set_texture(self.webgpu_config.device.as_ref().unwrap(), self.webgpu_config.queue.as_ref().unwrap(), tileset, image);
+        }
+    }
+
+    //
+    //  (x, y) in the world at the centre of the screen, `scale` pixels per world unit
+    //
+    pub fn set_tile_camera(&mut self, x: f32, y: f32, scale: f32) {
+        let screen = self.webgpu_config.size();
+        if let Some(t) = self.tilemap.as_mut() {
+            t.set_camera(self.webgpu_config.queue.as_ref().unwrap(), x, y, scale, screen);
+        }
+    }
+
     pub fn update_vertex(&mut self, index: usize, data: Vec<Vertex>) {
         self.vertex[index] = data.clone();
         self.webgpu_config.queue.as_mut().unwrap().write_buffer(&self.buffer[index], 0, bytemuck::cast_slice(&data));
@@ -197,6 +297,31 @@ impl<'s> RenderWebGpu<'s> {
         let mut output = self.webgpu_config.surface.as_ref().unwrap().get_current_texture().unwrap();
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+        let overlay_len = self.overlay.len() as u32;
+        if overlay_len > 0 {
+            let bytes: &[u8] = bytemuck::cast_slice(&self.overlay);
+            let too_small = match &self.overlay_buffer {
+                Some(b) => b.size() < bytes.len() as BufferAddress,
+                None => true,
+            };
+
+            if too_small {
+                self.overlay_buffer = Some(self.webgpu_config.device().create_buffer(&BufferDescriptor {
+                    label: Some("Overlay Vertex Buffer"),
+                    size: (bytes.len() as BufferAddress).next_power_of_two(),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+
+            self.webgpu_config.queue.as_ref().unwrap().write_buffer(self.overlay_buffer.as_ref().unwrap(), 0, bytes);
+            self.overlay.clear();
+        }
+
+        if let Some(t) = self.text.as_mut() {
+            t.prepare(self.webgpu_config.device.as_ref().unwrap(), self.webgpu_config.queue.as_ref().unwrap());
+        }
+
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -215,12 +340,23 @@ impl<'s> RenderWebGpu<'s> {
 
             rpass.set_pipeline(&self.pipeline.last().unwrap());
 
-            for i in &self.buffer {
-                for j in &self.vertex {
-                    let l = j.len() as u32;
-                    rpass.set_vertex_buffer(0, i.slice(..));
-                    rpass.draw(0..l, 0..1);
-                }
+            for (buf, data) in self.buffer.iter().zip(&self.vertex) {
+                let l = data.len() as u32;
+                rpass.set_vertex_buffer(0, buf.slice(..));
+                rpass.draw(0..l, 0..1);
+            }
+
+            if let Some(t) = &self.tilemap {
+                t.render(&mut rpass);
+            }
+
+            if overlay_len > 0 {
+                rpass.set_vertex_buffer(0, self.overlay_buffer.as_ref().unwrap().slice(..));
+                rpass.draw(0..overlay_len, 0..1);
+            }
+
+            if let Some(t) = &self.text {
+                t.render(&mut rpass);
             }
 
         }    
@@ -382,6 +518,13 @@ impl<'s> ConfigWebGPU<'s> {
         }
     }
 
+    pub fn size(&self) -> (u32, u32) {
+        match &self.surface_config {
+            Some(c) => (c.width, c.height),
+            None => (0, 0),
+        }
+    }
+
     fn window(&self) -> &Window {
         self.window.as_ref().unwrap()
     }
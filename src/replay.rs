@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+
+use crate::scene::Scene;
+
+
+//
+//  input recording: one InputFrame per simulation tick + the rng seed and the level it starts
+//  from. the same build fed the same frames produces the same Physics state
+//
+
+
+pub const REPLAY_VERSION: u32 = 2;
+const REPLAY_MAGIC: &[u8; 4] = b"YORP";
+
+
+
+//
+//  what gameplay sees of the input for one tick
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub pressed: Vec<String>,
+    pub held: Vec<String>,
+    pub axes: Vec<(String, f32)>,
+    // pointer in world units
+    pub pointer: [f32; 2],
+}
+
+impl InputFrame {
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.iter().any(|a| a == action)
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.held.iter().any(|a| a == action)
+    }
+
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axes.iter().find(|(a, _)| a == axis).map(|(_, v)| *v).unwrap_or(0.0)
+    }
+
+    //
+    //  presses that happened between ticks are kept until a tick uses them
+    //
+    pub fn merge_pressed(&mut self, newer: InputFrame) {
+        let mut pressed = std::mem::take(&mut self.pressed);
+        for p in newer.pressed.iter() {
+            if !pressed.contains(p) {
+                pressed.push(p.clone());
+            }
+        }
+        *self = newer;
+        self.pressed = pressed;
+    }
+
+    //
+    //  a frame from a remote client, with the axes clamped to -1..1 the way Input::axis does it
+    //  locally. None when an axis or the pointer is not a finite number
+    //
+    pub fn checked(mut self) -> Option<InputFrame> {
+        if !self.pointer.iter().all(|v| v.is_finite()) || !self.axes.iter().all(|(_, v)| v.is_finite()) {
+            return None;
+        }
+        for (_, v) in self.axes.iter_mut() {
+            *v = v.clamp(-1.0, 1.0);
+        }
+        Some(self)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub tick_dt: f32,
+    // scene json the first tick starts from, the built-in level when None
+    pub scene: Option<String>,
+    pub frames: Vec<InputFrame>,
+    // Physics state hash after every tick, used to find the first divergent tick
+    pub hashes: Vec<u64>,
+}
+
+impl Replay {
+
+    pub fn new(seed: u64, tick_dt: f32) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            tick_dt,
+            scene: None,
+            frames: vec![],
+            hashes: vec![],
+        }
+    }
+
+    pub fn with_scene(mut self, scene: &Scene) -> anyhow::Result<Self> {
+        self.scene = Some(serde_json::to_string(scene)?);
+        Ok(self)
+    }
+
+    pub fn scene(&self) -> anyhow::Result<Option<Scene>> {
+        self.scene.as_deref().map(Scene::from_json).transpose()
+    }
+
+    pub fn push(&mut self, frame: InputFrame, hash: u64) {
+        self.frames.push(frame);
+        self.hashes.push(hash);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = REPLAY_MAGIC.to_vec();
+        out.extend(bincode::serialize(self)?);
+        Ok(out)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Replay> {
+        if data.len() < 4 || &data[..4] != REPLAY_MAGIC {
+            anyhow::bail!("not a replay file");
+        }
+
+        let replay: Replay = bincode::deserialize(&data[4..])?;
+        if replay.version != REPLAY_VERSION {
+            anyhow::bail!("replay version {} is not supported, expected {}", replay.version, REPLAY_VERSION);
+        }
+
+        Ok(replay)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Replay> {
+        Replay::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    pub pos: usize,
+}
+
+impl ReplayPlayer {
+
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer { replay, pos: 0 }
+    }
+
+    // hash recorded for the tick just returned by next()
+    pub fn expected_hash(&self) -> Option<u64> {
+        self.replay.hashes.get(self.pos.checked_sub(1)?).copied()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.pos >= self.replay.frames.len()
+    }
+}
+
+impl Iterator for ReplayPlayer {
+    type Item = InputFrame;
+
+    fn next(&mut self) -> Option<InputFrame> {
+        let f = self.replay.frames.get(self.pos).cloned();
+        self.pos += 1;
+        f
+    }
+}
+
+
+//
+//  recording / playback state shared by the game loop and console commands
+//
+#[derive(Default)]
+pub struct ReplayState {
+    pub recording: Option<Replay>,
+    pub last: Option<Replay>,
+    pub playing: Option<ReplayPlayer>,
+    // the game loop rebuilds the simulation with this seed before the next tick
+    pub reset_seed: Option<u64>,
+}
+
+impl ReplayState {
+
+    // anything changing the simulation outside of the input would not be in the recording
+    pub fn active(&self) -> bool {
+        self.recording.is_some() || self.playing.is_some()
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, ensure, Context};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::rng::Rng;
+use crate::scene::Scene;
+use crate::sim::Sim;
+
+
+//
+//  save games: the level as it is now (a captured Scene, bodies with their velocities), the
+//  tick and rng, and whatever else the game puts in `data`. json, in localStorage in the
+//  browser and one file per slot on native
+//
+//  every save records the version it was written with. loading an older one runs the
+//  migrations from that version up, one step at a time, on the json before it is read.
+//  when the format changes: bump SAVE_VERSION and add the step from the old version to
+//  Migrations::builtin()
+//
+//  rapier's contact and solver caches are not saved, a loaded game carries on from the same
+//  positions and velocities but is not bit for bit the run that was saved
+//
+
+
+pub const SAVE_VERSION: u32 = 1;
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub tick: u64,
+    pub seed: u64,
+    pub rng: u64,
+    pub scene: Scene,
+    // game state outside the world: progress, settings, inventory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub data: BTreeMap<String, Value>,
+}
+
+impl SaveGame {
+
+    //
+    //  everything but players and Transient entities
+    //
+    pub fn capture(sim: &Sim) -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            tick: sim.tick,
+            seed: sim.seed,
+            rng: sim.rng.state(),
+            scene: Scene::capture(&sim.world, &sim.physics),
+            data: BTreeMap::new(),
+        }
+    }
+
+    pub fn restore(&self) -> anyhow::Result<Sim> {
+        let mut sim = Sim::from_scene(self.seed, &self.scene)?;
+        sim.tick = self.tick;
+        sim.rng = Rng::new(self.rng);
+        Ok(sim)
+    }
+
+    pub fn set<T: Serialize>(&mut self, key: &str, value: &T) -> anyhow::Result<()> {
+        self.data.insert(key.to_string(), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    // None when missing, an error when it is there but not a T
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        match self.data.get(key) {
+            Some(v) => Ok(Some(serde_json::from_value(v.clone()).with_context(|| key.to_string())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    // older versions are migrated first
+    pub fn from_json(s: &str, migrations: &Migrations) -> anyhow::Result<SaveGame> {
+        let value = migrations.migrate(serde_json::from_str(s)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+
+
+pub type Migration = Box<dyn Fn(&mut Value) -> anyhow::Result<()>>;
+
+//
+//  steps from each version to the next, up to `current`. a step edits the json in place,
+//  the version number is bumped after it
+//
+pub struct Migrations {
+    pub current: u32,
+    steps: BTreeMap<u32, Migration>,
+}
+
+impl Migrations {
+
+    pub fn new(current: u32) -> Self {
+        Migrations { current, steps: BTreeMap::new() }
+    }
+
+    // the steps for SaveGame, none so far
+    pub fn builtin() -> Self {
+        Migrations::new(SAVE_VERSION)
+    }
+
+    // the step from version `from` to `from + 1`
+    pub fn with(mut self, from: u32, step: impl Fn(&mut Value) -> anyhow::Result<()> + 'static) -> Self {
+        self.steps.insert(from, Box::new(step));
+        self
+    }
+
+    pub fn migrate(&self, mut value: Value) -> anyhow::Result<Value> {
+        let version = value.get("version").and_then(|v| v.as_u64()).ok_or_else(|| anyhow!("save has no version"))? as u32;
+        ensure!(version <= self.current, "save is from version {}, newer than this game's {}", version, self.current);
+
+        for v in version..self.current {
+            let step = self.steps.get(&v).ok_or_else(|| anyhow!("no migration from save version {}", v))?;
+            step(&mut value).with_context(|| format!("migrating save version {} to {}", v, v + 1))?;
+            ensure!(value.is_object(), "migrating save version {} left no object", v);
+            value["version"] = Value::from(v + 1);
+        }
+        Ok(value)
+    }
+}
+
+
+
+//
+//  where saves are kept, by slot name
+//
+pub trait SaveStore {
+    fn read(&self, slot: &str) -> anyhow::Result<Option<String>>;
+    fn write(&mut self, slot: &str, data: &str) -> anyhow::Result<()>;
+    fn remove(&mut self, slot: &str) -> anyhow::Result<()>;
+    fn slots(&self) -> anyhow::Result<Vec<String>>;
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    pub slots: BTreeMap<String, String>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SaveStore for MemoryStore {
+    fn read(&self, slot: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.slots.get(slot).cloned())
+    }
+
+    fn write(&mut self, slot: &str, data: &str) -> anyhow::Result<()> {
+        self.slots.insert(slot.to_string(), data.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, slot: &str) -> anyhow::Result<()> {
+        self.slots.remove(slot);
+        Ok(())
+    }
+
+    fn slots(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.slots.keys().cloned().collect())
+    }
+}
+
+
+//
+//  native: <dir>/<slot>.json, written next to it first and renamed so a crash mid-write
+//  keeps the old save
+//
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStore {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStore {
+    pub fn new(dir: &str) -> Self {
+        FileStore { dir: dir.into() }
+    }
+
+    fn path(&self, slot: &str) -> std::path::PathBuf {
+        self.dir.join(format!("{}.json", slot))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SaveStore for FileStore {
+    fn read(&self, slot: &str) -> anyhow::Result<Option<String>> {
+        let path = self.path(slot);
+        match std::fs::read_to_string(&path) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| path.display().to_string()),
+        }
+    }
+
+    fn write(&mut self, slot: &str, data: &str) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir).with_context(|| self.dir.display().to_string())?;
+        let path = self.path(slot);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data).with_context(|| tmp.display().to_string())?;
+        std::fs::rename(&tmp, &path).with_context(|| path.display().to_string())
+    }
+
+    fn remove(&mut self, slot: &str) -> anyhow::Result<()> {
+        let path = self.path(slot);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).with_context(|| path.display().to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn slots(&self) -> anyhow::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).with_context(|| self.dir.display().to_string()),
+        };
+
+        let mut out: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().and_then(|n| n.strip_suffix(".json")).map(|n| n.to_string()))
+            .collect();
+        out.sort();
+        Ok(out)
+    }
+}
+
+
+//
+//  browser: localStorage under "<prefix><slot>". about 5 MB for the whole site, plenty for
+//  json levels
+//
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+pub struct LocalStorageStore {
+    prefix: String,
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+impl LocalStorageStore {
+    pub fn new(prefix: &str) -> Self {
+        LocalStorageStore { prefix: prefix.to_string() }
+    }
+
+    fn storage(&self) -> anyhow::Result<web_sys::Storage> {
+        web_sys::window()
+            .and_then(|w| w.local_storage().ok().flatten())
+            .ok_or_else(|| anyhow!("localStorage is not available"))
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+impl SaveStore for LocalStorageStore {
+    fn read(&self, slot: &str) -> anyhow::Result<Option<String>> {
+        self.storage()?.get_item(&format!("{}{}", self.prefix, slot)).map_err(|e| anyhow!("localStorage: {:?}", e))
+    }
+
+    fn write(&mut self, slot: &str, data: &str) -> anyhow::Result<()> {
+        // fails when the quota is used up
+        self.storage()?.set_item(&format!("{}{}", self.prefix, slot), data).map_err(|e| anyhow!("localStorage: {:?}", e))
+    }
+
+    fn remove(&mut self, slot: &str) -> anyhow::Result<()> {
+        self.storage()?.remove_item(&format!("{}{}", self.prefix, slot)).map_err(|e| anyhow!("localStorage: {:?}", e))
+    }
+
+    fn slots(&self) -> anyhow::Result<Vec<String>> {
+        let storage = self.storage()?;
+        let len = storage.length().map_err(|e| anyhow!("localStorage: {:?}", e))?;
+
+        let mut out: Vec<String> = (0..len)
+            .filter_map(|i| storage.key(i).ok().flatten())
+            .filter_map(|k| k.strip_prefix(&self.prefix).map(|s| s.to_string()))
+            .collect();
+        out.sort();
+        Ok(out)
+    }
+}
+
+
+
+//
+//  save slots on top of a store, migrating old saves on load
+//
+pub struct Saves {
+    store: Box<dyn SaveStore>,
+    migrations: Migrations,
+}
+
+impl Saves {
+
+    pub fn new(store: Box<dyn SaveStore>) -> Self {
+        Saves { store, migrations: Migrations::builtin() }
+    }
+
+    //
+    //  localStorage keys starting "<name>/" in the browser, the directory `name` on native
+    //
+    pub fn platform(name: &str) -> Self {
+        #[cfg(all(target_arch = "wasm32", feature = "client"))]
+        let store: Box<dyn SaveStore> = Box::new(LocalStorageStore::new(&format!("{}/", name)));
+        #[cfg(not(target_arch = "wasm32"))]
+        let store: Box<dyn SaveStore> = Box::new(FileStore::new(name));
+        #[cfg(all(target_arch = "wasm32", not(feature = "client")))]
+        let store: Box<dyn SaveStore> = Box::new(MemoryStore::new());
+        Saves::new(store)
+    }
+
+    pub fn migrations(mut self, migrations: Migrations) -> Self {
+        self.migrations = migrations;
+        self
+    }
+
+    pub fn save(&mut self, slot: &str, game: &SaveGame) -> anyhow::Result<()> {
+        check_slot(slot)?;
+        self.store.write(slot, &game.to_json()?).with_context(|| format!("saving {}", slot))
+    }
+
+    // None for an empty slot
+    pub fn load(&self, slot: &str) -> anyhow::Result<Option<SaveGame>> {
+        check_slot(slot)?;
+        match self.store.read(slot)? {
+            Some(s) => Ok(Some(SaveGame::from_json(&s, &self.migrations).with_context(|| format!("loading {}", slot))?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove(&mut self, slot: &str) -> anyhow::Result<()> {
+        check_slot(slot)?;
+        self.store.remove(slot)
+    }
+
+    pub fn slots(&self) -> anyhow::Result<Vec<String>> {
+        self.store.slots()
+    }
+}
+
+// slot names end up in file names and storage keys
+fn check_slot(slot: &str) -> anyhow::Result<()> {
+    ensure!(!slot.is_empty() && slot.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "bad save slot {:?}, use letters, digits, - and _", slot);
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Context};
+use hecs::*;
+use rapier2d::prelude::ColliderHandle;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::Asset;
+use crate::components::*;
+use crate::physics::*;
+
+
+//
+//  levels as json: the gravity, colliders fixed in the world and a list of entities with their
+//  components, rigid body and colliders. spawn() builds it into a World + Physics, capture()
+//  goes the other way so a level tweaked in the console can be saved back out
+//
+//      {
+//        "controlled": "ball",
+//        "colliders": [ { "shape": { "type": "Cuboid", "hx": 100.0, "hy": 0.0 } } ],
+//        "entities": [
+//          {
+//            "name": "ball",
+//            "transform": { "x": 0.0, "y": 10.0 },
+//            "circle": { "radius": 0.5, "color": [1.0, 1.0, 1.0] },
+//            "body": { "kind": "Dynamic" },
+//            "colliders": [ { "shape": { "type": "Ball", "radius": 0.5 }, "restitution": 0.7 } ],
+//            "assets": { "sound": "sfx/bounce.wav" }
+//          }
+//        ]
+//      }
+//
+//  everything but the entity list can be left out
+//
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub gravity: [f32; 2],
+    // name of the entity single player input drives
+    pub controlled: Option<String>,
+    pub colliders: Vec<ColliderDesc>,
+    pub entities: Vec<EntityDesc>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            gravity: [0.0, -9.81],
+            controlled: None,
+            colliders: vec![],
+            entities: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntityDesc {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub circle: Option<Circle>,
+    pub body: Option<BodyDesc>,
+    // attached to the body, or fixed at the transform without one
+    pub colliders: Vec<ColliderDesc>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub assets: BTreeMap<String, String>,
+}
+
+
+impl Scene {
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(s: &str) -> anyhow::Result<Scene> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Scene> {
+        let s = std::fs::read_to_string(path).with_context(|| path.to_string())?;
+        Scene::from_json(&s).with_context(|| path.to_string())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_json()?).with_context(|| path.to_string())
+    }
+
+    // every asset path the entities refer to, for preloading
+    pub fn assets(&self) -> BTreeSet<&str> {
+        self.entities.iter().flat_map(|e| e.assets.values().map(|p| p.as_str())).collect()
+    }
+
+
+    //
+    //  adds to what is already there, gravity is replaced. the entities come back in file order.
+    //  nothing is left behind when it fails
+    //
+    pub fn spawn(&self, world: &mut World, physics: &mut Physics) -> anyhow::Result<Vec<Entity>> {
+        let (gravity, ball) = (physics.gravity(), physics.ball());
+
+        let mut spawned = Spawned::default();
+        match self.spawn_into(world, physics, &mut spawned) {
+            Ok(()) => Ok(spawned.entities),
+            Err(e) => {
+                spawned.undo(world, physics);
+                physics.set_gravity(gravity.0, gravity.1);
+                physics.set_ball(ball);
+                Err(e)
+            }
+        }
+    }
+
+    fn spawn_into(&self, world: &mut World, physics: &mut Physics, out: &mut Spawned) -> anyhow::Result<()> {
+        physics.set_gravity(self.gravity[0], self.gravity[1]);
+
+        for c in &self.colliders {
+            out.colliders.push(physics.add_collider(c, None)?);
+        }
+
+        // names need not be unique, the first body with it is the one
+        let mut controlled = self.controlled.clone();
+
+        for (i, desc) in self.entities.iter().enumerate() {
+            let label = desc.name.clone().unwrap_or_else(|| format!("entity {}", i));
+            let e = desc.spawn_into(world, physics, out).with_context(|| label)?;
+
+            if desc.name.is_some() && desc.name == controlled {
+                if let Ok(b) = world.get::<&Body>(e) {
+                    physics.set_ball(b.0);
+                    controlled = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+
+    //
+    //  the current state as a scene. players belong to the session, not the level, so they are
+    //  left out, as is anything Transient
+    //
+    pub fn capture(world: &World, physics: &Physics) -> Scene {
+        let (gx, gy) = physics.gravity();
+        let mut scene = Scene {
+            gravity: [gx, gy],
+            colliders: physics.describe_colliders(None),
+            ..Default::default()
+        };
+
+        let mut entities: Vec<_> = world.iter().filter(|e| !e.has::<Player>() && !e.has::<Transient>()).collect();
+        // hecs iterates by archetype, sort for a stable file
+        entities.sort_by_key(|e| e.entity().id());
+
+        for e in entities {
+            let body = e.get::<&Body>().map(|b| b.0);
+            let name = e.get::<&Name>().map(|n| n.0.clone());
+
+            if body.is_some() && body == Some(physics.ball()) {
+                scene.controlled = name.clone();
+            }
+
+            scene.entities.push(EntityDesc {
+                name,
+                transform: e.get::<&Transform>().map(|t| *t).unwrap_or_default(),
+                circle: e.get::<&Circle>().map(|c| *c),
+                body: body.and_then(|b| physics.describe_body(b)),
+                colliders: body.map(|b| physics.describe_colliders(Some(b))).unwrap_or_default(),
+                assets: e.get::<&AssetRefs>().map(|a| a.0.clone()).unwrap_or_default(),
+            });
+        }
+
+        scene
+    }
+}
+
+
+impl EntityDesc {
+
+    pub fn spawn(&self, world: &mut World, physics: &mut Physics) -> anyhow::Result<Entity> {
+        let mut spawned = Spawned::default();
+        self.spawn_into(world, physics, &mut spawned).inspect_err(|_| {
+            spawned.undo(world, physics);
+        })
+    }
+
+    //
+    //  spawn, adding the entity and any fixed colliders to `out` so a caller spawning several
+    //  can take them all out again. a failed spawn leaves what it added so far in `out`
+    //
+    pub fn spawn_into(&self, world: &mut World, physics: &mut Physics, out: &mut Spawned) -> anyhow::Result<Entity> {
+        let t = self.transform;
+        let mut builder = EntityBuilder::new();
+        builder.add(t);
+
+        if let Some(name) = &self.name {
+            builder.add(Name(name.clone()));
+        }
+        if let Some(circle) = self.circle {
+            builder.add(circle);
+        }
+        if !self.assets.is_empty() {
+            builder.add(AssetRefs(self.assets.clone()));
+        }
+
+        match &self.body {
+            Some(desc) => {
+                let handle = physics.add_body(desc, t.x, t.y, t.rotation);
+                for c in &self.colliders {
+                    if let Err(e) = physics.add_collider(c, Some(handle)) {
+                        physics.remove_body(handle);
+                        return Err(e);
+                    }
+                }
+                builder.add(Body(handle));
+            }
+            None => {
+                // no body to follow, the colliders are placed at the transform once
+                for c in &self.colliders {
+                    let (sin, cos) = t.rotation.sin_cos();
+                    let mut c = c.clone();
+                    c.offset = [t.x + c.offset[0] * cos - c.offset[1] * sin, t.y + c.offset[0] * sin + c.offset[1] * cos];
+                    c.rotation += t.rotation;
+                    out.colliders.push(physics.add_collider(&c, None)?);
+                }
+            }
+        }
+
+        let e = world.spawn(builder.build());
+        out.entities.push(e);
+        Ok(e)
+    }
+}
+
+
+//
+//  what spawning added to the world and physics, for rolling back a spawn that failed part way
+//
+#[derive(Debug, Default)]
+pub struct Spawned {
+    pub entities: Vec<Entity>,
+    // fixed in the world, of the scene or of entities without a body
+    pub colliders: Vec<ColliderHandle>,
+}
+
+impl Spawned {
+
+    pub fn undo(self, world: &mut World, physics: &mut Physics) {
+        for e in self.entities {
+            if let Ok(b) = world.get::<&Body>(e).map(|b| b.0) {
+                physics.remove_body(b);
+            }
+            world.despawn(e).ok();
+        }
+        for c in self.colliders {
+            physics.remove_collider(c);
+        }
+    }
+}
+
+
+impl Asset for Scene {
+    fn decode(path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        let s = std::str::from_utf8(bytes).map_err(|_| anyhow!("{} is not utf-8", path))?;
+        Scene::from_json(s)
+    }
+}
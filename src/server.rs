@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+
+use log::{info, warn};
+
+use crate::lobby::*;
+use crate::net::NetStats;
+use crate::protocol::*;
+use crate::replay::*;
+use crate::sim::*;
+use crate::snapshot::*;
+use crate::transport::*;
+
+
+//
+//  authoritative server: every running room owns a Sim, all of them step at a fixed tick
+//  clients send Input, the server answers with Snapshot deltas against their last Ack
+//  chat is relayed to the room (or to everyone outside of rooms)
+//  runs on any Listener, so tests can drive it in-process through LoopbackListener
+//
+
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub seed: u64,
+    pub tick_dt: f32,
+    // broadcast a snapshot every n ticks
+    pub snapshot_every: u64,
+    // inputs buffered per client, older ones are dropped
+    pub max_inputs: usize,
+    // a client that sends nothing for this long is dropped, Net pings well within it
+    pub timeout_ms: f64,
+}
+
+impl ServerConfig {
+
+    pub fn new(seed: u64) -> Self {
+        ServerConfig {
+            seed,
+            tick_dt: TICK_DT,
+            snapshot_every: 2,
+            max_inputs: 8,
+            timeout_ms: 10000.0,
+        }
+    }
+
+    pub fn snapshot_every(mut self, ticks: u64) -> Self {
+        self.snapshot_every = ticks.max(1);
+        self
+    }
+
+    pub fn max_inputs(mut self, n: usize) -> Self {
+        self.max_inputs = n.max(1);
+        self
+    }
+
+    pub fn timeout(mut self, ms: f64) -> Self {
+        self.timeout_ms = ms;
+        self
+    }
+}
+
+
+
+pub struct Client {
+    pub id: u32,
+    pub name: String,
+    // Hello received
+    pub joined: bool,
+    // last client input tick applied
+    pub ack: u64,
+    // newest snapshot the client confirmed, the base for its deltas
+    pub acked: Option<u64>,
+    // traffic with this client, rtt stays None, the server does not ping
+    pub stats: NetStats,
+    transport: Box<dyn Transport>,
+    inputs: VecDeque<(u64, InputFrame)>,
+    // repeated while the client sends nothing, without the one-shot presses
+    last_input: InputFrame,
+    // when anything last arrived from the client
+    last_seen: f64,
+    closed: bool,
+}
+
+impl Client {
+    pub fn send(&mut self, message: &Message) -> bool {
+        let data = encode(message);
+        self.send_raw(&data)
+    }
+
+    fn send_raw(&mut self, data: &[u8]) -> bool {
+        if !self.transport.send(data) {
+            return false;
+        }
+        self.stats.bytes_out += data.len() as u64;
+        self.stats.messages_out += 1;
+        true
+    }
+
+    // a new game: input ticks and snapshot acks start over
+    fn reset(&mut self) {
+        self.ack = 0;
+        self.acked = None;
+        self.inputs.clear();
+        self.last_input = InputFrame::default();
+    }
+
+    //
+    //  one input per tick, a stalled client keeps holding what it held
+    //
+    fn next_input(&mut self) -> InputFrame {
+        match self.inputs.pop_front() {
+            Some((tick, frame)) => {
+                self.ack = tick;
+                self.last_input = InputFrame { pressed: vec![], ..frame.clone() };
+                frame
+            }
+            None => self.last_input.clone(),
+        }
+    }
+}
+
+
+pub struct Server {
+    pub config: ServerConfig,
+    pub lobby: Lobby,
+    pub clients: Vec<Client>,
+    listener: Box<dyn Listener>,
+    step: FixedStep,
+    last_update: Option<f64>,
+    now: f64,
+    next_id: u32,
+}
+
+impl Server {
+
+    pub fn new(listener: Box<dyn Listener>, config: ServerConfig) -> Self {
+        Server {
+            lobby: Lobby::new(config.seed),
+            step: FixedStep::new(config.tick_dt),
+            config,
+            clients: vec![],
+            listener,
+            last_update: None,
+            now: 0.0,
+            next_id: 1,
+        }
+    }
+
+    //
+    //  network + as many ticks as `now` asks for, never blocks
+    //
+    pub fn update(&mut self, now_ms: f64) {
+        self.now = now_ms;
+        self.poll();
+
+        let elapsed = now_ms - self.last_update.unwrap_or(now_ms);
+        self.last_update = Some(now_ms);
+
+        for _ in 0..self.step.advance(elapsed) {
+            self.tick();
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let every = self.config.snapshot_every;
+
+        for room in self.lobby.rooms.iter_mut() {
+            let sim = match room.sim.as_mut() {
+                Some(s) => s,
+                None => continue,
+            };
+
+            // room order is join order, the same every tick
+            let mut inputs = vec![];
+            for p in &room.players {
+                if let Some(c) = self.clients.iter_mut().find(|c| c.id == p.id) {
+                    inputs.push((c.id, c.next_input()));
+                }
+            }
+            sim.step_players(&inputs);
+
+            if sim.tick % every == 0 {
+                let entities = quantise(&capture(sim));
+                let tick = sim.tick;
+
+                for c in self.clients.iter_mut().filter(|c| room.has(c.id)) {
+                    let base = c.acked.and_then(|t| room.history.get(t).map(|b| (t, b)));
+                    let delta = diff(base, &entities, tick, c.ack);
+                    c.send(&Message::Snapshot(delta));
+                }
+
+                room.history.push(tick, entities);
+            }
+        }
+    }
+
+    // (client, message) pairs coming back from the lobby
+    fn deliver(&mut self, out: Vec<(u32, Message)>) {
+        for (to, m) in out {
+            if let Some(c) = self.clients.iter_mut().find(|c| c.id == to) {
+                if let Message::GameStarted { .. } = m {
+                    c.reset();
+                }
+                c.send(&m);
+            }
+        }
+    }
+
+    fn poll(&mut self) {
+        while let Some(transport) = self.listener.accept() {
+            info!("client {} connected", self.next_id);
+            self.clients.push(Client {
+                id: self.next_id,
+                name: String::new(),
+                joined: false,
+                ack: 0,
+                acked: None,
+                stats: NetStats::default(),
+                transport,
+                inputs: VecDeque::new(),
+                last_input: InputFrame::default(),
+                last_seen: self.now,
+                closed: false,
+            });
+            self.next_id += 1;
+        }
+
+        let mut relay = vec![];
+        let mut lobby = vec![];
+        let max_inputs = self.config.max_inputs;
+        let timeout_ms = self.config.timeout_ms;
+        let now = self.now;
+
+        for c in self.clients.iter_mut() {
+            c.transport.set_time(now);
+            for e in c.transport.poll() {
+                if let TransportEvent::Data(data) = &e {
+                    c.stats.bytes_in += data.len() as u64;
+                    c.stats.messages_in += 1;
+                    c.last_seen = now;
+                }
+                match e {
+                    TransportEvent::Data(data) => match decode(&data) {
+                        Ok(Message::Ping { time }) => { c.send(&Message::Pong { time }); },
+                        Ok(Message::Pong { .. }) => (),
+                        Ok(Message::Hello { name }) => {
+                            info!("client {} is {}", c.id, name);
+                            c.name = name;
+                            c.joined = true;
+                            c.send(&Message::Welcome { id: c.id });
+                        }
+                        // the client's own clamping cannot be trusted
+                        Ok(Message::Input { tick, frame }) => match frame.checked() {
+                            Some(frame) => {
+                                if c.inputs.len() >= max_inputs {
+                                    c.inputs.pop_front();
+                                }
+                                c.inputs.push_back((tick, frame));
+                            }
+                            None => warn!("client {}: dropped input {} with a value that is not finite", c.id, tick),
+                        },
+                        Ok(Message::Ack { tick }) => {
+                            c.acked = Some(c.acked.map_or(tick, |t| t.max(tick)));
+                        }
+                        Ok(m) if !c.joined => warn!("client {}: {:?} before Hello", c.id, m),
+                        Ok(Message::Chat { .. }) => relay.push((c.id, data)),
+                        Ok(m) => lobby.push((c.id, c.name.clone(), m)),
+                        Err(err) => warn!("client {}: dropped message: {}", c.id, err),
+                    },
+                    TransportEvent::Close(code, reason) => {
+                        info!("client {} disconnected ({} {})", c.id, code, reason);
+                        c.closed = true;
+                    }
+                    TransportEvent::Error(err) => warn!("client {}: {}", c.id, err),
+                    TransportEvent::Text(_) => c.last_seen = now,
+                    TransportEvent::Open => (),
+                }
+            }
+
+            // went quiet without closing, its room slot is free again
+            if !c.closed && now - c.last_seen > timeout_ms {
+                info!("client {} timed out", c.id);
+                c.transport.close();
+                c.closed = true;
+            }
+        }
+
+        for (id, name, m) in lobby {
+            let before = self.lobby.room_of(id).map(|r| r.id);
+            let out = self.lobby.handle(id, &name, &m);
+            if self.lobby.room_of(id).map(|r| r.id) != before {
+                if let Some(c) = self.clients.iter_mut().find(|c| c.id == id) {
+                    c.reset();
+                }
+            }
+            self.deliver(out);
+        }
+
+        let closed: Vec<u32> = self.clients.iter().filter(|c| c.closed).map(|c| c.id).collect();
+        self.clients.retain(|c| !c.closed);
+        for id in closed {
+            let out = self.lobby.leave(id);
+            self.deliver(out);
+        }
+
+        for (from, data) in relay {
+            let room = self.lobby.room_of(from).map(|r| r.id);
+            for c in self.clients.iter_mut().filter(|c| c.id != from) {
+                if self.lobby.room_of(c.id).map(|r| r.id) == room {
+                    c.send_raw(&data);
+                }
+            }
+        }
+    }
+}
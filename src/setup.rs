@@ -16,32 +16,86 @@ pub fn setup_canvas(window: &Window) {
         let doc = win.document().unwrap();
         let body = doc.get_element_by_id("main-body").unwrap();
         let canvas = web_sys::Element::from(window.canvas().unwrap());
+
+        // touches go to the game instead of scrolling and zooming the page
+        canvas.set_attribute("style", "touch-action: none");
         body.append_child(&canvas);
     }
 }
 
 
 //
-//  function for get message with channel
+//  ?name=value from the page url, always None on native
+//
+pub fn url_param(name: &str) -> Option<String> {
+
+    #[cfg(target_arch = "wasm32")] {
+        let search = web_sys::window().unwrap().location().search().unwrap_or_default();
+        if let Ok(params) = web_sys::UrlSearchParams::new_with_str(&search) {
+            return params.get(name);
+        }
+    }
+
+    None
+}
+
+//
+//  writes the file next to the game, a browser downloads it instead
 //
-pub fn setup_onmessage(ws: WebSocket) -> Receiver<String> {
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+    std::fs::write(name, bytes)?;
+    Ok(())
+}
 
-    
-    let (sx, rx) = channel::<String>();
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+    use web_sys::js_sys::{Array, Uint8Array};
+    let js = |e: wasm_bindgen::JsValue| anyhow::anyhow!("{:?}", e);
 
-    let closure = Closure::<dyn FnMut(MessageEvent)>::new(move |e: MessageEvent| {
-        sx.send(e.data().as_string().unwrap());
-    });
-    ws.set_onmessage(Some(closure.as_ref().unchecked_ref()));
-    closure.forget();
+    let parts = Array::of1(&Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(js)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js)?;
 
-    rx
+    let doc = web_sys::window().unwrap().document().unwrap();
+    let a: web_sys::HtmlAnchorElement = doc.create_element("a").map_err(js)?.dyn_into().map_err(|_| anyhow::anyhow!("not an anchor"))?;
+    a.set_href(&url);
+    a.set_download(name);
+    a.click();
+    web_sys::Url::revoke_object_url(&url).map_err(js)?;
+    Ok(())
+}
+
+
+//
+//  server url, can be overridden with ?server=ws://host:port in the page url
+//
+pub fn server_url(default: &str) -> String {
+    url_param("server").unwrap_or_else(|| default.to_string())
 }
 
 pub const default_shader: &str = include_str!("shaders/default.wgsl");
 pub const test_shader: &str = include_str!("shaders/test.wgsl");
+pub const text_shader: &str = include_str!("shaders/text.wgsl");
+pub const tile_shader: &str = include_str!("shaders/tile.wgsl");
+
+// ui text works before any asset has loaded
+pub const default_font: &[u8] = include_bytes!("fonts/DejaVuSansMono.ttf");
 
 pub enum Shaders {
     Default,
     Test,
+    Text,
+    Tile,
+}
+
+impl Shaders {
+    pub fn source(&self) -> &'static str {
+        match self {
+            Shaders::Default => default_shader,
+            Shaders::Test => test_shader,
+            Shaders::Text => text_shader,
+            Shaders::Tile => tile_shader,
+        }
+    }
 }
\ No newline at end of file
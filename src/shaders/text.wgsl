@@ -0,0 +1,33 @@
+struct VSOut {
+    @builtin(position) Position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) color: vec4f,
+};
+
+@group(0) @binding(0) var atlas: texture_2d<f32>;
+@group(0) @binding(1) var atlas_sampler: sampler;
+
+@vertex
+fn vs_main(@location(0) inPos: vec2f,
+           @location(1) inUv: vec2f,
+           @location(2) inColor: vec4f) -> VSOut {
+    var vsOut: VSOut;
+    vsOut.Position = vec4f(inPos, 0.0, 1.0);
+    vsOut.uv = inUv;
+    vsOut.color = inColor;
+    return vsOut;
+}
+
+@fragment
+fn fs_bitmap(@location(0) inUv: vec2f, @location(1) inColor: vec4f) -> @location(0) vec4f {
+    let coverage = textureSample(atlas, atlas_sampler, inUv).r;
+    return vec4f(inColor.rgb, inColor.a * coverage);
+}
+
+@fragment
+fn fs_sdf(@location(0) inUv: vec2f, @location(1) inColor: vec4f) -> @location(0) vec4f {
+    let dist = textureSample(atlas, atlas_sampler, inUv).r;
+    let width = max(fwidth(dist), 0.0001);
+    let alpha = smoothstep(0.5 - width, 0.5 + width, dist);
+    return vec4f(inColor.rgb, inColor.a * alpha);
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+struct Camera {
+    // world units to clip space
+    scale: vec2f,
+    offset: vec2f,
+};
+
+struct Layer {
+    opacity: f32,
+};
+
+struct VSOut {
+    @builtin(position) Position: vec4f,
+    @location(0) uv: vec2f,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var tiles: texture_2d<f32>;
+@group(1) @binding(1) var tiles_sampler: sampler;
+@group(2) @binding(0) var<uniform> layer: Layer;
+
+@vertex
+fn vs_main(@location(0) inPos: vec2f,
+           @location(1) inUv: vec2f) -> VSOut {
+    var vsOut: VSOut;
+    vsOut.Position = vec4f(inPos * camera.scale + camera.offset, 0.0, 1.0);
+    vsOut.uv = inUv;
+    return vsOut;
+}
+
+@fragment
+fn fs_main(@location(0) inUv: vec2f) -> @location(0) vec4f {
+    let color = textureSample(tiles, tiles_sampler, inUv);
+    return vec4f(color.rgb, color.a * layer.opacity);
+}
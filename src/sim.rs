@@ -0,0 +1,311 @@
+extern crate hecs;
+use hecs::*;
+
+use crate::components::*;
+use crate::physics::*;
+use crate::replay::*;
+use crate::rng::*;
+use crate::scene::*;
+use crate::prefab::*;
+
+extern crate rapier2d;
+use rapier2d::prelude::RigidBodyHandle;
+
+
+//
+//  gameplay simulation, everything that must be deterministic lives here
+//  no winit / wgpu / web-sys so it runs headless
+//
+
+
+pub const TICK_DT: f32 = 1.0 / 60.0;
+// never run more ticks than this per rendered frame
+const MAX_TICKS_PER_FRAME: u32 = 5;
+// remote players could hold "spawn" down forever, past this many their oldest ball goes
+pub const MAX_SPAWNS_PER_PLAYER: usize = 16;
+pub const SPAWN_COOLDOWN_TICKS: u64 = 15;
+
+
+
+pub struct Sim {
+    pub world: World,
+    pub physics: Physics,
+    pub rng: Rng,
+    pub tick: u64,
+    pub seed: u64,
+}
+
+impl Sim {
+
+    pub fn new(seed: u64) -> Self {
+        let mut physics = Physics::new();
+        physics.set_timestep(TICK_DT);
+
+        let mut world = World::new();
+        world.spawn((
+            Name("ball".to_string()),
+            Transform::default(),
+            Body(physics.ball()),
+            Circle { radius: 0.5, color: [1.0, 1.0, 1.0] },
+        ));
+
+        Sim {
+            world,
+            physics,
+            rng: Rng::new(seed),
+            tick: 0,
+            seed,
+        }
+    }
+
+    //
+    //  a level from a scene file instead of the built-in ground and ball
+    //
+    pub fn from_scene(seed: u64, scene: &Scene) -> anyhow::Result<Self> {
+        let mut physics = Physics::empty();
+        physics.set_timestep(TICK_DT);
+
+        let mut world = World::new();
+        scene.spawn(&mut world, &mut physics)?;
+
+        Ok(Sim {
+            world,
+            physics,
+            rng: Rng::new(seed),
+            tick: 0,
+            seed,
+        })
+    }
+
+    //
+    //  where a recording starts: its level and its tick length
+    //
+    pub fn from_replay(replay: &Replay) -> anyhow::Result<Self> {
+        let mut sim = match replay.scene()? {
+            Some(scene) => Sim::from_scene(replay.seed, &scene)?,
+            None => Sim::new(replay.seed),
+        };
+        sim.physics.set_timestep(replay.tick_dt);
+        Ok(sim)
+    }
+
+    //
+    //  single player, the input drives the built-in ball
+    //
+    pub fn step(&mut self, input: &InputFrame) {
+        let ball = self.physics.ball();
+        self.apply_input(ball, input, None);
+        self.advance();
+    }
+
+    //
+    //  multiplayer, every input drives the body of its Player
+    //  callers pass inputs in a fixed order (by id) to stay deterministic
+    //
+    pub fn step_players(&mut self, inputs: &[(u32, InputFrame)]) {
+        for (id, input) in inputs {
+            if let Some(body) = self.player_body(*id) {
+                self.apply_input(body, input, Some(*id));
+            }
+        }
+        self.advance();
+    }
+
+    pub fn add_player(&mut self, id: u32) -> Entity {
+        let x = self.rng.range(-4.0, 4.0);
+        let handle = self.physics.add_ball(x, 10.0, 0.5);
+        let color = [self.rng.range(0.3, 1.0), self.rng.range(0.3, 1.0), self.rng.range(0.3, 1.0)];
+
+        self.world.spawn((
+            Name(format!("player {}", id)),
+            Transform::new(x, 10.0),
+            Body(handle),
+            Circle { radius: 0.5, color },
+            Player(id),
+        ))
+    }
+
+    // the player's ball and every ball it spawned
+    pub fn remove_player(&mut self, id: u32) {
+        let found = self.world.query::<&Player>().iter()
+            .find(|(_, p)| p.0 == id)
+            .map(|(e, _)| e);
+
+        if let Some(e) = found {
+            self.remove_entity(e);
+        }
+
+        for (e, _) in self.spawned_by(id) {
+            self.remove_entity(e);
+        }
+    }
+
+    // despawn together with the rigid body
+    pub fn remove_entity(&mut self, e: Entity) {
+        if let Ok(b) = self.world.get::<&Body>(e).map(|b| b.0) {
+            self.physics.remove_body(b);
+        }
+        let _ = self.world.despawn(e);
+    }
+
+    pub fn player_body(&self, id: u32) -> Option<RigidBodyHandle> {
+        self.world.query::<(&Player, &Body)>().iter()
+            .find(|(_, (p, _))| p.0 == id)
+            .map(|(_, (_, b))| b.0)
+    }
+
+    fn apply_input(&mut self, body: RigidBodyHandle, input: &InputFrame, player: Option<u32>) {
+        self.physics.apply_impulse(body, input.axis("move_x") * 0.1, 0.0);
+        if input.pressed("jump") {
+            self.physics.apply_impulse(body, 0.0, 5.0);
+        }
+
+        if input.pressed("spawn") && self.may_spawn(player) {
+            let [x, y] = input.pointer;
+            if let Some(player) = player {
+                self.make_room(player);
+            }
+            let e = self.spawn_ball(x, y, 0.5);
+            if let Some(player) = player {
+                self.world.insert_one(e, SpawnedBy { player, tick: self.tick }).ok();
+            }
+        }
+    }
+
+    // the local player spawns freely, remote ones once per SPAWN_COOLDOWN_TICKS
+    fn may_spawn(&self, player: Option<u32>) -> bool {
+        match player {
+            Some(p) => self.spawned_by(p).iter().all(|(_, tick)| self.tick >= tick + SPAWN_COOLDOWN_TICKS),
+            None => true,
+        }
+    }
+
+    // the oldest balls of a player at MAX_SPAWNS_PER_PLAYER go before a new one comes
+    fn make_room(&mut self, player: u32) {
+        let mut spawned = self.spawned_by(player);
+        spawned.sort_by_key(|(e, tick)| (*tick, e.id()));
+
+        let extra = (spawned.len() + 1).saturating_sub(MAX_SPAWNS_PER_PLAYER);
+        for (e, _) in spawned.into_iter().take(extra) {
+            self.remove_entity(e);
+        }
+    }
+
+    // balls spawned by a remote player and the tick of each
+    fn spawned_by(&self, player: u32) -> Vec<(Entity, u64)> {
+        self.world.query::<&SpawnedBy>().iter()
+            .filter(|(_, s)| s.player == player)
+            .map(|(e, s)| (e, s.tick))
+            .collect()
+    }
+
+    fn advance(&mut self) {
+        self.physics.update_physics();
+        for (_, (t, b)) in self.world.query_mut::<(&mut Transform, &Body)>() {
+            if let Some((x, y, r)) = self.physics.body_transform(b.0) {
+                *t = Transform { x, y, rotation: r };
+            }
+        }
+
+        self.tick += 1;
+    }
+
+    pub fn spawn_ball(&mut self, x: f32, y: f32, radius: f32) -> Entity {
+        let handle = self.physics.add_ball(x, y, radius);
+        let color = [self.rng.range(0.3, 1.0), self.rng.range(0.3, 1.0), self.rng.range(0.3, 1.0)];
+
+        self.world.spawn((
+            Name("ball".to_string()),
+            Transform::new(x, y),
+            Body(handle),
+            Circle { radius, color },
+        ))
+    }
+
+    pub fn spawn_prefab(&mut self, prefabs: &Prefabs, instance: &Instance) -> anyhow::Result<Vec<Entity>> {
+        prefabs.spawn_prefab(&mut self.world, &mut self.physics, instance)
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        self.physics.state_hash()
+    }
+}
+
+
+
+//
+//  turns real elapsed time into a whole number of fixed ticks
+//
+pub struct FixedStep {
+    pub dt_ms: f64,
+    acc: f64,
+}
+
+impl FixedStep {
+
+    pub fn new(dt: f32) -> Self {
+        FixedStep {
+            dt_ms: dt as f64 * 1000.0,
+            acc: 0.0,
+        }
+    }
+
+    pub fn advance(&mut self, elapsed_ms: f64) -> u32 {
+        self.acc += elapsed_ms.max(0.0);
+
+        let mut ticks = 0;
+        while self.acc >= self.dt_ms && ticks < MAX_TICKS_PER_FRAME {
+            self.acc -= self.dt_ms;
+            ticks += 1;
+        }
+
+        // drop time we could not catch up with instead of spiralling
+        if ticks == MAX_TICKS_PER_FRAME {
+            self.acc = self.acc.min(self.dt_ms);
+        }
+
+        ticks
+    }
+
+    // 0..1 between the last and the next tick, for interpolation
+    pub fn alpha(&self) -> f32 {
+        (self.acc / self.dt_ms) as f32
+    }
+}
+
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    pub ticks: usize,
+    pub final_hash: u64,
+    // first tick whose hash differs from the recorded one
+    pub first_mismatch: Option<usize>,
+}
+
+//
+//  headless playback, used by the replay binary and regression tests
+//
+pub fn run_replay(replay: &Replay) -> anyhow::Result<ReplayReport> {
+    let mut sim = Sim::from_replay(replay)?;
+    let mut first_mismatch = None;
+
+    for (i, frame) in replay.frames.iter().enumerate() {
+        sim.step(frame);
+
+        let hash = sim.state_hash();
+        if first_mismatch.is_none() {
+            if let Some(expected) = replay.hashes.get(i) {
+                if *expected != hash {
+                    first_mismatch = Some(i);
+                }
+            }
+        }
+    }
+
+    Ok(ReplayReport {
+        ticks: replay.frames.len(),
+        final_hash: sim.state_hash(),
+        first_mismatch,
+    })
+}
@@ -0,0 +1,436 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::components::*;
+use crate::sim::*;
+
+
+//
+//  what the server tells clients about the world after a tick
+//
+//  capture -> quantise -> diff against the last snapshot the client acked -> SnapshotDelta
+//  client: SnapshotHistory::receive patches it back together, Interpolator plays it back a bit late
+//
+
+
+// 1/512 world unit
+pub const POS_SCALE: f32 = 512.0;
+// +-512 units/s fits an i16
+pub const VEL_SCALE: f32 = 64.0;
+pub const SIZE_SCALE: f32 = 256.0;
+// snapshots kept on both ends, an ack older than this means a full snapshot
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityState {
+    // hecs Entity::to_bits on the server
+    pub id: u64,
+    pub x: f32,
+    pub y: f32,
+    pub rotation: f32,
+    pub vx: f32,
+    pub vy: f32,
+    // angular velocity, prediction needs it to replay rolling bodies
+    pub spin: f32,
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub player: Option<u32>,
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    // last input tick of the receiving client the server has applied
+    pub ack: u64,
+    pub entities: Vec<EntityState>,
+}
+
+
+//
+//  every drawable entity, sorted by id so equal worlds give equal snapshots
+//  bodies are read straight from the physics sets, Transform only for entities without one
+//
+pub fn capture(sim: &Sim) -> Vec<EntityState> {
+    let mut out: Vec<EntityState> = sim.world.query::<(&Transform, &Circle, Option<&Body>, Option<&Player>)>().iter()
+        .map(|(e, (t, c, b, p))| {
+            let (x, y, rotation) = b.and_then(|b| sim.physics.body_transform(b.0)).unwrap_or((t.x, t.y, t.rotation));
+            let (vx, vy, spin) = b.and_then(|b| sim.physics.body_velocity(b.0)).unwrap_or((0.0, 0.0, 0.0));
+
+            EntityState {
+                id: e.to_bits().get(),
+                x,
+                y,
+                rotation,
+                vx,
+                vy,
+                spin,
+                radius: c.radius,
+                color: c.color,
+                player: p.map(|p| p.0),
+            }
+        })
+        .collect();
+
+    out.sort_by_key(|s| s.id);
+    out
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuantEntity {
+    pub id: u64,
+    pub x: i32,
+    pub y: i32,
+    // full turn = 65536
+    pub rotation: u16,
+    pub vx: i16,
+    pub vy: i16,
+    pub spin: i16,
+    pub radius: u16,
+    pub color: [u8; 3],
+    pub player: Option<u32>,
+}
+
+impl QuantEntity {
+
+    pub fn quantise(e: &EntityState) -> Self {
+        let turn = std::f32::consts::PI * 2.0;
+        let q16 = |v: f32, scale: f32| (v * scale).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+
+        QuantEntity {
+            id: e.id,
+            x: (e.x * POS_SCALE).round() as i32,
+            y: (e.y * POS_SCALE).round() as i32,
+            rotation: ((e.rotation.rem_euclid(turn) / turn) * 65536.0).round() as u32 as u16,
+            vx: q16(e.vx, VEL_SCALE),
+            vy: q16(e.vy, VEL_SCALE),
+            spin: q16(e.spin, VEL_SCALE),
+            radius: (e.radius * SIZE_SCALE).round().clamp(0.0, u16::MAX as f32) as u16,
+            color: [
+                (e.color[0].clamp(0.0, 1.0) * 255.0).round() as u8,
+                (e.color[1].clamp(0.0, 1.0) * 255.0).round() as u8,
+                (e.color[2].clamp(0.0, 1.0) * 255.0).round() as u8,
+            ],
+            player: e.player,
+        }
+    }
+
+    pub fn dequantise(&self) -> EntityState {
+        EntityState {
+            id: self.id,
+            x: self.x as f32 / POS_SCALE,
+            y: self.y as f32 / POS_SCALE,
+            rotation: self.rotation as f32 / 65536.0 * std::f32::consts::PI * 2.0,
+            vx: self.vx as f32 / VEL_SCALE,
+            vy: self.vy as f32 / VEL_SCALE,
+            spin: self.spin as f32 / VEL_SCALE,
+            radius: self.radius as f32 / SIZE_SCALE,
+            color: [
+                self.color[0] as f32 / 255.0,
+                self.color[1] as f32 / 255.0,
+                self.color[2] as f32 / 255.0,
+            ],
+            player: self.player,
+        }
+    }
+}
+
+pub fn quantise(entities: &[EntityState]) -> Vec<QuantEntity> {
+    entities.iter().map(QuantEntity::quantise).collect()
+}
+
+
+
+//
+//  only the fields that changed, x / y are relative to the base entity
+//  an entity that is not in the base carries every field as an absolute value
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityDelta {
+    pub id: u64,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub rotation: Option<u16>,
+    pub vx: Option<i16>,
+    pub vy: Option<i16>,
+    pub spin: Option<i16>,
+    // (radius, color, player), rarely changes
+    pub shape: Option<(u16, [u8; 3], Option<u32>)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotDelta {
+    pub tick: u64,
+    // tick of the snapshot this is relative to, None = full snapshot
+    pub base: Option<u64>,
+    pub ack: u64,
+    pub changed: Vec<EntityDelta>,
+    pub removed: Vec<u64>,
+}
+
+
+fn changed<T: PartialEq + Copy>(old: T, new: T) -> Option<T> {
+    if old != new { Some(new) } else { None }
+}
+
+fn find(list: &[QuantEntity], id: u64) -> Option<&QuantEntity> {
+    list.binary_search_by_key(&id, |e| e.id).ok().map(|i| &list[i])
+}
+
+//
+//  both lists sorted by id, as capture() returns them
+//
+pub fn diff(base: Option<(u64, &[QuantEntity])>, current: &[QuantEntity], tick: u64, ack: u64) -> SnapshotDelta {
+    let mut out = SnapshotDelta {
+        tick,
+        base: base.map(|(t, _)| t),
+        ack,
+        changed: vec![],
+        removed: vec![],
+    };
+
+    let old_list = base.map(|(_, b)| b).unwrap_or(&[]);
+
+    for e in current {
+        let d = match find(old_list, e.id) {
+            Some(o) => EntityDelta {
+                id: e.id,
+                x: changed(o.x, e.x).map(|x| x.wrapping_sub(o.x)),
+                y: changed(o.y, e.y).map(|y| y.wrapping_sub(o.y)),
+                rotation: changed(o.rotation, e.rotation),
+                vx: changed(o.vx, e.vx),
+                vy: changed(o.vy, e.vy),
+                spin: changed(o.spin, e.spin),
+                shape: changed((o.radius, o.color, o.player), (e.radius, e.color, e.player)),
+            },
+            None => EntityDelta {
+                id: e.id,
+                x: Some(e.x),
+                y: Some(e.y),
+                rotation: Some(e.rotation),
+                vx: Some(e.vx),
+                vy: Some(e.vy),
+                spin: Some(e.spin),
+                shape: Some((e.radius, e.color, e.player)),
+            },
+        };
+
+        let unchanged = d.x.is_none() && d.y.is_none() && d.rotation.is_none()
+            && d.vx.is_none() && d.vy.is_none() && d.spin.is_none() && d.shape.is_none();
+        if !unchanged {
+            out.changed.push(d);
+        }
+    }
+
+    for o in old_list {
+        if find(current, o.id).is_none() {
+            out.removed.push(o.id);
+        }
+    }
+
+    out
+}
+
+//
+//  inverse of diff, `base` must be the snapshot delta.base refers to
+//
+pub fn patch(base: Option<&[QuantEntity]>, delta: &SnapshotDelta) -> Vec<QuantEntity> {
+    let mut out: Vec<QuantEntity> = base.unwrap_or(&[]).iter()
+        .filter(|e| !delta.removed.contains(&e.id))
+        .copied()
+        .collect();
+
+    for d in &delta.changed {
+        match out.binary_search_by_key(&d.id, |e| e.id) {
+            Ok(i) => {
+                let e = &mut out[i];
+                if let Some(x) = d.x { e.x = e.x.wrapping_add(x); }
+                if let Some(y) = d.y { e.y = e.y.wrapping_add(y); }
+                if let Some(r) = d.rotation { e.rotation = r; }
+                if let Some(vx) = d.vx { e.vx = vx; }
+                if let Some(vy) = d.vy { e.vy = vy; }
+                if let Some(w) = d.spin { e.spin = w; }
+                if let Some((radius, color, player)) = d.shape {
+                    e.radius = radius;
+                    e.color = color;
+                    e.player = player;
+                }
+            }
+            Err(i) => {
+                let (radius, color, player) = d.shape.unwrap_or_default();
+                out.insert(i, QuantEntity {
+                    id: d.id,
+                    x: d.x.unwrap_or(0),
+                    y: d.y.unwrap_or(0),
+                    rotation: d.rotation.unwrap_or(0),
+                    vx: d.vx.unwrap_or(0),
+                    vy: d.vy.unwrap_or(0),
+                    spin: d.spin.unwrap_or(0),
+                    radius,
+                    color,
+                    player,
+                });
+            }
+        }
+    }
+
+    out
+}
+
+
+
+//
+//  recent quantised snapshots by tick
+//  server: what was sent, client: what was received, deltas are resolved against it
+//
+pub struct SnapshotHistory {
+    entries: VecDeque<(u64, Vec<QuantEntity>)>,
+    cap: usize,
+}
+
+impl SnapshotHistory {
+
+    pub fn new(cap: usize) -> Self {
+        SnapshotHistory {
+            entries: VecDeque::new(),
+            cap: cap.max(1),
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, entities: Vec<QuantEntity>) {
+        if self.entries.len() >= self.cap {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((tick, entities));
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&[QuantEntity]> {
+        self.entries.iter().rev().find(|(t, _)| *t == tick).map(|(_, e)| e.as_slice())
+    }
+
+    //
+    //  client side, None when the base has already been forgotten
+    //
+    pub fn receive(&mut self, delta: &SnapshotDelta) -> Option<Snapshot> {
+        let entities = match delta.base {
+            Some(t) => patch(Some(self.get(t)?), delta),
+            None => patch(None, delta),
+        };
+
+        let snapshot = Snapshot {
+            tick: delta.tick,
+            ack: delta.ack,
+            entities: entities.iter().map(|e| e.dequantise()).collect(),
+        };
+
+        self.push(delta.tick, entities);
+        Some(snapshot)
+    }
+}
+
+
+
+//
+//  plays snapshots back `delay_ms` behind the newest one and blends between the two around it
+//  a longer delay hides more jitter and loss at the cost of latency
+//
+pub struct Interpolator {
+    pub delay_ms: f64,
+    tick_ms: f64,
+    buffer: VecDeque<Snapshot>,
+    // local clock - server clock, the smallest one seen is the least delayed
+    offset: Option<f64>,
+}
+
+impl Interpolator {
+
+    pub fn new(delay_ms: f64, tick_dt: f32) -> Self {
+        Interpolator {
+            delay_ms,
+            tick_ms: tick_dt as f64 * 1000.0,
+            buffer: VecDeque::new(),
+            offset: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.offset = None;
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot, now: f64) {
+        let sample = now - snapshot.tick as f64 * self.tick_ms;
+        self.offset = Some(match self.offset {
+            // creep up slowly so one fast packet does not pin the clock forever
+            Some(o) if sample >= o => o + (sample - o) * 0.05,
+            _ => sample,
+        });
+
+        match self.buffer.iter().position(|s| s.tick >= snapshot.tick) {
+            Some(i) if self.buffer[i].tick == snapshot.tick => (),
+            Some(i) => self.buffer.insert(i, snapshot),
+            None => self.buffer.push_back(snapshot),
+        }
+
+        while self.buffer.len() > SNAPSHOT_HISTORY {
+            self.buffer.pop_front();
+        }
+    }
+
+    pub fn sample(&self, now: f64) -> Option<Snapshot> {
+        let offset = self.offset?;
+        let target = (now - offset - self.delay_ms) / self.tick_ms;
+
+        let first = self.buffer.front()?;
+        if target <= first.tick as f64 {
+            return Some(first.clone());
+        }
+
+        for (a, b) in self.buffer.iter().zip(self.buffer.iter().skip(1)) {
+            if target < b.tick as f64 {
+                let t = ((target - a.tick as f64) / (b.tick - a.tick) as f64) as f32;
+                return Some(lerp_snapshot(a, b, t));
+            }
+        }
+
+        // ran out of snapshots, hold the newest one
+        self.buffer.back().cloned()
+    }
+}
+
+
+fn lerp_snapshot(a: &Snapshot, b: &Snapshot, t: f32) -> Snapshot {
+    let turn = std::f32::consts::PI * 2.0;
+
+    let entities = b.entities.iter().map(|e| {
+        match a.entities.binary_search_by_key(&e.id, |o| o.id) {
+            Ok(i) => {
+                let o = &a.entities[i];
+                // shortest way around
+                let dr = (e.rotation - o.rotation + turn * 1.5).rem_euclid(turn) - turn * 0.5;
+
+                EntityState {
+                    x: o.x + (e.x - o.x) * t,
+                    y: o.y + (e.y - o.y) * t,
+                    rotation: o.rotation + dr * t,
+                    vx: o.vx + (e.vx - o.vx) * t,
+                    vy: o.vy + (e.vy - o.vy) * t,
+                    spin: o.spin + (e.spin - o.spin) * t,
+                    ..e.clone()
+                }
+            }
+            Err(_) => e.clone(),
+        }
+    }).collect();
+
+    Snapshot {
+        tick: b.tick,
+        ack: b.ack,
+        entities,
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+
+use hecs::*;
+
+use crate::audio::*;
+use crate::components::*;
+use crate::physics::*;
+
+
+//
+//  2d positional audio: an Emitter on an entity plays its sound from that entity's Transform,
+//  heard by the entity with the Listener (the camera, or the player). distance sets the volume,
+//  the side sets the pan, and with Bodies on either end the closing speed bends the pitch
+//
+//  SpatialAudio::update() once per frame owns the voices, despawning an emitter stops its sound
+//
+
+
+//
+//  distance models, same shapes as OpenAL's clamped ones. full volume up to `min`
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rolloff {
+    None,
+    // silent at `max`
+    Linear { min: f32, max: f32 },
+    // min / (min + factor * (d - min)), never quite silent
+    Inverse { min: f32, factor: f32 },
+    // (d / min) ^ -factor
+    Exponential { min: f32, factor: f32 },
+}
+
+impl Rolloff {
+    pub fn gain(&self, distance: f32) -> f32 {
+        match *self {
+            Rolloff::None => 1.0,
+            Rolloff::Linear { min, max } => {
+                if max <= min {
+                    return if distance <= min { 1.0 } else { 0.0 };
+                }
+                1.0 - (distance.clamp(min, max) - min) / (max - min)
+            }
+            Rolloff::Inverse { min, factor } => {
+                let min = min.max(0.001);
+                min / (min + factor * (distance.max(min) - min))
+            }
+            Rolloff::Exponential { min, factor } => {
+                let min = min.max(0.001);
+                (distance.max(min) / min).powf(-factor)
+            }
+        }
+    }
+}
+
+
+
+#[derive(Clone)]
+pub struct Emitter {
+    pub sound: Sound,
+    // volume and pitch are multiplied by the spatial ones, pan is replaced
+    pub play: Play,
+    pub rolloff: Rolloff,
+    // 0 = off, 1 = physical
+    pub doppler: f32,
+    started: bool,
+}
+
+impl Emitter {
+
+    pub fn new(sound: Sound, play: Play) -> Self {
+        Emitter {
+            sound,
+            play,
+            rolloff: Rolloff::Inverse { min: 1.0, factor: 1.0 },
+            doppler: 0.0,
+            started: false,
+        }
+    }
+
+    pub fn rolloff(mut self, rolloff: Rolloff) -> Self {
+        self.rolloff = rolloff;
+        self
+    }
+
+    pub fn doppler(mut self, doppler: f32) -> Self {
+        self.doppler = doppler.max(0.0);
+        self
+    }
+
+    // play the sound again on the next update, one-shots only play once otherwise
+    pub fn restart(&mut self) {
+        self.started = false;
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    // sideways distance at which a sound is fully in one ear
+    pub pan_width: f32,
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Listener { pan_width: 10.0 }
+    }
+}
+
+
+
+pub struct SpatialAudio {
+    // world units per second, the default treats a unit as a metre
+    pub speed_of_sound: f32,
+    // used when no entity has a Listener
+    pub listener: Transform,
+    voices: HashMap<Entity, Voice>,
+}
+
+impl Default for SpatialAudio {
+    fn default() -> Self {
+        SpatialAudio::new()
+    }
+}
+
+impl SpatialAudio {
+
+    pub fn new() -> Self {
+        SpatialAudio {
+            speed_of_sound: 343.0,
+            listener: Transform::default(),
+            voices: HashMap::new(),
+        }
+    }
+
+    pub fn voice(&self, e: Entity) -> Option<Voice> {
+        self.voices.get(&e).copied()
+    }
+
+    // the world was replaced, entity ids mean nothing any more
+    pub fn clear(&mut self, mixer: &mut Mixer) {
+        for (_, v) in self.voices.drain() {
+            mixer.stop(v);
+        }
+    }
+
+    pub fn update(&mut self, world: &mut World, physics: &Physics, mixer: &mut Mixer) {
+        let velocity = |body: Option<&Body>| {
+            body.and_then(|b| physics.body_velocity(b.0)).map_or((0.0, 0.0), |(vx, vy, _)| (vx, vy))
+        };
+
+        let (lt, listener, lv) = world.query::<(&Transform, &Listener, Option<&Body>)>().iter()
+            .next()
+            .map(|(_, (t, l, b))| (*t, *l, velocity(b)))
+            .unwrap_or((self.listener, Listener::default(), (0.0, 0.0)));
+
+        let mut alive = HashSet::new();
+
+        for (e, (t, em, body)) in world.query_mut::<(&Transform, &mut Emitter, Option<&Body>)>() {
+            let (dx, dy) = (t.x - lt.x, t.y - lt.y);
+            let distance = (dx * dx + dy * dy).sqrt();
+
+            let volume = em.play.volume * em.rolloff.gain(distance);
+            let pan = (dx / listener.pan_width.max(0.001)).clamp(-1.0, 1.0);
+
+            //
+            //  f' = f (c + v_listener . u) / (c + v_source . u), u from the listener to the source
+            //
+            let mut pitch = em.play.pitch;
+            if em.doppler > 0.0 && distance > 0.001 {
+                let (ux, uy) = (dx / distance, dy / distance);
+                let sv = velocity(body);
+                let c = self.speed_of_sound.max(1.0);
+                let towards = (lv.0 * ux + lv.1 * uy) * em.doppler;
+                let away = (sv.0 * ux + sv.1 * uy) * em.doppler;
+                pitch *= ((c + towards) / (c + away).max(0.1 * c)).clamp(0.5, 2.0);
+            }
+
+            match self.voices.get(&e).copied() {
+                Some(v) if mixer.is_playing(v) => {
+                    mixer.set_volume(v, volume);
+                    mixer.set_pan(v, pan);
+                    mixer.set_pitch(v, pitch);
+                    alive.insert(e);
+                }
+                _ if !em.started => {
+                    em.started = true;
+                    let v = mixer.play(&em.sound, em.play.volume(volume).pan(pan).pitch(pitch));
+                    self.voices.insert(e, v);
+                    alive.insert(e);
+                }
+                _ => (),
+            }
+        }
+
+        // despawned, lost their Emitter or finished
+        self.voices.retain(|e, v| {
+            if !alive.contains(e) {
+                mixer.stop(*v);
+            }
+            alive.contains(e)
+        });
+    }
+}
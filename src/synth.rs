@@ -0,0 +1,301 @@
+use std::f32::consts::PI;
+
+use crate::audio::*;
+use crate::rng::*;
+
+
+//
+//  sfxr style sound effects: one oscillator through an envelope, with pitch slide, vibrato
+//  and a single arpeggio jump. everything is rendered offline into a Sound, so a preset is
+//  a few numbers instead of a file and the same seed always gives the same sound
+//
+//     volume  /|‾‾‾‾\___
+//            / |    |   \___
+//           attack sustain decay        (punch lifts the start of the sustain)
+//
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wave {
+    Square,
+    Saw,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SynthParams {
+    pub wave: Wave,
+
+    // seconds
+    pub attack: f32,
+    pub sustain: f32,
+    pub decay: f32,
+    // 0..1 extra volume at the start of the sustain
+    pub punch: f32,
+
+    // Hz
+    pub freq: f32,
+    // the sound stops when a downward slide gets below this, 0 = never
+    pub min_freq: f32,
+    // octaves per second, and its change per second
+    pub slide: f32,
+    pub delta_slide: f32,
+
+    // fraction of the frequency, and Hz
+    pub vibrato_depth: f32,
+    pub vibrato_speed: f32,
+
+    // frequency multiplier applied once after arp_time seconds, 1 = off
+    pub arp_mult: f32,
+    pub arp_time: f32,
+
+    // square wave duty cycle 0..1, and its change per second
+    pub duty: f32,
+    pub duty_sweep: f32,
+
+    // one pole low pass 0..1, 1 = off
+    pub lowpass: f32,
+
+    pub volume: f32,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        SynthParams {
+            wave: Wave::Square,
+            attack: 0.0,
+            sustain: 0.1,
+            decay: 0.2,
+            punch: 0.0,
+            freq: 440.0,
+            min_freq: 0.0,
+            slide: 0.0,
+            delta_slide: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            arp_mult: 1.0,
+            arp_time: 0.0,
+            duty: 0.5,
+            duty_sweep: 0.0,
+            lowpass: 1.0,
+            volume: 0.5,
+        }
+    }
+}
+
+
+impl SynthParams {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // seconds
+    pub fn duration(&self) -> f32 {
+        self.attack + self.sustain + self.decay
+    }
+
+
+    //
+    //  presets, the rng picks a variation so repeated sounds do not all sound the same
+    //
+
+    pub fn jump(rng: &mut Rng) -> Self {
+        SynthParams {
+            wave: Wave::Square,
+            duty: rng.range(0.2, 0.6),
+            freq: rng.range(250.0, 450.0),
+            slide: rng.range(2.0, 4.0),
+            sustain: rng.range(0.05, 0.15),
+            decay: rng.range(0.1, 0.25),
+            ..Self::default()
+        }
+    }
+
+    pub fn coin(rng: &mut Rng) -> Self {
+        SynthParams {
+            wave: if rng.next_f32() < 0.5 { Wave::Square } else { Wave::Saw },
+            freq: rng.range(700.0, 1300.0),
+            sustain: rng.range(0.03, 0.1),
+            decay: rng.range(0.15, 0.35),
+            punch: rng.range(0.3, 0.6),
+            arp_mult: rng.range(1.3, 1.6),
+            arp_time: rng.range(0.04, 0.1),
+            ..Self::default()
+        }
+    }
+
+    pub fn explosion(rng: &mut Rng) -> Self {
+        SynthParams {
+            wave: Wave::Noise,
+            freq: rng.range(60.0, 200.0),
+            slide: rng.range(-1.5, -0.2),
+            sustain: rng.range(0.1, 0.3),
+            decay: rng.range(0.3, 0.6),
+            punch: rng.range(0.2, 0.8),
+            vibrato_depth: if rng.next_f32() < 0.5 { rng.range(0.0, 0.3) } else { 0.0 },
+            vibrato_speed: rng.range(5.0, 20.0),
+            lowpass: rng.range(0.3, 0.8),
+            ..Self::default()
+        }
+    }
+
+    pub fn laser(rng: &mut Rng) -> Self {
+        let freq = rng.range(500.0, 1500.0);
+        SynthParams {
+            wave: if rng.next_f32() < 0.5 { Wave::Square } else { Wave::Saw },
+            duty: rng.range(0.1, 0.5),
+            duty_sweep: rng.range(0.0, 1.0),
+            freq,
+            min_freq: freq * rng.range(0.1, 0.3),
+            slide: rng.range(-8.0, -3.0),
+            sustain: rng.range(0.05, 0.15),
+            decay: rng.range(0.05, 0.2),
+            ..Self::default()
+        }
+    }
+
+    pub fn hit(rng: &mut Rng) -> Self {
+        SynthParams {
+            wave: if rng.next_f32() < 0.5 { Wave::Noise } else { Wave::Square },
+            freq: rng.range(150.0, 600.0),
+            slide: rng.range(-6.0, -2.0),
+            sustain: rng.range(0.01, 0.05),
+            decay: rng.range(0.05, 0.15),
+            ..Self::default()
+        }
+    }
+
+    pub fn powerup(rng: &mut Rng) -> Self {
+        SynthParams {
+            wave: if rng.next_f32() < 0.5 { Wave::Square } else { Wave::Triangle },
+            freq: rng.range(200.0, 400.0),
+            slide: rng.range(1.0, 3.0),
+            vibrato_depth: rng.range(0.0, 0.1),
+            vibrato_speed: rng.range(8.0, 16.0),
+            sustain: rng.range(0.15, 0.3),
+            decay: rng.range(0.1, 0.3),
+            ..Self::default()
+        }
+    }
+
+    pub fn blip(rng: &mut Rng) -> Self {
+        SynthParams {
+            wave: if rng.next_f32() < 0.5 { Wave::Square } else { Wave::Sine },
+            freq: rng.range(600.0, 1200.0),
+            sustain: rng.range(0.02, 0.06),
+            decay: rng.range(0.01, 0.05),
+            ..Self::default()
+        }
+    }
+
+    pub const PRESETS: [&'static str; 7] = ["jump", "coin", "explosion", "laser", "hit", "powerup", "blip"];
+
+    pub fn preset(name: &str, rng: &mut Rng) -> Option<Self> {
+        Some(match name {
+            "jump" => Self::jump(rng),
+            "coin" => Self::coin(rng),
+            "explosion" => Self::explosion(rng),
+            "laser" => Self::laser(rng),
+            "hit" => Self::hit(rng),
+            "powerup" => Self::powerup(rng),
+            "blip" => Self::blip(rng),
+            _ => return None,
+        })
+    }
+
+    //
+    //  nudge every parameter by up to +-amount of itself
+    //
+    pub fn mutate(&self, rng: &mut Rng, amount: f32) -> Self {
+        let mut m = |v: f32| v * (1.0 + rng.range(-amount, amount));
+        SynthParams {
+            attack: m(self.attack),
+            sustain: m(self.sustain),
+            decay: m(self.decay),
+            punch: m(self.punch).clamp(0.0, 1.0),
+            freq: m(self.freq),
+            slide: m(self.slide),
+            delta_slide: m(self.delta_slide),
+            vibrato_depth: m(self.vibrato_depth),
+            vibrato_speed: m(self.vibrato_speed),
+            duty: m(self.duty).clamp(0.0, 1.0),
+            ..self.clone()
+        }
+    }
+
+
+    //
+    //  mono samples at `rate`, noise comes from `seed`
+    //
+    pub fn render(&self, rate: u32, seed: u64) -> Sound {
+        let rate = rate.max(1);
+        let dt = 1.0 / rate as f32;
+        let n = (self.duration().max(0.0) * rate as f32) as usize;
+
+        let mut rng = Rng::new(seed);
+        let mut noise = 0.0f32;
+        let mut phase = 0.0f32;
+        let mut filtered = 0.0f32;
+        let mut out = Vec::with_capacity(n);
+
+        for i in 0..n {
+            let t = i as f32 * dt;
+
+            let mut f = self.freq * 2f32.powf(self.slide * t + 0.5 * self.delta_slide * t * t);
+            if self.min_freq > 0.0 && f < self.min_freq {
+                break;
+            }
+            if self.arp_mult != 1.0 && t >= self.arp_time {
+                f *= self.arp_mult;
+            }
+            if self.vibrato_depth > 0.0 {
+                f *= 1.0 + self.vibrato_depth * (2.0 * PI * self.vibrato_speed * t).sin();
+            }
+
+            phase += f * dt;
+            if phase >= 1.0 {
+                phase -= phase.floor();
+            }
+
+            // white noise every sample, low passed at the frequency so it still colours the
+            // noise. holding one value per period instead buzzes at the frequency
+            let k = 1.0 - (-2.0 * PI * f * dt).exp();
+            noise += (rng.range(-1.0, 1.0) - noise) * k;
+
+            let s = match self.wave {
+                Wave::Square => {
+                    let duty = (self.duty + self.duty_sweep * t).clamp(0.05, 0.95);
+                    if phase < duty { 1.0 } else { -1.0 }
+                }
+                Wave::Saw => 2.0 * phase - 1.0,
+                Wave::Sine => (2.0 * PI * phase).sin(),
+                Wave::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+                // the filter takes power away, by k / (2 - k) for a one pole. the peaks it
+                // brings back are rounded off rather than clipped
+                Wave::Noise if k > 0.0 => (noise * ((2.0 - k) / k).sqrt()).tanh(),
+                Wave::Noise => 0.0,
+            };
+
+            filtered += (s - filtered) * self.lowpass.clamp(0.0, 1.0);
+            out.push(filtered * self.envelope(t) * self.volume);
+        }
+
+        Sound::new(rate, 1, out)
+    }
+
+    fn envelope(&self, t: f32) -> f32 {
+        if t < self.attack {
+            return t / self.attack;
+        }
+        let t = t - self.attack;
+        if t < self.sustain {
+            return 1.0 + self.punch * (1.0 - t / self.sustain);
+        }
+        let t = t - self.sustain;
+        if self.decay > 0.0 { (1.0 - t / self.decay).max(0.0) } else { 0.0 }
+    }
+}
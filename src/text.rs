@@ -0,0 +1,903 @@
+use std::collections::HashMap;
+
+extern crate fontdue;
+
+extern crate wgpu;
+use wgpu::*;
+
+use log::warn;
+
+use super::{default_font, Shaders};
+use crate::assets::Asset;
+
+
+//
+//  text: font -> glyph atlas -> layout -> textured quads
+//
+
+
+pub const ATLAS_SIZE: u32 = 1024;
+pub const SDF_BASE_PX: f32 = 48.0;
+pub const SDF_SPREAD: u32 = 6;
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontId(pub usize);
+
+#[derive(Clone)]
+pub struct Font {
+    inner: fontdue::Font,
+}
+
+impl Font {
+
+    //
+    //  ttf / otf bytes
+    //
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Font> {
+        let inner = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+            .map_err(|e| anyhow::anyhow!("font load error: {}", e))?;
+
+        Ok(Font { inner })
+    }
+
+    // the font built into the binary
+    pub fn builtin() -> Font {
+        Font::from_bytes(default_font).expect("built-in font")
+    }
+
+    pub fn line_metrics(&self, px: f32) -> (f32, f32, f32) {
+        match self.inner.horizontal_line_metrics(px) {
+            Some(m) => (m.ascent, m.descent, m.new_line_size),
+            None => (px * 0.8, -px * 0.2, px * 1.2),
+        }
+    }
+
+    fn glyph_index(&self, c: char) -> u16 {
+        self.inner.lookup_glyph_index(c)
+    }
+
+    fn advance(&self, index: u16, px: f32) -> f32 {
+        self.inner.metrics_indexed(index, px).advance_width
+    }
+
+    fn kern(&self, left: u16, right: u16, px: f32) -> f32 {
+        self.inner.horizontal_kern_indexed(left, right, px).unwrap_or(0.0)
+    }
+}
+
+impl Asset for Font {
+    fn decode(_path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        Font::from_bytes(bytes)
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlyphMode {
+    Bitmap,
+    // glyphs are rasterised once at SDF_BASE_PX and scaled to any size
+    Sdf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: usize,
+    index: u16,
+    // 0 for sdf glyphs
+    px: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlyphInfo {
+    // u0, v0, u1, v1
+    pub uv: [f32; 4],
+    // bitmap size and offset from the pen position, in raster pixels
+    pub size: [f32; 2],
+    pub offset: [f32; 2],
+    // px the glyph was rasterised at
+    pub raster_px: f32,
+}
+
+
+pub struct GlyphAtlas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub mode: GlyphMode,
+    pub dirty: bool,
+    glyphs: HashMap<GlyphKey, GlyphInfo>,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_h: u32,
+}
+
+impl GlyphAtlas {
+
+    pub fn new(width: u32, height: u32, mode: GlyphMode) -> Self {
+        GlyphAtlas {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize],
+            mode,
+            dirty: true,
+            glyphs: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_h: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = 0);
+        self.glyphs.clear();
+        self.shelf_x = 0;
+        self.shelf_y = 0;
+        self.shelf_h = 0;
+        self.dirty = true;
+    }
+
+    //
+    //  rasterise on first use, None when the atlas is full
+    //
+    pub fn glyph(&mut self, font_id: FontId, font: &Font, index: u16, px: f32) -> Option<GlyphInfo> {
+
+        let key = GlyphKey {
+            font: font_id.0,
+            index,
+            px: if self.mode == GlyphMode::Sdf { 0 } else { px.round() as u32 },
+        };
+
+        if let Some(info) = self.glyphs.get(&key) {
+            return Some(*info);
+        }
+
+        let raster_px = match self.mode {
+            GlyphMode::Bitmap => px.round().max(1.0),
+            GlyphMode::Sdf => SDF_BASE_PX,
+        };
+
+        let (metrics, coverage) = font.inner.rasterize_indexed(index, raster_px);
+
+        let (w, h, bitmap, pad) = match self.mode {
+            GlyphMode::Bitmap => (metrics.width as u32, metrics.height as u32, coverage, 0),
+            GlyphMode::Sdf => {
+                let (w, h, d) = sdf(&coverage, metrics.width, metrics.height, SDF_SPREAD);
+                (w, h, d, SDF_SPREAD as i32)
+            }
+        };
+
+        let (x, y) = self.allocate(w, h)?;
+        for row in 0..h {
+            let src = (row * w) as usize;
+            let dst = ((y + row) * self.width + x) as usize;
+            self.pixels[dst..dst + w as usize].copy_from_slice(&bitmap[src..src + w as usize]);
+        }
+        self.dirty = true;
+
+        let info = GlyphInfo {
+            uv: [
+                x as f32 / self.width as f32,
+                y as f32 / self.height as f32,
+                (x + w) as f32 / self.width as f32,
+                (y + h) as f32 / self.height as f32,
+            ],
+            size: [w as f32, h as f32],
+            // fontdue ymin is the bottom edge relative to the baseline, y grows up
+            offset: [
+                (metrics.xmin - pad) as f32,
+                -(metrics.ymin - pad + h as i32) as f32,
+            ],
+            raster_px,
+        };
+
+        self.glyphs.insert(key, info);
+        Some(info)
+    }
+
+    // shelf packing with one pixel of padding between glyphs
+    fn allocate(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+
+        if w + 1 > self.width || h + 1 > self.height {
+            return None;
+        }
+
+        if self.shelf_x + w + 1 > self.width {
+            self.shelf_y += self.shelf_h;
+            self.shelf_x = 0;
+            self.shelf_h = 0;
+        }
+
+        if self.shelf_y + h + 1 > self.height {
+            return None;
+        }
+
+        let pos = (self.shelf_x, self.shelf_y);
+        self.shelf_x += w + 1;
+        self.shelf_h = self.shelf_h.max(h + 1);
+
+        Some(pos)
+    }
+}
+
+
+//
+//  brute force signed distance inside a `spread` window, 0.5 is the glyph edge
+//
+fn sdf(coverage: &[u8], w: usize, h: usize, spread: u32) -> (u32, u32, Vec<u8>) {
+
+    let s = spread as i32;
+    let ow = w as i32 + s * 2;
+    let oh = h as i32 + s * 2;
+
+    let inside = |x: i32, y: i32| -> bool {
+        let (gx, gy) = (x - s, y - s);
+        if gx < 0 || gy < 0 || gx >= w as i32 || gy >= h as i32 {
+            return false;
+        }
+        coverage[gy as usize * w + gx as usize] > 127
+    };
+
+    let mut out = vec![0u8; (ow * oh) as usize];
+
+    for y in 0..oh {
+        for x in 0..ow {
+            let me = inside(x, y);
+            let mut best = (s * s) as f32;
+
+            for dy in -s..=s {
+                for dx in -s..=s {
+                    if inside(x + dx, y + dy) != me {
+                        best = best.min((dx * dx + dy * dy) as f32);
+                    }
+                }
+            }
+
+            let d = best.sqrt() / s as f32 * 0.5;
+            let v = if me { 0.5 + d } else { 0.5 - d };
+            out[(y * ow + x) as usize] = (v.clamp(0.0, 1.0) * 255.0) as u8;
+        }
+    }
+
+    (ow as u32, oh as u32, out)
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextStyle {
+    pub px: f32,
+    pub color: [f32; 4],
+    pub align: TextAlign,
+    // wrap on word boundaries when set
+    pub max_width: Option<f32>,
+    // multiplier for the font line height
+    pub line_height: f32,
+    // x, y, w, h in pixels, glyphs are cut at its edges
+    pub clip: Option<[f32; 4]>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            px: 16.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            align: TextAlign::Left,
+            max_width: None,
+            line_height: 1.0,
+            clip: None,
+        }
+    }
+}
+
+impl TextStyle {
+
+    pub fn new(px: f32) -> Self {
+        TextStyle {
+            px,
+            ..Default::default()
+        }
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn max_width(mut self, width: f32) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    pub fn line_height(mut self, line_height: f32) -> Self {
+        self.line_height = line_height;
+        self
+    }
+
+    pub fn clip(mut self, x: f32, y: f32, w: f32, h: f32) -> Self {
+        self.clip = Some([x, y, w, h]);
+        self
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct PositionedGlyph {
+    pub index: u16,
+    // pen position on the baseline, pixels from the layout origin (top-left)
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub width: f32,
+    pub height: f32,
+}
+
+
+//
+//  lines are broken on '\n' and, with max_width, between words
+//
+pub fn layout_text(font: &Font, text: &str, style: &TextStyle) -> TextLayout {
+
+    let px = style.px;
+    let (ascent, _, line_size) = font.line_metrics(px);
+    let line_size = line_size * style.line_height;
+
+    // (glyph, x) per line
+    let mut lines: Vec<(Vec<(u16, f32)>, f32)> = vec![];
+
+    for paragraph in text.split('\n') {
+
+        let mut line: Vec<(u16, f32)> = vec![];
+        let mut pen = 0.0;
+        // where the last word ends, trailing whitespace takes no room
+        let mut ink = 0.0;
+        let mut prev: Option<u16> = None;
+
+        for word in split_words(paragraph) {
+
+            let mut glyphs = vec![];
+            let mut word_pen = 0.0;
+            let mut word_prev = prev;
+
+            for c in word.chars() {
+                let index = font.glyph_index(c);
+                if let Some(p) = word_prev {
+                    word_pen += font.kern(p, index, px);
+                }
+                glyphs.push((index, word_pen));
+                word_pen += font.advance(index, px);
+                word_prev = Some(index);
+            }
+
+            let is_space = word.chars().all(char::is_whitespace);
+            // whitespace hangs past the edge instead of wrapping
+            let overflow = match style.max_width {
+                Some(max) => !is_space && pen + word_pen > max && !line.is_empty(),
+                None => false,
+            };
+
+            if overflow {
+                lines.push((std::mem::take(&mut line), ink));
+                pen = 0.0;
+                ink = 0.0;
+
+                // redo the word without kerning against the previous line
+                glyphs.clear();
+                word_pen = 0.0;
+                word_prev = None;
+                for c in word.chars() {
+                    let index = font.glyph_index(c);
+                    if let Some(p) = word_prev {
+                        word_pen += font.kern(p, index, px);
+                    }
+                    glyphs.push((index, word_pen));
+                    word_pen += font.advance(index, px);
+                    word_prev = Some(index);
+                }
+            }
+
+            for (index, x) in glyphs {
+                line.push((index, pen + x));
+            }
+            pen += word_pen;
+            prev = word_prev;
+            if !is_space {
+                ink = pen;
+            }
+        }
+
+        lines.push((line, ink));
+    }
+
+    let width = lines.iter().map(|l| l.1).fold(0.0, f32::max);
+    let box_width = style.max_width.unwrap_or(width);
+
+    let mut out = TextLayout {
+        glyphs: vec![],
+        width,
+        height: line_size * lines.len() as f32,
+    };
+
+    for (i, (line, line_width)) in lines.iter().enumerate() {
+        let shift = match style.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (box_width - line_width) * 0.5,
+            TextAlign::Right => box_width - line_width,
+        };
+
+        let baseline = ascent + line_size * i as f32;
+        for (index, x) in line {
+            out.glyphs.push(PositionedGlyph { index: *index, x: x + shift, y: baseline });
+        }
+    }
+
+    out
+}
+
+// words and the whitespace runs between them, kept in order
+fn split_words(s: &str) -> Vec<&str> {
+    let mut out = vec![];
+    let mut start = 0;
+    let mut last_space: Option<bool> = None;
+
+    for (i, c) in s.char_indices() {
+        let space = c.is_whitespace();
+        if last_space.is_some() && last_space != Some(space) {
+            out.push(&s[start..i]);
+            start = i;
+        }
+        last_space = Some(space);
+    }
+
+    if start < s.len() {
+        out.push(&s[start..]);
+    }
+
+    out
+}
+
+
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Default, Debug, Clone, Copy)]
+pub struct TextVertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl TextVertex {
+    pub fn layout() -> VertexBufferLayout<'static> {
+
+        VertexBufferLayout {
+
+            array_stride: size_of::<TextVertex>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &[
+
+            VertexAttribute {
+                format: VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0
+            },
+
+            VertexAttribute {
+                format: VertexFormat::Float32x2,
+                offset: size_of::<[f32; 2]>() as BufferAddress,
+                shader_location: 1
+            },
+
+            VertexAttribute {
+                format: VertexFormat::Float32x4,
+                offset: (size_of::<[f32; 2]>() * 2) as BufferAddress,
+                shader_location: 2
+            }
+
+            ]
+        }
+    }
+}
+
+
+
+pub struct TextRenderer {
+    pub fonts: Vec<Font>,
+    pub atlas: GlyphAtlas,
+    texture: Texture,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+    buffer: Option<Buffer>,
+    // text queued for the current frame, turned into quads by prepare
+    queued: Vec<QueuedText>,
+    vertex: Vec<TextVertex>,
+    drawn: u32,
+    // warned about a full atlas
+    overflowed: bool,
+}
+
+struct QueuedText {
+    font: FontId,
+    text: String,
+    x: f32,
+    y: f32,
+    style: TextStyle,
+    screen: (u32, u32),
+}
+
+impl TextRenderer {
+
+    pub fn new(device: &Device, format: TextureFormat, mode: GlyphMode) -> Self {
+
+        let atlas = GlyphAtlas::new(ATLAS_SIZE, ATLAS_SIZE, mode);
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Glyph Atlas"),
+            size: Extent3d { width: atlas.width, height: atlas.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Glyph Sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Text Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Text Bind Group"),
+            layout: &bind_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: BindingResource::TextureView(&view) },
+                BindGroupEntry { binding: 1, resource: BindingResource::Sampler(&sampler) },
+            ],
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Text Shader"),
+            source: ShaderSource::Wgsl(std::borrow::Cow::Borrowed(Shaders::Text.source())),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[TextVertex::layout()],
+            },
+
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: match mode {
+                    GlyphMode::Bitmap => "fs_bitmap",
+                    GlyphMode::Sdf => "fs_sdf",
+                },
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        TextRenderer {
+            fonts: vec![],
+            atlas,
+            texture,
+            bind_group,
+            pipeline,
+            buffer: None,
+            queued: vec![],
+            vertex: vec![],
+            drawn: 0,
+            overflowed: false,
+        }
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len() - 1)
+    }
+
+    // None for a font this renderer did not hand out
+    pub fn measure(&self, font: FontId, text: &str, style: &TextStyle) -> Option<(f32, f32)> {
+        let l = layout_text(self.fonts.get(font.0)?, text, style);
+        Some((l.width, l.height))
+    }
+
+    //
+    //  queue text for this frame, (x, y) is the top-left corner in pixels
+    //  text in a font this renderer does not know is skipped
+    //
+    pub fn queue(&mut self, font: FontId, text: &str, x: f32, y: f32, style: &TextStyle, screen: (u32, u32)) {
+        self.queued.push(QueuedText { font, text: text.to_string(), x, y, style: style.clone(), screen });
+    }
+
+    // quads for everything queued, false when the atlas ran out of room
+    fn build(&mut self) -> bool {
+        let mut fits = true;
+        for t in &self.queued {
+            if let Some(f) = self.fonts.get(t.font.0) {
+                fits &= text_quads(&mut self.atlas, t.font, f, &t.text, t.x, t.y, &t.style, t.screen, &mut self.vertex);
+            }
+        }
+        fits
+    }
+
+    //
+    //  upload atlas and queued quads, call before the render pass
+    //
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+
+        self.vertex.clear();
+        if !self.build() {
+            // evict everything and keep only the glyphs this frame needs
+            self.atlas.clear();
+            self.vertex.clear();
+            if !self.build() && !self.overflowed {
+                warn!("glyph atlas is full, some text is not drawn");
+                self.overflowed = true;
+            }
+        }
+        self.queued.clear();
+
+        if self.atlas.dirty {
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                &self.atlas.pixels,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.atlas.width),
+                    rows_per_image: Some(self.atlas.height),
+                },
+                Extent3d { width: self.atlas.width, height: self.atlas.height, depth_or_array_layers: 1 },
+            );
+            self.atlas.dirty = false;
+        }
+
+        self.drawn = self.vertex.len() as u32;
+        if self.vertex.is_empty() {
+            return;
+        }
+
+        let bytes: &[u8] = bytemuck::cast_slice(&self.vertex);
+        let too_small = match &self.buffer {
+            Some(b) => b.size() < bytes.len() as BufferAddress,
+            None => true,
+        };
+
+        if too_small {
+            self.buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: Some("Text Vertex Buffer"),
+                size: (bytes.len() as BufferAddress).next_power_of_two(),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+
+        queue.write_buffer(self.buffer.as_ref().unwrap(), 0, bytes);
+    }
+
+    pub fn render(&self, rpass: &mut RenderPass) {
+        if self.drawn == 0 {
+            return;
+        }
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.buffer.as_ref().unwrap().slice(..));
+        rpass.draw(0..self.drawn, 0..1);
+    }
+}
+
+
+//
+//  textured quads for one piece of text, in ndc. false when a glyph did not fit in the atlas
+//
+#[allow(clippy::too_many_arguments)]
+fn text_quads(atlas: &mut GlyphAtlas, font: FontId, f: &Font, text: &str, x: f32, y: f32, style: &TextStyle, screen: (u32, u32), out: &mut Vec<TextVertex>) -> bool {
+
+    let layout = layout_text(f, text, style);
+
+    let sx = 2.0 / screen.0.max(1) as f32;
+    let sy = 2.0 / screen.1.max(1) as f32;
+    let ndc = |px: f32, py: f32| [px * sx - 1.0, 1.0 - py * sy];
+    let mut fits = true;
+
+    for g in &layout.glyphs {
+        let info = match atlas.glyph(font, f, g.index, style.px) {
+            Some(info) => info,
+            None => {
+                fits = false;
+                continue;
+            }
+        };
+        if info.size[0] == 0.0 || info.size[1] == 0.0 {
+            continue;
+        }
+
+        let k = style.px / info.raster_px;
+        let mut x0 = x + g.x + info.offset[0] * k;
+        let mut y0 = y + g.y + info.offset[1] * k;
+        let mut x1 = x0 + info.size[0] * k;
+        let mut y1 = y0 + info.size[1] * k;
+        let [mut u0, mut v0, mut u1, mut v1] = info.uv;
+
+        // cut the quad and its uvs to the clip rect
+        if let Some([cx, cy, cw, ch]) = style.clip {
+            let (nx0, ny0, nx1, ny1) = (x0.max(cx), y0.max(cy), x1.min(cx + cw), y1.min(cy + ch));
+            if nx0 >= nx1 || ny0 >= ny1 {
+                continue;
+            }
+            let du = (u1 - u0) / (x1 - x0);
+            let dv = (v1 - v0) / (y1 - y0);
+            u0 += (nx0 - x0) * du;
+            u1 -= (x1 - nx1) * du;
+            v0 += (ny0 - y0) * dv;
+            v1 -= (y1 - ny1) * dv;
+            (x0, y0, x1, y1) = (nx0, ny0, nx1, ny1);
+        }
+
+        let c = style.color;
+
+        let a = TextVertex { pos: ndc(x0, y0), uv: [u0, v0], color: c };
+        let b = TextVertex { pos: ndc(x1, y0), uv: [u1, v0], color: c };
+        let d = TextVertex { pos: ndc(x1, y1), uv: [u1, v1], color: c };
+        let e = TextVertex { pos: ndc(x0, y1), uv: [u0, v1], color: c };
+
+        out.extend_from_slice(&[a, b, d, a, d, e]);
+    }
+
+    fits
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn width(font: &Font, text: &str) -> f32 {
+        layout_text(font, text, &TextStyle::new(20.0)).width
+    }
+
+    #[test]
+    fn trailing_whitespace_takes_no_room() {
+        let font = Font::builtin();
+        assert!(width(&font, "ab") > 0.0);
+        assert_eq!(width(&font, "ab   "), width(&font, "ab"));
+        // leading whitespace is indentation and counts
+        assert!(width(&font, "  ab") > width(&font, "ab"));
+
+        let w = width(&font, "ab");
+        let right = layout_text(&font, "ab  ", &TextStyle::new(20.0).max_width(200.0).align(TextAlign::Right));
+        assert!((right.glyphs[0].x - (200.0 - w)).abs() < 1e-3);
+        let center = layout_text(&font, "ab \n", &TextStyle::new(20.0).max_width(200.0).align(TextAlign::Center));
+        assert!((center.glyphs[0].x - (200.0 - w) * 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        let font = Font::builtin();
+        let style = TextStyle::new(20.0);
+        let (_, _, line) = font.line_metrics(20.0);
+
+        let one = layout_text(&font, "aaa bbb", &style);
+        assert!((one.height - line).abs() < 1e-3);
+
+        // room for "aaa " and a bit, "bbb" goes to the next line and starts at the left
+        let wrapped = layout_text(&font, "aaa bbb", &style.clone().max_width(width(&font, "aaa ") + 1.0));
+        assert!((wrapped.height - line * 2.0).abs() < 1e-3);
+        assert_eq!(wrapped.glyphs[4].x, 0.0);
+        assert!(wrapped.glyphs[4].y > wrapped.glyphs[0].y);
+        assert_eq!(wrapped.width, width(&font, "aaa"));
+
+        let lines = layout_text(&font, "a\n\nb", &style);
+        assert!((lines.height - line * 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn full_atlas_is_reported_and_cleared() {
+        let font = Font::builtin();
+        let mut atlas = GlyphAtlas::new(64, 64, GlyphMode::Bitmap);
+        let mut out = vec![];
+        assert!(text_quads(&mut atlas, FontId(0), &font, "ab", 0.0, 0.0, &TextStyle::new(20.0), (100, 100), &mut out));
+        assert_eq!(out.len(), 12);
+
+        // far more glyphs than fit in 64x64
+        let many: String = ('A'..='Z').chain('a'..='z').collect();
+        assert!(!text_quads(&mut atlas, FontId(0), &font, &many, 0.0, 0.0, &TextStyle::new(20.0), (100, 100), &mut out));
+
+        atlas.clear();
+        out.clear();
+        assert!(text_quads(&mut atlas, FontId(0), &font, "ab", 0.0, 0.0, &TextStyle::new(20.0), (100, 100), &mut out));
+    }
+
+    #[test]
+    fn clipped_glyphs_are_cut() {
+        let font = Font::builtin();
+        let mut atlas = GlyphAtlas::new(256, 256, GlyphMode::Bitmap);
+        let (mut whole, mut cut) = (vec![], vec![]);
+        let style = TextStyle::new(20.0);
+        text_quads(&mut atlas, FontId(0), &font, "ab", 0.0, 0.0, &style, (100, 100), &mut whole);
+
+        // the clip edge runs through the middle of the first glyph, the second is gone
+        let mid = (whole[0].pos[0] + whole[1].pos[0]) * 0.5;
+        let mid_px = (mid + 1.0) * 50.0;
+        text_quads(&mut atlas, FontId(0), &font, "ab", 0.0, 0.0, &style.clip(0.0, 0.0, mid_px, 100.0), (100, 100), &mut cut);
+        assert_eq!(cut.len(), 6);
+        assert!((cut[1].pos[0] - mid).abs() < 1e-5);
+        assert!((cut[1].uv[0] - (whole[0].uv[0] + whole[1].uv[0]) * 0.5).abs() < 1e-5);
+        assert_eq!(cut[0].uv, whole[0].uv);
+    }
+}
@@ -0,0 +1,1243 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, ensure, Context};
+use serde_json::Value;
+
+use crate::assets::Asset;
+use crate::physics::*;
+
+
+//
+//  maps made in Tiled, .tmj (json) or .tmx (xml), orthogonal and not infinite.
+//  tile layers, object layers, tilesets with per-tile properties and collision shapes, and
+//  custom properties everywhere. groups are flattened, their offset and visibility carried down
+//
+//  in the world the map's top-left corner is the origin and y goes up, `scale` world units per
+//  pixel (one tile is one unit by default). chunks() turns tile layers into quads for the
+//  renderer, colliders() turns collision layers and tile shapes into fixed colliders
+//
+//  collision: a tile layer or object layer named "collision" or with a true "collision"
+//  property is solid, as is any object with that property. tiles with shapes drawn in the
+//  tileset editor collide with those shapes wherever they are placed
+//
+
+
+// gid bits Tiled uses for flipped tiles
+pub const FLIP_H: u32 = 0x8000_0000;
+pub const FLIP_V: u32 = 0x4000_0000;
+pub const FLIP_D: u32 = 0x2000_0000;
+const GID_MASK: u32 = 0x0fff_ffff;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    // string, color, file and class values
+    String(String),
+}
+
+impl Property {
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Property::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Property::Int(i) => Some(*i as f64),
+            Property::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Property::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+pub type Properties = BTreeMap<String, Property>;
+
+fn flagged(props: &Properties, name: &str) -> bool {
+    props.get(name).and_then(|p| p.as_bool()).unwrap_or(false)
+}
+
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    Rect,
+    Ellipse,
+    Point,
+    // points relative to the object's x, y
+    Polygon(Vec<[f32; 2]>),
+    Polyline(Vec<[f32; 2]>),
+}
+
+//
+//  positions in pixels, y down. x, y is the top-left corner, or the bottom-left for tile objects
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub id: u32,
+    pub name: String,
+    // "type" in older versions
+    pub class: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    // degrees clockwise
+    pub rotation: f32,
+    pub gid: Option<u32>,
+    pub visible: bool,
+    pub shape: ObjectShape,
+    pub properties: Properties,
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileData {
+    pub properties: Properties,
+    // drawn in the tileset editor, relative to the tile's top-left corner
+    pub collision: Vec<Object>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tileset {
+    pub first_gid: u32,
+    pub name: String,
+    // external tilesets keep their path here until load_tileset() fills them in
+    pub source: Option<String>,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tile_count: u32,
+    pub columns: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    // relative to the file the tileset came from
+    pub image: Option<String>,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub properties: Properties,
+    pub tiles: BTreeMap<u32, TileData>,
+}
+
+impl Tileset {
+
+    // u0, v0, u1, v1 of a tile in the tileset image
+    pub fn uv(&self, id: u32) -> [f32; 4] {
+        let columns = self.columns.max(1);
+        let (col, row) = (id % columns, id / columns);
+        let (iw, ih) = (self.image_width.max(1) as f32, self.image_height.max(1) as f32);
+
+        // in f32, sizes straight from the file would overflow u32
+        let x = self.margin as f32 + col as f32 * (self.tile_width as f32 + self.spacing as f32);
+        let y = self.margin as f32 + row as f32 * (self.tile_height as f32 + self.spacing as f32);
+        [x / iw, y / ih, (x + self.tile_width as f32) / iw, (y + self.tile_height as f32) / ih]
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    pub width: u32,
+    pub height: u32,
+    // gids with the flip bits, row by row, 0 is empty
+    pub data: Vec<u32>,
+}
+
+impl TileLayer {
+    pub fn get(&self, x: u32, y: u32) -> u32 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        self.data[(y * self.width + x) as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerKind {
+    Tiles(TileLayer),
+    Objects(Vec<Object>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    // pixels, including the offsets of the groups it was in
+    pub offset: [f32; 2],
+    pub properties: Properties,
+    pub kind: LayerKind,
+}
+
+impl Layer {
+    pub fn is_collision(&self) -> bool {
+        self.name.eq_ignore_ascii_case("collision") || flagged(&self.properties, "collision")
+    }
+}
+
+
+//
+//  a placed tile resolved against the tilesets
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileRef {
+    pub tileset: usize,
+    // local id in the tileset
+    pub id: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+}
+
+
+//
+//  the tiles of one layer in one chunk that share a tileset, as triangles of [x, y, u, v]
+//  in world units, ready for one draw call
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileChunk {
+    pub layer: usize,
+    pub tileset: usize,
+    pub cx: u32,
+    pub cy: u32,
+    // min x, min y, max x, max y in world units, for culling
+    pub bounds: [f32; 4],
+    pub vertices: Vec<[f32; 4]>,
+}
+
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Map {
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // world units per pixel
+    pub scale: f32,
+    pub properties: Properties,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<Layer>,
+}
+
+impl Map {
+
+    //
+    //  .tmj or .tmx, told apart by the first character
+    //
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Map> {
+        let s = std::str::from_utf8(bytes).map_err(|_| anyhow!("map is not utf-8"))?;
+        match s.trim_start().chars().next() {
+            Some('{') => Map::from_json(&serde_json::from_str(s)?),
+            Some('<') => Map::from_xml(&xml::parse(s)?),
+            _ => bail!("not a tiled map"),
+        }
+    }
+
+    //
+    //  native: external tilesets are read from next to the map
+    //
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Map> {
+        let mut map = Map::parse(&std::fs::read(path).with_context(|| path.to_string())?).with_context(|| path.to_string())?;
+        let dir = std::path::Path::new(path).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+
+        for i in 0..map.tilesets.len() {
+            if let Some(source) = map.tilesets[i].source.clone() {
+                let full = dir.join(&source);
+                let bytes = std::fs::read(&full).with_context(|| full.display().to_string())?;
+                map.load_tileset(i, &bytes).with_context(|| source)?;
+            }
+        }
+        Ok(map)
+    }
+
+    // paths of the tilesets still to be loaded, relative to the map
+    pub fn external_tilesets(&self) -> Vec<(usize, String)> {
+        self.tilesets.iter().enumerate()
+            .filter_map(|(i, t)| t.source.clone().map(|s| (i, s)))
+            .collect()
+    }
+
+    // tileset images to load for the renderer, as paths next to the map at `map_path`
+    pub fn tileset_images(&self, map_path: &str) -> Vec<(usize, String)> {
+        self.tilesets.iter().enumerate()
+            .filter_map(|(i, t)| t.image.as_ref().map(|img| (i, relative_to(map_path, img))))
+            .collect()
+    }
+
+    //
+    //  fill in an external tileset (.tsj / .tsx) from its bytes
+    //
+    pub fn load_tileset(&mut self, index: usize, bytes: &[u8]) -> anyhow::Result<()> {
+        let s = std::str::from_utf8(bytes).map_err(|_| anyhow!("tileset is not utf-8"))?;
+        let first_gid = self.tilesets[index].first_gid;
+        let mut tileset = match s.trim_start().chars().next() {
+            Some('{') => tileset_from_json(&serde_json::from_str(s)?)?,
+            Some('<') => tileset_from_xml(&xml::parse(s)?)?,
+            _ => bail!("not a tiled tileset"),
+        };
+        tileset.first_gid = first_gid;
+        tileset.source = None;
+        self.tilesets[index] = tileset;
+        Ok(())
+    }
+
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn tile(&self, gid: u32) -> Option<TileRef> {
+        let id = gid & GID_MASK;
+        if id == 0 {
+            return None;
+        }
+
+        // tilesets are sorted by first gid, the last one at or below the gid has it
+        let (i, ts) = self.tilesets.iter().enumerate().rev().find(|(_, t)| t.first_gid <= id)?;
+        Some(TileRef {
+            tileset: i,
+            id: id - ts.first_gid,
+            flip_h: gid & FLIP_H != 0,
+            flip_v: gid & FLIP_V != 0,
+            flip_d: gid & FLIP_D != 0,
+        })
+    }
+
+    pub fn tile_data(&self, gid: u32) -> Option<&TileData> {
+        let t = self.tile(gid)?;
+        self.tilesets[t.tileset].tiles.get(&t.id)
+    }
+
+    // returns false outside the layer or for an object layer
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, gid: u32) -> bool {
+        match self.layers.get_mut(layer).map(|l| &mut l.kind) {
+            Some(LayerKind::Tiles(t)) if x < t.width && y < t.height => {
+                t.data[(y * t.width + x) as usize] = gid;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn to_world(&self, px: f32, py: f32) -> [f32; 2] {
+        [px * self.scale, -py * self.scale]
+    }
+
+
+    //
+    //  every visible tile layer cut into `size` x `size` tile chunks
+    //
+    pub fn chunks(&self, size: u32) -> Vec<TileChunk> {
+        let size = size.max(1);
+        let mut out = vec![];
+        for (i, layer) in self.layers.iter().enumerate() {
+            if let LayerKind::Tiles(t) = &layer.kind {
+                for cy in 0..t.height.div_ceil(size) {
+                    for cx in 0..t.width.div_ceil(size) {
+                        out.extend(self.chunk(i, cx, cy, size));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    //
+    //  one chunk again, after set_tile(). one TileChunk per tileset used in it
+    //
+    pub fn chunk(&self, layer: usize, cx: u32, cy: u32, size: u32) -> Vec<TileChunk> {
+        let l = match self.layers.get(layer) {
+            Some(l) if l.visible => l,
+            _ => return vec![],
+        };
+        let t = match &l.kind {
+            LayerKind::Tiles(t) => t,
+            _ => return vec![],
+        };
+
+        let mut by_tileset: BTreeMap<usize, TileChunk> = BTreeMap::new();
+        for y in cy.saturating_mul(size)..(cy.saturating_add(1).saturating_mul(size)).min(t.height) {
+            for x in cx.saturating_mul(size)..(cx.saturating_add(1).saturating_mul(size)).min(t.width) {
+                let tile = match self.tile(t.get(x, y)) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let ts = &self.tilesets[tile.tileset];
+                if ts.image.is_none() {
+                    continue;
+                }
+
+                // tiles bigger than the grid stick out upwards, as in the editor
+                let left = x as f32 * self.tile_width as f32 + l.offset[0];
+                let bottom = (y as f32 + 1.0) * self.tile_height as f32 + l.offset[1];
+                let [x0, y0] = self.to_world(left, bottom - ts.tile_height as f32);
+                let [x1, y1] = self.to_world(left + ts.tile_width as f32, bottom);
+
+                let [u0, v0, u1, v1] = ts.uv(tile.id);
+                // top-left, top-right, bottom-right, bottom-left
+                let mut uv = [[u0, v0], [u1, v0], [u1, v1], [u0, v1]];
+                if tile.flip_d {
+                    uv.swap(1, 3);
+                }
+                if tile.flip_h {
+                    uv.swap(0, 1);
+                    uv.swap(2, 3);
+                }
+                if tile.flip_v {
+                    uv.swap(0, 3);
+                    uv.swap(1, 2);
+                }
+
+                let c = by_tileset.entry(tile.tileset).or_insert_with(|| TileChunk {
+                    layer,
+                    tileset: tile.tileset,
+                    cx,
+                    cy,
+                    bounds: [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+                    vertices: vec![],
+                });
+                let v = |p: [f32; 2], uv: [f32; 2]| [p[0], p[1], uv[0], uv[1]];
+                let (tl, tr, br, bl) = ([x0, y0], [x1, y0], [x1, y1], [x0, y1]);
+                c.vertices.extend_from_slice(&[v(tl, uv[0]), v(tr, uv[1]), v(br, uv[2]), v(tl, uv[0]), v(br, uv[2]), v(bl, uv[3])]);
+                c.bounds = [c.bounds[0].min(x0), c.bounds[1].min(y1), c.bounds[2].max(x1), c.bounds[3].max(y0)];
+            }
+        }
+
+        by_tileset.into_values().collect()
+    }
+
+
+    //
+    //  fixed colliders for everything solid, in world units. runs of solid cells in collision
+    //  layers are merged into as few boxes as possible
+    //
+    pub fn colliders(&self) -> Vec<ColliderDesc> {
+        let mut out = vec![];
+
+        for layer in &self.layers {
+            match &layer.kind {
+                LayerKind::Tiles(t) => {
+                    let mut solid = vec![false; t.data.len()];
+                    for y in 0..t.height {
+                        for x in 0..t.width {
+                            let gid = t.get(x, y);
+                            let ts = match self.tile(gid) {
+                                Some(tile) => &self.tilesets[tile.tileset],
+                                None => continue,
+                            };
+
+                            match self.tile_data(gid).filter(|d| !d.collision.is_empty()) {
+                                Some(d) => {
+                                    let origin = [
+                                        x as f32 * self.tile_width as f32 + layer.offset[0],
+                                        (y as f32 + 1.0) * self.tile_height as f32 - ts.tile_height as f32 + layer.offset[1],
+                                    ];
+                                    out.extend(d.collision.iter().filter_map(|o| self.object_collider(o, origin)));
+                                }
+                                None if layer.is_collision() => solid[(y * t.width + x) as usize] = true,
+                                None => (),
+                            }
+                        }
+                    }
+
+                    for [x, y, w, h] in merge_cells(&solid, t.width, t.height) {
+                        let (tw, th) = (self.tile_width as f32, self.tile_height as f32);
+                        let px = [x as f32 * tw + layer.offset[0], y as f32 * th + layer.offset[1]];
+                        let size = [w as f32 * tw, h as f32 * th];
+                        let centre = self.to_world(px[0] + size[0] * 0.5, px[1] + size[1] * 0.5);
+                        out.push(ColliderDesc::new(ColliderShape::Cuboid { hx: size[0] * 0.5 * self.scale, hy: size[1] * 0.5 * self.scale })
+                            .offset(centre[0], centre[1]));
+                    }
+                }
+
+                LayerKind::Objects(objects) => {
+                    for o in objects.iter().filter(|o| layer.is_collision() || flagged(&o.properties, "collision")) {
+                        out.extend(self.object_collider(o, layer.offset));
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn add_colliders(&self, physics: &mut Physics) -> anyhow::Result<usize> {
+        let colliders = self.colliders();
+        for c in &colliders {
+            physics.add_collider(c, None)?;
+        }
+        Ok(colliders.len())
+    }
+
+    //
+    //  an object's position in the world: the centre of rects, ellipses and tile objects,
+    //  the anchor point of the rest
+    //
+    pub fn object_position(&self, layer: &Layer, o: &Object) -> [f32; 2] {
+        let local = match (&o.shape, o.gid) {
+            (_, Some(_)) => [o.width * 0.5, -o.height * 0.5],
+            (ObjectShape::Rect, _) | (ObjectShape::Ellipse, _) => [o.width * 0.5, o.height * 0.5],
+            _ => [0.0, 0.0],
+        };
+        let p = rotate(local, o.rotation);
+        self.to_world(o.x + p[0] + layer.offset[0], o.y + p[1] + layer.offset[1])
+    }
+
+
+    fn object_collider(&self, o: &Object, origin: [f32; 2]) -> Option<ColliderDesc> {
+        let s = self.scale;
+        let world = |p: [f32; 2]| {
+            let r = rotate(p, o.rotation);
+            self.to_world(origin[0] + o.x + r[0], origin[1] + o.y + r[1])
+        };
+        // y flips, so clockwise in the editor is clockwise on screen
+        let rotation = -o.rotation.to_radians();
+
+        let desc = match &o.shape {
+            ObjectShape::Rect if o.width > 0.0 && o.height > 0.0 => {
+                // tile objects hang up from their anchor
+                let centre = if o.gid.is_some() { [o.width * 0.5, -o.height * 0.5] } else { [o.width * 0.5, o.height * 0.5] };
+                let [x, y] = world(centre);
+                ColliderDesc { rotation, ..ColliderDesc::new(ColliderShape::Cuboid { hx: o.width * 0.5 * s, hy: o.height * 0.5 * s }).offset(x, y) }
+            }
+            ObjectShape::Ellipse if o.width > 0.0 => {
+                // circles only, an oval gets the average radius
+                let [x, y] = world([o.width * 0.5, o.height * 0.5]);
+                ColliderDesc::new(ColliderShape::Ball { radius: (o.width + o.height) * 0.25 * s }).offset(x, y)
+            }
+            ObjectShape::Polygon(points) if points.len() >= 3 => {
+                let points: Vec<[f32; 2]> = points.iter().map(|p| world(*p)).collect();
+                if is_convex(&points) {
+                    ColliderDesc::new(ColliderShape::Convex { points })
+                } else {
+                    // solid from the outside only, fine for level geometry
+                    let mut closed = points.clone();
+                    closed.push(points[0]);
+                    ColliderDesc::new(ColliderShape::Polyline { points: closed })
+                }
+            }
+            ObjectShape::Polyline(points) if points.len() >= 2 => {
+                ColliderDesc::new(ColliderShape::Polyline { points: points.iter().map(|p| world(*p)).collect() })
+            }
+            _ => return None,
+        };
+
+        let mut desc = desc;
+        if let Some(f) = o.properties.get("friction").and_then(|p| p.as_f64()) {
+            desc.friction = f as f32;
+        }
+        if let Some(r) = o.properties.get("restitution").and_then(|p| p.as_f64()) {
+            desc.restitution = r as f32;
+        }
+        desc.sensor = flagged(&o.properties, "sensor");
+        Some(desc)
+    }
+}
+
+
+impl Asset for Map {
+    fn decode(_path: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        Map::parse(bytes)
+    }
+}
+
+
+
+//
+//  `rel` as written in the file at `file`, with any leading ../ resolved. "maps/1.tmj" and
+//  "../tiles/a.png" give "tiles/a.png"
+//
+pub fn relative_to(file: &str, rel: &str) -> String {
+    let mut parts: Vec<&str> = file.split('/').collect();
+    parts.pop();
+    for p in rel.split('/') {
+        match p {
+            "." | "" => (),
+            ".." if parts.last().is_some_and(|l| *l != "..") => {
+                parts.pop();
+            }
+            p => parts.push(p),
+        }
+    }
+    parts.join("/")
+}
+
+// clockwise in pixel space, y down
+fn rotate(p: [f32; 2], degrees: f32) -> [f32; 2] {
+    if degrees == 0.0 {
+        return p;
+    }
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [p[0] * cos - p[1] * sin, p[0] * sin + p[1] * cos]
+}
+
+fn is_convex(points: &[[f32; 2]]) -> bool {
+    let n = points.len();
+    let mut sign = 0.0f32;
+    for i in 0..n {
+        let (a, b, c) = (points[i], points[(i + 1) % n], points[(i + 2) % n]);
+        let cross = (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0]);
+        if cross.abs() < 1e-6 {
+            continue;
+        }
+        if sign != 0.0 && cross.signum() != sign {
+            return false;
+        }
+        sign = cross.signum();
+    }
+    true
+}
+
+//
+//  greedy: grow each unvisited solid cell right as far as it goes, then down while the whole
+//  row below is solid too. [x, y, w, h] in cells
+//
+fn merge_cells(solid: &[bool], width: u32, height: u32) -> Vec<[u32; 4]> {
+    let (w, h) = (width as usize, height as usize);
+    let mut used = vec![false; solid.len()];
+    let free = |used: &Vec<bool>, x: usize, y: usize| solid[y * w + x] && !used[y * w + x];
+
+    let mut out = vec![];
+    for y in 0..h {
+        for x in 0..w {
+            if !free(&used, x, y) {
+                continue;
+            }
+
+            let mut rw = 1;
+            while x + rw < w && free(&used, x + rw, y) {
+                rw += 1;
+            }
+            let mut rh = 1;
+            while y + rh < h && (x..x + rw).all(|cx| free(&used, cx, y + rh)) {
+                rh += 1;
+            }
+
+            for cy in y..y + rh {
+                for cx in x..x + rw {
+                    used[cy * w + cx] = true;
+                }
+            }
+            out.push([x as u32, y as u32, rw as u32, rh as u32]);
+        }
+    }
+    out
+}
+
+
+
+//
+//  tile data in either format: csv, or base64 of little endian u32s, maybe zlib or gzip
+//
+fn decode_tiles(text: &str, encoding: &str, compression: &str, count: usize) -> anyhow::Result<Vec<u32>> {
+    let data: Vec<u32> = match encoding {
+        "csv" => text.split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<u32>().map_err(|_| anyhow!("bad tile {:?}", s)))
+            .collect::<anyhow::Result<_>>()?,
+        "base64" => {
+            let raw = base64(text)?;
+            let raw = match compression {
+                "" => raw,
+                // a few kB of zeros can inflate to gigabytes, never past what the layer holds
+                "zlib" => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&raw, count.saturating_mul(4))
+                    .map_err(|e| anyhow!("zlib: {:?}", e.status))?,
+                "gzip" => miniz_oxide::inflate::decompress_to_vec_with_limit(gzip_body(&raw)?, count.saturating_mul(4))
+                    .map_err(|e| anyhow!("gzip: {:?}", e.status))?,
+                other => bail!("{} compression is not supported", other),
+            };
+            ensure!(raw.len() % 4 == 0, "tile data is not whole u32s");
+            raw.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+        }
+        other => bail!("{} encoding is not supported", other),
+    };
+
+    ensure!(data.len() == count, "layer has {} tiles, expected {}", data.len(), count);
+    Ok(data)
+}
+
+fn base64(text: &str) -> anyhow::Result<Vec<u8>> {
+    let mut out = vec![];
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => bail!("bad base64"),
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// the deflate stream inside a gzip member
+fn gzip_body(raw: &[u8]) -> anyhow::Result<&[u8]> {
+    ensure!(raw.len() >= 18 && raw[0] == 0x1f && raw[1] == 0x8b && raw[2] == 8, "not gzip");
+    let flags = raw[3];
+    let mut i = 10;
+    if flags & 4 != 0 {
+        ensure!(raw.len() > i + 2, "gzip header cut short");
+        i += 2 + u16::from_le_bytes([raw[i], raw[i + 1]]) as usize;
+    }
+    // name and comment are zero terminated
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            while i < raw.len() && raw[i] != 0 {
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+    if flags & 2 != 0 {
+        i += 2;
+    }
+    ensure!(i + 8 <= raw.len(), "gzip header cut short");
+    Ok(&raw[i..raw.len() - 8])
+}
+
+
+
+//
+//  .tmj
+//
+
+fn num(v: &Value, key: &str) -> f32 {
+    v.get(key).and_then(|v| v.as_f64()).unwrap_or(0.0) as f32
+}
+
+fn uint(v: &Value, key: &str) -> u32 {
+    v.get(key).and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+fn string(v: &Value, key: &str) -> String {
+    v.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string()
+}
+
+fn json_properties(v: &Value) -> Properties {
+    let mut out = Properties::new();
+    for p in v.get("properties").and_then(|p| p.as_array()).into_iter().flatten() {
+        let value = match (p.get("type").and_then(|t| t.as_str()).unwrap_or("string"), p.get("value")) {
+            (_, Some(Value::Bool(b))) => Property::Bool(*b),
+            ("int", Some(v)) | ("object", Some(v)) => Property::Int(v.as_i64().unwrap_or(0)),
+            ("float", Some(v)) => Property::Float(v.as_f64().unwrap_or(0.0)),
+            (_, Some(Value::String(s))) => Property::String(s.clone()),
+            (_, Some(v)) => Property::String(v.to_string()),
+            (_, None) => continue,
+        };
+        out.insert(string(p, "name"), value);
+    }
+    out
+}
+
+fn json_points(v: &Value) -> Vec<[f32; 2]> {
+    v.as_array().into_iter().flatten().map(|p| [num(p, "x"), num(p, "y")]).collect()
+}
+
+fn json_object(v: &Value) -> Object {
+    let shape = if let Some(p) = v.get("polygon") {
+        ObjectShape::Polygon(json_points(p))
+    } else if let Some(p) = v.get("polyline") {
+        ObjectShape::Polyline(json_points(p))
+    } else if v.get("ellipse").and_then(|b| b.as_bool()) == Some(true) {
+        ObjectShape::Ellipse
+    } else if v.get("point").and_then(|b| b.as_bool()) == Some(true) {
+        ObjectShape::Point
+    } else {
+        ObjectShape::Rect
+    };
+
+    let class = match string(v, "class") {
+        c if c.is_empty() => string(v, "type"),
+        c => c,
+    };
+
+    Object {
+        id: uint(v, "id"),
+        name: string(v, "name"),
+        class,
+        x: num(v, "x"),
+        y: num(v, "y"),
+        width: num(v, "width"),
+        height: num(v, "height"),
+        rotation: num(v, "rotation"),
+        gid: v.get("gid").and_then(|g| g.as_u64()).map(|g| g as u32),
+        visible: v.get("visible").and_then(|b| b.as_bool()).unwrap_or(true),
+        shape,
+        properties: json_properties(v),
+    }
+}
+
+fn tileset_from_json(v: &Value) -> anyhow::Result<Tileset> {
+    let mut ts = Tileset {
+        first_gid: uint(v, "firstgid"),
+        name: string(v, "name"),
+        source: v.get("source").and_then(|s| s.as_str()).map(|s| s.to_string()),
+        tile_width: uint(v, "tilewidth"),
+        tile_height: uint(v, "tileheight"),
+        tile_count: uint(v, "tilecount"),
+        columns: uint(v, "columns"),
+        margin: uint(v, "margin"),
+        spacing: uint(v, "spacing"),
+        image: v.get("image").and_then(|s| s.as_str()).map(|s| s.to_string()),
+        image_width: uint(v, "imagewidth"),
+        image_height: uint(v, "imageheight"),
+        properties: json_properties(v),
+        tiles: BTreeMap::new(),
+    };
+
+    for t in v.get("tiles").and_then(|t| t.as_array()).into_iter().flatten() {
+        let collision = t.get("objectgroup").and_then(|g| g.get("objects")).and_then(|o| o.as_array())
+            .map(|o| o.iter().map(json_object).collect())
+            .unwrap_or_default();
+        ts.tiles.insert(uint(t, "id"), TileData { properties: json_properties(t), collision });
+    }
+    Ok(ts)
+}
+
+fn json_layers(v: &Value, offset: [f32; 2], visible: bool, out: &mut Vec<Layer>) -> anyhow::Result<()> {
+    for l in v.get("layers").and_then(|l| l.as_array()).into_iter().flatten() {
+        let name = string(l, "name");
+        let offset = [offset[0] + num(l, "offsetx"), offset[1] + num(l, "offsety")];
+        let visible = visible && l.get("visible").and_then(|b| b.as_bool()).unwrap_or(true);
+
+        let kind = match l.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+            "tilelayer" => {
+                ensure!(l.get("chunks").is_none(), "{}: infinite maps are not supported", name);
+                let (width, height) = (uint(l, "width"), uint(l, "height"));
+                let count = width.checked_mul(height).ok_or_else(|| anyhow!("{}: {}x{} tiles is too many", name, width, height))? as usize;
+                let data = match l.get("data") {
+                    Some(Value::Array(a)) => {
+                        ensure!(a.len() == count, "{}: layer has {} tiles, expected {}", name, a.len(), count);
+                        a.iter().map(|g| g.as_u64().unwrap_or(0) as u32).collect()
+                    }
+                    Some(Value::String(s)) => decode_tiles(s, "base64", &string(l, "compression"), count).with_context(|| name.clone())?,
+                    _ => bail!("{}: no tile data", name),
+                };
+                LayerKind::Tiles(TileLayer { width, height, data })
+            }
+            "objectgroup" => LayerKind::Objects(
+                l.get("objects").and_then(|o| o.as_array()).into_iter().flatten().map(json_object).collect()
+            ),
+            "group" => {
+                json_layers(l, offset, visible, out)?;
+                continue;
+            }
+            // image layers have nothing for us
+            _ => continue,
+        };
+
+        out.push(Layer {
+            name,
+            visible,
+            opacity: l.get("opacity").and_then(|o| o.as_f64()).unwrap_or(1.0) as f32,
+            offset,
+            properties: json_properties(l),
+            kind,
+        });
+    }
+    Ok(())
+}
+
+impl Map {
+    fn from_json(v: &Value) -> anyhow::Result<Map> {
+        check_header(v.get("orientation").and_then(|o| o.as_str()), v.get("infinite").and_then(|i| i.as_bool()).unwrap_or(false))?;
+
+        let mut layers = vec![];
+        json_layers(v, [0.0, 0.0], true, &mut layers)?;
+
+        let tilesets = v.get("tilesets").and_then(|t| t.as_array()).into_iter().flatten()
+            .map(tileset_from_json)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Map::build(uint(v, "width"), uint(v, "height"), uint(v, "tilewidth"), uint(v, "tileheight"), json_properties(v), tilesets, layers)
+    }
+
+    fn from_xml(root: &xml::Element) -> anyhow::Result<Map> {
+        ensure!(root.name == "map", "not a tiled map");
+        check_header(root.attr("orientation"), root.attr("infinite") == Some("1"))?;
+
+        let mut layers = vec![];
+        xml_layers(root, [0.0, 0.0], true, &mut layers)?;
+
+        let tilesets = root.children("tileset").map(tileset_from_xml).collect::<anyhow::Result<Vec<_>>>()?;
+
+        Map::build(root.uint("width"), root.uint("height"), root.uint("tilewidth"), root.uint("tileheight"), xml_properties(root), tilesets, layers)
+    }
+
+    fn build(width: u32, height: u32, tile_width: u32, tile_height: u32, properties: Properties, mut tilesets: Vec<Tileset>, layers: Vec<Layer>) -> anyhow::Result<Map> {
+        ensure!(tile_width > 0 && tile_height > 0, "map has no tile size");
+        tilesets.sort_by_key(|t| t.first_gid);
+
+        Ok(Map {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            scale: 1.0 / tile_width as f32,
+            properties,
+            tilesets,
+            layers,
+        })
+    }
+}
+
+fn check_header(orientation: Option<&str>, infinite: bool) -> anyhow::Result<()> {
+    match orientation {
+        None | Some("orthogonal") => (),
+        Some(o) => bail!("{} maps are not supported", o),
+    }
+    ensure!(!infinite, "infinite maps are not supported");
+    Ok(())
+}
+
+
+
+//
+//  .tmx
+//
+
+fn xml_properties(e: &xml::Element) -> Properties {
+    let mut out = Properties::new();
+    for p in e.children("properties").flat_map(|p| p.children("property")) {
+        // long strings are stored as the element's text
+        let raw = p.attr("value").map(|v| v.to_string()).unwrap_or_else(|| p.text.clone());
+        let value = match p.attr("type").unwrap_or("string") {
+            "bool" => Property::Bool(raw == "true"),
+            "int" | "object" => Property::Int(raw.parse().unwrap_or(0)),
+            "float" => Property::Float(raw.parse().unwrap_or(0.0)),
+            _ => Property::String(raw),
+        };
+        out.insert(p.attr("name").unwrap_or("").to_string(), value);
+    }
+    out
+}
+
+fn xml_points(s: &str) -> Vec<[f32; 2]> {
+    s.split_whitespace()
+        .filter_map(|p| {
+            let (x, y) = p.split_once(',')?;
+            Some([x.parse().ok()?, y.parse().ok()?])
+        })
+        .collect()
+}
+
+fn xml_object(e: &xml::Element) -> Object {
+    let shape = if let Some(p) = e.child("polygon") {
+        ObjectShape::Polygon(xml_points(p.attr("points").unwrap_or("")))
+    } else if let Some(p) = e.child("polyline") {
+        ObjectShape::Polyline(xml_points(p.attr("points").unwrap_or("")))
+    } else if e.child("ellipse").is_some() {
+        ObjectShape::Ellipse
+    } else if e.child("point").is_some() {
+        ObjectShape::Point
+    } else {
+        ObjectShape::Rect
+    };
+
+    Object {
+        id: e.uint("id"),
+        name: e.attr("name").unwrap_or("").to_string(),
+        class: e.attr("class").or(e.attr("type")).unwrap_or("").to_string(),
+        x: e.num("x"),
+        y: e.num("y"),
+        width: e.num("width"),
+        height: e.num("height"),
+        rotation: e.num("rotation"),
+        gid: e.attr("gid").and_then(|g| g.parse().ok()),
+        visible: e.attr("visible") != Some("0"),
+        shape,
+        properties: xml_properties(e),
+    }
+}
+
+fn tileset_from_xml(e: &xml::Element) -> anyhow::Result<Tileset> {
+    ensure!(e.name == "tileset", "not a tiled tileset");
+    let image = e.child("image");
+
+    let mut ts = Tileset {
+        first_gid: e.uint("firstgid"),
+        name: e.attr("name").unwrap_or("").to_string(),
+        source: e.attr("source").map(|s| s.to_string()),
+        tile_width: e.uint("tilewidth"),
+        tile_height: e.uint("tileheight"),
+        tile_count: e.uint("tilecount"),
+        columns: e.uint("columns"),
+        margin: e.uint("margin"),
+        spacing: e.uint("spacing"),
+        image: image.and_then(|i| i.attr("source")).map(|s| s.to_string()),
+        image_width: image.map_or(0, |i| i.uint("width")),
+        image_height: image.map_or(0, |i| i.uint("height")),
+        properties: xml_properties(e),
+        tiles: BTreeMap::new(),
+    };
+
+    for t in e.children("tile") {
+        let collision = t.children("objectgroup").flat_map(|g| g.children("object")).map(xml_object).collect();
+        ts.tiles.insert(t.uint("id"), TileData { properties: xml_properties(t), collision });
+    }
+    Ok(ts)
+}
+
+fn xml_layers(e: &xml::Element, offset: [f32; 2], visible: bool, out: &mut Vec<Layer>) -> anyhow::Result<()> {
+    for l in &e.nodes {
+        let name = l.attr("name").unwrap_or("").to_string();
+        let offset = [offset[0] + l.num("offsetx"), offset[1] + l.num("offsety")];
+        let visible = visible && l.attr("visible") != Some("0");
+
+        let kind = match l.name.as_str() {
+            "layer" => {
+                let (width, height) = (l.uint("width"), l.uint("height"));
+                let count = width.checked_mul(height).ok_or_else(|| anyhow!("{}: {}x{} tiles is too many", name, width, height))? as usize;
+                let data = l.child("data").ok_or_else(|| anyhow!("{}: no tile data", name))?;
+                ensure!(data.child("chunk").is_none(), "{}: infinite maps are not supported", name);
+
+                let tiles = match data.attr("encoding") {
+                    Some(encoding) => decode_tiles(&data.text, encoding, data.attr("compression").unwrap_or(""), count).with_context(|| name.clone())?,
+                    // one <tile gid=".."/> per cell
+                    None => {
+                        let tiles: Vec<u32> = data.children("tile").map(|t| t.uint("gid")).collect();
+                        ensure!(tiles.len() == count, "{}: layer has {} tiles, expected {}", name, tiles.len(), count);
+                        tiles
+                    }
+                };
+                LayerKind::Tiles(TileLayer { width, height, data: tiles })
+            }
+            "objectgroup" => LayerKind::Objects(l.children("object").map(xml_object).collect()),
+            "group" => {
+                xml_layers(l, offset, visible, out)?;
+                continue;
+            }
+            _ => continue,
+        };
+
+        out.push(Layer {
+            name,
+            visible,
+            opacity: l.attr("opacity").and_then(|o| o.parse().ok()).unwrap_or(1.0),
+            offset,
+            properties: xml_properties(l),
+            kind,
+        });
+    }
+    Ok(())
+}
+
+
+
+//
+//  just enough xml for tiled files: elements, attributes, text, comments and the declaration
+//
+mod xml {
+    use anyhow::{anyhow, bail, ensure};
+
+    #[derive(Debug, Default)]
+    pub struct Element {
+        pub name: String,
+        pub attrs: Vec<(String, String)>,
+        pub nodes: Vec<Element>,
+        pub text: String,
+    }
+
+    impl Element {
+
+        pub fn attr(&self, name: &str) -> Option<&str> {
+            self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+        }
+
+        pub fn num(&self, name: &str) -> f32 {
+            self.attr(name).and_then(|v| v.parse().ok()).unwrap_or(0.0)
+        }
+
+        pub fn uint(&self, name: &str) -> u32 {
+            self.attr(name).and_then(|v| v.parse().ok()).unwrap_or(0)
+        }
+
+        pub fn child(&self, name: &str) -> Option<&Element> {
+            self.nodes.iter().find(|e| e.name == name)
+        }
+
+        pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+            self.nodes.iter().filter(move |e| e.name == name)
+        }
+    }
+
+
+    // elements inside elements, each one is a level of recursion
+    const MAX_DEPTH: usize = 128;
+
+    pub fn parse(s: &str) -> anyhow::Result<Element> {
+        let mut p = Parser { s: s.as_bytes(), i: 0, depth: 0 };
+        p.skip_misc()?;
+        let root = p.element()?;
+        p.skip_misc()?;
+        ensure!(p.i == p.s.len(), "trailing content after the root element");
+        Ok(root)
+    }
+
+    struct Parser<'a> {
+        s: &'a [u8],
+        i: usize,
+        depth: usize,
+    }
+
+    impl<'a> Parser<'a> {
+
+        fn starts(&self, prefix: &str) -> bool {
+            self.s[self.i..].starts_with(prefix.as_bytes())
+        }
+
+        fn skip_ws(&mut self) {
+            while self.i < self.s.len() && self.s[self.i].is_ascii_whitespace() {
+                self.i += 1;
+            }
+        }
+
+        fn skip_past(&mut self, end: &str) -> anyhow::Result<()> {
+            while self.i < self.s.len() {
+                if self.starts(end) {
+                    self.i += end.len();
+                    return Ok(());
+                }
+                self.i += 1;
+            }
+            bail!("missing {}", end)
+        }
+
+        // whitespace, comments, <?xml ?> and <!DOCTYPE>
+        fn skip_misc(&mut self) -> anyhow::Result<()> {
+            loop {
+                self.skip_ws();
+                if self.starts("<?") {
+                    self.skip_past("?>")?;
+                } else if self.starts("<!--") {
+                    self.skip_past("-->")?;
+                } else if self.starts("<!") {
+                    self.skip_past(">")?;
+                } else {
+                    return Ok(());
+                }
+            }
+        }
+
+        fn name(&mut self) -> anyhow::Result<String> {
+            let start = self.i;
+            while self.i < self.s.len() && (self.s[self.i].is_ascii_alphanumeric() || b"_-.:".contains(&self.s[self.i])) {
+                self.i += 1;
+            }
+            ensure!(self.i > start, "expected a name at byte {}", start);
+            Ok(String::from_utf8_lossy(&self.s[start..self.i]).into_owned())
+        }
+
+        fn expect(&mut self, c: u8) -> anyhow::Result<()> {
+            ensure!(self.s.get(self.i) == Some(&c), "expected '{}' at byte {}", c as char, self.i);
+            self.i += 1;
+            Ok(())
+        }
+
+        fn element(&mut self) -> anyhow::Result<Element> {
+            self.expect(b'<')?;
+            let mut e = Element { name: self.name()?, ..Default::default() };
+
+            loop {
+                self.skip_ws();
+                match self.s.get(self.i) {
+                    Some(b'/') => {
+                        self.i += 1;
+                        self.expect(b'>')?;
+                        return Ok(e);
+                    }
+                    Some(b'>') => {
+                        self.i += 1;
+                        break;
+                    }
+                    Some(_) => {
+                        let key = self.name()?;
+                        self.skip_ws();
+                        self.expect(b'=')?;
+                        self.skip_ws();
+                        let quote = *self.s.get(self.i).ok_or_else(|| anyhow!("unexpected end"))?;
+                        ensure!(quote == b'"' || quote == b'\'', "expected a quoted value for {}", key);
+                        self.i += 1;
+                        let start = self.i;
+                        while self.i < self.s.len() && self.s[self.i] != quote {
+                            self.i += 1;
+                        }
+                        ensure!(self.i < self.s.len(), "unterminated value for {}", key);
+                        let value = unescape(&String::from_utf8_lossy(&self.s[start..self.i]));
+                        self.i += 1;
+                        e.attrs.push((key, value));
+                    }
+                    None => bail!("unexpected end in <{}>", e.name),
+                }
+            }
+
+            // content until the closing tag
+            loop {
+                if self.i >= self.s.len() {
+                    bail!("<{}> is not closed", e.name);
+                }
+                if self.starts("</") {
+                    self.i += 2;
+                    let name = self.name()?;
+                    ensure!(name == e.name, "</{}> closes <{}>", name, e.name);
+                    self.skip_ws();
+                    self.expect(b'>')?;
+                    return Ok(e);
+                } else if self.starts("<!--") {
+                    self.skip_past("-->")?;
+                } else if self.starts("<![CDATA[") {
+                    self.i += 9;
+                    let start = self.i;
+                    self.skip_past("]]>")?;
+                    e.text.push_str(&String::from_utf8_lossy(&self.s[start..self.i - 3]));
+                } else if self.starts("<") {
+                    ensure!(self.depth < MAX_DEPTH, "elements nest deeper than {}", MAX_DEPTH);
+                    self.depth += 1;
+                    let child = self.element()?;
+                    self.depth -= 1;
+                    e.nodes.push(child);
+                } else {
+                    let start = self.i;
+                    while self.i < self.s.len() && self.s[self.i] != b'<' {
+                        self.i += 1;
+                    }
+                    e.text.push_str(&unescape(&String::from_utf8_lossy(&self.s[start..self.i])));
+                }
+            }
+        }
+    }
+
+    fn unescape(s: &str) -> String {
+        if !s.contains('&') {
+            return s.to_string();
+        }
+        s.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&#10;", "\n")
+            .replace("&amp;", "&")
+    }
+}
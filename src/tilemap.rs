@@ -0,0 +1,326 @@
+use std::mem::size_of;
+
+use log::warn;
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::*;
+
+use crate::tiled::{Map, TileChunk};
+use super::Shaders;
+
+
+//
+//  draws a tiled Map: every chunk of every layer is one vertex buffer per tileset, built once
+//  and kept on the gpu. per frame only the camera uniform changes, chunks outside the view
+//  are skipped. vertices are in world units, the camera maps them to the screen. each layer
+//  has a small uniform of its own with the layer's opacity
+//
+
+
+pub const CHUNK_SIZE: u32 = 16;
+
+
+struct GpuChunk {
+    layer: usize,
+    tileset: usize,
+    cx: u32,
+    cy: u32,
+    bounds: [f32; 4],
+    buffer: Buffer,
+    count: u32,
+}
+
+pub struct TilemapRenderer {
+    pipeline: RenderPipeline,
+    camera: Buffer,
+    camera_bind: BindGroup,
+    texture_layout: BindGroupLayout,
+    layer_layout: BindGroupLayout,
+    // one per map layer
+    layers: Vec<BindGroup>,
+    sampler: Sampler,
+    // one per tileset, chunks of tilesets without an image yet are skipped
+    textures: Vec<Option<BindGroup>>,
+    chunks: Vec<GpuChunk>,
+    // min x, min y, max x, max y of the world on screen
+    view: [f32; 4],
+}
+
+impl TilemapRenderer {
+
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+
+        let camera = device.create_buffer(&BufferDescriptor {
+            label: Some("Tile Camera"),
+            size: 16,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let camera_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tile Camera Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let camera_bind = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Tile Camera Bind Group"),
+            layout: &camera_layout,
+            entries: &[BindGroupEntry { binding: 0, resource: camera.as_entire_binding() }],
+        });
+
+        let texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tileset Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let layer_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tile Layer Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // pixel art, no blurring between texels
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Tileset Sampler"),
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Tile Shader"),
+            source: ShaderSource::Wgsl(std::borrow::Cow::Borrowed(Shaders::Tile.source())),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&camera_layout, &texture_layout, &layer_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Tile Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[VertexBufferLayout {
+                    array_stride: size_of::<[f32; 4]>() as BufferAddress,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &[
+                        VertexAttribute { format: VertexFormat::Float32x2, offset: 0, shader_location: 0 },
+                        VertexAttribute { format: VertexFormat::Float32x2, offset: size_of::<[f32; 2]>() as BufferAddress, shader_location: 1 },
+                    ],
+                }],
+            },
+
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        TilemapRenderer {
+            pipeline,
+            camera,
+            camera_bind,
+            texture_layout,
+            layer_layout,
+            layers: vec![],
+            sampler,
+            textures: vec![],
+            chunks: vec![],
+            view: [f32::MIN, f32::MIN, f32::MAX, f32::MAX],
+        }
+    }
+
+    //
+    //  replaces the whole map, textures stay until set_texture() replaces them
+    //
+    pub fn set_map(&mut self, device: &Device, map: &Map) {
+        self.chunks.clear();
+        for c in map.chunks(CHUNK_SIZE) {
+            self.upload(device, c);
+        }
+        self.textures.resize_with(map.tilesets.len(), || None);
+
+        self.layers = map.layers.iter().map(|l| {
+            // padded to the 16 bytes a uniform needs
+            let uniform = [l.opacity.clamp(0.0, 1.0), 0.0, 0.0, 0.0];
+            let buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Tile Layer"),
+                contents: bytemuck::cast_slice(&uniform),
+                usage: BufferUsages::UNIFORM,
+            });
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Tile Layer Bind Group"),
+                layout: &self.layer_layout,
+                entries: &[BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+            })
+        }).collect();
+    }
+
+    //
+    //  rebuild the chunk under a tile after Map::set_tile()
+    //
+    pub fn update_tile(&mut self, device: &Device, map: &Map, layer: usize, x: u32, y: u32) {
+        let (cx, cy) = (x / CHUNK_SIZE, y / CHUNK_SIZE);
+        self.chunks.retain(|c| !(c.layer == layer && c.cx == cx && c.cy == cy));
+        for c in map.chunk(layer, cx, cy, CHUNK_SIZE) {
+            self.upload(device, c);
+        }
+        // layers draw in order, a rebuilt chunk must not end up over the layers above it
+        self.chunks.sort_by_key(|c| (c.layer, c.cy, c.cx, c.tileset));
+    }
+
+    //
+    //  a tileset image the gpu cannot take is skipped, its tiles are not drawn
+    //
+    pub fn set_texture(&mut self, device: &Device, queue: &Queue, tileset: usize, image: &crate::assets::Texture) {
+        let max = device.limits().max_texture_dimension_2d;
+        if image.width == 0 || image.height == 0 || image.width > max || image.height > max {
+            warn!("tileset {}: a {}x{} image does not fit in a texture (at most {}x{})", tileset, image.width, image.height, max, max);
+            return;
+        }
+
+        let size = Extent3d { width: image.width, height: image.height, depth_or_array_layers: 1 };
+        let texture = device.create_texture_with_data(queue, &TextureDescriptor {
+            label: Some("Tileset"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        }, wgpu::util::TextureDataOrder::LayerMajor, &image.pixels);
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Tileset Bind Group"),
+            layout: &self.texture_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: BindingResource::TextureView(&view) },
+                BindGroupEntry { binding: 1, resource: BindingResource::Sampler(&self.sampler) },
+            ],
+        });
+
+        if self.textures.len() <= tileset {
+            self.textures.resize_with(tileset + 1, || None);
+        }
+        self.textures[tileset] = Some(bind);
+    }
+
+    //
+    //  (x, y) in the world at the centre of the screen, `scale` pixels per world unit
+    //
+    pub fn set_camera(&mut self, queue: &Queue, x: f32, y: f32, scale: f32, screen: (u32, u32)) {
+        let sx = scale * 2.0 / screen.0.max(1) as f32;
+        let sy = scale * 2.0 / screen.1.max(1) as f32;
+        let uniform = [sx, sy, -x * sx, -y * sy];
+        queue.write_buffer(&self.camera, 0, bytemuck::cast_slice(&uniform));
+
+        let (hw, hh) = (1.0 / sx, 1.0 / sy);
+        self.view = [x - hw, y - hh, x + hw, y + hh];
+    }
+
+    pub fn render(&self, rpass: &mut RenderPass) {
+        if self.chunks.is_empty() {
+            return;
+        }
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.camera_bind, &[]);
+
+        let v = self.view;
+        for c in &self.chunks {
+            let visible = c.bounds[0] <= v[2] && c.bounds[2] >= v[0] && c.bounds[1] <= v[3] && c.bounds[3] >= v[1];
+            let texture = self.textures.get(c.tileset).and_then(|t| t.as_ref());
+            if let (true, Some(texture), Some(layer)) = (visible, texture, self.layers.get(c.layer)) {
+                rpass.set_bind_group(1, texture, &[]);
+                rpass.set_bind_group(2, layer, &[]);
+                rpass.set_vertex_buffer(0, c.buffer.slice(..));
+                rpass.draw(0..c.count, 0..1);
+            }
+        }
+    }
+
+
+    fn upload(&mut self, device: &Device, c: TileChunk) {
+        if c.vertices.is_empty() {
+            return;
+        }
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Tile Chunk"),
+            contents: bytemuck::cast_slice(&c.vertices),
+            usage: BufferUsages::VERTEX,
+        });
+
+        self.chunks.push(GpuChunk {
+            layer: c.layer,
+            tileset: c.tileset,
+            cx: c.cx,
+            cy: c.cy,
+            bounds: c.bounds,
+            buffer,
+            count: c.vertices.len() as u32,
+        });
+    }
+}
@@ -0,0 +1,435 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+
+//
+//  byte pipes under Net: browser WebSocket, native websocket (tungstenite) and an in-process loopback
+//  transports never call back into the game, everything is collected with poll()
+//
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportEvent {
+    Open,
+    Data(Vec<u8>),
+    Text(String),
+    Close(u16, String),
+    Error(String),
+}
+
+
+pub trait Transport {
+    // false when the data could not be handed to the socket
+    fn send(&mut self, data: &[u8]) -> bool;
+    fn close(&mut self);
+    fn poll(&mut self) -> Vec<TransportEvent>;
+    // the caller's clock, for transports that need one (netsim), called before poll
+    fn set_time(&mut self, _now_ms: f64) {}
+}
+
+// server side, hands out one transport per connected client
+pub trait Listener {
+    fn accept(&mut self) -> Option<Box<dyn Transport>>;
+}
+
+pub type Connector = Box<dyn FnMut(&str) -> Result<Box<dyn Transport>, String>>;
+
+
+//
+//  the websocket transport of the current platform
+//
+pub fn connect(url: &str) -> Result<Box<dyn Transport>, String> {
+
+    #[cfg(all(target_arch = "wasm32", feature = "client"))]
+    return Ok(Box::new(WebSocketTransport::connect(url)?));
+
+    #[cfg(all(target_arch = "wasm32", not(feature = "client")))]
+    return Err("websockets need the client feature".to_string());
+
+    #[cfg(not(target_arch = "wasm32"))]
+    return Ok(Box::new(NativeTransport::connect(url)));
+}
+
+
+
+//
+//  browser
+//
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+pub use web::WebSocketTransport;
+
+#[cfg(all(target_arch = "wasm32", feature = "client"))]
+mod web {
+    use super::*;
+
+    use wasm_bindgen::{prelude::Closure, JsCast};
+    use web_sys::{js_sys::{ArrayBuffer, Uint8Array}, BinaryType, CloseEvent, Event, MessageEvent, WebSocket};
+
+    pub struct WebSocketTransport {
+        ws: WebSocket,
+        rx: Receiver<TransportEvent>,
+        _open: Closure<dyn FnMut(Event)>,
+        _message: Closure<dyn FnMut(MessageEvent)>,
+        _close: Closure<dyn FnMut(CloseEvent)>,
+        _error: Closure<dyn FnMut(Event)>,
+    }
+
+    impl WebSocketTransport {
+
+        pub fn connect(url: &str) -> Result<Self, String> {
+            let ws = WebSocket::new(url).map_err(|e| format!("{:?}", e))?;
+            ws.set_binary_type(BinaryType::Arraybuffer);
+
+            let (sx, rx) = channel();
+
+            let s = sx.clone();
+            let open = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                let _ = s.send(TransportEvent::Open);
+            });
+
+            let s = sx.clone();
+            let message = Closure::<dyn FnMut(MessageEvent)>::new(move |e: MessageEvent| {
+                let data = e.data();
+                if let Ok(buf) = data.clone().dyn_into::<ArrayBuffer>() {
+                    let _ = s.send(TransportEvent::Data(Uint8Array::new(&buf).to_vec()));
+                } else if let Some(text) = data.as_string() {
+                    let _ = s.send(TransportEvent::Text(text));
+                }
+            });
+
+            let s = sx.clone();
+            let close = Closure::<dyn FnMut(CloseEvent)>::new(move |e: CloseEvent| {
+                let _ = s.send(TransportEvent::Close(e.code(), e.reason()));
+            });
+
+            let s = sx;
+            let error = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                let _ = s.send(TransportEvent::Error("websocket error".to_string()));
+            });
+
+            ws.set_onopen(Some(open.as_ref().unchecked_ref()));
+            ws.set_onmessage(Some(message.as_ref().unchecked_ref()));
+            ws.set_onclose(Some(close.as_ref().unchecked_ref()));
+            ws.set_onerror(Some(error.as_ref().unchecked_ref()));
+
+            Ok(WebSocketTransport { ws, rx, _open: open, _message: message, _close: close, _error: error })
+        }
+    }
+
+    impl Transport for WebSocketTransport {
+
+        fn send(&mut self, data: &[u8]) -> bool {
+            self.ws.send_with_u8_array(data).is_ok()
+        }
+
+        fn close(&mut self) {
+            let _ = self.ws.close();
+        }
+
+        fn poll(&mut self) -> Vec<TransportEvent> {
+            self.rx.try_iter().collect()
+        }
+    }
+
+    impl Drop for WebSocketTransport {
+        fn drop(&mut self) {
+            self.ws.set_onopen(None);
+            self.ws.set_onmessage(None);
+            self.ws.set_onclose(None);
+            self.ws.set_onerror(None);
+            let _ = self.ws.close();
+        }
+    }
+}
+
+
+
+//
+//  native, one thread per socket so the blocking tungstenite api never stalls the caller
+//
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{NativeTransport, WsListener};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use super::*;
+
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::mpsc::TryRecvError;
+    use std::time::Duration;
+
+    use log::warn;
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::WebSocket;
+
+    // how long the socket thread blocks on read before checking for outgoing data
+    const POLL_INTERVAL: Duration = Duration::from_millis(2);
+    // a client that connects and never finishes the upgrade is dropped after this
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    enum Outgoing {
+        Data(Vec<u8>),
+        Close,
+    }
+
+    pub struct NativeTransport {
+        out: Sender<Outgoing>,
+        rx: Receiver<TransportEvent>,
+    }
+
+    impl NativeTransport {
+
+        pub fn connect(url: &str) -> Self {
+            let (sx, rx) = channel();
+            let (out, out_rx) = channel();
+            let url = url.to_string();
+
+            std::thread::spawn(move || {
+                match tungstenite::connect(url.as_str()) {
+                    Ok((mut ws, _)) => {
+                        if let MaybeTlsStream::Plain(s) = ws.get_mut() {
+                            let _ = s.set_read_timeout(Some(POLL_INTERVAL));
+                        }
+                        run_socket(ws, out_rx, sx);
+                    }
+                    Err(e) => {
+                        let _ = sx.send(TransportEvent::Error(e.to_string()));
+                        let _ = sx.send(TransportEvent::Close(1006, e.to_string()));
+                    }
+                }
+            });
+
+            NativeTransport { out, rx }
+        }
+
+        // server side of a freshly accepted tcp connection
+        pub fn accept(stream: TcpStream) -> Self {
+            NativeTransport::accept_within(stream, HANDSHAKE_TIMEOUT)
+        }
+
+        // the upgrade fails when the client takes longer than `timeout` for it
+        pub fn accept_within(stream: TcpStream, timeout: Duration) -> Self {
+            let (sx, rx) = channel();
+            let (out, out_rx) = channel();
+
+            std::thread::spawn(move || {
+                let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                let ready = stream.set_nonblocking(false)
+                    .and_then(|_| stream.set_read_timeout(Some(timeout)))
+                    .and_then(|_| stream.set_write_timeout(Some(timeout)));
+                if let Err(e) = ready {
+                    warn!("websocket {}: {}", peer, e);
+                    let _ = sx.send(TransportEvent::Error(e.to_string()));
+                    let _ = sx.send(TransportEvent::Close(1006, e.to_string()));
+                    return;
+                }
+
+                match tungstenite::accept(stream) {
+                    Ok(mut ws) => {
+                        let _ = ws.get_mut().set_read_timeout(Some(POLL_INTERVAL));
+                        run_socket(ws, out_rx, sx);
+                    }
+                    Err(e) => {
+                        warn!("websocket {}: handshake failed: {}", peer, e);
+                        let _ = sx.send(TransportEvent::Error(e.to_string()));
+                        let _ = sx.send(TransportEvent::Close(1006, e.to_string()));
+                    }
+                }
+            });
+
+            NativeTransport { out, rx }
+        }
+    }
+
+    impl Transport for NativeTransport {
+
+        fn send(&mut self, data: &[u8]) -> bool {
+            self.out.send(Outgoing::Data(data.to_vec())).is_ok()
+        }
+
+        fn close(&mut self) {
+            let _ = self.out.send(Outgoing::Close);
+        }
+
+        fn poll(&mut self) -> Vec<TransportEvent> {
+            self.rx.try_iter().collect()
+        }
+    }
+
+
+    fn run_socket<S: Read + Write>(mut ws: WebSocket<S>, out: Receiver<Outgoing>, sx: Sender<TransportEvent>) {
+        use tungstenite::{Error, Message};
+
+        let _ = sx.send(TransportEvent::Open);
+        let mut close = (1005, String::new());
+
+        loop {
+            loop {
+                match out.try_recv() {
+                    Ok(Outgoing::Data(data)) => {
+                        if let Err(e) = ws.send(Message::Binary(data)) {
+                            let _ = sx.send(TransportEvent::Error(e.to_string()));
+                            let _ = sx.send(TransportEvent::Close(1006, e.to_string()));
+                            return;
+                        }
+                    }
+                    // close() or the transport was dropped
+                    Ok(Outgoing::Close) | Err(TryRecvError::Disconnected) => {
+                        let _ = ws.close(None);
+                        let _ = ws.flush();
+                        let _ = sx.send(TransportEvent::Close(1000, String::new()));
+                        return;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                }
+            }
+
+            match ws.read() {
+                Ok(Message::Binary(data)) => { let _ = sx.send(TransportEvent::Data(data)); },
+                Ok(Message::Text(text)) => { let _ = sx.send(TransportEvent::Text(text)); },
+                Ok(Message::Close(frame)) => {
+                    if let Some(f) = frame {
+                        close = (u16::from(f.code), f.reason.to_string());
+                    }
+                }
+                // ping / pong are answered by tungstenite
+                Ok(_) => (),
+                Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => (),
+                Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => {
+                    let _ = sx.send(TransportEvent::Close(close.0, close.1));
+                    return;
+                }
+                Err(e) => {
+                    let _ = sx.send(TransportEvent::Error(e.to_string()));
+                    let _ = sx.send(TransportEvent::Close(1006, e.to_string()));
+                    return;
+                }
+            }
+        }
+    }
+
+
+    pub struct WsListener {
+        listener: TcpListener,
+        handshake_timeout: Duration,
+    }
+
+    impl WsListener {
+
+        pub fn bind(addr: &str) -> std::io::Result<Self> {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            Ok(WsListener { listener, handshake_timeout: HANDSHAKE_TIMEOUT })
+        }
+
+        pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+            self.handshake_timeout = timeout;
+            self
+        }
+
+        // useful after binding to port 0
+        pub fn local_addr(&self) -> SocketAddr {
+            self.listener.local_addr().unwrap()
+        }
+    }
+
+    impl Listener for WsListener {
+        fn accept(&mut self) -> Option<Box<dyn Transport>> {
+            match self.listener.accept() {
+                Ok((stream, _)) => Some(Box::new(NativeTransport::accept_within(stream, self.handshake_timeout))),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => None,
+                Err(e) => {
+                    warn!("websocket accept: {}", e);
+                    None
+                }
+            }
+        }
+    }
+}
+
+
+
+//
+//  in-process pair, both ends see Open on their first poll
+//
+pub struct LoopbackTransport {
+    peer: Sender<TransportEvent>,
+    own: Sender<TransportEvent>,
+    rx: Receiver<TransportEvent>,
+    closed: bool,
+}
+
+pub fn loopback_pair() -> (LoopbackTransport, LoopbackTransport) {
+    let (a_sx, a_rx) = channel();
+    let (b_sx, b_rx) = channel();
+    let _ = a_sx.send(TransportEvent::Open);
+    let _ = b_sx.send(TransportEvent::Open);
+
+    (
+        LoopbackTransport { peer: b_sx.clone(), own: a_sx.clone(), rx: a_rx, closed: false },
+        LoopbackTransport { peer: a_sx, own: b_sx, rx: b_rx, closed: false },
+    )
+}
+
+impl Transport for LoopbackTransport {
+
+    fn send(&mut self, data: &[u8]) -> bool {
+        !self.closed && self.peer.send(TransportEvent::Data(data.to_vec())).is_ok()
+    }
+
+    fn close(&mut self) {
+        if !self.closed {
+            self.closed = true;
+            let _ = self.peer.send(TransportEvent::Close(1000, String::new()));
+            let _ = self.own.send(TransportEvent::Close(1000, String::new()));
+        }
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.rx.try_iter().collect()
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+
+//
+//  loopback server end, connector() gives the client side for Net
+//
+pub struct LoopbackListener {
+    sx: Sender<LoopbackTransport>,
+    rx: Receiver<LoopbackTransport>,
+}
+
+impl Default for LoopbackListener {
+    fn default() -> Self {
+        LoopbackListener::new()
+    }
+}
+
+impl LoopbackListener {
+
+    pub fn new() -> Self {
+        let (sx, rx) = channel();
+        LoopbackListener { sx, rx }
+    }
+
+    pub fn connector(&self) -> Connector {
+        let sx = self.sx.clone();
+        Box::new(move |_url: &str| {
+            let (client, server) = loopback_pair();
+            sx.send(server).map_err(|_| "loopback listener is gone".to_string())?;
+            Ok(Box::new(client) as Box<dyn Transport>)
+        })
+    }
+}
+
+impl Listener for LoopbackListener {
+    fn accept(&mut self) -> Option<Box<dyn Transport>> {
+        self.rx.try_recv().ok().map(|t| Box::new(t) as Box<dyn Transport>)
+    }
+}
@@ -0,0 +1,159 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use yo_yo::assets::*;
+use yo_yo::audio::Sound;
+
+
+fn png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(rgba).unwrap();
+    }
+    out
+}
+
+
+#[test]
+fn loads_once_and_caches() {
+    let io = MemoryIo::new().with("hello.txt", b"hello");
+    let mut assets = AssetServer::new(Box::new(io));
+
+    let a: Handle<String> = assets.load("hello.txt");
+    let b: Handle<String> = assets.load("hello.txt");
+    assert_eq!(a, b);
+    assert_eq!(a.state(), LoadState::Loading);
+    assert_eq!(assets.loading(), 1);
+
+    assets.update();
+    assert!(b.is_loaded());
+    assert_eq!(assets.get(&a).map(|s| s.as_str()), Some("hello"));
+
+    // the same path as another type is a separate asset
+    let raw: Handle<Vec<u8>> = assets.load("hello.txt");
+    assert_ne!(raw.id(), a.id());
+    assert_eq!(assets.len(), 2);
+}
+
+#[test]
+fn missing_and_broken_files_fail() {
+    let io = MemoryIo::new().with("noise.wav", b"definitely not a wav");
+    let mut assets = AssetServer::new(Box::new(io));
+
+    let missing: Handle<Sound> = assets.load("missing.wav");
+    let broken: Handle<Sound> = assets.load("noise.wav");
+    assets.update();
+
+    assert!(matches!(missing.state(), LoadState::Failed(e) if e.contains("missing.wav")));
+    assert!(matches!(broken.state(), LoadState::Failed(_)));
+    assert!(assets.get(&broken).is_none());
+}
+
+#[test]
+fn unloads_when_the_last_handle_drops() {
+    let io = MemoryIo::new().with("a.bin", &[1, 2, 3]);
+    let mut assets = AssetServer::new(Box::new(io));
+
+    let a: Handle<Vec<u8>> = assets.load("a.bin");
+    let copy = a.clone();
+    assets.update();
+
+    drop(a);
+    assets.update();
+    assert_eq!(assets.len(), 1);
+
+    let id = copy.id();
+    drop(copy);
+    assets.update();
+    assert_eq!(assets.len(), 0);
+
+    // loads again from scratch
+    let again: Handle<Vec<u8>> = assets.load("a.bin");
+    assert_ne!(again.id(), id);
+    assert_eq!(again.state(), LoadState::Loading);
+}
+
+#[test]
+fn inserted_assets_are_found_by_path() {
+    let mut assets = AssetServer::new(Box::new(MemoryIo::new()));
+    let made = assets.insert("generated/beep", Sound::new(8000, 1, vec![0.5; 80]));
+    assert!(made.is_loaded());
+
+    let found: Handle<Sound> = assets.load("generated/beep");
+    assert_eq!(found, made);
+    assert_eq!(assets.get(&found).unwrap().frames(), 80);
+
+    assets.get_mut(&found).unwrap().rate = 16000;
+    assert_eq!(assets.get(&made).unwrap().rate, 16000);
+}
+
+#[test]
+fn decodes_png_textures() {
+    let pixels = [255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 255, 255, 255, 255, 0];
+    let io = MemoryIo::new().with("tiles.png", &png(2, 2, &pixels));
+    let mut assets = AssetServer::new(Box::new(io));
+
+    let tex: Handle<Texture> = assets.load("tiles.png");
+    assets.update();
+
+    let tex = assets.get(&tex).unwrap();
+    assert_eq!((tex.width, tex.height), (2, 2));
+    assert_eq!(tex.pixels, pixels);
+}
+
+#[test]
+fn reads_files_on_native() {
+    let dir = std::env::temp_dir().join("yo_yo_assets_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("level.txt"), "###\n#.#\n###").unwrap();
+
+    let mut assets = AssetServer::platform(dir.to_str().unwrap());
+    let level: Handle<String> = assets.load("level.txt");
+    let missing: Handle<String> = assets.load("nope.txt");
+
+    assert_eq!(assets.wait(&level, Duration::from_secs(5)), LoadState::Loaded);
+    assert_eq!(assets.get(&level).unwrap().lines().count(), 3);
+    assert!(matches!(assets.wait(&missing, Duration::from_secs(5)), LoadState::Failed(_)));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn ready_future_resolves() {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Woken(std::sync::atomic::AtomicUsize);
+    impl Wake for Woken {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    let io = MemoryIo::new().with("a.txt", b"a");
+    let mut assets = AssetServer::new(Box::new(io));
+    let h: Handle<String> = assets.load("a.txt");
+
+    let woken = Arc::new(Woken(Default::default()));
+    let waker = Waker::from(woken.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut ready = Box::pin(h.ready());
+    // a dropped future is not woken
+    let mut dropped = Box::pin(h.ready());
+    assert_eq!(dropped.as_mut().poll(&mut cx), Poll::Pending);
+    drop(dropped);
+
+    // polled again and again, it is still woken once
+    for _ in 0..3 {
+        assert_eq!(ready.as_mut().poll(&mut cx), Poll::Pending);
+    }
+    assets.update();
+    assert_eq!(woken.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert_eq!(ready.as_mut().poll(&mut cx), Poll::Ready(LoadState::Loaded));
+}
@@ -0,0 +1,143 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::audio::*;
+
+
+fn tone(rate: u32, frames: usize) -> Sound {
+    let samples = (0..frames).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+    Sound::new(rate, 1, samples)
+}
+
+fn dc(rate: u32, frames: usize, v: f32) -> Sound {
+    Sound::new(rate, 1, vec![v; frames])
+}
+
+fn render(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+    let mut out = vec![0.0; frames * 2];
+    mixer.render(&mut out);
+    out
+}
+
+
+#[test]
+fn wav_round_trip() {
+    let sound = tone(22050, 1000);
+    let back = Sound::from_wav(&sound.to_wav()).unwrap();
+
+    assert_eq!(back.rate, 22050);
+    assert_eq!(back.channels, 1);
+    assert_eq!(back.frames(), 1000);
+    for (a, b) in sound.samples.iter().zip(back.samples.iter()) {
+        assert!((a - b).abs() < 1.0 / 16000.0);
+    }
+
+    assert!(Sound::from_wav(b"RIFF....WAVX").is_err());
+}
+
+#[test]
+fn truncated_wavs_are_errors() {
+    let wav = tone(22050, 10).to_wav();
+    // the fmt chunk header says 16 bytes, none of them are there
+    assert!(Sound::from_wav(&wav[..20]).is_err());
+    for len in 12..44 {
+        assert!(Sound::from_wav(&wav[..len]).is_err(), "{}", len);
+    }
+    // a data chunk longer than the file keeps what is there
+    assert_eq!(Sound::from_wav(&wav[..wav.len() - 4]).unwrap().frames(), 8);
+}
+
+#[test]
+fn one_shots_end_and_loops_do_not() {
+    let mut mixer = Mixer::new(MIX_RATE);
+    let sound = dc(MIX_RATE, 100, 0.5);
+
+    let once = mixer.play(&sound, Play::new());
+    let looped = mixer.play(&sound, Play::new().looping().volume(0.5));
+
+    let out = render(&mut mixer, 150);
+    assert_eq!(out[0], 0.75);
+    // only the loop is left after 100 frames
+    assert_eq!(out[120 * 2], 0.25);
+
+    assert!(!mixer.is_playing(once));
+    assert!(mixer.is_playing(looped));
+    mixer.stop(looped);
+    assert_eq!(mixer.playing(), 0);
+}
+
+#[test]
+fn the_oldest_one_shot_is_cut_never_the_music() {
+    let mut mixer = Mixer::new(MIX_RATE);
+    mixer.max_voices = 3;
+    let sound = dc(MIX_RATE, 100, 0.5);
+
+    let music = mixer.play(&sound, Play::new().looping().bus(Bus::Music));
+    let first = mixer.play(&sound, Play::new());
+    let second = mixer.play(&sound, Play::new());
+    let third = mixer.play(&sound, Play::new());
+    assert!(mixer.is_playing(music));
+    assert!(!mixer.is_playing(first));
+    assert!(mixer.is_playing(second) && mixer.is_playing(third));
+
+    // with every voice looping there is nothing to cut, the new sound is dropped
+    mixer.stop_all();
+    let loops: Vec<_> = (0..3).map(|_| mixer.play(&sound, Play::new().looping())).collect();
+    let late = mixer.play(&sound, Play::new());
+    assert!(!mixer.is_playing(late));
+    assert!(loops.iter().all(|v| mixer.is_playing(*v)));
+}
+
+#[test]
+fn pan_pitch_and_resampling() {
+    let mut mixer = Mixer::new(MIX_RATE);
+
+    mixer.play(&dc(MIX_RATE, 100, 0.5), Play::new().pan(-1.0));
+    let out = render(&mut mixer, 10);
+    assert_eq!((out[0], out[1]), (0.5, 0.0));
+    mixer.stop_all();
+
+    // an octave up takes half as long
+    let v = mixer.play(&dc(MIX_RATE, 100, 0.5), Play::new().pitch(2.0));
+    render(&mut mixer, 50);
+    assert!(!mixer.is_playing(v));
+
+    // half the rate takes twice as long
+    let v = mixer.play(&dc(MIX_RATE / 2, 100, 0.5), Play::new());
+    render(&mut mixer, 150);
+    assert!(mixer.is_playing(v));
+    render(&mut mixer, 50);
+    assert!(!mixer.is_playing(v));
+}
+
+#[test]
+fn bus_gains() {
+    let mut mixer = Mixer::new(MIX_RATE);
+    mixer.play(&dc(MIX_RATE, 100, 0.25), Play::new().bus(Bus::Sfx));
+    mixer.play(&dc(MIX_RATE, 100, 0.25), Play::new().bus(Bus::Music));
+
+    mixer.set_bus_gain(Bus::Sfx, 0.0);
+    assert_eq!(render(&mut mixer, 1)[0], 0.25);
+
+    mixer.master = 2.0;
+    assert_eq!(render(&mut mixer, 1)[0], 0.5);
+
+    mixer.stop_bus(Bus::Music);
+    assert_eq!(mixer.playing(), 1);
+}
+
+#[test]
+fn output_pulls_wall_clock_time() {
+    let output = WavOutput::new(MIX_RATE);
+    let recording = output.recording();
+    let mut audio = Audio::new(Box::new(output));
+
+    audio.play(&tone(MIX_RATE, 4410), Play::new());
+    audio.update(0.0);
+    audio.update(50.0);
+    audio.update(100.0);
+
+    let samples = recording.borrow();
+    assert_eq!(samples.len(), 4410 * 2);
+    assert!(samples.iter().any(|s| *s != 0.0));
+    assert_eq!(audio.mixer.playing(), 0);
+}
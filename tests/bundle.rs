@@ -0,0 +1,127 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use yo_yo::assets::*;
+use yo_yo::bundle::*;
+use yo_yo::rng::Rng;
+
+
+fn level() -> Vec<u8> {
+    "#.".repeat(500).into_bytes()
+}
+
+
+#[test]
+fn roundtrip_with_compression() {
+    let mut rng = Rng::new(7);
+    let noise: Vec<u8> = (0..256).map(|_| rng.next_u64() as u8).collect();
+    let bytes = BundleWriter::new()
+        .compress(true)
+        .with("levels/1.txt", &level())
+        .with("noise.bin", &noise)
+        .with("empty", &[])
+        .finish();
+
+    let bundle = Bundle::parse(&bytes).unwrap();
+    assert_eq!(bundle.paths().collect::<Vec<_>>(), ["levels/1.txt", "noise.bin", "empty"]);
+
+    // text shrinks, noise is stored as is
+    let e = bundle.entry("levels/1.txt").unwrap();
+    assert_eq!(e.compression, Compression::Deflate);
+    assert!(e.size < e.raw_size / 10);
+    assert_eq!(bundle.entry("noise.bin").unwrap().compression, Compression::None);
+
+    assert_eq!(bundle.read("levels/1.txt").unwrap(), level());
+    assert_eq!(bundle.read("noise.bin").unwrap(), noise);
+    assert!(bundle.read("empty").unwrap().is_empty());
+    assert!(bundle.read("nope").is_err());
+    assert_eq!(bundle.raw_size(), 1000 + 256);
+}
+
+#[test]
+fn rejects_corrupt_bundles() {
+    let mut bytes = BundleWriter::new().with("a.txt", b"hello").finish();
+    assert!(Bundle::parse(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Bundle::parse(b"RIFF....").is_err());
+
+    // a manifest length past the end, as big as it gets
+    let mut huge = bytes.clone();
+    huge[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(Bundle::parse(&huge).unwrap_err().to_string().contains("cut short"));
+
+    // a flipped byte in the data fails the hash
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    let bundle = Bundle::parse(&bytes).unwrap();
+    assert!(bundle.read("a.txt").unwrap_err().to_string().contains("hash"));
+}
+
+#[test]
+fn loads_wait_for_the_bundle() {
+    let packed = BundleWriter::new().with("ui.txt", b"from the bundle").finish();
+    let io = MemoryIo::new()
+        .with("game.bundle", &packed)
+        .with("ui.txt", b"separate request")
+        .with("other.txt", b"not packed");
+    let mut assets = AssetServer::new(Box::new(io));
+
+    let bundle = assets.load_bundle("game.bundle");
+    let ui: Handle<String> = assets.load("ui.txt");
+    let other: Handle<String> = assets.load("other.txt");
+
+    // the bundle arrives and is mounted, then the waiting loads go out
+    assets.update();
+    assert!(bundle.is_loaded());
+    assert_eq!(assets.get(&ui).map(|s| s.as_str()), Some("from the bundle"));
+    assert_eq!(other.state(), LoadState::Loading);
+
+    assets.update();
+    assert_eq!(assets.get(&other).map(|s| s.as_str()), Some("not packed"));
+}
+
+#[test]
+fn missing_bundle_falls_back_to_files() {
+    let io = MemoryIo::new().with("ui.txt", b"separate request");
+    let mut assets = AssetServer::new(Box::new(io));
+
+    let bundle = assets.load_bundle("game.bundle");
+    let ui: Handle<String> = assets.load("ui.txt");
+    assets.update();
+    assert!(matches!(bundle.state(), LoadState::Failed(_)));
+
+    assets.update();
+    assert_eq!(assets.get(&ui).map(|s| s.as_str()), Some("separate request"));
+}
+
+#[test]
+fn preload_reports_progress() {
+    let mut assets = AssetServer::new(Box::new(MemoryIo::new().with("a", b"a").with("b", b"b")));
+    let a: Handle<Vec<u8>> = assets.load("a");
+    let b: Handle<Vec<u8>> = assets.load("b");
+    let c: Handle<Vec<u8>> = assets.load("c");
+
+    let seen = Rc::new(RefCell::new(vec![]));
+    let log = seen.clone();
+    let mut preload = Preload::new()
+        .with(&a)
+        .with(&b)
+        .with(&c)
+        .with(&a)
+        .on_progress(move |p| log.borrow_mut().push(*p));
+
+    let p = preload.update();
+    assert_eq!((p.total, p.fraction()), (3, 0.0));
+    assert_eq!(preload.current(), Some("a"));
+    // nothing changed, no callback
+    preload.update();
+    assert_eq!(seen.borrow().len(), 1);
+
+    assets.update();
+    let p = preload.update();
+    assert!(p.finished());
+    assert_eq!((p.loaded, p.failed), (2, 1));
+    assert_eq!(preload.failures()[0].0, "c");
+    assert_eq!(seen.borrow().len(), 2);
+}
@@ -0,0 +1,127 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::{Duration, Instant};
+
+use yo_yo::lobby::*;
+use yo_yo::net::*;
+use yo_yo::protocol::*;
+use yo_yo::server::*;
+use yo_yo::transport::*;
+
+
+fn error(out: &[(u32, Message)]) -> Option<String> {
+    out.iter().find_map(|(_, m)| match m {
+        Message::LobbyError { reason } => Some(reason.clone()),
+        _ => None,
+    })
+}
+
+fn started(out: &[(u32, Message)]) -> Vec<u32> {
+    out.iter().filter(|(_, m)| matches!(m, Message::GameStarted { .. })).map(|(c, _)| *c).collect()
+}
+
+
+#[test]
+fn host_starts_once_everyone_is_ready() {
+    let mut lobby = Lobby::new(1);
+    lobby.handle(1, "a", &Message::CreateRoom { name: "room".to_string(), max_players: 2 });
+    let room = lobby.room_of(1).unwrap().id;
+
+    lobby.handle(2, "b", &Message::JoinRoom { room });
+    assert_eq!(error(&lobby.handle(3, "c", &Message::JoinRoom { room })).as_deref(), Some("room is full"));
+
+    assert_eq!(error(&lobby.handle(1, "a", &Message::StartGame)).as_deref(), Some("not everyone is ready"));
+    assert_eq!(error(&lobby.handle(2, "b", &Message::StartGame)).as_deref(), Some("only the host can start"));
+
+    lobby.handle(2, "b", &Message::SetReady { ready: true });
+    let out = lobby.handle(1, "a", &Message::StartGame);
+    assert_eq!(started(&out), vec![1, 2]);
+
+    let r = lobby.room_of(1).unwrap();
+    assert!(r.info().playing);
+    assert!(r.sim.as_ref().unwrap().player_body(2).is_some());
+}
+
+#[test]
+fn host_migrates_and_empty_rooms_close() {
+    let mut lobby = Lobby::new(1);
+    lobby.handle(1, "a", &Message::CreateRoom { name: "room".to_string(), max_players: 4 });
+    let room = lobby.room_of(1).unwrap().id;
+    lobby.handle(2, "b", &Message::JoinRoom { room });
+
+    let out = lobby.leave(1);
+    assert!(out.iter().any(|(c, m)| *c == 2 && matches!(m, Message::RoomUpdate(info) if info.host == 2)));
+
+    lobby.handle(2, "b", &Message::LeaveRoom);
+    assert!(lobby.rooms.is_empty());
+}
+
+#[test]
+fn matchmaking_groups_by_size() {
+    let mut lobby = Lobby::new(1);
+
+    assert!(started(&lobby.handle(1, "a", &Message::FindMatch { size: 2 })).is_empty());
+    assert!(started(&lobby.handle(2, "b", &Message::FindMatch { size: 3 })).is_empty());
+    assert!(lobby.queued(1));
+
+    let out = lobby.handle(3, "c", &Message::FindMatch { size: 2 });
+    assert_eq!(started(&out), vec![1, 3]);
+    assert!(!lobby.queued(1));
+    assert!(lobby.queued(2));
+
+    lobby.handle(2, "b", &Message::CancelMatch);
+    assert!(!lobby.queued(2));
+}
+
+#[test]
+fn rooms_over_a_local_server() {
+    let listener = LoopbackListener::new();
+    let mut a = Net::with_connector(NetConfig::new("loopback"), listener.connector());
+    let mut b = Net::with_connector(NetConfig::new("loopback"), listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    a.connect();
+    b.connect();
+    a.send(&Message::Hello { name: "a".to_string() });
+    b.send(&Message::Hello { name: "b".to_string() });
+    a.send(&Message::CreateRoom { name: "room".to_string(), max_players: 2 });
+
+    let start = Instant::now();
+    let mut b_joined = false;
+    let mut a_started = false;
+
+    while start.elapsed() < Duration::from_secs(5) && !a_started {
+        let now = start.elapsed().as_secs_f64() * 1000.0;
+        server.update(now);
+
+        for e in a.update(now) {
+            match e {
+                NetEvent::Message(Message::RoomUpdate(info)) if info.players.len() == 2 && info.players[1].ready => {
+                    a.send(&Message::StartGame);
+                }
+                NetEvent::Message(Message::GameStarted { .. }) => a_started = true,
+                _ => (),
+            }
+        }
+
+        for e in b.update(now) {
+            match e {
+                NetEvent::Message(Message::Welcome { .. }) => b.send(&Message::ListRooms),
+                NetEvent::Message(Message::RoomList { rooms }) if !b_joined && !rooms.is_empty() => {
+                    b_joined = true;
+                    b.send(&Message::JoinRoom { room: rooms[0].id });
+                    b.send(&Message::SetReady { ready: true });
+                }
+                // the room may not exist yet when the first list arrives
+                NetEvent::Message(Message::RoomList { .. }) if !b_joined => b.send(&Message::ListRooms),
+                _ => (),
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert!(a_started);
+    assert_eq!(server.lobby.rooms.len(), 1);
+    assert_eq!(server.lobby.rooms[0].players.len(), 2);
+}
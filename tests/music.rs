@@ -0,0 +1,214 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{cell::RefCell, rc::Rc};
+
+use yo_yo::audio::*;
+use yo_yo::music::*;
+
+
+fn dc(rate: u32, frames: usize, v: f32) -> Sound {
+    Sound::new(rate, 1, vec![v; frames])
+}
+
+fn mix(player: &mut MusicPlayer, frames: usize) -> Vec<f32> {
+    let mut out = vec![0.0; frames * 2];
+    player.mix(&mut out, 1000, 1.0, false);
+    out
+}
+
+
+#[test]
+fn wav_streams_in_pieces() {
+    let sound = Sound::new(22050, 2, (0..2000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect());
+    let wav = sound.to_wav();
+
+    let mut stream = WavStream::new();
+    stream.push(&wav[..30]).unwrap();
+    assert_eq!(stream.buffered(), 0);
+    assert_eq!(stream.read(0, &mut [0.0; 8]), 0);
+
+    for piece in wav[30..].chunks(333) {
+        stream.push(piece).unwrap();
+    }
+    stream.finish();
+
+    assert_eq!(stream.rate(), 22050);
+    assert_eq!(stream.total_frames(), Some(1000));
+
+    let mut out = vec![0.0; 200];
+    assert_eq!(stream.read(900, &mut out), 100);
+    assert!((out[0] - sound.samples[1800]).abs() < 1e-3);
+    assert!((out[199] - sound.samples[1999]).abs() < 1e-3);
+}
+
+#[test]
+fn wav_streams_from_a_file() {
+    let path = std::env::temp_dir().join("yo_yo_music_test.wav");
+    std::fs::write(&path, dc(8000, 50000, 0.25).to_wav()).unwrap();
+
+    let mut stream = WavStream::open(path.to_str().unwrap()).unwrap();
+    assert!(!stream.complete());
+
+    // asking past the end reads the file to its end
+    let mut out = vec![0.0; 40];
+    assert_eq!(stream.read(49990, &mut out), 10);
+    assert!((out[0] - 0.25).abs() < 1e-3);
+    assert!(stream.complete());
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn wav_plays_while_it_arrives() {
+    let wav = dc(1000, 20000, 0.5).to_wav();
+    let head = wav.len() - 20000 * 2;
+
+    let stream = Rc::new(RefCell::new(WavStream::new()));
+    stream.borrow_mut().push(&wav[..head + 2000]).unwrap();
+
+    let mut player = MusicPlayer::new();
+    player.crossfade = 0.0;
+    player.enqueue(Track::new("live", stream.clone()));
+
+    // 1000 frames arrived, then it waits for more
+    let out = mix(&mut player, 1500);
+    assert!((out[999 * 2] - 0.5).abs() < 1e-3);
+    assert_eq!(out[1200 * 2], 0.0);
+
+    stream.borrow_mut().push(&wav[head + 2000..]).unwrap();
+    stream.borrow_mut().finish();
+    let out = mix(&mut player, 10000);
+    assert!((out[0] - 0.5).abs() < 1e-3);
+
+    // what played is gone, so it cannot start over either
+    assert!(stream.borrow().held_bytes() < 10000 * 2);
+    mix(&mut player, 10000);
+    assert_eq!(player.current(), None);
+}
+
+#[test]
+fn streams_keep_the_loop() {
+    let mut stream = WavStream::from_bytes(dc(1000, 20000, 0.5).to_wav()).unwrap();
+    stream.discard(15000);
+    assert_eq!(stream.read(0, &mut [0.0; 8]), 0);
+    let stream = Rc::new(RefCell::new(WavStream::from_bytes(dc(1000, 20000, 0.5).to_wav()).unwrap()));
+
+    let mut player = MusicPlayer::new();
+    player.crossfade = 0.0;
+    let t = player.add(Track::new("loop", stream.clone()).looping(1000, None));
+    player.play(t);
+
+    let out = mix(&mut player, 30000);
+    assert!(out.chunks(2).all(|f| (f[0] - 0.5).abs() < 1e-3));
+    assert!(stream.borrow().held_bytes() >= 19000 * 2);
+}
+
+#[test]
+fn file_streams_seek_back_to_play_again() {
+    let path = std::env::temp_dir().join("yo_yo_music_replay.wav");
+    std::fs::write(&path, dc(1000, 20000, 0.25).to_wav()).unwrap();
+    let stream = Rc::new(RefCell::new(WavStream::open(path.to_str().unwrap()).unwrap()));
+
+    let mut player = MusicPlayer::new();
+    player.crossfade = 0.0;
+    player.enqueue(Track::new("file", stream.clone()));
+
+    mix(&mut player, 15000);
+    assert!(stream.borrow().held_bytes() < 10000 * 2);
+
+    // the one track playlist starts over
+    mix(&mut player, 5000);
+    let out = mix(&mut player, 1000);
+    assert_eq!(player.current(), Some("file"));
+    assert!((out[0] - 0.25).abs() < 1e-3);
+    assert!((out[999 * 2] - 0.25).abs() < 1e-3);
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn crossfade_between_tracks() {
+    let mut player = MusicPlayer::new();
+    player.crossfade = 0.5;
+    let a = player.add(Track::new("a", dc(1000, 10000, 0.25)));
+    let b = player.add(Track::new("b", dc(1000, 10000, 0.5)));
+
+    player.play(a);
+    let out = mix(&mut player, 1000);
+    assert_eq!(out[0], 0.0);
+    assert!((out[999 * 2] - 0.25).abs() < 1e-3);
+
+    player.play(b);
+    let out = mix(&mut player, 1000);
+    // halfway both are at half volume
+    assert!((out[250 * 2] - 0.375).abs() < 0.01);
+    assert!((out[999 * 2] - 0.5).abs() < 1e-3);
+    assert_eq!(player.current(), Some("b"));
+}
+
+#[test]
+fn short_tracks_shorten_the_crossfade() {
+    let mut player = MusicPlayer::new();
+    player.crossfade = 2.0;
+    player.enqueue(Track::new("a", dc(1000, 400, 0.5)));
+    player.enqueue(Track::new("b", dc(1000, 400, 0.5)));
+
+    // the fade is cut to 0.2s, so a plays until halfway instead of skipping on every mix
+    mix(&mut player, 10);
+    assert_eq!(player.current(), Some("a"));
+    mix(&mut player, 190);
+    assert_eq!(player.current(), Some("a"));
+    mix(&mut player, 10);
+    assert_eq!(player.current(), Some("b"));
+}
+
+#[test]
+fn loop_points() {
+    let ramp = Sound::new(1000, 1, (0..100).map(|i| i as f32 / 1000.0).collect());
+    let mut player = MusicPlayer::new();
+    player.crossfade = 0.0;
+    let t = player.add(Track::new("ramp", ramp).looping(50, Some(80)));
+    player.play(t);
+
+    let out = mix(&mut player, 200);
+    assert_eq!(out[79 * 2], 0.079);
+    assert_eq!(out[80 * 2], 0.05);
+    assert!(out.iter().all(|s| *s < 0.08));
+}
+
+#[test]
+fn playlist_moves_on_and_stops() {
+    let mut player = MusicPlayer::new();
+    player.crossfade = 0.0;
+    player.repeat = false;
+    player.enqueue(Track::new("a", dc(1000, 100, 0.5)));
+    player.enqueue(Track::new("b", dc(1000, 100, 0.5)));
+    assert_eq!(player.current(), Some("a"));
+
+    mix(&mut player, 150);
+    assert_eq!(player.current(), Some("b"));
+
+    mix(&mut player, 200);
+    assert_eq!(player.current(), None);
+}
+
+#[test]
+fn music_ducks_under_dialogue() {
+    let output = WavOutput::new(MIX_RATE);
+    let recording = output.recording();
+    let mut audio = Audio::new(Box::new(output));
+
+    audio.music.crossfade = 0.0;
+    audio.music.enqueue(Track::new("theme", dc(MIX_RATE, MIX_RATE as usize, 0.5)).looping(0, None));
+    let line = audio.play(&dc(MIX_RATE, MIX_RATE as usize, 0.0), Play::new().bus(Bus::Dialogue));
+
+    audio.update(0.0);
+    audio.update(200.0);
+    let last = *recording.borrow().last().unwrap();
+    assert!((last - 0.15).abs() < 0.01, "{}", last);
+
+    audio.stop(line);
+    audio.update(1200.0);
+    let last = *recording.borrow().last().unwrap();
+    assert!((last - 0.5).abs() < 0.01, "{}", last);
+}
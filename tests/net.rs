@@ -0,0 +1,322 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::{Duration, Instant};
+
+use yo_yo::components::*;
+use yo_yo::net::*;
+use yo_yo::protocol::*;
+use yo_yo::replay::*;
+use yo_yo::server::*;
+use yo_yo::sim::*;
+use yo_yo::snapshot::*;
+use yo_yo::transport::*;
+
+
+//
+//  drive server and clients until `done` or a 5 second timeout
+//
+fn run(server: &mut Server, clients: &mut [&mut Net], mut done: impl FnMut(usize, &NetEvent) -> bool) -> bool {
+    let start = Instant::now();
+
+    while start.elapsed() < Duration::from_secs(5) {
+        let now = start.elapsed().as_secs_f64() * 1000.0;
+        server.update(now);
+        for (i, c) in clients.iter_mut().enumerate() {
+            for e in c.update(now) {
+                if done(i, &e) {
+                    return true;
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    false
+}
+
+
+#[test]
+fn loopback_relays_between_clients() {
+    let listener = LoopbackListener::new();
+    let mut a = Net::with_connector(NetConfig::new("loopback"), listener.connector());
+    let mut b = Net::with_connector(NetConfig::new("loopback"), listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    a.connect();
+    b.connect();
+    // queued until the transport opens, chat before Hello is not relayed
+    a.send(&Message::Chat { text: "anyone?".to_string() });
+    a.send(&Message::Hello { name: "a".to_string() });
+    a.send(&Message::Chat { text: "hi".to_string() });
+
+    let mut first = None;
+    let got = run(&mut server, &mut [&mut a, &mut b], |i, e| match e {
+        NetEvent::Message(Message::Chat { text }) if i == 1 => {
+            first.get_or_insert(text.clone());
+            text == "hi"
+        }
+        _ => false,
+    });
+
+    assert!(got);
+    assert_eq!(first.as_deref(), Some("hi"));
+    assert_eq!(a.queued(), 0);
+}
+
+#[test]
+fn silent_clients_time_out() {
+    let listener = LoopbackListener::new();
+    // never pings and never gives up on its own
+    let config = NetConfig::new("loopback").heartbeat(60_000.0).timeout(60_000.0).reconnect(false);
+    let mut a = Net::with_connector(config, listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1).timeout(200.0));
+
+    a.connect();
+    assert!(run(&mut server, &mut [&mut a], |_, e| *e == NetEvent::Connected));
+    assert_eq!(server.clients.len(), 1);
+
+    assert!(run(&mut server, &mut [&mut a], |_, e| matches!(e, NetEvent::Disconnected { .. })));
+    assert!(server.clients.is_empty());
+}
+
+#[test]
+fn loopback_reconnects_after_server_drop() {
+    let listener = LoopbackListener::new();
+    let mut a = Net::with_connector(NetConfig::new("loopback").backoff(1.0, 5.0), listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    a.connect();
+    assert!(run(&mut server, &mut [&mut a], |_, e| *e == NetEvent::Connected));
+
+    server.clients.clear();
+
+    let mut disconnected = false;
+    let got = run(&mut server, &mut [&mut a], |_, e| {
+        if let NetEvent::Disconnected { .. } = e {
+            disconnected = true;
+        }
+        disconnected && *e == NetEvent::Connected
+    });
+
+    assert!(got);
+}
+
+#[test]
+fn nothing_from_a_dropped_session_is_resent() {
+    let listener = LoopbackListener::new();
+    let mut a = Net::with_connector(NetConfig::new("loopback").backoff(50.0, 50.0), listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    a.connect();
+    assert!(run(&mut server, &mut [&mut a], |_, e| *e == NetEvent::Connected));
+
+    server.clients.clear();
+    assert!(run(&mut server, &mut [&mut a], |_, e| matches!(e, NetEvent::Disconnected { .. })));
+
+    // stamped with a tick of the old session
+    a.send(&Message::Ack { tick: 7 });
+    assert_eq!(a.queued(), 0);
+    assert!(run(&mut server, &mut [&mut a], |_, e| *e == NetEvent::Connected));
+}
+
+#[test]
+fn native_websocket_round_trip() {
+    let listener = WsListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    let mut a = Net::new(NetConfig::new(&url));
+    let mut b = Net::new(NetConfig::new(&url));
+    a.connect();
+    b.connect();
+
+    let mut connected = 0;
+    assert!(run(&mut server, &mut [&mut a, &mut b], |_, e| {
+        if *e == NetEvent::Connected {
+            connected += 1;
+        }
+        connected == 2
+    }));
+
+    a.send(&Message::Hello { name: "a".to_string() });
+    b.send(&Message::Hello { name: "b".to_string() });
+    a.send(&Message::FindMatch { size: 2 });
+    b.send(&Message::FindMatch { size: 2 });
+
+    // both players show up in the snapshots of both clients
+    let mut seen = [false, false];
+    let mut history = [SnapshotHistory::new(SNAPSHOT_HISTORY), SnapshotHistory::new(SNAPSHOT_HISTORY)];
+    let got = run(&mut server, &mut [&mut a, &mut b], |i, e| {
+        if let NetEvent::Message(Message::Snapshot(d)) = e {
+            let s = history[i].receive(d).unwrap();
+            seen[i] |= s.entities.iter().filter(|e| e.player.is_some()).count() == 2;
+        }
+        seen[0] && seen[1]
+    });
+
+    assert!(got);
+    let mut names: Vec<&str> = server.clients.iter().map(|c| c.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["a", "b"]);
+}
+
+#[test]
+fn stalled_handshakes_time_out() {
+    let start = Instant::now();
+    let listener = WsListener::bind("127.0.0.1:0").unwrap().handshake_timeout(Duration::from_millis(100));
+    let addr = listener.local_addr();
+    let url = format!("ws://{}", addr);
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    // opens tcp and never sends the upgrade request
+    let _silent = std::net::TcpStream::connect(addr).unwrap();
+    let mut a = Net::new(NetConfig::new(&url));
+    a.connect();
+    // the other client got through meanwhile
+    assert!(run(&mut server, &mut [&mut a], |_, e| *e == NetEvent::Connected));
+
+    while server.clients.len() > 1 && start.elapsed() < Duration::from_secs(5) {
+        server.update(start.elapsed().as_secs_f64() * 1000.0);
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(server.clients.len(), 1);
+}
+
+#[test]
+fn server_applies_client_input() {
+    let listener = LoopbackListener::new();
+    let mut a = Net::with_connector(NetConfig::new("loopback"), listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1).snapshot_every(1));
+
+    a.connect();
+    a.send(&Message::Hello { name: "a".to_string() });
+    a.send(&Message::FindMatch { size: 1 });
+
+    let mut id = 0;
+    assert!(run(&mut server, &mut [&mut a], |_, e| {
+        match e {
+            NetEvent::Message(Message::Welcome { id: i }) => id = *i,
+            NetEvent::Message(Message::GameStarted { .. }) => return true,
+            _ => (),
+        }
+        false
+    }));
+
+    let start_x = server.lobby.rooms[0].sim.as_ref().unwrap().world.query::<(&yo_yo::components::Player, &yo_yo::components::Transform)>().iter()
+        .find(|(_, (p, _))| p.0 == id)
+        .map(|(_, (_, t))| t.x)
+        .unwrap();
+
+    for tick in 1..=30 {
+        let mut frame = InputFrame::default();
+        frame.axes.push(("move_x".to_string(), 1.0));
+        a.send(&Message::Input { tick, frame });
+    }
+
+    let mut history = SnapshotHistory::new(SNAPSHOT_HISTORY);
+    let got = run(&mut server, &mut [&mut a], |_, e| match e {
+        NetEvent::Message(Message::Snapshot(d)) => {
+            let s = history.receive(d).unwrap();
+            match s.ack {
+                30 => {
+                    let me = s.entities.iter().find(|e| e.player == Some(id)).unwrap();
+                    assert!(me.x > start_x);
+                    true
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    });
+
+    assert!(got);
+}
+
+#[test]
+fn remote_input_is_checked() {
+    let listener = LoopbackListener::new();
+    let mut a = Net::with_connector(NetConfig::new("loopback"), listener.connector());
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1).snapshot_every(1));
+
+    a.connect();
+    a.send(&Message::Hello { name: "a".to_string() });
+    a.send(&Message::FindMatch { size: 1 });
+
+    let mut id = 0;
+    assert!(run(&mut server, &mut [&mut a], |_, e| {
+        match e {
+            NetEvent::Message(Message::Welcome { id: i }) => id = *i,
+            NetEvent::Message(Message::GameStarted { .. }) => return true,
+            _ => (),
+        }
+        false
+    }));
+
+    let player_x = |server: &Server| server.lobby.rooms[0].sim.as_ref().unwrap().world.query::<(&Player, &Transform)>().iter()
+        .find(|(_, (p, _))| p.0 == id)
+        .map(|(_, (_, t))| t.x)
+        .unwrap();
+    let start_x = player_x(&server);
+
+    // a spawn at a NaN pointer is dropped whole, the huge axis counts as 1
+    let mut nan = InputFrame { pointer: [f32::NAN, 0.0], ..Default::default() };
+    nan.pressed.push("spawn".to_string());
+    a.send(&Message::Input { tick: 1, frame: nan });
+    for tick in 2..=30 {
+        let mut frame = InputFrame::default();
+        frame.axes.push(("move_x".to_string(), 1e6));
+        a.send(&Message::Input { tick, frame });
+    }
+
+    assert!(run(&mut server, &mut [&mut a], |_, e| matches!(e, NetEvent::Message(Message::Snapshot(d)) if d.ack == 30)));
+
+    let sim = server.lobby.rooms[0].sim.as_ref().unwrap();
+    assert_eq!(sim.world.query::<&SpawnedBy>().iter().count(), 0);
+    assert!(sim.world.query::<&Transform>().iter().all(|(_, t)| t.x.is_finite() && t.y.is_finite()));
+    let moved = player_x(&server) - start_x;
+    assert!(moved > 0.0 && moved < 2.0, "{}", moved);
+}
+
+#[test]
+fn remote_spawns_are_limited() {
+    let mut sim = Sim::new(4);
+    sim.add_player(1);
+    sim.add_player(2);
+
+    let mut spam = InputFrame::default();
+    spam.pressed.push("spawn".to_string());
+    let idle = InputFrame::default();
+
+    let spawned = |sim: &Sim, player: u32| -> Vec<u64> {
+        sim.world.query::<&SpawnedBy>().iter().filter(|(_, s)| s.player == player).map(|(_, s)| s.tick).collect()
+    };
+
+    // held down for a second, one ball per cooldown
+    for _ in 0..60 {
+        sim.step_players(&[(1, spam.clone()), (2, idle.clone())]);
+    }
+    assert_eq!(spawned(&sim, 1).len(), 60_usize.div_ceil(SPAWN_COOLDOWN_TICKS as usize));
+    assert!(spawned(&sim, 2).is_empty());
+
+    // never more than the cap, new balls replace the oldest
+    for _ in 0..SPAWN_COOLDOWN_TICKS * MAX_SPAWNS_PER_PLAYER as u64 * 2 {
+        sim.step_players(&[(1, spam.clone()), (2, spam.clone())]);
+    }
+    let ticks = spawned(&sim, 1);
+    assert_eq!(ticks.len(), MAX_SPAWNS_PER_PLAYER);
+    assert_eq!(spawned(&sim, 2).len(), MAX_SPAWNS_PER_PLAYER);
+    assert!(ticks.iter().all(|t| *t >= sim.tick - SPAWN_COOLDOWN_TICKS * MAX_SPAWNS_PER_PLAYER as u64));
+
+    // leaving takes the player's balls along
+    let balls = sim.world.query::<&Circle>().iter().count();
+    sim.remove_player(1);
+    assert!(spawned(&sim, 1).is_empty());
+    assert_eq!(sim.world.query::<&Circle>().iter().count(), balls - MAX_SPAWNS_PER_PLAYER - 1);
+
+    // the local player is not limited
+    let mut local = Sim::new(4);
+    for _ in 0..3 {
+        local.step(&spam);
+    }
+    assert_eq!(local.world.query::<&Circle>().iter().count(), 4);
+}
@@ -0,0 +1,138 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::{Duration, Instant};
+
+use yo_yo::net::*;
+use yo_yo::netsim::*;
+use yo_yo::protocol::*;
+use yo_yo::server::*;
+use yo_yo::transport::*;
+
+
+// a simulated end talking to a plain loopback end, time is driven by hand
+fn pair(conditions: Conditions) -> (SimulatedTransport, LoopbackTransport) {
+    let (a, b) = loopback_pair();
+    (SimulatedTransport::new(Box::new(a), conditions), b)
+}
+
+fn data(events: Vec<TransportEvent>) -> Vec<Vec<u8>> {
+    events.into_iter().filter_map(|e| match e {
+        TransportEvent::Data(d) => Some(d),
+        _ => None,
+    }).collect()
+}
+
+fn step(a: &mut SimulatedTransport, b: &mut LoopbackTransport, now: f64) -> Vec<Vec<u8>> {
+    a.set_time(now);
+    a.poll();
+    data(b.poll())
+}
+
+
+#[test]
+fn latency_delays_in_order() {
+    let (mut a, mut b) = pair(Conditions::new().latency(50.0).jitter(20.0));
+
+    for i in 0..20u8 {
+        a.set_time(i as f64);
+        a.send(&[i]);
+    }
+
+    assert!(step(&mut a, &mut b, 25.0).is_empty());
+
+    let mut got = vec![];
+    for t in 26..200 {
+        got.extend(step(&mut a, &mut b, t as f64));
+    }
+    assert_eq!(got, (0..20u8).map(|i| vec![i]).collect::<Vec<_>>());
+}
+
+#[test]
+fn loss_drops_about_the_configured_fraction() {
+    let (mut a, mut b) = pair(Conditions::new().loss(0.25).seed(7));
+
+    for i in 0..1000u32 {
+        a.send(&i.to_le_bytes());
+    }
+    let got = step(&mut a, &mut b, 0.0).len() as u64;
+
+    assert!(got > 700 && got < 800, "{} of 1000 arrived", got);
+    assert_eq!(a.stats.dropped_out, 1000 - got);
+}
+
+#[test]
+fn bandwidth_limits_throughput() {
+    // 1000 bytes per second, 100 byte messages take 100ms each
+    let (mut a, mut b) = pair(Conditions::new().bandwidth(1000.0));
+
+    for _ in 0..5 {
+        a.send(&[0; 100]);
+    }
+
+    assert_eq!(step(&mut a, &mut b, 250.0).len(), 2);
+    assert_eq!(step(&mut a, &mut b, 499.0).len(), 2);
+    assert_eq!(step(&mut a, &mut b, 500.0).len(), 1);
+}
+
+#[test]
+fn duplicates_arrive_twice() {
+    let (mut a, mut b) = pair(Conditions::new().duplicate(1.0));
+    a.send(&[1]);
+    a.send(&[2]);
+    assert_eq!(step(&mut a, &mut b, 0.0), vec![vec![1], vec![1], vec![2], vec![2]]);
+    assert_eq!((a.stats.duplicated_out, a.stats.duplicated_in), (2, 0));
+
+    b.send(&[3]);
+    a.set_time(1.0);
+    assert_eq!(data(a.poll()), vec![vec![3], vec![3]]);
+    assert_eq!((a.stats.duplicated_out, a.stats.duplicated_in), (2, 1));
+}
+
+#[test]
+fn close_drops_what_is_still_delayed() {
+    let (mut a, mut b) = pair(Conditions::new().latency(50.0));
+    a.send(&[1]);
+    a.send(&[2]);
+    a.set_time(60.0);
+    a.send(&[3]);
+    a.poll();
+    a.close();
+
+    // the first two were due, the last one was still on its way
+    assert_eq!(data(b.poll()), vec![vec![1], vec![2]]);
+    assert_eq!(a.stats.dropped_out, 1);
+}
+
+#[test]
+fn parse_from_url() {
+    assert_eq!(Conditions::parse("100,20,0.05"), Some(Conditions::new().latency(100.0).jitter(20.0).loss(0.05)));
+    assert_eq!(Conditions::parse("80,0,0,0.1,5000").unwrap().bandwidth, Some(5000.0));
+    assert_eq!(Conditions::parse("slow"), None);
+}
+
+#[test]
+fn net_stats_over_a_slow_link() {
+    let listener = LoopbackListener::new();
+    let connector = simulated(listener.connector(), Conditions::new().latency(20.0));
+    let mut a = Net::with_connector(NetConfig::new("loopback").heartbeat(30.0), connector);
+    let mut server = Server::new(Box::new(listener), ServerConfig::new(1));
+
+    a.connect();
+    a.send(&Message::Hello { name: "a".to_string() });
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) && a.stats().pongs < 3 {
+        let now = start.elapsed().as_secs_f64() * 1000.0;
+        server.update(now);
+        a.update(now);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let stats = a.stats();
+    assert!(stats.pongs >= 3);
+    // 20ms each way
+    assert!(stats.rtt.unwrap() >= 40.0);
+    assert_eq!(stats.lost, 0);
+    assert!(stats.bytes_out > 0 && stats.bytes_in > 0);
+    assert!(server.clients[0].stats.messages_in >= 4);
+}
@@ -0,0 +1,85 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::prediction::*;
+use yo_yo::replay::*;
+use yo_yo::sim::*;
+use yo_yo::snapshot::*;
+
+
+//
+//  server sim holding only player 1, like the predictor's own sim
+//
+fn server() -> Sim {
+    let mut sim = Sim::new(9);
+    let all: Vec<_> = sim.world.iter().map(|e| e.entity()).collect();
+    for e in all {
+        sim.remove_entity(e);
+    }
+    sim.add_player(1);
+    sim
+}
+
+fn input(i: u64) -> InputFrame {
+    let mut frame = InputFrame::default();
+    frame.axes.push(("move_x".to_string(), if i < 40 { 1.0 } else { -1.0 }));
+    if i % 25 == 5 {
+        frame.pressed.push("jump".to_string());
+    }
+    frame
+}
+
+fn player_pos(snapshot: &Snapshot) -> (f32, f32) {
+    let e = snapshot.entities.iter().find(|e| e.player == Some(1)).unwrap();
+    (e.x, e.y)
+}
+
+#[test]
+fn reconcile_replays_unacked_inputs() {
+    let mut server = server();
+    let mut predictor = Predictor::new(1);
+    let latency = 6;
+
+    // the first snapshot only places the body, the predictor starts wherever the server put us
+    predictor.reconcile(&Snapshot { tick: 0, ack: 0, entities: capture(&server) });
+
+    for i in 1..=80u64 {
+        predictor.step(&input(i));
+
+        // the server sees our inputs `latency` ticks late
+        if i > latency {
+            server.step_players(&[(1, input(i - latency))]);
+            predictor.reconcile(&Snapshot { tick: server.tick, ack: i - latency, entities: capture(&server) });
+        }
+    }
+    assert_eq!(predictor.pending(), latency as usize);
+
+    // let the server catch up, both have now run the same inputs
+    for i in 80 - latency + 1..=80 {
+        server.step_players(&[(1, input(i))]);
+    }
+    let truth = player_pos(&Snapshot { tick: server.tick, ack: 80, entities: capture(&server) });
+    let (x, y, _) = predictor.position();
+
+    assert!((x - truth.0).abs() < 0.05, "{} vs {}", x, truth.0);
+    assert!((y - truth.1).abs() < 0.05, "{} vs {}", y, truth.1);
+}
+
+#[test]
+fn visual_error_fades_out() {
+    let server = server();
+    let mut predictor = Predictor::new(1);
+    predictor.reconcile(&Snapshot { tick: 0, ack: 0, entities: capture(&server) });
+
+    // a correction of half a unit
+    let mut entities = capture(&server);
+    entities[0].x += 0.5;
+    let before = predictor.position();
+    predictor.reconcile(&Snapshot { tick: 1, ack: 0, entities });
+
+    // drawn where it was, then eased over
+    assert!((predictor.position().0 - before.0).abs() < 0.001);
+    for _ in 0..60 {
+        predictor.update(1.0 / 60.0);
+    }
+    assert!((predictor.position().0 - (before.0 + 0.5)).abs() < 0.01);
+}
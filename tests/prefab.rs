@@ -0,0 +1,144 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use hecs::World;
+
+use yo_yo::components::*;
+use yo_yo::physics::*;
+use yo_yo::prefab::*;
+use yo_yo::sim::*;
+
+
+const PREFABS: &str = r#"{
+    "crate": {
+        "name": "crate",
+        "circle": { "radius": 0.5, "color": [0.6, 0.4, 0.2] },
+        "body": { "kind": "Dynamic" },
+        "colliders": [ { "shape": { "type": "Cuboid", "hx": 0.5, "hy": 0.5 }, "friction": 0.9 } ],
+        "assets": { "sprite": "crate.png" }
+    },
+    "heavy crate": { "base": "crate", "circle": { "color": [0.3, 0.3, 0.3] }, "colliders": [
+        { "shape": { "type": "Cuboid", "hx": 0.5, "hy": 0.5 }, "density": 8.0 }
+    ] },
+    "cart": {
+        "name": "cart",
+        "body": { "kind": "Dynamic" },
+        "colliders": [ { "shape": { "type": "Cuboid", "hx": 1.0, "hy": 0.25 } } ],
+        "children": [
+            { "prefab": "crate", "transform": { "x": -0.5, "y": 0.75 } },
+            { "prefab": "heavy crate", "transform": { "x": 0.5, "y": 0.75 }, "circle": { "radius": 0.4 } }
+        ]
+    },
+    "loop a": { "children": [ { "prefab": "loop b" } ] },
+    "loop b": { "children": [ { "prefab": "loop a" } ] },
+    "broken": { "base": "crate", "colliders": [ { "shape": { "type": "Convex", "points": [] } } ] },
+    "fence": { "colliders": [ { "shape": { "type": "Cuboid", "hx": 0.1, "hy": 1.0 } } ] },
+    "fenced broken": { "children": [ { "prefab": "fence" }, { "prefab": "broken" } ] }
+}"#;
+
+
+#[test]
+fn bases_and_overrides_merge() {
+    let prefabs = Prefabs::from_json(PREFABS).unwrap();
+    assert_eq!(prefabs.len(), 8);
+
+    let heavy = prefabs.resolve("heavy crate", &Default::default()).unwrap();
+    // objects merge field by field, lists are replaced
+    let circle = heavy.desc.circle.unwrap();
+    assert_eq!((circle.radius, circle.color), (0.5, [0.3, 0.3, 0.3]));
+    assert_eq!(heavy.desc.colliders.len(), 1);
+    assert_eq!((heavy.desc.colliders[0].density, heavy.desc.colliders[0].friction), (8.0, 0.5));
+    assert_eq!(heavy.desc.assets["sprite"], "crate.png");
+
+    let instance = Instance::new("crate").set("circle.radius", 0.25).set("name", "small crate");
+    let small = prefabs.resolve("crate", &instance.overrides).unwrap();
+    assert_eq!(small.desc.circle.unwrap().radius, 0.25);
+    assert_eq!(small.desc.circle.unwrap().color, [0.6, 0.4, 0.2]);
+    assert_eq!(small.desc.name.as_deref(), Some("small crate"));
+
+    // null clears a field
+    let ghost = Instance::new("crate").set("body", serde_json::Value::Null);
+    assert!(prefabs.resolve("crate", &ghost.overrides).unwrap().desc.body.is_none());
+}
+
+#[test]
+fn nested_prefabs_spawn_relative_to_their_parent() {
+    let prefabs = Prefabs::from_json(PREFABS).unwrap();
+    let mut world = World::new();
+    let mut physics = Physics::empty();
+
+    let instance = Instance::new("cart").at(10.0, 2.0).rotation(std::f32::consts::PI);
+    let spawned = prefabs.spawn_prefab(&mut world, &mut physics, &instance).unwrap();
+    assert_eq!(spawned.len(), 3);
+
+    let cart = spawned[0];
+    assert_eq!(world.get::<&PrefabRef>(cart).unwrap().0, "cart");
+    assert!(world.get::<&Parent>(cart).is_err());
+
+    let heavy = spawned[2];
+    assert_eq!(world.get::<&PrefabRef>(heavy).unwrap().0, "heavy crate");
+    assert_eq!(world.get::<&Parent>(heavy).unwrap().0, cart);
+    assert_eq!(world.get::<&Circle>(heavy).unwrap().radius, 0.4);
+
+    // turned half way round, the right hand crate ends up on the left, under the cart
+    let t = *world.get::<&Transform>(heavy).unwrap();
+    assert!((t.x - 9.5).abs() < 1e-5 && (t.y - 1.25).abs() < 1e-5, "{:?}", t);
+    let body = world.get::<&Body>(heavy).unwrap().0;
+    let (x, y, _) = physics.body_transform(body).unwrap();
+    assert!((x - 9.5).abs() < 1e-5 && (y - 1.25).abs() < 1e-5);
+    assert_eq!(physics.describe_colliders(Some(body))[0].density, 8.0);
+}
+
+#[test]
+fn bad_prefabs_leave_nothing_behind() {
+    let prefabs = Prefabs::from_json(PREFABS).unwrap();
+    let mut world = World::new();
+    let mut physics = Physics::empty();
+
+    let err = prefabs.spawn_prefab(&mut world, &mut physics, &Instance::new("loop a")).unwrap_err();
+    assert!(format!("{:#}", err).contains("contains itself"));
+    assert!(prefabs.spawn_prefab(&mut world, &mut physics, &Instance::new("nope")).is_err());
+
+    let with_broken = prefabs.clone().with("broken cart", Prefab::from_json(r#"{ "base": "cart",
+        "children": [ { "prefab": "broken" } ] }"#).unwrap());
+    let err = with_broken.spawn_prefab(&mut world, &mut physics, &Instance::new("broken cart")).unwrap_err();
+    assert!(format!("{:#}", err).contains("broken"));
+    assert_eq!(world.len(), 0);
+    assert!(physics.describe_colliders(None).is_empty());
+
+    // a bodyless child's colliders are fixed in the world, they go too
+    assert!(prefabs.spawn_prefab(&mut world, &mut physics, &Instance::new("fenced broken")).is_err());
+    assert_eq!(world.len(), 0);
+    assert!(physics.describe_colliders(None).is_empty());
+
+    let self_based = Prefabs::new().with("a", Prefab { base: Some("a".to_string()), ..Default::default() });
+    assert!(self_based.resolve("a", &Default::default()).is_err());
+
+    // eight children of eight children ... is 8^6 entities at depth 6, far from the depth limit
+    let mut fanned = Prefabs::new().with("f0", Prefab::default());
+    for i in 1..=6 {
+        let children = vec![format!(r#"{{ "prefab": "f{}" }}"#, i - 1); 8].join(", ");
+        fanned = fanned.with(&format!("f{}", i), Prefab::from_json(&format!(r#"{{ "children": [{}] }}"#, children)).unwrap());
+    }
+    assert!(fanned.resolve("f3", &Default::default()).is_ok());
+    let err = fanned.resolve("f6", &Default::default()).unwrap_err();
+    assert!(format!("{:#}", err).contains("more than 4096 entities"));
+}
+
+#[test]
+fn spawning_is_deterministic() {
+    let prefabs = Prefabs::from_json(PREFABS).unwrap();
+    let mut a = Sim::new(3);
+    let mut b = Sim::new(3);
+    for sim in [&mut a, &mut b].iter_mut() {
+        sim.spawn_prefab(&prefabs, &Instance::new("cart").at(2.0, 3.0)).unwrap();
+        sim.spawn_prefab(&prefabs, &Instance::new("crate").at(-2.0, 5.0).set("body.linvel", vec![1.0, 0.0])).unwrap();
+    }
+
+    let frame = Default::default();
+    for _ in 0..90 {
+        a.step(&frame);
+        b.step(&frame);
+    }
+    assert_eq!(a.state_hash(), b.state_hash());
+    assert_ne!(a.state_hash(), Sim::new(3).state_hash());
+}
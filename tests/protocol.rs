@@ -0,0 +1,97 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::lobby::*;
+use yo_yo::protocol::*;
+use yo_yo::replay::InputFrame;
+use yo_yo::snapshot::SnapshotDelta;
+
+
+fn every_message() -> Vec<Message> {
+    let room = RoomInfo {
+        id: 2,
+        name: "room".to_string(),
+        max_players: 4,
+        host: 7,
+        players: vec![PlayerInfo { id: 7, name: "ann".to_string(), ready: true }],
+        playing: false,
+    };
+    let mut frame = InputFrame::default();
+    frame.pressed.push("jump".to_string());
+    frame.axes.push(("move_x".to_string(), -0.5));
+
+    // in declaration order
+    vec![
+        Message::Hello { name: "ann".to_string() },
+        Message::Ping { time: 1.5 },
+        Message::Pong { time: 1.5 },
+        Message::Chat { text: "hi".to_string() },
+        Message::Text("plain".to_string()),
+        Message::Welcome { id: 7 },
+        Message::Input { tick: 300, frame },
+        Message::Snapshot(SnapshotDelta { tick: 12, base: Some(10), ack: 9, changed: vec![], removed: vec![3] }),
+        Message::Ack { tick: 12 },
+        Message::ListRooms,
+        Message::RoomList { rooms: vec![room.clone()] },
+        Message::CreateRoom { name: "room".to_string(), max_players: 4 },
+        Message::JoinRoom { room: 2 },
+        Message::LeaveRoom,
+        Message::SetReady { ready: true },
+        Message::StartGame,
+        Message::FindMatch { size: 2 },
+        Message::CancelMatch,
+        Message::RoomUpdate(room),
+        Message::GameStarted { room: 2 },
+        Message::LobbyError { reason: "full".to_string() },
+    ]
+}
+
+
+#[test]
+fn every_message_round_trips() {
+    for m in every_message() {
+        assert_eq!(decode(&encode(&m)).unwrap(), m);
+    }
+}
+
+#[test]
+fn variants_keep_their_place() {
+    // the variant index is the first byte after the header, appending must not move any
+    for (i, m) in every_message().iter().enumerate() {
+        let bytes = encode(m);
+        assert_eq!(&bytes[..4], &[b'Y', b'Y', PROTOCOL_VERSION as u8, (PROTOCOL_VERSION >> 8) as u8]);
+        assert_eq!(bytes[4] as usize, i, "{:?}", m);
+    }
+}
+
+#[test]
+fn versions_outside_the_range_are_refused() {
+    let mut bytes = encode(&Message::Ping { time: 2.0 });
+
+    for version in [0, MIN_PROTOCOL_VERSION.saturating_sub(1), PROTOCOL_VERSION + 1, u16::MAX].iter() {
+        bytes[2..4].copy_from_slice(&version.to_le_bytes());
+        let supported = (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(version);
+        assert_eq!(decode(&bytes).is_ok(), supported, "version {}", version);
+        if !supported {
+            assert!(decode(&bytes).unwrap_err().to_string().contains("version"));
+        }
+    }
+
+    for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+        bytes[2..4].copy_from_slice(&version.to_le_bytes());
+        assert_eq!(decode(&bytes).unwrap(), Message::Ping { time: 2.0 });
+    }
+}
+
+#[test]
+fn garbage_is_an_error() {
+    let bytes = encode(&Message::Chat { text: "hello".to_string() });
+    assert!(decode(&bytes[..3]).is_err());
+    assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    assert!(decode(b"XY\x03\x00\x01").is_err());
+
+    // a variant past the end, as sent by a newer peer
+    let mut unknown = bytes.clone();
+    unknown.truncate(4);
+    unknown.push(every_message().len() as u8);
+    assert!(decode(&unknown).is_err());
+}
@@ -0,0 +1,88 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::replay::*;
+use yo_yo::scene::*;
+use yo_yo::sim::*;
+
+
+fn record(seed: u64, ticks: usize) -> Replay {
+    let mut sim = Sim::new(seed);
+    let mut replay = Replay::new(seed, TICK_DT);
+
+    for i in 0..ticks {
+        let mut frame = InputFrame::default();
+        frame.axes.push(("move_x".to_string(), if i < ticks / 2 { 1.0 } else { -0.5 }));
+        if i % 60 == 10 {
+            frame.pressed.push("jump".to_string());
+        }
+        if i == 30 {
+            frame.pressed.push("spawn".to_string());
+            frame.pointer = [1.5, 6.0];
+        }
+
+        sim.step(&frame);
+        replay.push(frame, sim.state_hash());
+    }
+
+    replay
+}
+
+#[test]
+fn replay_reproduces_recording() {
+    let replay = record(7, 240);
+    let report = run_replay(&replay).unwrap();
+
+    assert_eq!(report.ticks, 240);
+    assert_eq!(report.first_mismatch, None);
+    assert_eq!(Some(&report.final_hash), replay.hashes.last());
+}
+
+#[test]
+fn replay_survives_serialisation() {
+    let replay = record(42, 120);
+    let bytes = replay.to_bytes().unwrap();
+    let loaded = Replay::from_bytes(&bytes).unwrap();
+
+    assert_eq!(loaded, replay);
+    assert_eq!(run_replay(&loaded).unwrap().first_mismatch, None);
+}
+
+#[test]
+fn edited_input_is_detected() {
+    let mut replay = record(1, 120);
+    replay.frames[50].pressed.push("jump".to_string());
+
+    assert_eq!(run_replay(&replay).unwrap().first_mismatch, Some(50));
+}
+
+#[test]
+fn replays_start_from_their_scene_and_tick() {
+    // a slower tick and a level with the ball somewhere else
+    let start = Scene::capture(&Sim::new(0).world, &Sim::new(0).physics);
+    let mut moved = start.clone();
+    moved.entities[0].transform.x = 3.0;
+
+    let mut replay = Replay::new(9, 1.0 / 30.0).with_scene(&moved).unwrap();
+    let mut sim = Sim::from_replay(&replay).unwrap();
+    for _ in 0..60 {
+        let mut frame = InputFrame::default();
+        frame.axes.push(("move_x".to_string(), 1.0));
+        sim.step(&frame);
+        replay.push(frame, sim.state_hash());
+    }
+
+    let loaded = Replay::from_bytes(&replay.to_bytes().unwrap()).unwrap();
+    assert_eq!(loaded.scene().unwrap(), Some(moved));
+    assert_eq!(run_replay(&loaded).unwrap().first_mismatch, None);
+
+    // the same input at the default tick from the built-in level is another game
+    let mut plain = replay.clone();
+    plain.scene = None;
+    assert_eq!(run_replay(&plain).unwrap().first_mismatch, Some(0));
+    let mut fast = replay.clone();
+    fast.tick_dt = TICK_DT;
+    assert_eq!(run_replay(&fast).unwrap().first_mismatch, Some(0));
+
+    fast.scene = Some("{ not json".to_string());
+    assert!(run_replay(&fast).is_err());
+}
@@ -0,0 +1,124 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use serde_json::{json, Value};
+
+use yo_yo::components::*;
+use yo_yo::replay::*;
+use yo_yo::save::*;
+use yo_yo::sim::*;
+
+
+fn played(ticks: usize) -> Sim {
+    let mut sim = Sim::new(5);
+    sim.spawn_ball(2.0, 6.0, 0.3);
+    let spark = sim.spawn_ball(-2.0, 6.0, 0.1);
+    sim.world.insert_one(spark, Transient).unwrap();
+    sim.add_player(1);
+
+    let mut frame = InputFrame::default();
+    frame.axes.push(("move_x".to_string(), 1.0));
+    for _ in 0..ticks {
+        sim.step(&frame);
+    }
+    sim
+}
+
+
+#[test]
+fn restores_bodies_tick_and_rng() {
+    let mut sim = played(45);
+    let mut save = SaveGame::capture(&sim);
+    save.set("coins", &12u32).unwrap();
+    assert_eq!(save.version, SAVE_VERSION);
+    // the player and the spark are not part of the save
+    assert_eq!(save.scene.entities.len(), 2);
+
+    let mut saves = Saves::new(Box::new(MemoryStore::new()));
+    saves.save("slot-1", &save).unwrap();
+    let loaded = saves.load("slot-1").unwrap().unwrap();
+    assert_eq!(loaded, save);
+    assert_eq!(loaded.get::<u32>("coins").unwrap(), Some(12));
+    assert!(loaded.get::<String>("coins").is_err());
+    assert_eq!(loaded.get::<u32>("lives").unwrap(), None);
+
+    let mut restored = loaded.restore().unwrap();
+    assert_eq!(restored.tick, 45);
+    assert_eq!(restored.rng.next_u64(), sim.rng.next_u64());
+
+    let (ball, again) = (sim.physics.ball(), restored.physics.ball());
+    assert_eq!(sim.physics.body_transform(ball), restored.physics.body_transform(again));
+    assert_eq!(sim.physics.body_velocity(ball), restored.physics.body_velocity(again));
+}
+
+#[test]
+fn migrates_old_saves_step_by_step() {
+    let migrations = Migrations::new(3)
+        .with(1, |v| {
+            // version 2 moved the score into data
+            let score = v["score"].take();
+            v["data"] = json!({ "score": score });
+            Ok(())
+        })
+        .with(2, |v| {
+            v["data"]["lives"] = json!(3);
+            Ok(())
+        });
+
+    let v1 = json!({ "version": 1, "score": 40 });
+    assert_eq!(migrations.migrate(v1).unwrap(), json!({ "version": 3, "score": null, "data": { "score": 40, "lives": 3 } }));
+
+    let v2 = json!({ "version": 2, "data": {} });
+    assert_eq!(migrations.migrate(v2).unwrap()["data"]["lives"], 3);
+
+    assert!(migrations.migrate(json!({ "version": 4 })).unwrap_err().to_string().contains("newer"));
+    assert!(migrations.migrate(json!({ "score": 1 })).is_err());
+    assert!(Migrations::new(3).with(2, |_| Ok(())).migrate(json!({ "version": 1 })).is_err());
+
+    let failing = Migrations::new(2).with(1, |_| anyhow::bail!("corrupt"));
+    assert!(format!("{:#}", failing.migrate(json!({ "version": 1 })).unwrap_err()).contains("corrupt"));
+}
+
+#[test]
+fn saves_load_through_migrations() {
+    // "tick" was "ticks" in a made up version 0, the migration renames it on load
+    let mut store = MemoryStore::new();
+    let current = SaveGame::capture(&played(10)).to_json().unwrap();
+    let mut old: Value = serde_json::from_str(&current).unwrap();
+    old["version"] = json!(0);
+    old["ticks"] = old["tick"].take();
+    store.slots.insert("old".to_string(), old.to_string());
+
+    let saves = Saves::new(Box::new(store));
+    assert!(saves.load("old").is_err());
+
+    let saves = saves.migrations(Migrations::new(SAVE_VERSION).with(0, |v| {
+        v["tick"] = v["ticks"].take();
+        Ok(())
+    }));
+    assert_eq!(saves.load("old").unwrap().unwrap().tick, 10);
+    assert!(saves.load("empty").unwrap().is_none());
+}
+
+#[test]
+fn file_slots() {
+    let dir = std::env::temp_dir().join("yo_yo_save_test");
+    std::fs::remove_dir_all(&dir).ok();
+    let mut saves = Saves::new(Box::new(FileStore::new(dir.to_str().unwrap())));
+    assert!(saves.slots().unwrap().is_empty());
+
+    let save = SaveGame::capture(&played(5));
+    saves.save("b", &save).unwrap();
+    saves.save("a", &save).unwrap();
+    saves.save("a", &save).unwrap();
+    assert_eq!(saves.slots().unwrap(), ["a", "b"]);
+    assert_eq!(saves.load("a").unwrap().unwrap(), save);
+
+    saves.remove("a").unwrap();
+    saves.remove("a").unwrap();
+    assert_eq!(saves.slots().unwrap(), ["b"]);
+    assert!(saves.load("a").unwrap().is_none());
+
+    assert!(saves.save("../escape", &save).is_err());
+    assert!(saves.load("").is_err());
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,121 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use hecs::World;
+
+use yo_yo::components::*;
+use yo_yo::physics::*;
+use yo_yo::replay::*;
+use yo_yo::scene::*;
+use yo_yo::sim::*;
+
+
+const LEVEL: &str = r#"{
+    "controlled": "ball",
+    "colliders": [ { "shape": { "type": "Cuboid", "hx": 100.0, "hy": 0.0 } } ],
+    "entities": [
+        {
+            "name": "ball",
+            "transform": { "x": 0.0, "y": 10.0 },
+            "circle": { "radius": 0.5, "color": [1.0, 1.0, 1.0] },
+            "body": { "kind": "Dynamic" },
+            "colliders": [ { "shape": { "type": "Ball", "radius": 0.5 }, "restitution": 0.7 } ]
+        },
+        {
+            "name": "ramp",
+            "transform": { "x": 5.0, "y": 1.0, "rotation": 0.3 },
+            "colliders": [ { "shape": { "type": "Polyline", "points": [[-2.0, 0.0], [2.0, 0.0]] } } ],
+            "assets": { "sprite": "ramp.png" }
+        }
+    ]
+}"#;
+
+
+#[test]
+fn spawns_entities_and_bodies() {
+    let scene = Scene::from_json(LEVEL).unwrap();
+    assert_eq!(scene.gravity, [0.0, -9.81]);
+    assert_eq!(scene.assets().into_iter().collect::<Vec<_>>(), ["ramp.png"]);
+
+    let mut world = World::new();
+    let mut physics = Physics::empty();
+    let spawned = scene.spawn(&mut world, &mut physics).unwrap();
+    assert_eq!(spawned.len(), 2);
+
+    let ball = world.get::<&Body>(spawned[0]).unwrap().0;
+    assert_eq!(physics.ball(), ball);
+    assert_eq!(physics.body_transform(ball), Some((0.0, 10.0, 0.0)));
+    assert_eq!(world.get::<&Name>(spawned[1]).unwrap().0, "ramp");
+    assert!(world.get::<&Body>(spawned[1]).is_err());
+    assert_eq!(world.get::<&AssetRefs>(spawned[1]).unwrap().0["sprite"], "ramp.png");
+
+    // the ramp's collider is fixed in the world at its transform
+    let fixed = physics.describe_colliders(None);
+    assert_eq!(fixed.len(), 2);
+    assert_eq!(fixed[1].offset, [5.0, 1.0]);
+    assert!((fixed[1].rotation - 0.3).abs() < 1e-6);
+}
+
+#[test]
+fn same_simulation_as_the_built_in_level() {
+    // without the ramp it is the ground and ball of Physics::new
+    let mut scene = Scene::from_json(LEVEL).unwrap();
+    scene.entities.truncate(1);
+
+    let mut a = Sim::new(1);
+    let mut b = Sim::from_scene(1, &scene).unwrap();
+
+    let mut frame = InputFrame::default();
+    frame.axes.push(("move_x".to_string(), 1.0));
+    for _ in 0..120 {
+        a.step(&frame);
+        b.step(&frame);
+    }
+    assert_eq!(a.state_hash(), b.state_hash());
+}
+
+#[test]
+fn capture_roundtrips() {
+    let mut sim = Sim::from_scene(2, &Scene::from_json(LEVEL).unwrap()).unwrap();
+    sim.spawn_ball(3.0, 4.0, 0.25);
+    sim.add_player(7);
+
+    let scene = Scene::capture(&sim.world, &sim.physics);
+    // the player is session state
+    assert_eq!(scene.entities.len(), 3);
+    assert_eq!(scene.controlled.as_deref(), Some("ball"));
+    assert_eq!(scene.entities[0].colliders[0].restitution, 0.7);
+
+    let again = Scene::from_json(&scene.to_json().unwrap()).unwrap();
+    assert_eq!(again, scene);
+
+    let mut world = World::new();
+    let mut physics = Physics::empty();
+    again.spawn(&mut world, &mut physics).unwrap();
+    let recaptured = Scene::capture(&world, &physics);
+    assert_eq!(recaptured.entities, scene.entities);
+    assert_eq!(recaptured.colliders.len(), 2);
+}
+
+#[test]
+fn bad_scenes_are_errors() {
+    assert!(Scene::from_json(r#"{ "entities": [ { "body": { "kind": "Floaty" } } ] }"#).is_err());
+
+    let scene = Scene::from_json(r#"{ "entities": [ { "name": "blob", "body": {},
+        "colliders": [ { "shape": { "type": "Convex", "points": [[0.0, 0.0]] } } ] } ] }"#).unwrap();
+    let mut world = World::new();
+    let mut physics = Physics::empty();
+    let err = scene.spawn(&mut world, &mut physics).unwrap_err();
+    assert!(format!("{:#}", err).contains("blob"));
+    assert_eq!(world.len(), 0);
+
+    // whatever spawned before the broken entity is taken out again, gravity included
+    let mut broken = Scene::from_json(LEVEL).unwrap();
+    broken.gravity = [0.0, -1.0];
+    broken.entities.push(scene.entities[0].clone());
+    let err = broken.spawn(&mut world, &mut physics).unwrap_err();
+    assert!(format!("{:#}", err).contains("blob"));
+    assert_eq!(world.len(), 0);
+    assert!(physics.describe_colliders(None).is_empty());
+    assert_eq!(physics.state_hash(), Physics::empty().state_hash());
+    assert_eq!(physics.gravity(), Physics::empty().gravity());
+}
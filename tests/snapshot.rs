@@ -0,0 +1,90 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::protocol::*;
+use yo_yo::replay::*;
+use yo_yo::sim::*;
+use yo_yo::snapshot::*;
+
+
+fn run(sim: &mut Sim, ticks: usize) -> Vec<QuantEntity> {
+    for _ in 0..ticks {
+        sim.step(&InputFrame::default());
+    }
+    quantise(&capture(sim))
+}
+
+#[test]
+fn delta_patches_back_to_the_same_snapshot() {
+    let mut sim = Sim::new(3);
+    sim.spawn_ball(1.0, 5.0, 0.5);
+    let base = run(&mut sim, 10);
+
+    let e = sim.spawn_ball(-2.0, 8.0, 0.25);
+    let current = run(&mut sim, 10);
+
+    let delta = diff(Some((10, &base[..])), &current, 20, 0);
+    assert_eq!(patch(Some(&base[..]), &delta), current);
+
+    sim.world.despawn(e).unwrap();
+    let after = quantise(&capture(&sim));
+    let delta = diff(Some((20, &current[..])), &after, 21, 0);
+    assert_eq!(delta.removed, vec![e.to_bits().get()]);
+    assert_eq!(patch(Some(&current[..]), &delta), after);
+}
+
+#[test]
+fn delta_is_smaller_than_a_full_snapshot() {
+    let mut sim = Sim::new(5);
+    for i in 0..20 {
+        sim.spawn_ball(i as f32 - 10.0, 2.0, 0.5);
+    }
+    let base = run(&mut sim, 120);
+    let current = run(&mut sim, 1);
+
+    let full = encode(&Message::Snapshot(diff(None, &current, 121, 0)));
+    let delta = encode(&Message::Snapshot(diff(Some((120, &base[..])), &current, 121, 0)));
+    assert!(delta.len() < full.len());
+}
+
+#[test]
+fn quantisation_error_is_bounded() {
+    let e = EntityState {
+        id: 1,
+        x: 12.3456,
+        y: -7.891,
+        rotation: 4.0,
+        vx: 3.21,
+        vy: -0.5,
+        spin: 1.0,
+        radius: 0.5,
+        color: [0.2, 0.4, 0.6],
+        player: Some(2),
+    };
+    let back = QuantEntity::quantise(&e).dequantise();
+
+    assert!((back.x - e.x).abs() <= 0.5 / POS_SCALE);
+    assert!((back.y - e.y).abs() <= 0.5 / POS_SCALE);
+    assert!((back.vx - e.vx).abs() <= 0.5 / VEL_SCALE);
+    assert!((back.rotation - e.rotation).abs() < 0.001);
+    assert_eq!(back.player, Some(2));
+}
+
+#[test]
+fn interpolator_blends_between_snapshots() {
+    let at = |tick: u64, x: f32| Snapshot {
+        tick,
+        ack: 0,
+        entities: vec![EntityState {
+            id: 1, x, y: 0.0, rotation: 0.0, vx: 0.0, vy: 0.0, spin: 0.0, radius: 0.5, color: [1.0; 3], player: None,
+        }],
+    };
+
+    let tick_ms = TICK_DT as f64 * 1000.0;
+    let mut interp = Interpolator::new(tick_ms * 2.0, TICK_DT);
+    interp.push(at(10, 0.0), 10.0 * tick_ms);
+    interp.push(at(12, 2.0), 12.0 * tick_ms);
+
+    // two ticks of delay puts the playback at tick 11, halfway between the two
+    let s = interp.sample(13.0 * tick_ms).unwrap();
+    assert!((s.entities[0].x - 1.0).abs() < 0.01);
+}
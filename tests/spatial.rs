@@ -0,0 +1,96 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use hecs::World;
+
+use yo_yo::audio::*;
+use yo_yo::components::*;
+use yo_yo::physics::*;
+use yo_yo::spatial::*;
+
+
+fn hum() -> Sound {
+    Sound::new(MIX_RATE, 1, vec![0.5; MIX_RATE as usize])
+}
+
+
+#[test]
+fn rolloff_models() {
+    let linear = Rolloff::Linear { min: 1.0, max: 11.0 };
+    assert_eq!(linear.gain(0.5), 1.0);
+    assert_eq!(linear.gain(6.0), 0.5);
+    assert_eq!(linear.gain(20.0), 0.0);
+
+    let inverse = Rolloff::Inverse { min: 1.0, factor: 1.0 };
+    assert_eq!(inverse.gain(2.0), 0.5);
+    assert_eq!(inverse.gain(4.0), 0.25);
+
+    let exp = Rolloff::Exponential { min: 1.0, factor: 2.0 };
+    assert_eq!(exp.gain(2.0), 0.25);
+    assert_eq!(Rolloff::None.gain(1000.0), 1.0);
+}
+
+#[test]
+fn distance_and_side() {
+    let mut world = World::new();
+    let physics = Physics::new();
+    let mut mixer = Mixer::new(MIX_RATE);
+    let mut spatial = SpatialAudio::new();
+
+    world.spawn((Transform::new(-3.0, 0.0), Listener { pan_width: 10.0 }));
+    let near = world.spawn((Transform::new(2.0, 0.0), Emitter::new(hum(), Play::new().looping())));
+    let far = world.spawn((Transform::new(-13.0, 0.0), Emitter::new(hum(), Play::new().looping())));
+
+    spatial.update(&mut world, &physics, &mut mixer);
+
+    let near = mixer.params(spatial.voice(near).unwrap()).unwrap();
+    let far = mixer.params(spatial.voice(far).unwrap()).unwrap();
+
+    // 5 to the right, 10 to the left of the listener
+    assert_eq!(near.pan, 0.5);
+    assert_eq!(far.pan, -1.0);
+    assert!(near.volume > far.volume * 1.5);
+}
+
+#[test]
+fn despawn_stops_the_sound() {
+    let mut world = World::new();
+    let physics = Physics::new();
+    let mut mixer = Mixer::new(MIX_RATE);
+    let mut spatial = SpatialAudio::new();
+
+    let e = world.spawn((Transform::new(1.0, 0.0), Emitter::new(hum(), Play::new().looping())));
+    spatial.update(&mut world, &physics, &mut mixer);
+    assert_eq!(mixer.playing(), 1);
+
+    world.despawn(e).unwrap();
+    spatial.update(&mut world, &physics, &mut mixer);
+    assert_eq!(mixer.playing(), 0);
+    assert!(spatial.voice(e).is_none());
+}
+
+#[test]
+fn doppler_from_body_velocity() {
+    let mut world = World::new();
+    let mut physics = Physics::new();
+    let mut mixer = Mixer::new(MIX_RATE);
+    let mut spatial = SpatialAudio::new();
+    spatial.speed_of_sound = 100.0;
+
+    let approaching = physics.add_ball(10.0, 0.0, 0.5);
+    physics.set_body_velocity(approaching, -20.0, 0.0, 0.0);
+    let leaving = physics.add_ball(-10.0, 0.0, 0.5);
+    physics.set_body_velocity(leaving, -20.0, 0.0, 0.0);
+
+    let emitter = Emitter::new(hum(), Play::new().looping()).doppler(1.0);
+    let a = world.spawn((Transform::new(10.0, 0.0), Body(approaching), emitter.clone()));
+    let b = world.spawn((Transform::new(-10.0, 0.0), Body(leaving), emitter.clone()));
+    let still = world.spawn((Transform::new(10.0, 0.0), emitter));
+
+    spatial.update(&mut world, &physics, &mut mixer);
+    let pitch = |e| mixer.params(spatial.voice(e).unwrap()).unwrap().pitch;
+
+    // 100 / (100 - 20) and 100 / (100 + 20)
+    assert!((pitch(a) - 1.25).abs() < 1e-4);
+    assert!((pitch(b) - 100.0 / 120.0).abs() < 1e-4);
+    assert_eq!(pitch(still), 1.0);
+}
@@ -0,0 +1,95 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::audio::*;
+use yo_yo::rng::*;
+use yo_yo::synth::*;
+
+
+// rising edges per second, a rough pitch
+fn crossings(samples: &[f32]) -> usize {
+    samples.windows(2).filter(|w| w[0] <= 0.0 && w[1] > 0.0).count()
+}
+
+
+#[test]
+fn presets_are_deterministic_and_audible() {
+    for name in SynthParams::PRESETS.iter() {
+        let a = SynthParams::preset(name, &mut Rng::new(3)).unwrap().render(MIX_RATE, 3);
+        let b = SynthParams::preset(name, &mut Rng::new(3)).unwrap().render(MIX_RATE, 3);
+
+        assert!(a == b, "{} is not deterministic", name);
+        assert!(a.frames() > 0);
+        assert!(a.samples.iter().all(|s| s.abs() <= 1.0), "{} clips", name);
+        assert!(a.samples.iter().any(|s| s.abs() > 0.05), "{} is silent", name);
+    }
+
+    assert!(SynthParams::preset("kazoo", &mut Rng::new(1)).is_none());
+}
+
+#[test]
+fn envelope_sets_the_length() {
+    let p = SynthParams { attack: 0.125, sustain: 0.25, decay: 0.125, ..SynthParams::new() };
+    let sound = p.render(1000, 1);
+    assert_eq!(sound.frames(), 500);
+
+    // silent at the start of the attack and the end of the decay
+    assert!(sound.samples[0].abs() < 0.01);
+    assert!(sound.samples[499].abs() < 0.01);
+}
+
+#[test]
+fn slide_and_arpeggio_change_pitch() {
+    let flat = SynthParams { wave: Wave::Sine, freq: 200.0, sustain: 1.0, decay: 0.0, ..SynthParams::new() };
+    let up = SynthParams { slide: 1.0, ..flat.clone() };
+    let arp = SynthParams { arp_mult: 2.0, arp_time: 0.5, ..flat.clone() };
+
+    let flat = flat.render(MIX_RATE, 1);
+    let up = up.render(MIX_RATE, 1);
+    let arp = arp.render(MIX_RATE, 1);
+
+    let half = MIX_RATE as usize / 2;
+    let (a, b) = (crossings(&flat.samples[..half]), crossings(&flat.samples[half..]));
+    assert!((a as i32 - b as i32).abs() <= 1);
+
+    assert!(crossings(&up.samples[half..]) > crossings(&up.samples[..half]) + 30);
+
+    let after = crossings(&arp.samples[half..]);
+    assert!(after >= 2 * b - 2 && after <= 2 * b + 2);
+}
+
+#[test]
+fn noise_changes_every_sample_and_follows_the_frequency() {
+    let low = SynthParams { wave: Wave::Noise, freq: 100.0, sustain: 1.0, decay: 0.0, ..SynthParams::new() };
+    let high = SynthParams { freq: 4000.0, ..low.clone() };
+    let low = low.render(MIX_RATE, 1);
+    let high = high.render(MIX_RATE, 1);
+
+    // not held for a whole period, which would buzz at 100 Hz
+    let held = low.samples.windows(2).filter(|w| w[0] == w[1]).count();
+    assert!(held < low.frames() / 100, "{}", held);
+    assert!(low.samples.iter().any(|s| s.abs() > 0.2));
+    assert!(crossings(&high.samples) > 4 * crossings(&low.samples));
+}
+
+#[test]
+fn min_freq_cuts_a_downward_slide() {
+    let p = SynthParams { freq: 800.0, slide: -4.0, min_freq: 100.0, sustain: 2.0, ..SynthParams::new() };
+    // 800 -> 100 is three octaves, 0.75 seconds at 4 octaves per second
+    let frames = p.render(1000, 1).frames();
+    assert!((749..=751).contains(&frames), "{}", frames);
+}
+
+#[test]
+fn plays_through_the_mixer() {
+    let sound = SynthParams::coin(&mut Rng::new(9)).render(MIX_RATE, 9);
+    let mut mixer = Mixer::new(MIX_RATE);
+    let v = mixer.play(&sound, Play::new());
+
+    let mut out = vec![0.0; sound.frames() * 2];
+    mixer.render(&mut out);
+    assert!(out.iter().any(|s| *s != 0.0));
+    assert!(!mixer.is_playing(v));
+
+    // and survives a trip through a file
+    assert_eq!(Sound::from_wav(&sound.to_wav()).unwrap().frames(), sound.frames());
+}
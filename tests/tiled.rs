@@ -0,0 +1,260 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use yo_yo::physics::*;
+use yo_yo::tiled::*;
+
+
+const CAVE: &str = r#"{
+    "orientation": "orthogonal", "infinite": false,
+    "width": 4, "height": 3, "tilewidth": 16, "tileheight": 16,
+    "properties": [
+        { "name": "music", "type": "file", "value": "music/cave.ogg" },
+        { "name": "gravity", "type": "float", "value": -4.5 }
+    ],
+    "tilesets": [ {
+        "firstgid": 1, "name": "terrain", "tilewidth": 16, "tileheight": 16, "tilecount": 8, "columns": 4,
+        "image": "../tiles/terrain.png", "imagewidth": 64, "imageheight": 32,
+        "tiles": [ {
+            "id": 5,
+            "properties": [ { "name": "slippery", "type": "bool", "value": true } ],
+            "objectgroup": { "objects": [ { "id": 1, "x": 0, "y": 8, "width": 16, "height": 8 } ] }
+        } ]
+    } ],
+    "layers": [
+        {
+            "type": "tilelayer", "name": "ground", "width": 4, "height": 3,
+            "properties": [ { "name": "collision", "type": "bool", "value": true } ],
+            "data": [0, 0, 0, 0,  1, 1, 0, 6,  2, 2, 2, 2]
+        },
+        { "type": "group", "name": "decor", "offsetx": 8, "offsety": -4, "layers": [
+            { "type": "tilelayer", "name": "vines", "width": 4, "height": 3, "opacity": 0.5,
+              "data": [0, 2147483651, 0, 0,  0, 0, 0, 0,  0, 0, 0, 0] }
+        ] },
+        { "type": "imagelayer", "name": "sky" },
+        {
+            "type": "objectgroup", "name": "things",
+            "objects": [
+                { "id": 2, "name": "start", "type": "spawn", "x": 8, "y": 16, "point": true },
+                { "id": 3, "name": "water", "class": "trigger", "x": 32, "y": 0, "width": 32, "height": 16,
+                  "properties": [
+                      { "name": "collision", "type": "bool", "value": true },
+                      { "name": "sensor", "type": "bool", "value": true }
+                  ] }
+            ]
+        }
+    ]
+}"#;
+
+
+fn tmx(data: &str) -> String {
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="note">two
+lines</property>
+ </properties>
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <!-- the same tiles three ways -->
+ <layer id="1" name="ground" width="3" height="2">
+  {}
+ </layer>
+ <group id="2" name="solid" offsetx="16">
+  <objectgroup id="3" name="collision">
+   <object id="1" x="0" y="0" width="16" height="16" rotation="90"/>
+   <object id="2" x="0" y="32">
+    <polygon points="0,0 16,0 16,-16"/>
+   </object>
+  </objectgroup>
+ </group>
+</map>"#, data)
+}
+
+const TERRAIN_TSX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="terrain" tilewidth="16" tileheight="16" spacing="2" margin="1" tilecount="8" columns="4">
+ <image source="terrain.png" width="70" height="37"/>
+ <tile id="2">
+  <properties>
+   <property name="hurts" type="int" value="3"/>
+  </properties>
+ </tile>
+</tileset>"#;
+
+const TILES: [u32; 6] = [1, 2, 0, 0, 3, 1];
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for c in bytes.chunks(3) {
+        let n = (c[0] as u32) << 16 | (*c.get(1).unwrap_or(&0) as u32) << 8 | *c.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= c.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+
+#[test]
+fn reads_tmj_layers_and_properties() {
+    let map = Map::parse(CAVE.as_bytes()).unwrap();
+    assert_eq!((map.width, map.height, map.scale), (4, 3, 1.0 / 16.0));
+    assert_eq!(map.properties["music"].as_str(), Some("music/cave.ogg"));
+    assert_eq!(map.properties["gravity"].as_f64(), Some(-4.5));
+
+    // groups are flattened, image layers dropped
+    let names: Vec<_> = map.layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(names, ["ground", "vines", "things"]);
+    let vines = map.layer("vines").unwrap();
+    assert_eq!((vines.offset, vines.opacity), ([8.0, -4.0], 0.5));
+
+    let t = map.tile(FLIP_H | 3).unwrap();
+    assert_eq!((t.tileset, t.id, t.flip_h, t.flip_v), (0, 2, true, false));
+    assert!(map.tile(0).is_none());
+    assert_eq!(map.tile_data(6).unwrap().properties["slippery"].as_bool(), Some(true));
+    assert_eq!(map.tileset_images("maps/cave.tmj"), [(0, "tiles/terrain.png".to_string())]);
+
+    let things = map.layer("things").unwrap();
+    let objects = match &things.kind {
+        LayerKind::Objects(o) => o,
+        _ => panic!("not an object layer"),
+    };
+    assert_eq!((objects[0].class.as_str(), &objects[0].shape), ("spawn", &ObjectShape::Point));
+    assert_eq!(objects[1].class, "trigger");
+    assert_eq!(map.object_position(things, &objects[0]), [0.5, -1.0]);
+    assert_eq!(map.object_position(things, &objects[1]), [3.0, -0.5]);
+}
+
+#[test]
+fn collision_layers_become_merged_boxes() {
+    let map = Map::parse(CAVE.as_bytes()).unwrap();
+    let colliders = map.colliders();
+    let shapes: Vec<_> = colliders.iter().map(|c| (c.shape.clone(), c.offset)).collect();
+    assert_eq!(shapes, [
+        // the slippery tile collides with the shape drawn on it, not its whole cell
+        (ColliderShape::Cuboid { hx: 0.5, hy: 0.25 }, [3.5, -1.75]),
+        // a 2x2 block on the left and the rest of the bottom row
+        (ColliderShape::Cuboid { hx: 1.0, hy: 1.0 }, [1.0, -2.0]),
+        (ColliderShape::Cuboid { hx: 1.0, hy: 0.5 }, [3.0, -2.5]),
+        (ColliderShape::Cuboid { hx: 1.0, hy: 0.5 }, [3.0, -0.5]),
+    ]);
+    assert!(colliders[3].sensor);
+    assert!(!colliders[0].sensor);
+
+    let mut physics = Physics::empty();
+    assert_eq!(map.add_colliders(&mut physics).unwrap(), 4);
+    assert_eq!(physics.describe_colliders(None).len(), 4);
+}
+
+#[test]
+fn chunks_batch_tiles_per_tileset() {
+    let mut map = Map::parse(CAVE.as_bytes()).unwrap();
+    let chunks = map.chunks(2);
+    // four chunks of ground, one with the vine
+    assert_eq!(chunks.len(), 5);
+    assert_eq!(chunks.iter().map(|c| c.vertices.len()).sum::<usize>(), 8 * 6);
+
+    let vine = chunks.iter().find(|c| c.layer == 1).unwrap();
+    assert_eq!((vine.cx, vine.cy), (0, 0));
+    assert_eq!(vine.bounds, [1.5, -0.75, 2.5, 0.25]);
+    // flipped, so the top-left corner shows the right edge of the tile
+    assert_eq!(vine.vertices[0], [1.5, 0.25, 0.75, 0.0]);
+
+    assert!(map.set_tile(0, 2, 0, 1));
+    assert_eq!(map.chunk(0, 1, 0, 2)[0].vertices.len(), 2 * 6);
+    assert!(!map.set_tile(0, 4, 0, 1));
+    assert!(!map.set_tile(2, 0, 0, 1));
+}
+
+#[test]
+fn reads_tmx_in_every_encoding() {
+    let bytes: Vec<u8> = TILES.iter().flat_map(|t| t.to_le_bytes()).collect();
+    let zlib = miniz_oxide::deflate::compress_to_vec_zlib(&bytes, 6);
+    let encodings = [
+        r#"<data encoding="csv">1,2,0,
+0,3,1</data>"#.to_string(),
+        format!(r#"<data encoding="base64">{}</data>"#, base64(&bytes)),
+        format!(r#"<data encoding="base64" compression="zlib">
+   {}
+  </data>"#, base64(&zlib)),
+        format!("<data>{}</data>", TILES.iter().map(|g| format!(r#"<tile gid="{}"/>"#, g)).collect::<String>()),
+    ];
+
+    for data in &encodings {
+        let map = Map::parse(tmx(data).as_bytes()).unwrap();
+        match &map.layers[0].kind {
+            LayerKind::Tiles(t) => assert_eq!(t.data, TILES, "{}", data),
+            _ => panic!("not a tile layer"),
+        }
+        assert_eq!(map.properties["note"].as_str(), Some("two\nlines"));
+    }
+
+    // inflating stops at the 6 tiles the layer holds
+    let bomb = miniz_oxide::deflate::compress_to_vec_zlib(&vec![0; 1 << 20], 6);
+    let data = format!(r#"<data encoding="base64" compression="zlib">{}</data>"#, base64(&bomb));
+    assert!(format!("{:#}", Map::parse(tmx(&data).as_bytes()).unwrap_err()).contains("zlib"));
+}
+
+#[test]
+fn external_tilesets_and_object_colliders() {
+    let mut map = Map::parse(tmx(r#"<data encoding="csv">1,2,0,0,3,1</data>"#).as_bytes()).unwrap();
+    assert_eq!(map.external_tilesets(), [(0, "terrain.tsx".to_string())]);
+    assert!(map.tileset_images("maps/1.tmx").is_empty());
+
+    map.load_tileset(0, TERRAIN_TSX.as_bytes()).unwrap();
+    assert!(map.external_tilesets().is_empty());
+    assert_eq!(map.tilesets[0].first_gid, 1);
+    assert_eq!(map.tile_data(3).unwrap().properties["hurts"].as_f64(), Some(3.0));
+    assert_eq!(map.tileset_images("maps/1.tmx"), [(0, "maps/terrain.png".to_string())]);
+    // margin and spacing
+    assert_eq!(map.tilesets[0].uv(5), [19.0 / 70.0, 19.0 / 37.0, 35.0 / 70.0, 35.0 / 37.0]);
+
+    // the ground layer is not solid, the group's object layer is
+    let colliders = map.colliders();
+    assert_eq!(colliders.len(), 2);
+
+    // turned a quarter clockwise around its top-left corner, then moved by the group
+    let box_ = &colliders[0];
+    assert_eq!(box_.shape, ColliderShape::Cuboid { hx: 0.5, hy: 0.5 });
+    assert!((box_.offset[0] - 0.5).abs() < 1e-5 && (box_.offset[1] + 0.5).abs() < 1e-5);
+    assert!((box_.rotation + std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+
+    assert_eq!(colliders[1].shape, ColliderShape::Convex { points: vec![[1.0, -2.0], [2.0, -2.0], [2.0, -1.0]] });
+}
+
+#[test]
+fn huge_tile_sizes_do_not_overflow() {
+    let huge = CAVE.replace(r#""tilewidth": 16"#, r#""tilewidth": 4294967295"#);
+    let map = Map::parse(huge.as_bytes()).unwrap();
+    assert!(!map.colliders().is_empty());
+    assert!(!map.chunks(16).is_empty());
+    assert!(map.tilesets[0].uv(2).iter().all(|v| v.is_finite()));
+}
+
+#[test]
+fn unsupported_maps_are_errors() {
+    let infinite = CAVE.replace(r#""infinite": false"#, r#""infinite": true"#);
+    assert!(Map::parse(infinite.as_bytes()).unwrap_err().to_string().contains("infinite"));
+
+    let iso = CAVE.replace("orthogonal", "isometric");
+    assert!(Map::parse(iso.as_bytes()).is_err());
+
+    // the tile count would overflow, not wrap round to something small
+    let huge = CAVE.replace(r#""ground", "width": 4, "height": 3"#, r#""ground", "width": 65536, "height": 65536"#);
+    assert!(format!("{:#}", Map::parse(huge.as_bytes()).unwrap_err()).contains("too many"));
+    let huge = tmx("<data></data>").replace(r#"width="3" height="2">"#, r#"width="65536" height="65537">"#);
+    assert!(format!("{:#}", Map::parse(huge.as_bytes()).unwrap_err()).contains("too many"));
+
+    let short = CAVE.replace("2, 2, 2, 2]", "2, 2, 2]");
+    assert!(format!("{:#}", Map::parse(short.as_bytes()).unwrap_err()).contains("ground"));
+
+    assert!(Map::parse(tmx(r#"<data encoding="csv">1,2,0</data>"#).as_bytes()).is_err());
+    assert!(Map::parse(b"<map><layer></map>").is_err());
+    // nesting that would overflow the stack
+    let deep = format!("<map>{}{}</map>", "<g>".repeat(100000), "</g>".repeat(100000));
+    assert!(Map::parse(deep.as_bytes()).unwrap_err().to_string().contains("deeper"));
+    assert!(Map::parse(b"P3 not a map").is_err());
+}